@@ -0,0 +1,108 @@
+use crate::{
+    emulator::{extract_i_type, extract_r_type, sign_extend, Emulator},
+    register::Register,
+    Result, Xlen,
+};
+
+// キャリーなし乗算(繰り上がりを無視した乗算)の128bitの結果を返す関数
+fn clmul(a: u64, b: u64) -> u128 {
+    (0..64)
+        .filter(|i| (b >> i) & 1 == 1)
+        .fold(0, |acc, i| acc ^ ((a as u128) << i))
+}
+
+// 各バイトが0でなければ0xff、0なら0x00にする関数
+fn orc_b(value: u64) -> u64 {
+    u64::from_le_bytes(
+        value
+            .to_le_bytes()
+            .map(|byte| if byte == 0 { 0 } else { 0xff }),
+    )
+}
+
+impl Emulator {
+    // Zba、Zbb、Zbc、Zbs拡張の命令を実行する関数
+    // *W命令と*.uw命令はrs1の下位32bitを使う。
+    // XLENが32のモードではrs1とrs2の下位32bitで演算し、ビットの位置やシフト量は下位5bitを使う。
+    // (RV64のみの命令はrv32_decodeで不正な命令にしている。)
+    pub(crate) fn exec_bitmanip(&mut self) -> Result<()> {
+        let raw = self.inst.raw();
+        let name = self.inst.name();
+
+        let (rd, rs1, rs2, _) = extract_r_type(raw);
+        let (_, _, imm) = extract_i_type(raw);
+
+        let xlen = self.current_xlen();
+        let bits = xlen.bits();
+        let shamt_mask = bits as u64 - 1;
+
+        let a = self.read_reg(Register::X(rs1)) & xlen.mask();
+        // 即値の命令はimmの下位6bit(シフト量やビットの位置)、それ以外はrs2を使う
+        let b = match name {
+            "slli_uw" | "rori" | "roriw" | "bseti" | "bclri" | "binvi" | "bexti" => imm & 0x3f,
+            _ => self.read_reg(Register::X(rs2)) & xlen.mask(),
+        };
+        let word = a & 0xffffffff;
+
+        let rotate_left = |value: u64, shamt: u64| match xlen {
+            Xlen::X32 => (value as u32).rotate_left(shamt as u32) as u64,
+            Xlen::X64 => value.rotate_left(shamt as u32),
+        };
+        let rotate_right = |value: u64, shamt: u64| match xlen {
+            Xlen::X32 => (value as u32).rotate_right(shamt as u32) as u64,
+            Xlen::X64 => value.rotate_right(shamt as u32),
+        };
+        // 符号付きの比較はread_regで符号拡張した値を使う
+        let (signed_a, signed_b) = (
+            self.read_reg(Register::X(rs1)) as i64,
+            self.read_reg(Register::X(rs2)) as i64,
+        );
+
+        let value = match name {
+            "add_uw" => b.wrapping_add(word),
+            "sh1add" => b.wrapping_add(a << 1),
+            "sh2add" => b.wrapping_add(a << 2),
+            "sh3add" => b.wrapping_add(a << 3),
+            "sh1add_uw" => b.wrapping_add(word << 1),
+            "sh2add_uw" => b.wrapping_add(word << 2),
+            "sh3add_uw" => b.wrapping_add(word << 3),
+            "slli_uw" => word << b,
+            "andn" => a & !b,
+            "orn" => a | !b,
+            "xnor" => !(a ^ b),
+            "clz" => (a.leading_zeros() - (64 - bits)) as u64,
+            "clzw" => (word as u32).leading_zeros() as u64,
+            "ctz" => a.trailing_zeros().min(bits) as u64,
+            "ctzw" => (word as u32).trailing_zeros() as u64,
+            "cpop" => a.count_ones() as u64,
+            "cpopw" => word.count_ones() as u64,
+            "max" => signed_a.max(signed_b) as u64,
+            "maxu" => a.max(b),
+            "min" => signed_a.min(signed_b) as u64,
+            "minu" => a.min(b),
+            "sext_b" => sign_extend(7, a & 0xff),
+            "sext_h" => sign_extend(15, a & 0xffff),
+            "zext_h" => a & 0xffff,
+            "rol" => rotate_left(a, b & shamt_mask),
+            "rolw" => sign_extend(31, (word as u32).rotate_left((b & 0x1f) as u32) as u64),
+            "ror" | "rori" => rotate_right(a, b & shamt_mask),
+            "rorw" | "roriw" => {
+                sign_extend(31, (word as u32).rotate_right((b & 0x1f) as u32) as u64)
+            }
+            "orc_b" => orc_b(a),
+            "rev8" => a.swap_bytes() >> (64 - bits),
+            "clmul" => clmul(a, b) as u64,
+            "clmulh" => (clmul(a, b) >> bits) as u64,
+            "clmulr" => (clmul(a, b) >> (bits - 1)) as u64,
+            "bset" | "bseti" => a | (1 << (b & shamt_mask)),
+            "bclr" | "bclri" => a & !(1 << (b & shamt_mask)),
+            "binv" | "binvi" => a ^ (1 << (b & shamt_mask)),
+            "bext" | "bexti" => (a >> (b & shamt_mask)) & 1,
+            _ => unimplemented!(),
+        };
+
+        self.write_reg(Register::X(rd), value);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use crate::{bus::DRAM_BASE, elf, emulator::Emulator, syscon::Syscon};
+
+// カーネルを配置するアドレスのデフォルト(OpenSBIのfw_jumpのFW_JUMP_ADDRと同じ)
+pub const DEFAULT_KERNEL_ADDRESS: u64 = DRAM_BASE + 0x20_0000;
+
+// fw_dynamicに渡す情報(struct fw_dynamic_info)
+const FW_DYNAMIC_INFO_MAGIC: u64 = 0x4942_534f; // "OSBI"
+const FW_DYNAMIC_INFO_VERSION: u64 = 2;
+const FW_DYNAMIC_INFO_NEXT_MODE_S: u64 = 1;
+
+const MIB: u64 = 1024 * 1024;
+
+// Linuxを起動するときの設定
+// firmwareはOpenSBIのfw_jumpかfw_dynamicで、ELFの場合はセグメントを、それ以外の場合はDRAM_BASEに配置する。
+// firmwareがNoneの場合は組み込みのSBIを使い、カーネルをSモードで直接起動する。
+// kernelはLinuxのImageで、kernel_addressに配置する。fw_jumpの場合はFW_JUMP_ADDRと同じにする必要がある。
+// initrd_addressとfdt_addressがNoneの場合はRAMの大きさから決める。
+// dump_fdtを指定した場合はゲストに渡すDTBをそのファイルにも書き出す。
+#[derive(Debug, Clone)]
+pub struct BootConfig {
+    pub firmware: Option<PathBuf>,
+    pub kernel: PathBuf,
+    pub initrd: Option<PathBuf>,
+    pub bootargs: String,
+    pub kernel_address: u64,
+    pub initrd_address: Option<u64>,
+    pub fdt_address: Option<u64>,
+    pub dump_fdt: Option<PathBuf>,
+}
+
+impl BootConfig {
+    pub fn new<P: Into<PathBuf>, Q: Into<PathBuf>>(firmware: P, kernel: Q) -> Self {
+        Self {
+            firmware: Some(firmware.into()),
+            ..Self::builtin_sbi(kernel)
+        }
+    }
+
+    // ファームウェアを使わずに組み込みのSBIで起動する設定を作る関数
+    pub fn builtin_sbi<P: Into<PathBuf>>(kernel: P) -> Self {
+        Self {
+            firmware: None,
+            kernel: kernel.into(),
+            initrd: None,
+            bootargs: "console=ttyS0".to_string(),
+            kernel_address: DEFAULT_KERNEL_ADDRESS,
+            initrd_address: None,
+            fdt_address: None,
+            dump_fdt: None,
+        }
+    }
+}
+
+impl Emulator {
+    // ファームウェアとカーネル、initrdをロードしてファームウェアから実行できる状態にする関数
+    // ハートの状態はリセット直後と同じで、a0にmhartid、a1にDTBのアドレス、a2にfw_dynamic_infoのアドレスを設定する。
+    // fw_jumpはa2を使わないので、どちらのファームウェアでも同じ方法で起動できる。
+    // ファームウェアがない場合はSモードでカーネルから実行し、a0とa1のみを設定する。
+    // ハートが複数ある場合、ファームウェアはすべてのハートで実行するが、
+    // カーネルはハート0のみで実行し、ほかのハートはSBIのHSMで起動されるまで停止しておく。
+    pub fn boot(&mut self, config: &BootConfig) -> core::result::Result<(), Box<dyn Error>> {
+        self.reset_harts();
+
+        self.riscv_tests_finished = false;
+        self.riscv_tests_exit_memory_address = None;
+        self.exit_code = None;
+        self.tohost_address = None;
+        self.fromhost_address = None;
+        self.bus.syscon = Syscon::default();
+        self.builtin_sbi = false;
+
+        self.bus.dram.initialize();
+
+        let entry = match &config.firmware {
+            Some(path) => {
+                let firmware = fs::read(path)?;
+
+                if elf::is_elf(&firmware) {
+                    self.load_elf(&firmware)?;
+                    self.pc
+                } else {
+                    self.load_image("firmware", DRAM_BASE, &firmware)?;
+                    DRAM_BASE
+                }
+            }
+            None => config.kernel_address,
+        };
+
+        let kernel = fs::read(&config.kernel)?;
+        self.load_image("kernel", config.kernel_address, &kernel)?;
+
+        // initrdはカーネルを展開しても上書きされないように、RAMの半分(最大128MiB)だけ離して配置する。(QEMUと同じ)
+        let initrd = match &config.initrd {
+            Some(path) => {
+                let bytes = fs::read(path)?;
+                let memory_size = self.bus.dram.size() as u64;
+                let address = config
+                    .initrd_address
+                    .unwrap_or(config.kernel_address + (memory_size / 2).min(128 * MIB));
+
+                self.load_image("initrd", address, &bytes)?;
+
+                Some((address, address + bytes.len() as u64))
+            }
+            None => None,
+        };
+
+        let fdt = self.device_tree(&config.bootargs, initrd);
+
+        if let Some(path) = &config.dump_fdt {
+            fs::write(path, &fdt)?;
+        }
+
+        // DTBはRAMの末尾に2MiBの境界に揃えて配置し、fw_dynamic_infoはその直後に置く。
+        let dram_end = DRAM_BASE + self.bus.dram.size() as u64;
+        let fdt_address = config
+            .fdt_address
+            .unwrap_or((dram_end - fdt.len() as u64 - 64) & !(2 * MIB - 1));
+        let info_address = (fdt_address + fdt.len() as u64 + 7) & !0x7;
+
+        // ハート0から起動する。
+        let boot_hart = 0;
+
+        let info: Vec<u8> = [
+            FW_DYNAMIC_INFO_MAGIC,
+            FW_DYNAMIC_INFO_VERSION,
+            config.kernel_address,
+            FW_DYNAMIC_INFO_NEXT_MODE_S,
+            0, // options
+            boot_hart,
+        ]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+
+        self.load_image("device tree", fdt_address, &fdt)?;
+
+        if config.firmware.is_some() {
+            self.load_image("fw_dynamic_info", info_address, &info)?;
+        }
+
+        for hart in 0..self.harts() {
+            self.switch_hart(hart);
+
+            // a0, a1, a2(x10, x11, x12)
+            self.regs[9] = hart as u64;
+            self.regs[10] = fdt_address;
+
+            if config.firmware.is_some() {
+                self.regs[11] = info_address;
+            } else if hart == boot_hart as usize {
+                self.enter_supervisor_with_sbi();
+            } else {
+                self.harts[hart].stopped = true;
+            }
+
+            self.pc = entry;
+        }
+
+        self.switch_hart(boot_hart as usize);
+
+        Ok(())
+    }
+
+    // 物理アドレスにバイナリを配置する関数
+    // RAMに収まらない場合はエラーにする。
+    fn load_image(
+        &mut self,
+        name: &str,
+        address: u64,
+        bytes: &[u8],
+    ) -> core::result::Result<(), Box<dyn Error>> {
+        if !self.bus.load_segment(address, bytes, bytes.len()) {
+            return Err(format!(
+                "The {} (0x{:x} bytes at 0x{:016x}) does not fit in the memory.",
+                name,
+                bytes.len(),
+                address
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+use crate::{
+    clint::{Clint, CLINT_BASE, CLINT_SIZE},
+    device_tree::DeviceTreeNode,
+    memory::Memory,
+    plic::{Plic, PLIC_BASE, PLIC_SIZE},
+    reservation::Reservations,
+    syscon::{Syscon, SYSCON_BASE, SYSCON_SIZE},
+};
+
+// RAMを配置する物理アドレス
+pub const DRAM_BASE: u64 = 0x8000_0000;
+// デフォルトのRAMのサイズ(128M byte)
+pub const DEFAULT_DRAM_SIZE: usize = 128 * 1024 * 1024;
+
+// バスに接続するデバイスのトレイト
+// offsetはデバイスに割り当てた領域の先頭からのオフセット、sizeは1, 2, 4, 8のいずれかである。
+// 対応していないアクセスの場合、readはNoneを、writeはfalseを返す(アクセスフォルトになる)。
+pub trait Device {
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64>;
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> bool;
+
+    // デバイスの割り込みの出力(レベルトリガ)
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // デバイスからRAMにアクセスする処理(DMA)を行う関数
+    // デバイスへの書き込みのあとに呼ばれる。
+    fn dma(&mut self, _memory: &mut Dma) {}
+
+    // デバイスツリーに含めるノード
+    // Noneの場合はデバイスツリーに含めない。
+    fn device_tree_node(&self) -> Option<DeviceTreeNode> {
+        None
+    }
+}
+
+// デバイスから物理アドレスでRAMにアクセスするための構造体
+// 書き込んだ範囲と重なるLR/SCの予約は無効になる。
+pub struct Dma<'a> {
+    dram: &'a mut Memory,
+    reservations: &'a mut Reservations,
+}
+
+impl Dma<'_> {
+    // 物理アドレスからbytesの長さだけ読み込む関数
+    // RAMの範囲外の場合はfalseを返す。
+    pub fn read(&self, address: u64, bytes: &mut [u8]) -> bool {
+        match dram_offset(self.dram, address, bytes.len()) {
+            Some(offset) => {
+                self.dram.read(offset, bytes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 物理アドレスにvaluesを書き込む関数
+    // RAMの範囲外の場合はfalseを返す。
+    pub fn write(&mut self, address: u64, values: &[u8]) -> bool {
+        match dram_offset(self.dram, address, values.len()) {
+            Some(offset) => {
+                self.dram.write(offset, values);
+                self.reservations.invalidate(address, values.len());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// 物理アドレスがRAMに含まれる場合はRAMの先頭からのオフセットを返す関数
+fn dram_offset(dram: &Memory, address: u64, size: usize) -> Option<usize> {
+    let offset = address.checked_sub(DRAM_BASE)? as usize;
+
+    dram.contains(offset, size).then_some(offset)
+}
+
+// アドレスの範囲に割り当てたデバイス
+// irqはデバイスの割り込みをつなぐPLICの割り込み源の番号
+struct MappedDevice {
+    base: u64,
+    size: u64,
+    irq: Option<u32>,
+    device: Box<dyn Device>,
+}
+
+impl MappedDevice {
+    fn contains(&self, address: u64, size: usize) -> bool {
+        address >= self.base
+            && address
+                .checked_add(size as u64)
+                .is_some_and(|end| end <= self.base + self.size)
+    }
+}
+
+// システムバス
+// DRAM_BASEから始まるRAMと、CLINTと、PLICと、システムコントローラと、アドレスの範囲に割り当てたデバイスへのアクセスを振り分ける。
+// CLINTとPLICはCPUの割り込みに、システムコントローラはエミュレータの終了に直接つながるのでバスが直接持つ。
+// どこにも割り当てられていないアドレスへのアクセスは失敗する。
+// すべての書き込みを見るので、LR/SCの予約もバスが持つ。
+pub struct Bus {
+    pub(crate) dram: Memory,
+    pub(crate) clint: Clint,
+    pub(crate) plic: Plic,
+    pub(crate) syscon: Syscon,
+    pub(crate) reservations: Reservations,
+    devices: Vec<MappedDevice>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new(DEFAULT_DRAM_SIZE)
+    }
+}
+
+impl Bus {
+    pub fn new(dram_size: usize) -> Self {
+        Self {
+            dram: Memory::new(dram_size),
+            clint: Clint::default(),
+            plic: Plic::default(),
+            syscon: Syscon::default(),
+            reservations: Reservations::default(),
+            devices: Vec::new(),
+        }
+    }
+
+    // デバイスをbaseからsizeバイトの範囲に割り当てる関数
+    // irqを指定した場合はデバイスの割り込みをPLICのその番号の割り込み源につなぐ。
+    // RAMや他のデバイスと範囲が重なる場合はパニックになる。
+    pub fn attach(&mut self, base: u64, size: u64, irq: Option<u32>, device: Box<dyn Device>) {
+        let overlaps = |start: u64, len: u64| base < start + len && start < base + size;
+
+        if overlaps(DRAM_BASE, self.dram.size() as u64)
+            || overlaps(CLINT_BASE, CLINT_SIZE)
+            || overlaps(PLIC_BASE, PLIC_SIZE)
+            || overlaps(SYSCON_BASE, SYSCON_SIZE)
+            || self
+                .devices
+                .iter()
+                .any(|mapped| overlaps(mapped.base, mapped.size))
+        {
+            panic!(
+                "Error: The device at 0x{:016x}-0x{:016x} overlaps another region.",
+                base,
+                base + size
+            );
+        }
+
+        self.devices.push(MappedDevice {
+            base,
+            size,
+            irq,
+            device,
+        });
+    }
+
+    // デバイスの割り込みの信号をPLICに伝える関数
+    pub(crate) fn update_irqs(&mut self) {
+        for mapped in &self.devices {
+            if let Some(irq) = mapped.irq {
+                self.plic.set_level(irq, mapped.device.irq());
+            }
+        }
+    }
+
+    // 割り当てたデバイスの(base, size, irq, デバイスツリーのノード)を返す関数
+    pub(crate) fn device_tree_nodes(
+        &self,
+    ) -> impl Iterator<Item = (u64, u64, Option<u32>, DeviceTreeNode)> + '_ {
+        self.devices.iter().filter_map(|mapped| {
+            mapped
+                .device
+                .device_tree_node()
+                .map(|node| (mapped.base, mapped.size, mapped.irq, node))
+        })
+    }
+
+    fn dram_offset(&self, address: u64, size: usize) -> Option<usize> {
+        dram_offset(&self.dram, address, size)
+    }
+
+    // 物理アドレスからsizeバイトの範囲がRAMに含まれるかを判定する関数
+    pub(crate) fn dram_contains(&self, address: u64, size: u64) -> bool {
+        self.dram_offset(address, size as usize).is_some()
+    }
+
+    // アクセスの範囲を含むデバイスとデバイス内のオフセットを返す関数
+    fn device(&mut self, address: u64, size: usize) -> Option<(&mut (dyn Device + 'static), u64)> {
+        let end = address.checked_add(size as u64)?;
+
+        if address >= CLINT_BASE && end <= CLINT_BASE + CLINT_SIZE {
+            return Some((&mut self.clint, address - CLINT_BASE));
+        }
+
+        if address >= PLIC_BASE && end <= PLIC_BASE + PLIC_SIZE {
+            return Some((&mut self.plic, address - PLIC_BASE));
+        }
+
+        if address >= SYSCON_BASE && end <= SYSCON_BASE + SYSCON_SIZE {
+            return Some((&mut self.syscon, address - SYSCON_BASE));
+        }
+
+        self.devices
+            .iter_mut()
+            .find(|mapped| mapped.contains(address, size))
+            .map(|mapped| (mapped.device.as_mut(), address - mapped.base))
+    }
+
+    // 物理アドレスからbytesの長さだけ読み込む関数
+    // 割り当てられていないアドレスの場合はfalseを返す。
+    pub(crate) fn read(&mut self, address: u64, bytes: &mut [u8]) -> bool {
+        if let Some(offset) = self.dram_offset(address, bytes.len()) {
+            self.dram.read(offset, bytes);
+            return true;
+        }
+
+        let size = bytes.len();
+
+        if !matches!(size, 1 | 2 | 4 | 8) {
+            return false;
+        }
+
+        let Some((device, offset)) = self.device(address, size) else {
+            return false;
+        };
+
+        match device.read(offset, size) {
+            Some(value) => {
+                bytes.copy_from_slice(&value.to_le_bytes()[..size]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 物理アドレスにvaluesを書き込む関数
+    // 割り当てられていないアドレスの場合はfalseを返す。
+    pub(crate) fn write(&mut self, address: u64, values: &[u8]) -> bool {
+        self.reservations.invalidate(address, values.len());
+
+        if let Some(offset) = self.dram_offset(address, values.len()) {
+            self.dram.write(offset, values);
+            return true;
+        }
+
+        let size = values.len();
+
+        if !matches!(size, 1 | 2 | 4 | 8) {
+            return false;
+        }
+
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(values);
+        let value = u64::from_le_bytes(bytes);
+
+        // 割り当てたデバイスの場合は書き込みのあとにDMAの処理を行う。
+        if let Some(mapped) = self
+            .devices
+            .iter_mut()
+            .find(|mapped| mapped.contains(address, size))
+        {
+            if !mapped.device.write(address - mapped.base, size, value) {
+                return false;
+            }
+
+            mapped.device.dma(&mut Dma {
+                dram: &mut self.dram,
+                reservations: &mut self.reservations,
+            });
+
+            return true;
+        }
+
+        match self.device(address, size) {
+            Some((device, offset)) => device.write(offset, size, value),
+            None => false,
+        }
+    }
+
+    // RAMのみを読み込む関数
+    // デバイスの状態を変えずに読み込みたい場合に使用する。
+    pub(crate) fn read_dram(&self, address: u64, bytes: &mut [u8]) -> bool {
+        match self.dram_offset(address, bytes.len()) {
+            Some(offset) => {
+                self.dram.read(offset, bytes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // セグメントをRAMに配置する関数
+    // セグメントがRAMに収まらない場合はfalseを返す。
+    pub(crate) fn load_segment(&mut self, address: u64, data: &[u8], mem_size: usize) -> bool {
+        match self.dram_offset(address, mem_size) {
+            Some(offset) => self.dram.load_segment(offset, data, mem_size),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::Instant;
+
+use crate::bus::Device;
+
+// CLINTを配置する物理アドレスとサイズ
+pub const CLINT_BASE: u64 = 0x0200_0000;
+pub const CLINT_SIZE: u64 = 0x10000;
+
+// デフォルトのタイムベースの周波数(10MHz)
+pub const DEFAULT_TIMEBASE_FREQUENCY: u64 = 10_000_000;
+
+// 各レジスタのオフセット(ACLINTのMSWIとMTIMERと同じ配置)
+const MSIP_BASE: u64 = 0x0;
+const MTIMECMP_BASE: u64 = 0x4000;
+const MTIME: u64 = 0xbff8;
+
+pub(crate) const MIP_MSIP: u64 = 1 << 3;
+pub(crate) const MIP_MTIP: u64 = 1 << 7;
+
+// CLINT(Core Local Interruptor)
+// ハートごとのmsipとmtimecmp、共通のmtimeを持つ。
+// mtimeはホストの経過時間をタイムベースの周波数で換算した値になる。
+#[derive(Debug)]
+pub struct Clint {
+    msip: Vec<u32>,
+    mtimecmp: Vec<u64>,
+    frequency: u64,
+    start: Instant,
+    // mtimeに書き込んだときの値と経過時間の差
+    offset: u64,
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self::new(1, DEFAULT_TIMEBASE_FREQUENCY)
+    }
+}
+
+impl Clint {
+    pub fn new(harts: usize, frequency: u64) -> Self {
+        Self {
+            msip: vec![0; harts],
+            mtimecmp: vec![u64::MAX; harts],
+            frequency,
+            start: Instant::now(),
+            offset: 0,
+        }
+    }
+
+    pub fn harts(&self) -> usize {
+        self.msip.len()
+    }
+
+    pub fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    pub fn mtime(&self) -> u64 {
+        let elapsed = self.start.elapsed().as_nanos();
+        let ticks = elapsed * self.frequency as u128 / 1_000_000_000;
+
+        (ticks as u64).wrapping_add(self.offset)
+    }
+
+    fn set_mtime(&mut self, value: u64) {
+        self.offset = 0;
+        self.offset = value.wrapping_sub(self.mtime());
+    }
+
+    // ハートのmipに反映する割り込み(MSIP, MTIP)を返す関数
+    // ホストの時刻の取得は遅いので、mtimeは呼び出し側で一度だけ読み込んだものを使う。
+    pub fn pending_interrupts(&self, hart: usize, mtime: u64) -> u64 {
+        let msip = if self.msip[hart] & 0x1 != 0 {
+            MIP_MSIP
+        } else {
+            0
+        };
+
+        let mtip = if mtime >= self.mtimecmp[hart] {
+            MIP_MTIP
+        } else {
+            0
+        };
+
+        msip | mtip
+    }
+
+    // 8バイト単位のレジスタの値を返す関数
+    // 割り当てられていないオフセットの場合はNoneを返す。
+    fn read_register(&self, offset: u64) -> Option<u64> {
+        let harts = self.msip.len() as u64;
+
+        match offset {
+            MSIP_BASE..MTIMECMP_BASE => {
+                let hart = (offset - MSIP_BASE) / 4;
+
+                (hart < harts).then(|| self.msip[hart as usize] as u64)
+            }
+            MTIME => Some(self.mtime()),
+            MTIMECMP_BASE..MTIME => {
+                let hart = (offset - MTIMECMP_BASE) / 8;
+
+                (hart < harts).then(|| self.mtimecmp[hart as usize])
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Device for Clint {
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64> {
+        // msipは4バイト、それ以外は8バイトのレジスタで、上位と下位の4バイトずつアクセスすることもできる。
+        let (base, width) = if offset < MTIMECMP_BASE {
+            (offset & !0x3, 4)
+        } else {
+            (offset & !0x7, 8)
+        };
+
+        let shift = (offset - base) * 8;
+
+        if (offset - base) as usize + size > width {
+            return None;
+        }
+
+        let value = self.read_register(base)? >> shift;
+
+        Some(if size == 8 {
+            value
+        } else {
+            value & ((1 << (size * 8)) - 1)
+        })
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> bool {
+        let (base, width) = if offset < MTIMECMP_BASE {
+            (offset & !0x3, 4)
+        } else {
+            (offset & !0x7, 8)
+        };
+
+        let shift = (offset - base) * 8;
+
+        if (offset - base) as usize + size > width {
+            return false;
+        }
+
+        let Some(old) = self.read_register(base) else {
+            return false;
+        };
+
+        let mask = if size == 8 {
+            u64::MAX
+        } else {
+            ((1 << (size * 8)) - 1) << shift
+        };
+
+        let new = (old & !mask) | ((value << shift) & mask);
+
+        match base {
+            MSIP_BASE..MTIMECMP_BASE => {
+                // msipは最下位ビットのみ有効
+                self.msip[((base - MSIP_BASE) / 4) as usize] = (new & 0x1) as u32;
+            }
+            MTIME => self.set_mtime(new),
+            _ => {
+                self.mtimecmp[((base - MTIMECMP_BASE) / 8) as usize] = new;
+            }
+        }
+
+        true
+    }
+}
@@ -1,4 +1,4 @@
-use crate::emulator::Emulator;
+use crate::{emulator::Emulator, Xlen};
 
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum InstClass {
@@ -18,6 +18,7 @@ pub enum InstFormat {
     I,
     J,
     R,
+    R4,
     S,
     U,
     Ca,
@@ -37,7 +38,15 @@ pub enum InstIsa {
     A,
     I,
     M,
+    F,
+    D,
     C,
+    V,
+    Zba,
+    Zbb,
+    Zbc,
+    Zbs,
+    Zicond,
     Zifencei,
     Zicsr,
     Invalid,
@@ -45,7 +54,7 @@ pub enum InstIsa {
 
 #[derive(Debug)]
 pub struct Inst {
-    name: String,
+    name: &'static str,
     class: InstClass,
     format: InstFormat,
     isa: InstIsa,
@@ -61,13 +70,22 @@ impl Default for Inst {
 impl Inst {
     fn invalid() -> Self {
         Self {
-            name: String::new(),
+            name: "",
             class: InstClass::Invalid,
             format: InstFormat::Other,
             isa: InstIsa::Invalid,
             raw: 0,
         }
     }
+
+    // デコードできない命令
+    // 実行するとIllegralInstructionになり、xtvalにはrawが入る。
+    fn illegal(raw: u32) -> Self {
+        Self {
+            raw,
+            ..Self::invalid()
+        }
+    }
 }
 
 impl Inst {
@@ -75,8 +93,8 @@ impl Inst {
         self.class != InstClass::Invalid
     }
 
-    pub fn name(&self) -> &str {
-        &self.name
+    pub fn name(&self) -> &'static str {
+        self.name
     }
 
     pub fn isa(&self) -> &InstIsa {
@@ -107,7 +125,7 @@ impl Inst {
 macro_rules! inst {
     ($op:ident, Jump, $isa:ident, $format:ident, $raw:expr) => {
         Inst {
-            name: String::from(stringify!($op)),
+            name: stringify!($op),
             isa: InstIsa::$isa,
             class: InstClass::Jump(false),
             format: InstFormat::$format,
@@ -117,7 +135,7 @@ macro_rules! inst {
 
     ($op:ident, $class:ident, $isa:ident, $format:ident, $raw:expr) => {
         Inst {
-            name: String::from(stringify!($op)),
+            name: stringify!($op),
             isa: InstIsa::$isa,
             class: InstClass::$class,
             format: InstFormat::$format,
@@ -134,15 +152,17 @@ impl Emulator {
 
         match (op, raw_inst >> 13) {
             (0, 0) => inst!(c_addi4spn, Alu, C, Ciw, raw_inst),
+            (0, 0b001) => inst!(c_fld, Load, C, Cl, raw_inst),
             (0, 0b010) => inst!(c_lw, Load, C, Cl, raw_inst),
             (0, 0b011) => inst!(c_ld, Load, C, Cl, raw_inst),
+            (0, 0b101) => inst!(c_fsd, Store, C, Cs, raw_inst),
             (0, 0b110) => inst!(c_sw, Store, C, Cs, raw_inst),
             (0, 0b111) => inst!(c_sd, Store, C, Cs, raw_inst),
             (0b01, 0b000) if (raw_inst >> 7) & 0x1f == 0 => inst!(c_nop, Alu, C, Ci, raw_inst),
             (0b01, 0b000) => inst!(c_addi, Alu, C, Ci, raw_inst),
             (0b01, 0b001) => inst!(c_addiw, Alu, C, Ci, raw_inst),
             (0b01, 0b010) => inst!(c_li, Load, C, Ci, raw_inst),
-            (0b01, 0b011) if (raw_inst >> 7) & 0x1f == 0 => Inst::invalid(), // reserved
+            (0b01, 0b011) if (raw_inst >> 7) & 0x1f == 0 => Inst::illegal(raw_inst), // reserved
             (0b01, 0b011) if (raw_inst >> 7) & 0x1f == 2 => inst!(c_addi16sp, Alu, C, Ci, raw_inst),
             (0b01, 0b011) => inst!(c_lui, Load, C, Ci, raw_inst), // reserved
             (0b01, 0b100) => match (raw_inst >> 10) & 0x3 {
@@ -156,14 +176,15 @@ impl Emulator {
                     (0, 0b11) => inst!(c_and, Alu, C, Ca, raw_inst),
                     (1, 0b00) => inst!(c_subw, Alu, C, Ca, raw_inst),
                     (1, 0b01) => inst!(c_addw, Alu, C, Ca, raw_inst),
-                    _ => unimplemented!(),
+                    _ => Inst::illegal(raw_inst),
                 },
-                _ => unimplemented!(),
+                _ => Inst::illegal(raw_inst),
             },
             (0b01, 0b101) => inst!(c_j, Jump, C, Cj, raw_inst),
             (0b01, 0b110) => inst!(c_beqz, Jump, C, Cb, raw_inst),
             (0b01, 0b111) => inst!(c_bnez, Jump, C, Cb, raw_inst),
             (0b10, 0) => inst!(c_slli, Alu, C, Ci, raw_inst),
+            (0b10, 0b001) => inst!(c_fldsp, Load, C, Ci, raw_inst),
             (0b10, 0b010) => inst!(c_lwsp, Load, C, Ci, raw_inst),
             (0b10, 0b011) => inst!(c_ldsp, Load, C, Ci, raw_inst),
             (0b10, 0b100) if raw_inst == 0x9002 => inst!(c_ebreak, System, C, Other, raw_inst),
@@ -172,11 +193,248 @@ impl Emulator {
                 (0, _) => inst!(c_mv, Alu, C, Cr, raw_inst),
                 (1, 0) => inst!(c_jalr, Jump, C, Cr, raw_inst),
                 (1, _) => inst!(c_add, Alu, C, Cr, raw_inst),
-                _ => unimplemented!(),
+                _ => Inst::illegal(raw_inst),
             },
+            (0b10, 0b101) => inst!(c_fsdsp, Store, C, Css, raw_inst),
             (0b10, 0b110) => inst!(c_swsp, Store, C, Css, raw_inst),
             (0b10, 0b111) => inst!(c_sdsp, Store, C, Css, raw_inst),
-            _ => unimplemented!(),
+            _ => Inst::illegal(raw_inst),
+        }
+    }
+
+    // RV32とRV64でエンコーディングの意味が異なる命令をRV32としてデコードする関数
+    // XLENが32のモード(RV32かUXL、SXLが32)で実行する場合に使用する。
+    // RV64と同じようにデコードできる命令の場合はNoneを返す。
+    fn rv32_decode(&self, raw_inst: u32) -> Option<Inst> {
+        let op = raw_inst & 0x7f;
+        let funct3 = (raw_inst >> 12) & 0x7;
+
+        if op & 0x3 < 3 {
+            let raw_inst = raw_inst & 0xffff;
+
+            return match (op & 0x3, raw_inst >> 13) {
+                // c.addiwの位置はc.jalになる。
+                (0b01, 0b001) => Some(inst!(c_jal, Jump, C, Cj, raw_inst)),
+                // c.ld、c.sd、c.ldsp、c.sdspの位置はc.flw、c.fsw、c.flwsp、c.fswspになる。
+                (0b00, 0b011) => Some(inst!(c_flw, Load, C, Cl, raw_inst)),
+                (0b00, 0b111) => Some(inst!(c_fsw, Store, C, Cs, raw_inst)),
+                (0b10, 0b011) => Some(inst!(c_flwsp, Load, C, Ci, raw_inst)),
+                (0b10, 0b111) => Some(inst!(c_fswsp, Store, C, Css, raw_inst)),
+                // c.subwとc.addw
+                (0b01, 0b100) if (raw_inst >> 10) & 0x7 == 0b111 => Some(Inst::illegal(raw_inst)),
+                // shamt[5]が1のc.srli、c.srai、c.slliはカスタム拡張のために予約されている。
+                (0b01, 0b100) if (raw_inst >> 10) & 0x3 < 0b10 && (raw_inst >> 12) & 0x1 != 0 => {
+                    Some(Inst::illegal(raw_inst))
+                }
+                (0b10, 0b000) if (raw_inst >> 12) & 0x1 != 0 => Some(Inst::illegal(raw_inst)),
+                _ => None,
+            };
+        }
+
+        match (op, funct3) {
+            // ld、lwu、sd、RV64Aの~.d
+            (0b0000011, 0b011 | 0b110) | (0b0100011 | 0b0101111, 0b011) => {
+                Some(Inst::illegal(raw_inst))
+            }
+            // ~wの命令(OP-IMM-32とOP-32)
+            (0b0011011 | 0b0111011, _) => Some(Inst::illegal(raw_inst)),
+            // shamt[5]が1のslli、srli、srai、rori、bseti、bclri、binvi、bexti
+            (0b0010011, 0b001 | 0b101) if (raw_inst >> 25) & 0x1 != 0 => {
+                Some(Inst::illegal(raw_inst))
+            }
+            // rev8とzext.hはRV64とエンコーディングが異なる。
+            (0b0010011, 0b101) if raw_inst >> 20 == 0x698 => {
+                Some(inst!(rev8, Alu, Zbb, I, raw_inst))
+            }
+            (0b0110011, 0b100) if raw_inst >> 20 == 0x080 => {
+                Some(inst!(zext_h, Alu, Zbb, R, raw_inst))
+            }
+            // fcvt.l[u].s、fcvt.s.l[u]、fcvt.l[u].d、fcvt.d.l[u]、fmv.x.d、fmv.d.x
+            (0b1010011, _)
+                if matches!(
+                    (raw_inst >> 25, (raw_inst >> 20) & 0x1f, funct3),
+                    (0b1100000 | 0b1101000 | 0b1100001 | 0b1101001, 2 | 3, _)
+                        | (0b1110001 | 0b1111001, 0, 0b000)
+                ) =>
+            {
+                Some(Inst::illegal(raw_inst))
+            }
+            _ => None,
+        }
+    }
+
+    // V拡張の命令をデコードする関数
+    // 算術命令の名前は.vvや.vxなどのオペランドの種類を除いたもので、種類は実行時にfunct3で判断する。
+    // ロードとストアはnf(セグメント)と要素の幅も実行時に判断する。
+    fn v_decode(&self, raw_inst: u32) -> Inst {
+        let op = raw_inst & 0x7f;
+        let funct3 = (raw_inst >> 12) & 0x7;
+        let funct6 = raw_inst >> 26;
+        let vm = (raw_inst >> 25) & 0x1;
+        let rs1 = (raw_inst >> 15) & 0x1f;
+        let rs2 = (raw_inst >> 20) & 0x1f;
+
+        // mew(ビット28)が1の場合は予約されている
+        if op != 0b1010111 && (raw_inst >> 28) & 0x1 != 0 {
+            return Inst::illegal(raw_inst);
+        }
+
+        match (op, funct3) {
+            (0b0000111, _) => match ((raw_inst >> 26) & 0x3, rs2) {
+                (0b00, 0b00000) => inst!(vle, Load, V, R, raw_inst),
+                (0b00, 0b01000) => inst!(vlr, Load, V, R, raw_inst),
+                (0b00, 0b01011) => inst!(vlm, Load, V, R, raw_inst),
+                (0b00, 0b10000) => inst!(vleff, Load, V, R, raw_inst),
+                (0b00, _) => Inst::illegal(raw_inst),
+                (0b01, _) => inst!(vluxei, Load, V, R, raw_inst),
+                (0b10, _) => inst!(vlse, Load, V, R, raw_inst),
+                _ => inst!(vloxei, Load, V, R, raw_inst),
+            },
+            (0b0100111, _) => match ((raw_inst >> 26) & 0x3, rs2) {
+                (0b00, 0b00000) => inst!(vse, Store, V, R, raw_inst),
+                (0b00, 0b01000) => inst!(vsr, Store, V, R, raw_inst),
+                (0b00, 0b01011) => inst!(vsm, Store, V, R, raw_inst),
+                (0b00, _) => Inst::illegal(raw_inst),
+                (0b01, _) => inst!(vsuxei, Store, V, R, raw_inst),
+                (0b10, _) => inst!(vsse, Store, V, R, raw_inst),
+                _ => inst!(vsoxei, Store, V, R, raw_inst),
+            },
+            (_, 0b111) => match raw_inst >> 25 {
+                0b0000000..=0b0111111 => inst!(vsetvli, Csr, V, I, raw_inst),
+                0b1100000..=0b1111111 => inst!(vsetivli, Csr, V, I, raw_inst),
+                0b1000000 => inst!(vsetvl, Csr, V, R, raw_inst),
+                _ => Inst::illegal(raw_inst),
+            },
+            // OPIVV(000), OPIVI(011), OPIVX(100)
+            (_, 0b000 | 0b011 | 0b100) => match (funct6, funct3) {
+                (0b000000, _) => inst!(vadd, Alu, V, R, raw_inst),
+                (0b000010, 0b000 | 0b100) => inst!(vsub, Alu, V, R, raw_inst),
+                (0b000011, 0b011 | 0b100) => inst!(vrsub, Alu, V, R, raw_inst),
+                (0b000100, 0b000 | 0b100) => inst!(vminu, Alu, V, R, raw_inst),
+                (0b000101, 0b000 | 0b100) => inst!(vmin, Alu, V, R, raw_inst),
+                (0b000110, 0b000 | 0b100) => inst!(vmaxu, Alu, V, R, raw_inst),
+                (0b000111, 0b000 | 0b100) => inst!(vmax, Alu, V, R, raw_inst),
+                (0b001001, _) => inst!(vand, Alu, V, R, raw_inst),
+                (0b001010, _) => inst!(vor, Alu, V, R, raw_inst),
+                (0b001011, _) => inst!(vxor, Alu, V, R, raw_inst),
+                (0b001100, _) => inst!(vrgather, Alu, V, R, raw_inst),
+                (0b001110, 0b000) => inst!(vrgatherei16, Alu, V, R, raw_inst),
+                (0b001110, _) => inst!(vslideup, Alu, V, R, raw_inst),
+                (0b001111, 0b011 | 0b100) => inst!(vslidedown, Alu, V, R, raw_inst),
+                (0b010000, _) => inst!(vadc, Alu, V, R, raw_inst),
+                (0b010001, _) => inst!(vmadc, Alu, V, R, raw_inst),
+                (0b010010, 0b000 | 0b100) => inst!(vsbc, Alu, V, R, raw_inst),
+                (0b010011, 0b000 | 0b100) => inst!(vmsbc, Alu, V, R, raw_inst),
+                (0b010111, _) if vm == 0 => inst!(vmerge, Alu, V, R, raw_inst),
+                (0b010111, _) if rs2 == 0 => inst!(vmv_v, Alu, V, R, raw_inst),
+                (0b011000, _) => inst!(vmseq, Alu, V, R, raw_inst),
+                (0b011001, _) => inst!(vmsne, Alu, V, R, raw_inst),
+                (0b011010, 0b000 | 0b100) => inst!(vmsltu, Alu, V, R, raw_inst),
+                (0b011011, 0b000 | 0b100) => inst!(vmslt, Alu, V, R, raw_inst),
+                (0b011100, _) => inst!(vmsleu, Alu, V, R, raw_inst),
+                (0b011101, _) => inst!(vmsle, Alu, V, R, raw_inst),
+                (0b011110, 0b011 | 0b100) => inst!(vmsgtu, Alu, V, R, raw_inst),
+                (0b011111, 0b011 | 0b100) => inst!(vmsgt, Alu, V, R, raw_inst),
+                (0b100000, _) => inst!(vsaddu, Alu, V, R, raw_inst),
+                (0b100001, _) => inst!(vsadd, Alu, V, R, raw_inst),
+                (0b100010, 0b000 | 0b100) => inst!(vssubu, Alu, V, R, raw_inst),
+                (0b100011, 0b000 | 0b100) => inst!(vssub, Alu, V, R, raw_inst),
+                (0b100101, _) => inst!(vsll, Alu, V, R, raw_inst),
+                // vmv<nr>r.vはsimm5にレジスタの数-1が入る
+                (0b100111, 0b011) if vm == 1 => inst!(vmvnr, Alu, V, R, raw_inst),
+                (0b100111, 0b000 | 0b100) => inst!(vsmul, Alu, V, R, raw_inst),
+                (0b101000, _) => inst!(vsrl, Alu, V, R, raw_inst),
+                (0b101001, _) => inst!(vsra, Alu, V, R, raw_inst),
+                (0b101010, _) => inst!(vssrl, Alu, V, R, raw_inst),
+                (0b101011, _) => inst!(vssra, Alu, V, R, raw_inst),
+                (0b101100, _) => inst!(vnsrl, Alu, V, R, raw_inst),
+                (0b101101, _) => inst!(vnsra, Alu, V, R, raw_inst),
+                (0b101110, _) => inst!(vnclipu, Alu, V, R, raw_inst),
+                (0b101111, _) => inst!(vnclip, Alu, V, R, raw_inst),
+                (0b110000, 0b000) => inst!(vwredsumu, Alu, V, R, raw_inst),
+                (0b110001, 0b000) => inst!(vwredsum, Alu, V, R, raw_inst),
+                _ => Inst::illegal(raw_inst),
+            },
+            // OPMVV(010), OPMVX(110)
+            (_, 0b010 | 0b110) => match (funct6, funct3) {
+                (0b000000, 0b010) => inst!(vredsum, Alu, V, R, raw_inst),
+                (0b000001, 0b010) => inst!(vredand, Alu, V, R, raw_inst),
+                (0b000010, 0b010) => inst!(vredor, Alu, V, R, raw_inst),
+                (0b000011, 0b010) => inst!(vredxor, Alu, V, R, raw_inst),
+                (0b000100, 0b010) => inst!(vredminu, Alu, V, R, raw_inst),
+                (0b000101, 0b010) => inst!(vredmin, Alu, V, R, raw_inst),
+                (0b000110, 0b010) => inst!(vredmaxu, Alu, V, R, raw_inst),
+                (0b000111, 0b010) => inst!(vredmax, Alu, V, R, raw_inst),
+                (0b001000, _) => inst!(vaaddu, Alu, V, R, raw_inst),
+                (0b001001, _) => inst!(vaadd, Alu, V, R, raw_inst),
+                (0b001010, _) => inst!(vasubu, Alu, V, R, raw_inst),
+                (0b001011, _) => inst!(vasub, Alu, V, R, raw_inst),
+                (0b001110, 0b110) => inst!(vslide1up, Alu, V, R, raw_inst),
+                (0b001111, 0b110) => inst!(vslide1down, Alu, V, R, raw_inst),
+                (0b010000, 0b010) => match rs1 {
+                    0b00000 if vm == 1 => inst!(vmv_x_s, Alu, V, R, raw_inst),
+                    0b10000 => inst!(vcpop_m, Alu, V, R, raw_inst),
+                    0b10001 => inst!(vfirst_m, Alu, V, R, raw_inst),
+                    _ => Inst::illegal(raw_inst),
+                },
+                (0b010000, 0b110) if vm == 1 && rs2 == 0 => inst!(vmv_s_x, Alu, V, R, raw_inst),
+                (0b010010, 0b010) => match rs1 {
+                    0b00010 => inst!(vzext_vf8, Alu, V, R, raw_inst),
+                    0b00011 => inst!(vsext_vf8, Alu, V, R, raw_inst),
+                    0b00100 => inst!(vzext_vf4, Alu, V, R, raw_inst),
+                    0b00101 => inst!(vsext_vf4, Alu, V, R, raw_inst),
+                    0b00110 => inst!(vzext_vf2, Alu, V, R, raw_inst),
+                    0b00111 => inst!(vsext_vf2, Alu, V, R, raw_inst),
+                    _ => Inst::illegal(raw_inst),
+                },
+                (0b010100, 0b010) => match rs1 {
+                    0b00001 => inst!(vmsbf_m, Alu, V, R, raw_inst),
+                    0b00010 => inst!(vmsof_m, Alu, V, R, raw_inst),
+                    0b00011 => inst!(vmsif_m, Alu, V, R, raw_inst),
+                    0b10000 => inst!(viota_m, Alu, V, R, raw_inst),
+                    0b10001 if rs2 == 0 => inst!(vid_v, Alu, V, R, raw_inst),
+                    _ => Inst::illegal(raw_inst),
+                },
+                (0b010111, 0b010) if vm == 1 => inst!(vcompress, Alu, V, R, raw_inst),
+                (0b011000, 0b010) if vm == 1 => inst!(vmandn, Alu, V, R, raw_inst),
+                (0b011001, 0b010) if vm == 1 => inst!(vmand, Alu, V, R, raw_inst),
+                (0b011010, 0b010) if vm == 1 => inst!(vmor, Alu, V, R, raw_inst),
+                (0b011011, 0b010) if vm == 1 => inst!(vmxor, Alu, V, R, raw_inst),
+                (0b011100, 0b010) if vm == 1 => inst!(vmorn, Alu, V, R, raw_inst),
+                (0b011101, 0b010) if vm == 1 => inst!(vmnand, Alu, V, R, raw_inst),
+                (0b011110, 0b010) if vm == 1 => inst!(vmnor, Alu, V, R, raw_inst),
+                (0b011111, 0b010) if vm == 1 => inst!(vmxnor, Alu, V, R, raw_inst),
+                (0b100000, _) => inst!(vdivu, Alu, V, R, raw_inst),
+                (0b100001, _) => inst!(vdiv, Alu, V, R, raw_inst),
+                (0b100010, _) => inst!(vremu, Alu, V, R, raw_inst),
+                (0b100011, _) => inst!(vrem, Alu, V, R, raw_inst),
+                (0b100100, _) => inst!(vmulhu, Alu, V, R, raw_inst),
+                (0b100101, _) => inst!(vmul, Alu, V, R, raw_inst),
+                (0b100110, _) => inst!(vmulhsu, Alu, V, R, raw_inst),
+                (0b100111, _) => inst!(vmulh, Alu, V, R, raw_inst),
+                (0b101001, _) => inst!(vmadd, Alu, V, R, raw_inst),
+                (0b101011, _) => inst!(vnmsub, Alu, V, R, raw_inst),
+                (0b101101, _) => inst!(vmacc, Alu, V, R, raw_inst),
+                (0b101111, _) => inst!(vnmsac, Alu, V, R, raw_inst),
+                (0b110000, _) => inst!(vwaddu, Alu, V, R, raw_inst),
+                (0b110001, _) => inst!(vwadd, Alu, V, R, raw_inst),
+                (0b110010, _) => inst!(vwsubu, Alu, V, R, raw_inst),
+                (0b110011, _) => inst!(vwsub, Alu, V, R, raw_inst),
+                (0b110100, _) => inst!(vwaddu_w, Alu, V, R, raw_inst),
+                (0b110101, _) => inst!(vwadd_w, Alu, V, R, raw_inst),
+                (0b110110, _) => inst!(vwsubu_w, Alu, V, R, raw_inst),
+                (0b110111, _) => inst!(vwsub_w, Alu, V, R, raw_inst),
+                (0b111000, _) => inst!(vwmulu, Alu, V, R, raw_inst),
+                (0b111010, _) => inst!(vwmulsu, Alu, V, R, raw_inst),
+                (0b111011, _) => inst!(vwmul, Alu, V, R, raw_inst),
+                (0b111100, _) => inst!(vwmaccu, Alu, V, R, raw_inst),
+                (0b111101, _) => inst!(vwmacc, Alu, V, R, raw_inst),
+                (0b111110, 0b110) => inst!(vwmaccus, Alu, V, R, raw_inst),
+                (0b111111, _) => inst!(vwmaccsu, Alu, V, R, raw_inst),
+                _ => Inst::illegal(raw_inst),
+            },
+            // OPFVV(001)とOPFVF(101)の浮動小数点の命令はサポートしない
+            _ => Inst::illegal(raw_inst),
         }
     }
 
@@ -188,6 +446,12 @@ impl Emulator {
         let op = raw_inst & 0x7f;
         let funct3 = (raw_inst >> 12) & 0x7;
 
+        if self.current_xlen() == Xlen::X32 {
+            if let Some(inst) = self.rv32_decode(raw_inst) {
+                return inst;
+            }
+        }
+
         if op & 0x3 < 3 {
             return self.c_decode(raw_inst);
         }
@@ -201,35 +465,68 @@ impl Emulator {
                 0b100 => inst!(lbu, Load, I, I, raw_inst),
                 0b101 => inst!(lhu, Load, I, I, raw_inst),
                 0b110 => inst!(lwu, Load, I, I, raw_inst),
-                _ => unimplemented!(),
+                _ => Inst::illegal(raw_inst),
+            },
+            0b0000111 => match funct3 {
+                0b010 => inst!(flw, Load, F, I, raw_inst),
+                0b011 => inst!(fld, Load, D, I, raw_inst),
+                0b000 | 0b101 | 0b110 | 0b111 => self.v_decode(raw_inst),
+                _ => Inst::illegal(raw_inst),
             },
             0b0001111 => inst!(fence, System, Zifencei, Other, raw_inst),
             0b0010011 => match (funct3, raw_inst >> 26) {
                 (0b000, _) => inst!(addi, Alu, I, I, raw_inst),
                 (0b001, 0b000000) => inst!(slli, Alu, I, I, raw_inst),
+                (0b001, 0b001010) => inst!(bseti, Alu, Zbs, I, raw_inst),
+                (0b001, 0b010010) => inst!(bclri, Alu, Zbs, I, raw_inst),
+                (0b001, 0b011010) => inst!(binvi, Alu, Zbs, I, raw_inst),
+                // 単項の命令はimmの下位ビット(rs2の位置)で区別する
+                (0b001, 0b011000) => match (raw_inst >> 20) & 0x3f {
+                    0b000000 => inst!(clz, Alu, Zbb, I, raw_inst),
+                    0b000001 => inst!(ctz, Alu, Zbb, I, raw_inst),
+                    0b000010 => inst!(cpop, Alu, Zbb, I, raw_inst),
+                    0b000100 => inst!(sext_b, Alu, Zbb, I, raw_inst),
+                    0b000101 => inst!(sext_h, Alu, Zbb, I, raw_inst),
+                    _ => Inst::illegal(raw_inst),
+                },
                 (0b010, _) => inst!(slti, Alu, I, I, raw_inst),
                 (0b011, _) => inst!(sltiu, Alu, I, I, raw_inst),
                 (0b100, _) => inst!(xori, Alu, I, I, raw_inst),
                 (0b101, 0b000000) => inst!(srli, Alu, I, I, raw_inst),
                 (0b101, 0b010000) => inst!(srai, Alu, I, I, raw_inst),
+                (0b101, 0b010010) => inst!(bexti, Alu, Zbs, I, raw_inst),
+                (0b101, 0b011000) => inst!(rori, Alu, Zbb, I, raw_inst),
+                (0b101, _) if raw_inst >> 20 == 0x287 => inst!(orc_b, Alu, Zbb, I, raw_inst),
+                (0b101, _) if raw_inst >> 20 == 0x6b8 => inst!(rev8, Alu, Zbb, I, raw_inst),
                 (0b110, _) => inst!(ori, Alu, I, I, raw_inst),
                 (0b111, _) => inst!(andi, Alu, I, I, raw_inst),
-                _ => unimplemented!(),
+                _ => Inst::illegal(raw_inst),
             },
             0b0010111 => inst!(auipc, Alu, I, U, raw_inst),
             0b0011011 => match (funct3, raw_inst >> 26) {
                 (0b000, _) => inst!(addiw, Alu, I, I, raw_inst),
                 (0b001, 0) => inst!(slliw, Alu, I, I, raw_inst),
+                (0b001, 0b000010) => inst!(slli_uw, Alu, Zba, I, raw_inst),
+                (0b001, _) if raw_inst >> 20 == 0x600 => inst!(clzw, Alu, Zbb, I, raw_inst),
+                (0b001, _) if raw_inst >> 20 == 0x601 => inst!(ctzw, Alu, Zbb, I, raw_inst),
+                (0b001, _) if raw_inst >> 20 == 0x602 => inst!(cpopw, Alu, Zbb, I, raw_inst),
                 (0b101, 0) => inst!(srliw, Alu, I, I, raw_inst),
                 (0b101, 0b010000) => inst!(sraiw, Alu, I, I, raw_inst),
-                _ => unimplemented!(),
+                (0b101, _) if raw_inst >> 25 == 0b0110000 => inst!(roriw, Alu, Zbb, I, raw_inst),
+                _ => Inst::illegal(raw_inst),
             },
             0b0100011 => match funct3 {
                 0b000 => inst!(sb, Store, I, S, raw_inst),
                 0b001 => inst!(sh, Store, I, S, raw_inst),
                 0b010 => inst!(sw, Store, I, S, raw_inst),
                 0b011 => inst!(sd, Store, I, S, raw_inst),
-                _ => unimplemented!(),
+                _ => Inst::illegal(raw_inst),
+            },
+            0b0100111 => match funct3 {
+                0b010 => inst!(fsw, Store, F, S, raw_inst),
+                0b011 => inst!(fsd, Store, D, S, raw_inst),
+                0b000 | 0b101 | 0b110 | 0b111 => self.v_decode(raw_inst),
+                _ => Inst::illegal(raw_inst),
             },
             0b0101111 => match (funct3, raw_inst >> 27) {
                 (0b010, 0) => inst!(amoadd_w, Atomic, A, R, raw_inst),
@@ -245,6 +542,8 @@ impl Emulator {
                 (0b010, 0b11100) => inst!(amomaxu_w, Atomic, A, R, raw_inst),
                 (0b011, 0) => inst!(amoadd_d, Atomic, A, R, raw_inst),
                 (0b011, 0b00001) => inst!(amoswap_d, Atomic, A, R, raw_inst),
+                (0b011, 0b00010) => inst!(lr_d, Atomic, A, R, raw_inst),
+                (0b011, 0b00011) => inst!(sc_d, Atomic, A, R, raw_inst),
                 (0b011, 0b00100) => inst!(amoxor_d, Atomic, A, R, raw_inst),
                 (0b011, 0b01000) => inst!(amoor_d, Atomic, A, R, raw_inst),
                 (0b011, 0b01100) => inst!(amoand_d, Atomic, A, R, raw_inst),
@@ -252,7 +551,7 @@ impl Emulator {
                 (0b011, 0b10100) => inst!(amomax_d, Atomic, A, R, raw_inst),
                 (0b011, 0b11000) => inst!(amominu_d, Atomic, A, R, raw_inst),
                 (0b011, 0b11100) => inst!(amomaxu_d, Atomic, A, R, raw_inst),
-                _ => unimplemented!(),
+                _ => Inst::illegal(raw_inst),
             },
             0b0110011 => match (funct3, raw_inst >> 25) {
                 (0, 0) => inst!(add, Alu, I, R, raw_inst),
@@ -273,7 +572,28 @@ impl Emulator {
                 (0b101, 0b0100000) => inst!(sra, Alu, I, R, raw_inst),
                 (0b111, 0) => inst!(and, Alu, I, R, raw_inst),
                 (0b111, 0b0000001) => inst!(remu, Alu, M, R, raw_inst),
-                _ => unimplemented!(),
+                (0b010, 0b0010000) => inst!(sh1add, Alu, Zba, R, raw_inst),
+                (0b100, 0b0010000) => inst!(sh2add, Alu, Zba, R, raw_inst),
+                (0b110, 0b0010000) => inst!(sh3add, Alu, Zba, R, raw_inst),
+                (0b100, 0b0100000) => inst!(xnor, Alu, Zbb, R, raw_inst),
+                (0b110, 0b0100000) => inst!(orn, Alu, Zbb, R, raw_inst),
+                (0b111, 0b0100000) => inst!(andn, Alu, Zbb, R, raw_inst),
+                (0b100, 0b0000101) => inst!(min, Alu, Zbb, R, raw_inst),
+                (0b101, 0b0000101) => inst!(minu, Alu, Zbb, R, raw_inst),
+                (0b110, 0b0000101) => inst!(max, Alu, Zbb, R, raw_inst),
+                (0b111, 0b0000101) => inst!(maxu, Alu, Zbb, R, raw_inst),
+                (0b001, 0b0110000) => inst!(rol, Alu, Zbb, R, raw_inst),
+                (0b101, 0b0110000) => inst!(ror, Alu, Zbb, R, raw_inst),
+                (0b001, 0b0000101) => inst!(clmul, Alu, Zbc, R, raw_inst),
+                (0b010, 0b0000101) => inst!(clmulr, Alu, Zbc, R, raw_inst),
+                (0b011, 0b0000101) => inst!(clmulh, Alu, Zbc, R, raw_inst),
+                (0b001, 0b0010100) => inst!(bset, Alu, Zbs, R, raw_inst),
+                (0b001, 0b0100100) => inst!(bclr, Alu, Zbs, R, raw_inst),
+                (0b001, 0b0110100) => inst!(binv, Alu, Zbs, R, raw_inst),
+                (0b101, 0b0100100) => inst!(bext, Alu, Zbs, R, raw_inst),
+                (0b101, 0b0000111) => inst!(czero_eqz, Alu, Zicond, R, raw_inst),
+                (0b111, 0b0000111) => inst!(czero_nez, Alu, Zicond, R, raw_inst),
+                _ => Inst::illegal(raw_inst),
             },
             0b0110111 => inst!(lui, Load, I, U, raw_inst),
             0b0111011 => match (funct3, raw_inst >> 25) {
@@ -287,8 +607,94 @@ impl Emulator {
                 (0b101, 0b0100000) => inst!(sraw, Alu, I, R, raw_inst),
                 (0b110, 0b0000001) => inst!(remw, Alu, M, R, raw_inst),
                 (0b111, 0b0000001) => inst!(remuw, Alu, M, R, raw_inst),
-                _ => unimplemented!(),
+                (0, 0b0000100) => inst!(add_uw, Alu, Zba, R, raw_inst),
+                (0b010, 0b0010000) => inst!(sh1add_uw, Alu, Zba, R, raw_inst),
+                (0b100, 0b0010000) => inst!(sh2add_uw, Alu, Zba, R, raw_inst),
+                (0b110, 0b0010000) => inst!(sh3add_uw, Alu, Zba, R, raw_inst),
+                (0b001, 0b0110000) => inst!(rolw, Alu, Zbb, R, raw_inst),
+                (0b101, 0b0110000) => inst!(rorw, Alu, Zbb, R, raw_inst),
+                // zext.hはrs2が0のpack命令
+                (0b100, 0b0000100) if (raw_inst >> 20) & 0x1f == 0 => {
+                    inst!(zext_h, Alu, Zbb, R, raw_inst)
+                }
+                _ => Inst::illegal(raw_inst),
+            },
+            // 積和演算はビット26:25が形式(00は単精度、01は倍精度)
+            0b1000011 => match (raw_inst >> 25) & 0x3 {
+                0b00 => inst!(fmadd_s, Alu, F, R4, raw_inst),
+                0b01 => inst!(fmadd_d, Alu, D, R4, raw_inst),
+                _ => Inst::illegal(raw_inst),
+            },
+            0b1000111 => match (raw_inst >> 25) & 0x3 {
+                0b00 => inst!(fmsub_s, Alu, F, R4, raw_inst),
+                0b01 => inst!(fmsub_d, Alu, D, R4, raw_inst),
+                _ => Inst::illegal(raw_inst),
+            },
+            0b1001011 => match (raw_inst >> 25) & 0x3 {
+                0b00 => inst!(fnmsub_s, Alu, F, R4, raw_inst),
+                0b01 => inst!(fnmsub_d, Alu, D, R4, raw_inst),
+                _ => Inst::illegal(raw_inst),
+            },
+            0b1001111 => match (raw_inst >> 25) & 0x3 {
+                0b00 => inst!(fnmadd_s, Alu, F, R4, raw_inst),
+                0b01 => inst!(fnmadd_d, Alu, D, R4, raw_inst),
+                _ => Inst::illegal(raw_inst),
+            },
+            // 丸めモードの検査は実行時に行う。
+            0b1010011 => match (raw_inst >> 25, (raw_inst >> 20) & 0x1f, funct3) {
+                (0b0000000, _, _) => inst!(fadd_s, Alu, F, R, raw_inst),
+                (0b0000100, _, _) => inst!(fsub_s, Alu, F, R, raw_inst),
+                (0b0001000, _, _) => inst!(fmul_s, Alu, F, R, raw_inst),
+                (0b0001100, _, _) => inst!(fdiv_s, Alu, F, R, raw_inst),
+                (0b0101100, 0, _) => inst!(fsqrt_s, Alu, F, R, raw_inst),
+                (0b0010000, _, 0b000) => inst!(fsgnj_s, Alu, F, R, raw_inst),
+                (0b0010000, _, 0b001) => inst!(fsgnjn_s, Alu, F, R, raw_inst),
+                (0b0010000, _, 0b010) => inst!(fsgnjx_s, Alu, F, R, raw_inst),
+                (0b0010100, _, 0b000) => inst!(fmin_s, Alu, F, R, raw_inst),
+                (0b0010100, _, 0b001) => inst!(fmax_s, Alu, F, R, raw_inst),
+                (0b1100000, 0, _) => inst!(fcvt_w_s, Alu, F, R, raw_inst),
+                (0b1100000, 1, _) => inst!(fcvt_wu_s, Alu, F, R, raw_inst),
+                (0b1100000, 2, _) => inst!(fcvt_l_s, Alu, F, R, raw_inst),
+                (0b1100000, 3, _) => inst!(fcvt_lu_s, Alu, F, R, raw_inst),
+                (0b1101000, 0, _) => inst!(fcvt_s_w, Alu, F, R, raw_inst),
+                (0b1101000, 1, _) => inst!(fcvt_s_wu, Alu, F, R, raw_inst),
+                (0b1101000, 2, _) => inst!(fcvt_s_l, Alu, F, R, raw_inst),
+                (0b1101000, 3, _) => inst!(fcvt_s_lu, Alu, F, R, raw_inst),
+                (0b1110000, 0, 0b000) => inst!(fmv_x_w, Alu, F, R, raw_inst),
+                (0b1110000, 0, 0b001) => inst!(fclass_s, Alu, F, R, raw_inst),
+                (0b1010000, _, 0b000) => inst!(fle_s, Alu, F, R, raw_inst),
+                (0b1010000, _, 0b001) => inst!(flt_s, Alu, F, R, raw_inst),
+                (0b1010000, _, 0b010) => inst!(feq_s, Alu, F, R, raw_inst),
+                (0b1111000, 0, 0b000) => inst!(fmv_w_x, Alu, F, R, raw_inst),
+                (0b0000001, _, _) => inst!(fadd_d, Alu, D, R, raw_inst),
+                (0b0000101, _, _) => inst!(fsub_d, Alu, D, R, raw_inst),
+                (0b0001001, _, _) => inst!(fmul_d, Alu, D, R, raw_inst),
+                (0b0001101, _, _) => inst!(fdiv_d, Alu, D, R, raw_inst),
+                (0b0101101, 0, _) => inst!(fsqrt_d, Alu, D, R, raw_inst),
+                (0b0010001, _, 0b000) => inst!(fsgnj_d, Alu, D, R, raw_inst),
+                (0b0010001, _, 0b001) => inst!(fsgnjn_d, Alu, D, R, raw_inst),
+                (0b0010001, _, 0b010) => inst!(fsgnjx_d, Alu, D, R, raw_inst),
+                (0b0010101, _, 0b000) => inst!(fmin_d, Alu, D, R, raw_inst),
+                (0b0010101, _, 0b001) => inst!(fmax_d, Alu, D, R, raw_inst),
+                (0b0100000, 1, _) => inst!(fcvt_s_d, Alu, D, R, raw_inst),
+                (0b0100001, 0, _) => inst!(fcvt_d_s, Alu, D, R, raw_inst),
+                (0b1100001, 0, _) => inst!(fcvt_w_d, Alu, D, R, raw_inst),
+                (0b1100001, 1, _) => inst!(fcvt_wu_d, Alu, D, R, raw_inst),
+                (0b1100001, 2, _) => inst!(fcvt_l_d, Alu, D, R, raw_inst),
+                (0b1100001, 3, _) => inst!(fcvt_lu_d, Alu, D, R, raw_inst),
+                (0b1101001, 0, _) => inst!(fcvt_d_w, Alu, D, R, raw_inst),
+                (0b1101001, 1, _) => inst!(fcvt_d_wu, Alu, D, R, raw_inst),
+                (0b1101001, 2, _) => inst!(fcvt_d_l, Alu, D, R, raw_inst),
+                (0b1101001, 3, _) => inst!(fcvt_d_lu, Alu, D, R, raw_inst),
+                (0b1110001, 0, 0b000) => inst!(fmv_x_d, Alu, D, R, raw_inst),
+                (0b1110001, 0, 0b001) => inst!(fclass_d, Alu, D, R, raw_inst),
+                (0b1010001, _, 0b000) => inst!(fle_d, Alu, D, R, raw_inst),
+                (0b1010001, _, 0b001) => inst!(flt_d, Alu, D, R, raw_inst),
+                (0b1010001, _, 0b010) => inst!(feq_d, Alu, D, R, raw_inst),
+                (0b1111001, 0, 0b000) => inst!(fmv_d_x, Alu, D, R, raw_inst),
+                _ => Inst::illegal(raw_inst),
             },
+            0b1010111 => self.v_decode(raw_inst),
             0b1100011 => match funct3 {
                 0b000 => inst!(beq, Jump, I, B, raw_inst),
                 0b001 => inst!(bne, Jump, I, B, raw_inst),
@@ -296,7 +702,7 @@ impl Emulator {
                 0b100 => inst!(blt, Jump, I, B, raw_inst),
                 0b110 => inst!(bltu, Jump, I, B, raw_inst),
                 0b111 => inst!(bgeu, Jump, I, B, raw_inst),
-                _ => unimplemented!(),
+                _ => Inst::illegal(raw_inst),
             },
             0b1100111 => inst!(jalr, Jump, I, I, raw_inst),
             0b1101111 => inst!(jal, Jump, I, J, raw_inst),
@@ -305,10 +711,11 @@ impl Emulator {
                     0b0001001 => inst!(sfence_vma, System, Zifencei, R, raw_inst),
                     _ => match raw_inst {
                         0x00000073 => inst!(ecall, System, I, Other, raw_inst),
+                        0x00100073 => inst!(ebreak, System, I, Other, raw_inst),
                         0x10200073 => inst!(sret, System, I, Other, raw_inst),
                         0x30200073 => inst!(mret, System, I, Other, raw_inst),
                         0x10500073 => inst!(wfi, System, I, Other, raw_inst),
-                        _ => unimplemented!(),
+                        _ => Inst::illegal(raw_inst),
                     },
                 },
                 0b001 => inst!(csrrw, Csr, Zicsr, I, raw_inst),
@@ -317,9 +724,9 @@ impl Emulator {
                 0b101 => inst!(csrrwi, Csr, Zicsr, I, raw_inst),
                 0b110 => inst!(csrrsi, Csr, Zicsr, I, raw_inst),
                 0b111 => inst!(csrrci, Csr, Zicsr, I, raw_inst),
-                _ => unimplemented!(),
+                _ => Inst::illegal(raw_inst),
             },
-            _ => unimplemented!("rv64 op: 0b{:07b} funct3: 0x{:x}", op, funct3),
+            _ => Inst::illegal(raw_inst),
         }
     }
 }
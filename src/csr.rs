@@ -1,9 +1,19 @@
 use crate::{
     emulator::Emulator,
     exception::Exception::{self, *},
-    Priv, Result,
+    mmu::{PagingMode, SATP_PPN_MASK},
+    pmp::{Pmp, CSR_PMPADDR0, CSR_PMPADDR63, CSR_PMPCFG0, CSR_PMPCFG15},
+    vector::VTYPE_VILL,
+    Priv, Result, Xlen,
 };
 
+pub(crate) const CSR_FFLAGS: u64 = 0x001;
+pub(crate) const CSR_FRM: u64 = 0x002;
+pub(crate) const CSR_FCSR: u64 = 0x003;
+pub(crate) const CSR_VSTART: u64 = 0x008;
+pub(crate) const CSR_VXSAT: u64 = 0x009;
+pub(crate) const CSR_VXRM: u64 = 0x00a;
+pub(crate) const CSR_VCSR: u64 = 0x00f;
 pub(crate) const CSR_SSTATUS: u64 = 0x100;
 pub(crate) const CSR_SIE: u64 = 0x104;
 pub(crate) const CSR_STVEC: u64 = 0x105;
@@ -11,19 +21,31 @@ pub(crate) const CSR_SEPC: u64 = 0x141;
 pub(crate) const CSR_SCAUSE: u64 = 0x142;
 pub(crate) const CSR_STVAL: u64 = 0x143;
 pub(crate) const CSR_SIP: u64 = 0x144;
+pub(crate) const CSR_STIMECMP: u64 = 0x14d;
+const CSR_STIMECMPH: u64 = 0x15d;
+pub(crate) const CSR_SATP: u64 = 0x180;
 pub(crate) const CSR_MSTATUS: u64 = 0x300;
 pub(crate) const CSR_MISA: u64 = 0x301;
 pub(crate) const CSR_MEDELEG: u64 = 0x302;
 pub(crate) const CSR_MIDELEG: u64 = 0x303;
 pub(crate) const CSR_MIE: u64 = 0x304;
 pub(crate) const CSR_MTVEC: u64 = 0x305;
-const CSR_MCOUNTEREN: u64 = 0x306;
+pub(crate) const CSR_MCOUNTEREN: u64 = 0x306;
+pub(crate) const CSR_MENVCFG: u64 = 0x30a;
+const CSR_MSTATUSH: u64 = 0x310;
+const CSR_MENVCFGH: u64 = 0x31a;
 pub(crate) const CSR_MEPC: u64 = 0x341;
 pub(crate) const CSR_MIP: u64 = 0x344;
 pub(crate) const CSR_MCAUSE: u64 = 0x342;
 pub(crate) const CSR_MTVAL: u64 = 0x343;
 
 const CSR_CYCLE: u64 = 0xc00;
+const CSR_TIME: u64 = 0xc01;
+const CSR_CYCLEH: u64 = 0xc80;
+const CSR_TIMEH: u64 = 0xc81;
+pub(crate) const CSR_VL: u64 = 0xc20;
+pub(crate) const CSR_VTYPE: u64 = 0xc21;
+pub(crate) const CSR_VLENB: u64 = 0xc22;
 
 pub(crate) const CSR_MSTATUS_MPP_MASK: u64 = 3 << 11;
 pub(crate) const CSR_MSTATUS_SPP_MASK: u64 = 1 << 8;
@@ -35,21 +57,52 @@ pub(crate) const CSR_MSTATUS_TVM_MASK: u64 = 1 << 20;
 pub(crate) const CSR_MSTATUS_TSR_MASK: u64 = 1 << 22;
 pub(crate) const CSR_MSTATUS_TW_MASK: u64 = 1 << 21;
 pub(crate) const CSR_MSTATUS_MPRV_MASK: u64 = 1 << 17;
+pub(crate) const CSR_MSTATUS_SUM_MASK: u64 = 1 << 18;
+pub(crate) const CSR_MSTATUS_MXR_MASK: u64 = 1 << 19;
+const CSR_MSTATUS_VS_MASK: u64 = 3 << 9;
+const CSR_MSTATUS_FS_MASK: u64 = 3 << 13;
+const CSR_MSTATUS_SD_MASK: u64 = 1 << 63;
 const CSR_MSTATUS_XXL_MASK: u64 = 0xa << 32;
+const CSR_MSTATUS_UXL_MASK: u64 = 0x3 << 32;
+const CSR_MSTATUS_SXL_MASK: u64 = 0x3 << 34;
+
+// mstatus.FSの状態(Dirtyは浮動小数点レジスタかfcsrが変更されたことを表す)
+const FS_OFF: u64 = 0;
+const FS_DIRTY: u64 = 3;
+
+// mstatus.VSの状態(Dirtyはベクトルレジスタかベクトルの制御用のCSRが変更されたことを表す)
+const VS_OFF: u64 = 0;
+const VS_DIRTY: u64 = 3;
+
+// menvcfgのSTCE(Sstc拡張のstimecmpを有効にするビット)
+pub(crate) const CSR_MENVCFG_STCE_MASK: u64 = 1 << 63;
 
 // 現在実装しているxstatus系のマスク
-const CSR_MSTATUS_MASK: u64 = 0xf0004019aa;
-pub(crate) const CSR_SSTATUS_MASK: u64 = 0x300000122;
+const CSR_MSTATUS_MASK: u64 = 0xf0005e7faa;
+pub(crate) const CSR_SSTATUS_MASK: u64 = 0x8000_0003_000c_6722;
 
 const CSR_MIX_MASK: u64 = 0xaaa;
 // si{e,p}についてサポートするマスク
 const CSR_SIX_MASK: u64 = 0x222;
+// mipでソフトウェアから書き込めるビット(SSIP, STIP)
+// menvcfg.STCEが1の場合はSTIPはstimecmpで決まるので書き込めない。
+const CSR_MIP_WRITABLE_MASK: u64 = 0x22;
+const CSR_MIP_STIP_MASK: u64 = 0x20;
+// sipでソフトウェアから書き込めるビット(SSIP)
+const CSR_SIP_WRITABLE_MASK: u64 = 0x2;
+
+const SATP_ASID_MASK: u64 = 0xffff << 44;
 
-const CAUSE_INTERRUPT_MASK: u64 = 0x2aaa;
 const CAUSE_EXCEPTION_MASK: u64 = 0xcbbff;
 
 #[derive(Debug)]
 pub(crate) struct Csr {
+    fcsr: u64, // 0x003(0x001はfflags、0x002はfrm)
+
+    pub(crate) vstart: u64, // 0x008
+    pub(crate) vxsat: u64,  // 0x009
+    pub(crate) vxrm: u64,   // 0x00a(0x00fはvxrmとvxsatをまとめたvcsr)
+
     stvec: u64,      // 0x105
     scounteren: u64, // 0x106
 
@@ -57,74 +110,165 @@ pub(crate) struct Csr {
     sepc: u64,     // 0x141
     scause: u64,   // 0x142
     stval: u64,    // 0x143
+    stimecmp: u64, // 0x14d
     satp: u64,     // 0x180
 
     mstatus: u64, // 0x300 or 0x100(sstatus)
     misa: u64,    // 0x301
     mtvec: u64,   // 0x305
 
-    medeleg: u64,    // 0x302
-    mideleg: u64,    // 0x303
-    mie: u64,        // 0x304
-    mcounteren: u64, // 0x306
-    mscratch: u64,   // 0x340
-    mepc: u64,       // 0x341
-    mcause: u64,     // 0x342
-    mtval: u64,      // 0x343
-    mip: u64,        // 0x344
-    pmpcfg0: u64,    // 0x3a0
-    pmpaddr0: u64,   // 0x3b0
+    medeleg: u64,                 // 0x302
+    mideleg: u64,                 // 0x303
+    mie: u64,                     // 0x304
+    mcounteren: u64,              // 0x306
+    menvcfg: u64,                 // 0x30a
+    mscratch: u64,                // 0x340
+    mepc: u64,                    // 0x341
+    mcause: u64,                  // 0x342
+    mtval: u64,                   // 0x343
+    mip: u64,                     // 0x344
+    pub(crate) mip_hardware: u64, // mipのうちデバイスが設定するビット
+    pub(crate) pmp: Pmp,          // 0x3a0-0x3af(pmpcfg), 0x3b0-0x3ef(pmpaddr)
 
     mnstatus: u64, // 0x744
 
     mcycle: u64, // 0x800
+
+    pub(crate) vl: u64,    // 0xc20
+    pub(crate) vtype: u64, // 0xc21(0xc22のvlenbはエミュレータの構成で決まる)
+
+    mhartid: u64, // 0xf14
 }
 
 impl Default for Csr {
     fn default() -> Self {
         Self {
+            fcsr: 0,
+            vstart: 0,
+            vxsat: 0,
+            vxrm: 0,
             stvec: 0,
             scounteren: 0,
             sscratch: 0,
             sepc: 0,
             scause: 0,
             stval: 0,
+            stimecmp: u64::MAX,
             satp: 0,
             mstatus: CSR_MSTATUS_XXL_MASK,
-            misa: (1 << 63) | 0x141105, // (64bit,imacsu)
+            misa: (1 << 63) | 0x14112d, // (64bit,imafdcsu)
             mtvec: 0,
             medeleg: 0,
             mideleg: 0,
             mie: 0,
             mcounteren: 0,
+            menvcfg: 0,
             mscratch: 0,
             mepc: 0,
             mcause: 0,
             mtval: 0,
             mip: 0,
-            pmpcfg0: 0,
-            pmpaddr0: 0,
+            mip_hardware: 0,
+            pmp: Pmp::default(),
             mnstatus: 0,
             mcycle: 0,
+            vl: 0,
+            vtype: VTYPE_VILL,
+            mhartid: 0,
         }
     }
 }
 
 impl Csr {
+    pub(crate) fn new(hartid: usize, xlen: Xlen) -> Self {
+        let misa = match xlen {
+            Xlen::X32 => (1 << 30) | 0x141105, // (32bit,imacsu)
+            Xlen::X64 => (1 << 63) | 0x14112d, // (64bit,imafdcsu)
+        };
+
+        Self {
+            misa,
+            mhartid: hartid as u64,
+            ..Self::default()
+        }
+    }
+
+    // mipのソフトウェアから書き込めるビット(SSIPなど)を設定する関数
+    // 他のハートに割り込みを送るときに使う。
+    pub(crate) fn raise_interrupt(&mut self, mask: u64) {
+        self.mip |= mask & CSR_MIP_WRITABLE_MASK;
+    }
+
+    // mstatus.FSが0(Off)でないかどうかを返す関数
+    // Offの場合は浮動小数点の命令とfcsrへのアクセスはすべて不正な命令になる。
+    pub(crate) fn is_fp_enabled(&self) -> bool {
+        (self.mstatus & CSR_MSTATUS_FS_MASK) >> 13 != FS_OFF
+    }
+
+    // 浮動小数点の状態が変更されたことをmstatus.FSに記録する関数
+    pub(crate) fn set_fp_dirty(&mut self) {
+        self.mstatus |= FS_DIRTY << 13 | CSR_MSTATUS_SD_MASK;
+    }
+
+    // 動的な丸めモード(frm)を返す関数
+    pub(crate) fn frm(&self) -> u64 {
+        (self.fcsr >> 5) & 0x7
+    }
+
+    // 浮動小数点の演算で起こった例外をfflagsに累積する関数
+    pub(crate) fn accrue_fflags(&mut self, flags: u64) {
+        if flags != 0 {
+            self.fcsr |= flags & 0x1f;
+            self.set_fp_dirty();
+        }
+    }
+
+    // mstatus.VSが0(Off)でないかどうかを返す関数
+    // Offの場合はベクトルの命令とベクトルのCSRへのアクセスはすべて不正な命令になる。
+    pub(crate) fn is_vector_enabled(&self) -> bool {
+        (self.mstatus & CSR_MSTATUS_VS_MASK) >> 9 != VS_OFF
+    }
+
+    // ベクトルの状態が変更されたことをmstatus.VSに記録する関数
+    pub(crate) fn set_vector_dirty(&mut self) {
+        self.mstatus |= VS_DIRTY << 9 | CSR_MSTATUS_SD_MASK;
+    }
+
+    // mstatus.SDをFSとVSに合わせて更新する関数
+    // SDはFSかVSがDirtyの場合に1になる読み込み専用のビットなので、FSやVSを変更したら呼ぶ。
+    fn update_sd(&mut self) {
+        if (self.mstatus & CSR_MSTATUS_FS_MASK) >> 13 == FS_DIRTY
+            || (self.mstatus & CSR_MSTATUS_VS_MASK) >> 9 == VS_DIRTY
+        {
+            self.mstatus |= CSR_MSTATUS_SD_MASK;
+        } else {
+            self.mstatus &= !CSR_MSTATUS_SD_MASK;
+        }
+    }
+
     // csrを読み込む関数
     // 権限やRWのチェック等を終わった段階で呼ぶ関数
     // エイリアス等が存在するCSRを読み込む場合に対応するための関数
     // 副作用はなく、ただ単純にCSRをよむのみを行う。
     pub(crate) fn read(&self, csr: u64) -> Option<u64> {
         match csr {
+            CSR_FFLAGS => Some(self.fcsr & 0x1f),                 // fflags
+            CSR_FRM => Some(self.frm()),                          // frm
+            CSR_FCSR => Some(self.fcsr),                          // fcsr
+            CSR_VSTART => Some(self.vstart),                      // vstart
+            CSR_VXSAT => Some(self.vxsat),                        // vxsat
+            CSR_VXRM => Some(self.vxrm),                          // vxrm
+            CSR_VCSR => Some((self.vxrm << 1) | self.vxsat),      // vcsr
             CSR_SSTATUS => Some(self.mstatus & CSR_SSTATUS_MASK), // sstatus
             CSR_SIE => Some(self.mie & CSR_SIX_MASK),             // sie
             CSR_STVEC => Some(self.stvec),                        // stvec
             0x140 => Some(self.sscratch),                         // sscratch
             CSR_SEPC => Some(self.sepc),                          // sepc
             CSR_SCAUSE => Some(self.scause),                      // scause
-            CSR_SIP => Some(self.mip & CSR_SIX_MASK),             // sip
-            0x180 => Some(self.satp),                             // satp
+            CSR_STVAL => Some(self.stval),                        // stval
+            CSR_SIP => Some((self.mip | self.mip_hardware) & CSR_SIX_MASK), // sip
+            CSR_STIMECMP => Some(self.stimecmp),                  // stimecmp
+            CSR_SATP => Some(self.satp),                          // satp
             CSR_MSTATUS => Some(self.mstatus),                    // mstatus
             CSR_MISA => Some(self.misa),                          // misa
             CSR_MEDELEG => Some(self.medeleg),                    // medeleg
@@ -132,16 +276,22 @@ impl Csr {
             CSR_MIE => Some(self.mie),                            // mie
             CSR_MTVEC => Some(self.mtvec),                        // mtvec
             CSR_MCOUNTEREN => Some(self.mcounteren),              // mcounteren
+            CSR_MENVCFG => Some(self.menvcfg),                    // menvcfg
             0x340 => Some(self.mscratch),                         // mscratch
             CSR_MEPC => Some(self.mepc),                          // mepc
             CSR_MCAUSE => Some(self.mcause),                      // mcause
             CSR_MTVAL => Some(self.mtval),                        // mtval
-            CSR_MIP => Some(self.mip),                            // mip
-            0x800 | CSR_CYCLE => Some(self.mcycle),               // mcycle or cycle
-            0xf11 => Some(0xba5eba11),                            // mvendorid(baseball)
-            0xf12 => Some(0x05500550),                            // mvendorid(ossoosso)
-            0xf13 => Some(0x1),                                   // mimpid(version 1)
-            0xf14 => Some(0),                                     // mhartid
+            // SEIPはソフトウェアが書き込んだ値とPLICの信号の論理和になる。
+            CSR_MIP => Some(self.mip | self.mip_hardware), // mip
+            CSR_PMPCFG0..=CSR_PMPCFG15 => self.pmp.read_cfg(csr), // pmpcfg
+            CSR_PMPADDR0..=CSR_PMPADDR63 => Some(self.pmp.read_addr(csr)), // pmpaddr
+            0x800 | CSR_CYCLE => Some(self.mcycle),        // mcycle or cycle
+            CSR_VL => Some(self.vl),                       // vl
+            CSR_VTYPE => Some(self.vtype),                 // vtype
+            0xf11 => Some(0xba5eba11),                     // mvendorid(baseball)
+            0xf12 => Some(0x05500550),                     // mvendorid(ossoosso)
+            0xf13 => Some(0x1),                            // mimpid(version 1)
+            0xf14 => Some(self.mhartid),                   // mhartid
             _ => None,
         }
     }
@@ -149,7 +299,8 @@ impl Csr {
 
 impl Emulator {
     pub(crate) fn initialize_csr(&mut self) {
-        self.csr = Csr::default();
+        self.csr = Csr::new(self.csr.mhartid as usize, self.xlen);
+        self.tlb.flush_all();
     }
 
     // C拡張を有効/無効にする関数
@@ -173,41 +324,146 @@ impl Emulator {
 
     fn check_csr_priv(&self, csr: u64) -> Result<()> {
         if (csr >> 8) & 0x3 > self.current_priv as u64 {
-            Err(IllegralInstruction)
-        } else {
-            Ok(())
+            return Err(IllegralInstruction);
         }
+
+        // mstatus.FSが0の場合は浮動小数点のCSRにアクセスできない。
+        if (CSR_FFLAGS..=CSR_FCSR).contains(&csr) && !self.csr.is_fp_enabled() {
+            return Err(IllegralInstruction);
+        }
+
+        // mstatus.VSが0の場合はベクトルのCSRにアクセスできない。
+        if matches!(
+            csr,
+            CSR_VSTART | CSR_VXSAT | CSR_VXRM | CSR_VCSR | CSR_VL | CSR_VTYPE | CSR_VLENB
+        ) && !self.csr.is_vector_enabled()
+        {
+            return Err(IllegralInstruction);
+        }
+
+        // mstatus.TVMが1の場合はSモードからsatpにアクセスできない。
+        if csr == CSR_SATP
+            && self.current_priv == Priv::S
+            && self.read_raw_csr(CSR_MSTATUS).unwrap() & CSR_MSTATUS_TVM_MASK != 0
+        {
+            return Err(IllegralInstruction);
+        }
+
+        // stimecmpはmenvcfg.STCEとmcounteren.TMが1の場合のみSモードからアクセスできる。
+        if matches!(csr, CSR_STIMECMP | CSR_STIMECMPH)
+            && self.current_priv != Priv::M
+            && (self.csr.menvcfg & CSR_MENVCFG_STCE_MASK == 0 || self.csr.mcounteren & 0x2 == 0)
+        {
+            return Err(IllegralInstruction);
+        }
+
+        Ok(())
     }
 
-    // 割り込みがアクティブかどうかを判定しアクティブな場合はErrとして割り込み用のExceptionを返す
-    // サポートされていない条件の場合かつ仕様書では割り込みの条件に入っている場合も実装していない場合はOKを返すので注意
-    pub(crate) fn check_interrupt_active(&self) -> Result<()> {
-        let mstatus = self.read_raw_csr(CSR_MSTATUS).unwrap();
+    // 現在のモードのXLENを返す関数
+    // MXLが64bitの場合、SモードのXLENはSXL、UモードのXLENはUXLで決まる。
+    pub(crate) fn current_xlen(&self) -> Xlen {
+        match self.current_priv {
+            _ if self.xlen == Xlen::X32 => Xlen::X32,
+            Priv::M => self.xlen,
+            Priv::S => self.sxlen(),
+            Priv::U => xl_to_xlen((self.csr.mstatus & CSR_MSTATUS_UXL_MASK) >> 32),
+        }
+    }
 
-        // mstatus.MIEが有効でない場合(0)は返す
-        if mstatus & CSR_MSTATUS_MIE_MASK == 0 {
-            return Ok(());
+    // SモードのXLEN(SXLEN)を返す関数
+    // satpの形式とサポートするページングのモードはSXLENで決まる。
+    pub(crate) fn sxlen(&self) -> Xlen {
+        match self.xlen {
+            Xlen::X32 => Xlen::X32,
+            Xlen::X64 => xl_to_xlen((self.csr.mstatus & CSR_MSTATUS_SXL_MASK) >> 34),
         }
+    }
 
-        // mstatus.MIEが有効な場合
+    // mstatusのmaskのビットを書き込む関数
+    // UXLとSXLは1(32bit)か2(64bit)のみ書き込めて、それ以外の値の場合は変更しない。(WARL)
+    // MXLが32bitの場合はF、D、V拡張をサポートしないので、FSとVSは0に固定する。
+    fn write_mstatus(&mut self, value: u64, mask: u64) {
+        let mut mstatus = (self.csr.mstatus & !mask) | (value & mask);
 
-        if self.current_priv != Priv::M {
-            return Ok(());
+        for xl_mask in [CSR_MSTATUS_UXL_MASK, CSR_MSTATUS_SXL_MASK] {
+            if !matches!((value & xl_mask) >> xl_mask.trailing_zeros(), 1 | 2) {
+                mstatus = (mstatus & !xl_mask) | (self.csr.mstatus & xl_mask);
+            }
         }
 
+        if self.xlen == Xlen::X32 {
+            mstatus &= !(CSR_MSTATUS_FS_MASK | CSR_MSTATUS_VS_MASK);
+        }
+
+        self.csr.mstatus = mstatus;
+        self.csr.update_sd();
+    }
+
+    // xepcに書き込むときのマスクを返す関数
+    fn epc_mask(&self) -> u64 {
+        if self.csr.misa & 4 != 0 {
+            !0x1
+        } else {
+            !0x3
+        }
+    }
+
+    // デバイスからの割り込みをmipに反映する関数
+    // MSIPとMTIPはCLINT、MEIPとSEIPはPLICの状態で決まる。
+    // menvcfg.STCEが1の場合はSTIPもmtimeとstimecmpの比較で決まる。(Sstc拡張)
+    pub(crate) fn update_interrupt_pending(&mut self) {
+        self.bus.update_irqs();
+
+        let mtime = self.bus.clint.mtime();
+
+        let stip = if self.csr.menvcfg & CSR_MENVCFG_STCE_MASK != 0 && mtime >= self.csr.stimecmp {
+            CSR_MIP_STIP_MASK
+        } else {
+            0
+        };
+
+        let hart = self.scheduler.current;
+
+        self.csr.mip_hardware = self.bus.clint.pending_interrupts(hart, mtime)
+            | self.bus.plic.pending_interrupts(hart)
+            | stip;
+    }
+
+    // 割り込みがアクティブかどうかを判定しアクティブな場合はErrとして割り込み用のExceptionを返す
+    // midelegで委譲されていない割り込みは現在のモードがMより低いか、MモードでmstatusのMIEが1の場合に有効になる。
+    // 委譲されている割り込みは現在のモードがSより低いか、SモードでmstatusのSIEが1の場合に有効になる。
+    // 複数の割り込みがある場合はMEI, MSI, MTI, SEI, SSI, STIの順で優先する。
+    pub(crate) fn check_interrupt_active(&self) -> Result<()> {
+        const PRIORITY: [u64; 6] = [11, 3, 7, 9, 1, 5];
+
         let mie = self.read_raw_csr(CSR_MIE).unwrap();
         let mip = self.read_raw_csr(CSR_MIP).unwrap();
 
-        let active = mie & mip;
+        let pending = mie & mip;
 
-        if active != 0 {
-            if active.count_ones() != 1 {
-                panic!("Error: Nested traps are not supported.");
-            }
+        if pending == 0 {
+            return Ok(());
+        }
 
-            match active {
-                2 => return Err(Exception::SuperSoftInt),
-                _ => panic!("Error: The active interrupt is not suported."),
+        let mstatus = self.read_raw_csr(CSR_MSTATUS).unwrap();
+        let mideleg = self.read_raw_csr(CSR_MIDELEG).unwrap();
+        let current_priv = self.current_priv as u64;
+
+        let m_enabled = current_priv < Priv::M as u64
+            || (current_priv == Priv::M as u64 && mstatus & CSR_MSTATUS_MIE_MASK != 0);
+        let s_enabled = current_priv < Priv::S as u64
+            || (current_priv == Priv::S as u64 && mstatus & CSR_MSTATUS_SIE_MASK != 0);
+
+        let enabled = if m_enabled { pending & !mideleg } else { 0 }
+            | if s_enabled { pending & mideleg } else { 0 };
+
+        // Mモードに委譲されていない割り込みはSモードの割り込みより優先する。
+        for delegated in [false, true] {
+            for bit in PRIORITY {
+                if enabled & (1 << bit) != 0 && ((mideleg >> bit) & 0x1 != 0) == delegated {
+                    return Err(Exception::from_interrupt(bit).unwrap());
+                }
             }
         }
 
@@ -222,6 +478,14 @@ impl Emulator {
 
     // 暗黙的にcsrを読み込む関数
     pub(crate) fn read_raw_csr(&self, csr: u64) -> Result<u64> {
+        if csr == CSR_TIME {
+            return Ok(self.bus.clint.mtime());
+        }
+
+        if csr == CSR_VLENB {
+            return Ok(self.vector.vlenb() as u64);
+        }
+
         match self.csr.read(csr) {
             Some(v) => Ok(v),
             None => Err(IllegralInstruction),
@@ -236,30 +500,169 @@ impl Emulator {
 
         self.check_csr_priv(csr)?;
 
-        eprintln!("[info]: read 0x{:x}[csr]", csr);
+        if self.trace {
+            eprintln!("[info]: read 0x{:x}[csr]", csr);
+        }
+
+        if matches!(csr, CSR_CYCLE | CSR_TIME | CSR_CYCLEH | CSR_TIMEH) {
+            // mcounteren(Uモードの場合はscounterenも)の対応するビットが0の場合は読み込めない。
+            let bit = 1 << (csr & 0x1f);
+
+            if self.current_priv != Priv::M
+                && (self.read_raw_csr(CSR_MCOUNTEREN).unwrap() & bit) == 0
+            {
+                return Err(IllegralInstruction);
+            }
+
+            if self.current_priv == Priv::U && self.csr.scounteren & bit == 0 {
+                return Err(IllegralInstruction);
+            }
+        }
+
+        // satpはSXLEN、それ以外のCSRは現在のモードのXLENの形式で読み込む。
+        let xlen = if csr == CSR_SATP {
+            self.sxlen()
+        } else {
+            self.current_xlen()
+        };
+
+        match xlen {
+            Xlen::X32 => self.read_rv32_csr(csr),
+            Xlen::X64 => self.read_raw_csr(csr),
+        }
+    }
+
+    // XLENが32のモードでCSRを読み込む関数
+    // CSRの値は内部ではRV64の形式で保持しているので、RV32の形式に変換する。
+    // 64bitのCSRの上位32bitは対応する~hのCSRで読み込む。
+    fn read_rv32_csr(&self, csr: u64) -> Result<u64> {
+        let value = match csr {
+            CSR_SSTATUS | CSR_MSTATUS => {
+                let value = self.read_raw_csr(csr)?;
+
+                // SDは63bit目から31bit目に移動する。
+                (value & 0x7fff_ffff) | ((value >> 63) << 31)
+            }
+            CSR_SCAUSE | CSR_MCAUSE => {
+                let value = self.read_raw_csr(csr)?;
+
+                // 割り込みかどうかを示すビットは63bit目から31bit目に移動する。
+                (value & 0x7fff_ffff) | ((value >> 63) << 31)
+            }
+            CSR_VTYPE => {
+                let value = self.read_raw_csr(csr)?;
+
+                // villは63bit目から31bit目に移動する。
+                (value & 0x7fff_ffff) | ((value >> 63) << 31)
+            }
+            CSR_SATP => {
+                // MODE(31bit目)、ASID(30:22bit目)、PPN(21:0bit目)の形式にする。
+                let satp = self.read_raw_csr(csr)?;
+
+                ((satp >> 60) << 31) | (((satp >> 44) & 0x1ff) << 22) | (satp & 0x3f_ffff)
+            }
+            CSR_PMPCFG0..=CSR_PMPCFG15 => {
+                // RV32では1つのpmpcfgに4エントリ分の設定を持つので、偶数番号のpmpcfgの半分になる。
+                self.read_raw_csr(csr & !0x1)? >> ((csr & 0x1) * 32)
+            }
+            CSR_MSTATUSH => {
+                // MBEとSBEは0(リトルエンディアン)に固定している。
+                0
+            }
+            CSR_STIMECMPH => self.read_raw_csr(CSR_STIMECMP)? >> 32,
+            CSR_MENVCFGH => self.read_raw_csr(CSR_MENVCFG)? >> 32,
+            CSR_CYCLEH | CSR_TIMEH => self.read_raw_csr(csr - 0x80)? >> 32,
+            _ => self.read_raw_csr(csr)?,
+        };
+
+        Ok(value & 0xffff_ffff)
+    }
+
+    // XLENが32のモードでCSRを書き込む関数
+    // 書き込む値をRV64の形式に変換してから書き込む。
+    // 64bitのCSRは書き込まない側の32bitを保持する。
+    fn write_rv32_csr(&mut self, csr: u64, value: u64) -> Result<()> {
+        let value = value & 0xffff_ffff;
 
         match csr {
-            CSR_CYCLE => {
-                if self.current_priv != Priv::M
-                    && (self.read_raw_csr(CSR_MCOUNTEREN).unwrap() & 0x1) == 0
-                {
-                    return Err(IllegralInstruction);
-                }
+            CSR_SSTATUS | CSR_MSTATUS => {
+                // SDは読み込み専用なので無視する。UXLとSXLは見えないので変更しない。
+                self.write_raw_csr(csr, value & 0x7fff_ffff)
+            }
+            CSR_SCAUSE | CSR_MCAUSE => {
+                self.write_raw_csr(csr, (value & 0x7fff_ffff) | ((value >> 31) << 63))
+            }
+            CSR_SATP => {
+                let satp =
+                    ((value >> 31) << 60) | (((value >> 22) & 0x1ff) << 44) | (value & 0x3f_ffff);
+
+                self.write_raw_csr(csr, satp)
+            }
+            CSR_PMPCFG0..=CSR_PMPCFG15 => {
+                // 偶数番号のpmpcfgの残りの半分は読み込んだ値を書き戻す。
+                // ロックされているエントリは変更されないので、書き戻しても問題はない。
+                let shift = (csr & 0x1) * 32;
+                let old = self.read_raw_csr(csr & !0x1)?;
+
+                self.write_raw_csr(
+                    csr & !0x1,
+                    (old & !(0xffff_ffff << shift)) | (value << shift),
+                )
+            }
+            CSR_MSTATUSH => Ok(()),
+            CSR_STIMECMP | CSR_MENVCFG => {
+                let old = self.read_raw_csr(csr)?;
+
+                self.write_raw_csr(csr, (old & !0xffff_ffff) | value)
+            }
+            CSR_STIMECMPH | CSR_MENVCFGH => {
+                let csr = csr - 0x10;
+                let old = self.read_raw_csr(csr)?;
 
-                self.read_raw_csr(csr)
-            } // cycle
-            _ => self.read_raw_csr(csr),
+                self.write_raw_csr(csr, (old & 0xffff_ffff) | (value << 32))
+            }
+            _ => self.write_raw_csr(csr, value),
         }
     }
 
     pub(crate) fn write_raw_csr(&mut self, csr: u64, value: u64) -> Result<()> {
         match csr {
+            CSR_FFLAGS => {
+                self.csr.fcsr = (self.csr.fcsr & !0x1f) | (value & 0x1f);
+                self.csr.set_fp_dirty();
+            } // fflags
+            CSR_FRM => {
+                self.csr.fcsr = (self.csr.fcsr & 0x1f) | ((value & 0x7) << 5);
+                self.csr.set_fp_dirty();
+            } // frm
+            CSR_FCSR => {
+                self.csr.fcsr = value & 0xff;
+                self.csr.set_fp_dirty();
+            } // fcsr
+            CSR_VSTART => {
+                // 要素のインデックスの最大値(VLEN-1)を表せるビットだけを保持する。
+                self.csr.vstart = value & (self.vector.vlen as u64 - 1);
+                self.csr.set_vector_dirty();
+            } // vstart
+            CSR_VXSAT => {
+                self.csr.vxsat = value & 0x1;
+                self.csr.set_vector_dirty();
+            } // vxsat
+            CSR_VXRM => {
+                self.csr.vxrm = value & 0x3;
+                self.csr.set_vector_dirty();
+            } // vxrm
+            CSR_VCSR => {
+                self.csr.vxsat = value & 0x1;
+                self.csr.vxrm = (value >> 1) & 0x3;
+                self.csr.set_vector_dirty();
+            } // vcsr
             CSR_SSTATUS => {
-                if value & 0x80_00_00_01_00_01_e6_40 != 0 {
+                if value & 0x00_00_00_00_00_01_80_40 != 0 {
                     // 下の条件を満たす場合は一旦エラーを出すようにする。
                     // * UBEがbig endian(1)
-                    // * xS, SDが１
-                    // * UXLが64bit以外(01, 11)
+                    // * XSが１
+                    // SDは読み込み専用なので、読み込んだ値を書き戻した場合も含めて無視する。
                     eprintln!(
                         "[warning]: The value(0x{:016x}) of writing sstatus is not support.",
                         value
@@ -267,17 +670,11 @@ impl Emulator {
                     return Err(IllegralInstruction);
                 }
 
-                if value & 0x3 << 17 != 0 {
-                    // MXRとSUMを実装したらこの警告を消す
-                    // 現状はCSR_xSTATUS_MASKによってMXRとSUMはクリアされる
-                    eprintln!("[warning]: sstatus.MXR or sstatus.SUM is not supported.");
-                }
-
-                self.csr.mstatus =
-                    (self.csr.mstatus & !CSR_MSTATUS_MASK) | (value & CSR_SSTATUS_MASK);
+                // sstatusから見えないビットとSDは変更しない。
+                self.write_mstatus(value, CSR_SSTATUS_MASK & !CSR_MSTATUS_SD_MASK);
             } // sstatus
             CSR_SIE => {
-                self.csr.mie = (self.csr.mie & !CSR_MIX_MASK) | (value & CSR_SIX_MASK);
+                self.csr.mie = (self.csr.mie & !CSR_SIX_MASK) | (value & CSR_SIX_MASK);
             } // sie
             CSR_STVEC => {
                 // mtvecと同様
@@ -290,8 +687,8 @@ impl Emulator {
                 self.csr.sscratch = value;
             } // sscratch
             CSR_SEPC => {
-                // とりあえず4byteのアライメントにする
-                self.csr.sepc = value & 0xfffffffffffffffc;
+                // C拡張が有効な場合は2byte、無効な場合は4byteのアライメントにする
+                self.csr.sepc = value & self.epc_mask();
             } // sepc
             CSR_SCAUSE => {
                 self.csr.scause = value
@@ -313,27 +710,32 @@ impl Emulator {
                 self.csr.stval = value;
             }
             CSR_SIP => {
-                self.csr.mip = (self.csr.mip & !CSR_MIX_MASK) | (value & CSR_SIX_MASK);
+                self.csr.mip =
+                    (self.csr.mip & !CSR_SIP_WRITABLE_MASK) | (value & CSR_SIP_WRITABLE_MASK);
             } // sip
-            0x180 => {
-                // Bareモードのみサポート
-                // Sモードをまともに実装するまでは何も行わないことにする。
-
-                if value != 0 {
-                    eprintln!("[warning]: Writing value(not 0) to satp is not supported.");
+            CSR_STIMECMP => {
+                self.csr.stimecmp = value;
+            } // stimecmp
+            CSR_SATP => {
+                // サポートしていないモードが書き込まれた場合は書き込み自体を無視する。(WARL)
+                // サポートするモードはEmulator::set_supported_paging_modesで設定する。
+                match PagingMode::from_satp(value) {
+                    Some(mode)
+                        if mode.is_valid_for(self.sxlen()) && self.paging_modes.contains(mode) =>
+                    {
+                        self.csr.satp = value & (0xf << 60 | SATP_ASID_MASK | SATP_PPN_MASK);
+                        self.tlb.flush_all();
+                    }
+                    _ => {}
                 }
-
-                eprint_not_working("satp");
             } // satp
             CSR_MSTATUS => {
-                if value & 0x8000_0005_002f_e640 != 0 {
+                if value & 0x0000_0000_0021_8040 != 0 {
                     // 下の条件を満たす場合は一旦エラーを出すようにする。
                     // * xBEがbig endian(1)
-                    // * VSやFS、XSに対して書き込みがある場合
-                    // * SDへの書き込み
-                    // * MXRが1
+                    // * XSに対して書き込みがある場合
+                    // * TWが1
                     // * ハイパバイザー関連のパラメータ
-                    // * xXLが64bit以外(01, 11)
                     eprintln!(
                         "[warning]: The value(0x{:016x}) of writing mstatus is not support.",
                         value
@@ -343,7 +745,10 @@ impl Emulator {
 
                 // Mモードでの書き込みの想定なので制限は特にない。
                 // self.csr.mstatus = 0xa00000000 & (value & 0x8000_003f_007f_ffea);
-                self.csr.mstatus = (value & CSR_MSTATUS_MASK) | CSR_MSTATUS_XXL_MASK;
+                self.write_mstatus(
+                    value,
+                    CSR_MSTATUS_MASK | CSR_MSTATUS_UXL_MASK | CSR_MSTATUS_SXL_MASK,
+                );
             } // mstatus
             0x301 => {
                 // C拡張を無効/有効にする以外を想定しない。
@@ -362,7 +767,8 @@ impl Emulator {
                 self.csr.medeleg = value & CAUSE_EXCEPTION_MASK;
             } // medeleg
             0x303 => {
-                self.csr.mideleg = value & CAUSE_INTERRUPT_MASK;
+                // Mモードの割り込み(MSI, MTI, MEI)は委譲できないので、Sモードの割り込みのみ書き込める。
+                self.csr.mideleg = value & CSR_SIX_MASK;
             } // mideleg
             CSR_MIE => {
                 // LCOFIPはサポートしない
@@ -371,12 +777,21 @@ impl Emulator {
             CSR_MCOUNTEREN => {
                 self.csr.mcounteren = value;
             } // mcounteren
+            CSR_MENVCFG => {
+                // STCE以外はサポートしていないので0に固定する。
+                self.csr.menvcfg = value & CSR_MENVCFG_STCE_MASK;
+
+                // STIPはstimecmpで決まるようになるので、ソフトウェアで書き込んだ値は消す。
+                if self.csr.menvcfg & CSR_MENVCFG_STCE_MASK != 0 {
+                    self.csr.mip &= !CSR_MIP_STIP_MASK;
+                }
+            } // menvcfg
             0x340 => {
                 self.csr.mscratch = value;
             } // mscratch
             0x341 => {
-                // とりあえず4byteのアライメントにする
-                self.csr.mepc = value & 0xfffffffffffffffc;
+                // C拡張が有効な場合は2byte、無効な場合は4byteのアライメントにする
+                self.csr.mepc = value & self.epc_mask();
             } // mepc
             CSR_MCAUSE => {
                 // ソフトウェアからの書き込みはしてはいけない。
@@ -404,16 +819,28 @@ impl Emulator {
             } // mtval
             CSR_MIP => {
                 // このレジスタは割り込みが起こっているかを示すレジスタらしい
-                self.csr.mip = value & CSR_MIX_MASK;
+                // Mモードの割り込み(MSIP, MTIP, MEIP)とSEIPはデバイスが設定するので書き込めない。
+                // SEIPを書き込めるようにするとcsrrsなどで読み込んだPLICの信号を書き戻してしまうため。
+                let mask = if self.csr.menvcfg & CSR_MENVCFG_STCE_MASK != 0 {
+                    CSR_MIP_WRITABLE_MASK & !CSR_MIP_STIP_MASK
+                } else {
+                    CSR_MIP_WRITABLE_MASK
+                };
+
+                self.csr.mip = (self.csr.mip & !mask) | (value & mask);
             } // mip
-            0x3a0 => {
-                self.csr.pmpcfg0 = value;
-                eprint_not_working("pmpcfg0");
-            } // pmpcfg0
-            0x3b0 => {
-                self.csr.pmpaddr0 = value & 0x3ffffffffffff;
-                eprint_not_working("pmpaddr0");
-            } // pmpaddr0
+            CSR_PMPCFG0..=CSR_PMPCFG15 => {
+                // RV64では奇数番号のpmpcfgは存在しない。
+                // RV32の奇数番号のpmpcfgはwrite_rv32_csrで偶数番号のpmpcfgの上位32bitとして書き込む。
+                if csr & 0x1 != 0 {
+                    return Err(IllegralInstruction);
+                }
+
+                self.csr.pmp.write_cfg(csr, value);
+            } // pmpcfg
+            CSR_PMPADDR0..=CSR_PMPADDR63 => {
+                self.csr.pmp.write_addr(csr, value);
+            } // pmpaddr
             0x744 => {
                 self.csr.mnstatus = value & 0x8;
                 eprint_not_working("mnstatus");
@@ -438,9 +865,29 @@ impl Emulator {
 
         self.check_csr_priv(csr)?;
 
-        eprintln!("[info]: write 0x{:x}[csr] value: 0x{:x}", csr, value);
+        if self.trace {
+            eprintln!("[info]: write 0x{:x}[csr] value: 0x{:x}", csr, value);
+        }
+
+        // satpはSXLEN、それ以外のCSRは現在のモードのXLENの形式で書き込む。
+        let xlen = if csr == CSR_SATP {
+            self.sxlen()
+        } else {
+            self.current_xlen()
+        };
+
+        match xlen {
+            Xlen::X32 => self.write_rv32_csr(csr, value),
+            Xlen::X64 => self.write_raw_csr(csr, value),
+        }
+    }
+}
 
-        self.write_raw_csr(csr, value)
+// mstatusのUXLかSXLの値をXLENに変換する関数
+fn xl_to_xlen(xl: u64) -> Xlen {
+    match xl {
+        1 => Xlen::X32,
+        _ => Xlen::X64,
     }
 }
 
@@ -0,0 +1,301 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    bus::DRAM_BASE,
+    clint::{CLINT_BASE, CLINT_SIZE},
+    csr::CSR_MISA,
+    emulator::Emulator,
+    fdt::Fdt,
+    mmu::PagingMode,
+    plic::{PLIC_BASE, PLIC_SIZE, PLIC_SOURCES},
+    syscon::{SYSCON_BASE, SYSCON_PASS, SYSCON_RESET, SYSCON_SIZE},
+    vector::VectorConfig,
+    Xlen,
+};
+
+// phandle(ハートの割り込みコントローラはPHANDLE_CPU_INTC + hartid)
+const PHANDLE_PLIC: u32 = 1;
+const PHANDLE_SYSCON: u32 = 2;
+const PHANDLE_CPU_INTC: u32 = 3;
+
+// ハートの割り込みコントローラの割り込み番号(mipのビット)
+const IRQ_M_SOFT: u32 = 3;
+const IRQ_M_TIMER: u32 = 7;
+const IRQ_S_EXT: u32 = 9;
+const IRQ_M_EXT: u32 = 11;
+
+// riscv,isaに書く1文字の拡張の順番
+const ISA_EXTENSION_ORDER: &str = "imafdqcbvh";
+
+// misaに含まれない、常にサポートする拡張
+const ISA_MULTI_LETTER_EXTENSIONS: [&str; 10] = [
+    "zicond",
+    "zicsr",
+    "zifencei",
+    "zihintntl",
+    "zihintpause",
+    "zba",
+    "zbb",
+    "zbc",
+    "zbs",
+    "sstc",
+];
+
+// デバイスツリーに含めるデバイスのノード
+// regとinterruptsはバスに接続したときのアドレスと割り込み番号から生成する。
+#[derive(Debug, Clone)]
+pub struct DeviceTreeNode {
+    pub name: &'static str,
+    pub compatible: &'static [&'static str],
+    pub properties: Vec<(&'static str, u32)>,
+}
+
+// #address-cellsと#size-cellsが2の場合のreg
+fn reg(base: u64, size: u64) -> [u32; 4] {
+    [
+        (base >> 32) as u32,
+        base as u32,
+        (size >> 32) as u32,
+        size as u32,
+    ]
+}
+
+// misaとXLENとベクトル拡張の構成からriscv,isa-baseとriscv,isa-extensionsに書く値を作る関数
+// ベクトル拡張は浮動小数点の命令をサポートしないので、Vの代わりにZve32xかZve64xとZvl*bを書く。
+// RV32ではベクトル拡張をサポートしない(mstatus.VSを0に固定している)ので書かない。
+fn isa_extensions(misa: u64, xlen: Xlen, vector: VectorConfig) -> (String, Vec<String>) {
+    let mut extensions: Vec<String> = ISA_EXTENSION_ORDER
+        .chars()
+        .filter(|c| misa & (1 << (*c as u8 - b'a')) != 0)
+        .map(|c| c.to_string())
+        .collect();
+
+    // Zで始まる拡張はカテゴリの文字の順に並べるので、Zvの拡張はZbの拡張の後でSの拡張の前に入れる。
+    let (z_extensions, s_extensions): (Vec<&str>, Vec<&str>) = ISA_MULTI_LETTER_EXTENSIONS
+        .iter()
+        .partition(|ext| ext.starts_with('z'));
+
+    extensions.extend(z_extensions.iter().map(|ext| ext.to_string()));
+
+    if xlen == Xlen::X64 {
+        extensions.push(format!("zve{}x", vector.elen));
+        extensions.push(format!("zvl{}b", vector.vlen));
+    }
+
+    extensions.extend(s_extensions.iter().map(|ext| ext.to_string()));
+
+    (format!("rv{}i", xlen.bits()), extensions)
+}
+
+impl Emulator {
+    // riscv,isaに書くISAの文字列を返す関数
+    // misaで有効になっている拡張とベクトル拡張の構成から作る。(例: rv64imafdc_zicond_zicsr_zifencei_zihintntl_zihintpause_zba_zbb_zbc_zbs_zve64x_zvl128b_sstc)
+    pub fn isa_string(&self) -> String {
+        let (base, extensions) =
+            isa_extensions(self.read_raw_csr(CSR_MISA).unwrap(), self.xlen, self.vector);
+        let mut isa = base.trim_end_matches('i').to_string();
+
+        for ext in extensions {
+            if ext.len() > 1 {
+                isa.push('_');
+            }
+
+            isa.push_str(&ext);
+        }
+
+        isa
+    }
+
+    // エミュレータの構成を表すデバイスツリー(DTB)を生成する関数
+    // RAM、ハート、CLINT、PLIC、システムコントローラ、接続したデバイスのノードを含む。
+    // initrdは(開始アドレス, 終了アドレス)で指定する。
+    pub fn device_tree(&self, bootargs: &str, initrd: Option<(u64, u64)>) -> Vec<u8> {
+        let mut fdt = Fdt::new();
+
+        fdt.begin_node("");
+        fdt.property_u32("#address-cells", 2);
+        fdt.property_u32("#size-cells", 2);
+        fdt.property_string("compatible", "riscv-virtio");
+        fdt.property_string("model", "tiny-riscv-emulator");
+
+        self.chosen_node(&mut fdt, bootargs, initrd);
+
+        fdt.begin_node(&format!("memory@{:x}", DRAM_BASE));
+        fdt.property_string("device_type", "memory");
+        fdt.property_cells("reg", &reg(DRAM_BASE, self.bus.dram.size() as u64));
+        fdt.end_node();
+
+        self.cpus_node(&mut fdt);
+
+        // 電源オフと再起動はシステムコントローラへの書き込みで行う。
+        for (name, compatible, value) in [
+            ("poweroff", "syscon-poweroff", SYSCON_PASS),
+            ("reboot", "syscon-reboot", SYSCON_RESET),
+        ] {
+            fdt.begin_node(name);
+            fdt.property_string("compatible", compatible);
+            fdt.property_u32("regmap", PHANDLE_SYSCON);
+            fdt.property_u32("offset", 0);
+            fdt.property_u32("value", value);
+            fdt.end_node();
+        }
+
+        self.soc_node(&mut fdt);
+
+        fdt.end_node();
+
+        fdt.finish()
+    }
+
+    // device_treeで生成したDTBをファイルに書き出す関数
+    // dtc -I dtb -O dts <path>で内容を確認できる。
+    pub fn dump_device_tree<P: AsRef<Path>>(
+        &self,
+        path: P,
+        bootargs: &str,
+        initrd: Option<(u64, u64)>,
+    ) -> io::Result<()> {
+        fs::write(path, self.device_tree(bootargs, initrd))
+    }
+
+    fn chosen_node(&self, fdt: &mut Fdt, bootargs: &str, initrd: Option<(u64, u64)>) {
+        fdt.begin_node("chosen");
+        fdt.property_string("bootargs", bootargs);
+
+        // コンソールは最初に接続したUART
+        if let Some((base, _, _, _)) = self
+            .bus
+            .device_tree_nodes()
+            .find(|(_, _, _, node)| node.name == "serial")
+        {
+            fdt.property_string("stdout-path", &format!("/soc/serial@{:x}", base));
+        }
+
+        if let Some((start, end)) = initrd {
+            fdt.property_u64("linux,initrd-start", start);
+            fdt.property_u64("linux,initrd-end", end);
+        }
+
+        fdt.end_node();
+    }
+
+    fn cpus_node(&self, fdt: &mut Fdt) {
+        let (isa_base, isa_extensions) =
+            isa_extensions(self.read_raw_csr(CSR_MISA).unwrap(), self.xlen, self.vector);
+        let isa_extensions: Vec<&str> = isa_extensions.iter().map(String::as_str).collect();
+
+        // satpに書き込めるもっとも大きいモード
+        let mmu_type = [
+            PagingMode::Sv57,
+            PagingMode::Sv48,
+            PagingMode::Sv39,
+            PagingMode::Sv32,
+        ]
+        .into_iter()
+        .find(|&mode| mode.is_valid_for(self.xlen) && self.paging_modes.contains(mode))
+        .map(|mode| match mode {
+            PagingMode::Sv57 => "riscv,sv57",
+            PagingMode::Sv48 => "riscv,sv48",
+            PagingMode::Sv39 => "riscv,sv39",
+            _ => "riscv,sv32",
+        });
+
+        fdt.begin_node("cpus");
+        fdt.property_u32("#address-cells", 1);
+        fdt.property_u32("#size-cells", 0);
+        fdt.property_u32("timebase-frequency", self.bus.clint.frequency() as u32);
+
+        for hart in 0..self.bus.clint.harts() as u32 {
+            fdt.begin_node(&format!("cpu@{:x}", hart));
+            fdt.property_string("device_type", "cpu");
+            fdt.property_u32("reg", hart);
+            fdt.property_string("status", "okay");
+            fdt.property_string("compatible", "riscv");
+            fdt.property_string("riscv,isa", &self.isa_string());
+            fdt.property_string("riscv,isa-base", &isa_base);
+            fdt.property_strings("riscv,isa-extensions", &isa_extensions);
+
+            if let Some(mmu_type) = mmu_type {
+                fdt.property_string("mmu-type", mmu_type);
+            }
+
+            fdt.begin_node("interrupt-controller");
+            fdt.property_u32("#interrupt-cells", 1);
+            fdt.property_null("interrupt-controller");
+            fdt.property_string("compatible", "riscv,cpu-intc");
+            fdt.property_u32("phandle", PHANDLE_CPU_INTC + hart);
+            fdt.end_node();
+
+            fdt.end_node();
+        }
+
+        fdt.end_node();
+    }
+
+    // 各ハートの割り込みコントローラへのinterrupts-extended
+    fn interrupts_extended(&self, irqs: &[u32]) -> Vec<u32> {
+        (0..self.bus.clint.harts() as u32)
+            .flat_map(|hart| {
+                irqs.iter()
+                    .flat_map(move |&irq| [PHANDLE_CPU_INTC + hart, irq])
+            })
+            .collect()
+    }
+
+    fn soc_node(&self, fdt: &mut Fdt) {
+        fdt.begin_node("soc");
+        fdt.property_u32("#address-cells", 2);
+        fdt.property_u32("#size-cells", 2);
+        fdt.property_string("compatible", "simple-bus");
+        fdt.property_null("ranges");
+
+        fdt.begin_node(&format!("test@{:x}", SYSCON_BASE));
+        fdt.property_strings("compatible", &["sifive,test1", "sifive,test0", "syscon"]);
+        fdt.property_cells("reg", &reg(SYSCON_BASE, SYSCON_SIZE));
+        fdt.property_u32("phandle", PHANDLE_SYSCON);
+        fdt.end_node();
+
+        fdt.begin_node(&format!("clint@{:x}", CLINT_BASE));
+        fdt.property_strings("compatible", &["sifive,clint0", "riscv,clint0"]);
+        fdt.property_cells("reg", &reg(CLINT_BASE, CLINT_SIZE));
+        fdt.property_cells(
+            "interrupts-extended",
+            &self.interrupts_extended(&[IRQ_M_SOFT, IRQ_M_TIMER]),
+        );
+        fdt.end_node();
+
+        // コンテキストの順番(Mモード、Sモード)に合わせる。
+        fdt.begin_node(&format!("plic@{:x}", PLIC_BASE));
+        fdt.property_strings("compatible", &["sifive,plic-1.0.0", "riscv,plic0"]);
+        fdt.property_cells("reg", &reg(PLIC_BASE, PLIC_SIZE));
+        fdt.property_u32("#address-cells", 0);
+        fdt.property_u32("#interrupt-cells", 1);
+        fdt.property_null("interrupt-controller");
+        fdt.property_u32("riscv,ndev", PLIC_SOURCES as u32 - 1);
+        fdt.property_cells(
+            "interrupts-extended",
+            &self.interrupts_extended(&[IRQ_M_EXT, IRQ_S_EXT]),
+        );
+        fdt.property_u32("phandle", PHANDLE_PLIC);
+        fdt.end_node();
+
+        for (base, size, irq, node) in self.bus.device_tree_nodes() {
+            fdt.begin_node(&format!("{}@{:x}", node.name, base));
+            fdt.property_strings("compatible", node.compatible);
+            fdt.property_cells("reg", &reg(base, size));
+
+            if let Some(irq) = irq {
+                fdt.property_u32("interrupts", irq);
+                fdt.property_u32("interrupt-parent", PHANDLE_PLIC);
+            }
+
+            for (name, value) in node.properties {
+                fdt.property_u32(name, value);
+            }
+
+            fdt.end_node();
+        }
+
+        fdt.end_node();
+    }
+}
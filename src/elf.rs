@@ -0,0 +1,282 @@
+use std::{error::Error, fmt};
+
+use crate::Xlen;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EM_RISCV: u16 = 243;
+
+const PT_LOAD: u32 = 1;
+
+const SHT_SYMTAB: u32 = 2;
+
+const ELF32_EHDR_SIZE: usize = 52;
+const ELF32_PHDR_SIZE: usize = 32;
+const ELF32_SHDR_SIZE: usize = 40;
+const ELF32_SYM_SIZE: usize = 16;
+
+const ELF64_EHDR_SIZE: usize = 64;
+const ELF64_PHDR_SIZE: usize = 56;
+const ELF64_SHDR_SIZE: usize = 64;
+const ELF64_SYM_SIZE: usize = 24;
+
+// ELFの読み込みに失敗したときのエラー
+#[derive(Debug)]
+pub enum ElfError {
+    Truncated,
+    InvalidMagic,
+    UnsupportedClass(u8),
+    UnsupportedEndian(u8),
+    UnsupportedMachine(u16),
+    InvalidSegment(u64),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfError::Truncated => write!(f, "The ELF file is truncated."),
+            ElfError::InvalidMagic => write!(f, "The file is not an ELF file."),
+            ElfError::UnsupportedClass(class) => {
+                write!(
+                    f,
+                    "The ELF class({}) is not supported. Only ELF32 and ELF64 are supported.",
+                    class
+                )
+            }
+            ElfError::UnsupportedEndian(data) => write!(
+                f,
+                "The ELF data encoding({}) is not supported. Only little endian is supported.",
+                data
+            ),
+            ElfError::UnsupportedMachine(machine) => write!(
+                f,
+                "The ELF machine({}) is not supported. Only RISC-V is supported.",
+                machine
+            ),
+            ElfError::InvalidSegment(address) => write!(
+                f,
+                "The segment at 0x{:016x} is out of the file or the memory.",
+                address
+            ),
+        }
+    }
+}
+
+impl Error for ElfError {}
+
+// PT_LOADのセグメントを表す構造体
+// dataはファイル上のデータでmem_sizeとの差分は0で埋める(.bss)。
+#[derive(Debug)]
+pub struct Segment<'a> {
+    pub paddr: u64,
+    pub data: &'a [u8],
+    pub mem_size: u64,
+}
+
+#[derive(Debug)]
+pub struct Elf<'a> {
+    bytes: &'a [u8],
+    xlen: Xlen,
+    entry: u64,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+    shoff: usize,
+    shentsize: usize,
+    shnum: usize,
+}
+
+fn read_bytes<const N: usize>(bytes: &[u8], offset: usize) -> Result<[u8; N], ElfError> {
+    offset
+        .checked_add(N)
+        .and_then(|end| bytes.get(offset..end))
+        .map(|b| b.try_into().unwrap())
+        .ok_or(ElfError::Truncated)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, ElfError> {
+    read_bytes::<2>(bytes, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ElfError> {
+    read_bytes::<4>(bytes, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ElfError> {
+    read_bytes::<8>(bytes, offset).map(u64::from_le_bytes)
+}
+
+// ELFのクラスに応じて32bitか64bitのアドレスやオフセットを読み込む関数
+fn read_word(bytes: &[u8], offset: usize, xlen: Xlen) -> Result<u64, ElfError> {
+    match xlen {
+        Xlen::X32 => read_u32(bytes, offset).map(u64::from),
+        Xlen::X64 => read_u64(bytes, offset),
+    }
+}
+
+// ELFファイルかどうかを先頭のマジックナンバーで判定する関数
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ELF_MAGIC)
+}
+
+impl<'a> Elf<'a> {
+    // ELFヘッダを解析する関数
+    // RISC-Vのリトルエンディアンの32bitか64bitのELF以外はエラーを返す。
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ElfError> {
+        if !is_elf(bytes) {
+            return Err(ElfError::InvalidMagic);
+        }
+
+        if bytes.len() < 6 {
+            return Err(ElfError::Truncated);
+        }
+
+        let (xlen, ehdr_size, phdr_size) = match bytes[4] {
+            ELFCLASS32 => (Xlen::X32, ELF32_EHDR_SIZE, ELF32_PHDR_SIZE),
+            ELFCLASS64 => (Xlen::X64, ELF64_EHDR_SIZE, ELF64_PHDR_SIZE),
+            class => return Err(ElfError::UnsupportedClass(class)),
+        };
+
+        if bytes[5] != ELFDATA2LSB {
+            return Err(ElfError::UnsupportedEndian(bytes[5]));
+        }
+
+        if bytes.len() < ehdr_size {
+            return Err(ElfError::Truncated);
+        }
+
+        let machine = read_u16(bytes, 18)?;
+        if machine != EM_RISCV {
+            return Err(ElfError::UnsupportedMachine(machine));
+        }
+
+        // e_entry以降のフィールドのオフセットはクラスによって異なる。
+        let (phoff, shoff, flags) = match xlen {
+            Xlen::X32 => (28, 32, 36),
+            Xlen::X64 => (32, 40, 48),
+        };
+
+        let phentsize = read_u16(bytes, flags + 6)? as usize;
+        if phentsize < phdr_size {
+            return Err(ElfError::Truncated);
+        }
+
+        Ok(Self {
+            bytes,
+            xlen,
+            entry: read_word(bytes, 24, xlen)?,
+            phoff: read_word(bytes, phoff, xlen)? as usize,
+            phentsize,
+            phnum: read_u16(bytes, flags + 8)? as usize,
+            shoff: read_word(bytes, shoff, xlen)? as usize,
+            shentsize: read_u16(bytes, flags + 10)? as usize,
+            shnum: read_u16(bytes, flags + 12)? as usize,
+        })
+    }
+
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    // ELFのクラスから決まるXLEN
+    pub fn xlen(&self) -> Xlen {
+        self.xlen
+    }
+
+    // PT_LOADのセグメントを取り出す関数
+    pub fn segments(&self) -> Result<Vec<Segment<'a>>, ElfError> {
+        let mut segments = Vec::new();
+
+        for i in 0..self.phnum {
+            let ph = self
+                .phoff
+                .checked_add(i * self.phentsize)
+                .ok_or(ElfError::Truncated)?;
+
+            if read_u32(self.bytes, ph)? != PT_LOAD {
+                continue;
+            }
+
+            // ELF32とELF64ではp_flagsの位置が異なる。
+            let (offset, paddr, file_size, mem_size) = match self.xlen {
+                Xlen::X32 => (4, 12, 16, 20),
+                Xlen::X64 => (8, 24, 32, 40),
+            };
+
+            let offset = read_word(self.bytes, ph + offset, self.xlen)? as usize;
+            let paddr = read_word(self.bytes, ph + paddr, self.xlen)?;
+            let file_size = read_word(self.bytes, ph + file_size, self.xlen)? as usize;
+            let mem_size = read_word(self.bytes, ph + mem_size, self.xlen)?;
+
+            if file_size as u64 > mem_size {
+                return Err(ElfError::InvalidSegment(paddr));
+            }
+
+            let data = offset
+                .checked_add(file_size)
+                .and_then(|end| self.bytes.get(offset..end))
+                .ok_or(ElfError::InvalidSegment(paddr))?;
+
+            segments.push(Segment {
+                paddr,
+                data,
+                mem_size,
+            });
+        }
+
+        Ok(segments)
+    }
+
+    // シンボルテーブルからシンボルのアドレスを探す関数
+    // シンボルテーブルがない場合やシンボルが見つからない場合はNoneを返す。
+    pub fn symbol(&self, name: &str) -> Option<u64> {
+        let (shdr_size, sym_size, sym_value) = match self.xlen {
+            Xlen::X32 => (ELF32_SHDR_SIZE, ELF32_SYM_SIZE, 4),
+            Xlen::X64 => (ELF64_SHDR_SIZE, ELF64_SYM_SIZE, 8),
+        };
+
+        if self.shentsize < shdr_size {
+            return None;
+        }
+
+        // sh_offset、sh_size、sh_linkのオフセット
+        let (sh_offset, sh_size, sh_link) = match self.xlen {
+            Xlen::X32 => (16, 20, 24),
+            Xlen::X64 => (24, 32, 40),
+        };
+        let read_word = |offset| read_word(self.bytes, offset, self.xlen).ok();
+
+        let section = |index: usize| self.shoff.checked_add(index * self.shentsize);
+
+        for i in 0..self.shnum {
+            let sh = section(i)?;
+
+            if read_u32(self.bytes, sh + 4).ok()? != SHT_SYMTAB {
+                continue;
+            }
+
+            let offset = read_word(sh + sh_offset)? as usize;
+            let size = read_word(sh + sh_size)? as usize;
+            let link = read_u32(self.bytes, sh + sh_link).ok()? as usize;
+
+            // sh_linkが文字列テーブルのセクションを指している。
+            let strtab = read_word(section(link)? + sh_offset)? as usize;
+
+            for j in 0..size / sym_size {
+                let sym = offset + j * sym_size;
+                let name_offset = strtab + read_u32(self.bytes, sym).ok()? as usize;
+
+                let symbol_name = self.bytes.get(name_offset..)?;
+                let end = symbol_name.iter().position(|&c| c == 0)?;
+
+                if &symbol_name[..end] == name.as_bytes() {
+                    return read_word(sym + sym_value);
+                }
+            }
+        }
+
+        None
+    }
+}
@@ -1,28 +1,33 @@
-use std::{error::Error, path::Path};
+use std::{error::Error, fs, path::Path};
 
 use crate::{
+    bus::{Bus, Device, DRAM_BASE},
+    clint::Clint,
     cpu::{Inst, InstClass, InstIsa},
     csr::{
-        Csr, CSR_MCAUSE, CSR_MEDELEG, CSR_MEPC, CSR_MIDELEG, CSR_MIE, CSR_MIP, CSR_MISA,
-        CSR_MSTATUS, CSR_MSTATUS_MIE_MASK, CSR_MSTATUS_MPIE_MASK, CSR_MSTATUS_MPP_MASK,
+        Csr, CSR_MCAUSE, CSR_MEDELEG, CSR_MEPC, CSR_MIDELEG, CSR_MISA, CSR_MSTATUS,
+        CSR_MSTATUS_MIE_MASK, CSR_MSTATUS_MPIE_MASK, CSR_MSTATUS_MPP_MASK, CSR_MSTATUS_MPRV_MASK,
         CSR_MSTATUS_SIE_MASK, CSR_MSTATUS_SPIE_MASK, CSR_MSTATUS_SPP_MASK, CSR_MSTATUS_TSR_MASK,
-        CSR_MSTATUS_TW_MASK, CSR_MTVAL, CSR_MTVEC, CSR_SCAUSE, CSR_SEPC, CSR_SSTATUS,
-        CSR_SSTATUS_MASK, CSR_STVAL, CSR_STVEC,
+        CSR_MSTATUS_TVM_MASK, CSR_MSTATUS_TW_MASK, CSR_MTVAL, CSR_MTVEC, CSR_SCAUSE, CSR_SEPC,
+        CSR_STVAL, CSR_STVEC,
     },
+    elf::{self, Elf, ElfError},
     exception::Exception::{self, *},
+    hart::{Hart, Scheduler},
     memory::Memory,
+    mmu::{AccessType, PagingMode, PagingModes, PAGE_SIZE},
     register::Register,
-    Priv, Result,
+    syscon::Syscon,
+    tlb::{Tlb, TlbConfig, TlbStats},
+    vector::VectorConfig,
+    Priv, Result, Xlen,
 };
 
-// 現在は1M byte
-const MEMORY_SIZE: usize = 1024 * 1024;
-
 // 符号拡張する関数
 // bitで符号に相当するビットを指定する。０インデックスである。
 // bitを64より大きい値を指定するとオーバーフローする。
 // 指定したbit以上の値を与えてはいけない。
-fn sign_extend(bit: u8, v: u64) -> u64 {
+pub(crate) fn sign_extend(bit: u8, v: u64) -> u64 {
     let mask = (u64::MAX >> 1) ^ (2u64.pow(bit as u32) - 1);
 
     (mask + v) ^ mask
@@ -34,7 +39,7 @@ fn sign_extend_128bit(bit: u8, v: u128) -> u128 {
     (mask + v) ^ mask
 }
 
-fn extract_r_type(instruction: u32) -> (u8, u8, u8, u8) {
+pub(crate) fn extract_r_type(instruction: u32) -> (u8, u8, u8, u8) {
     let rd = (instruction >> 7) & 0x1f;
     let rs1 = (instruction >> 15) & 0x1f;
     let rs2 = (instruction >> 20) & 0x1f;
@@ -43,7 +48,7 @@ fn extract_r_type(instruction: u32) -> (u8, u8, u8, u8) {
     (rd as u8, rs1 as u8, rs2 as u8, funct7 as u8)
 }
 
-fn extract_i_type(instruction: u32) -> (u8, u8, u64) {
+pub(crate) fn extract_i_type(instruction: u32) -> (u8, u8, u64) {
     let rd = (instruction >> 7) & 0x1f;
     let rs1 = (instruction >> 15) & 0x1f;
     let imm = (instruction >> 20) as u64;
@@ -51,7 +56,7 @@ fn extract_i_type(instruction: u32) -> (u8, u8, u64) {
     (rd as u8, rs1 as u8, imm)
 }
 
-fn extract_s_type(instruction: u32) -> (u8, u8, u64) {
+pub(crate) fn extract_s_type(instruction: u32) -> (u8, u8, u64) {
     let rs1 = (instruction >> 15) & 0x1f;
     let rs2 = (instruction >> 20) & 0x1f;
     let imm = ((instruction & 0xfe000000) >> 20) | ((instruction & 0xf80) >> 7);
@@ -121,10 +126,11 @@ fn extract_ciw_type(instruction: u16) -> (u8, u64) {
 
 // CL: (rd, rs1, imm)
 // CS: (rs2, rs1, imm)
+// immはinst[12:10|6:5]の順に並べた値
 fn extract_clcs_type(instruction: u16) -> (u8, u8, u64) {
     let rd = convert_from_c_reg_to_i((instruction >> 2) & 0x7);
     let rs1 = convert_from_c_reg_to_i((instruction >> 7) & 0x7);
-    let imm = (instruction >> 5) & 0x3;
+    let imm = ((instruction >> 8) & 0x1c) | ((instruction >> 5) & 0x3);
 
     (rd, rs1, imm as u64)
 }
@@ -151,61 +157,232 @@ fn calc_c_offset_5_3_7_6(imm: u64) -> u64 {
     ((imm << 6) & 0xc0) | ((imm << 1) & 0x38)
 }
 
+// c.ldspとc.sdspのオフセット(offset[5:3|8:6]の順に並んでいる)
+fn calc_c_offset_5_3_8_6(imm: u64) -> u64 {
+    ((imm << 6) & 0x1c0) | (imm & 0x38)
+}
+
 #[derive(Default)]
 pub struct Emulator {
-    pub(crate) memory: Memory<MEMORY_SIZE>,
+    pub(crate) bus: Bus,
     pub(crate) regs: [u64; 31],
+    pub(crate) fregs: [u64; 32], // 浮動小数点レジスタ(単精度の値はNaN-boxingして格納する)
+    pub(crate) vregs: Vec<u8>, // ベクトルレジスタ(v0からv31をリトルエンディアンで連続して格納する)
     pub(crate) csr: Csr,
     pub(crate) pc: u64,
+    pub(crate) xlen: Xlen, // すべてのハートで共通のXLEN
     pub(crate) current_priv: Priv,
     pub(crate) inst: Inst,
-    pub(crate) reserved_memory_ranges: Vec<(usize, usize)>, // 予約されたメモリ領域を指定する。(begin, end)
+    pub(crate) paging_modes: PagingModes, // satpに書き込めるページングのモード
+    pub(crate) tlb: Tlb,
+    pub(crate) vector: VectorConfig, // ベクトル拡張の構成(VLENとELEN)
+    pub(crate) harts: Vec<Hart>,     // 実行中ではないハートの状態
+    pub(crate) scheduler: Scheduler,
+
+    pub(crate) trace: bool, // 実行した命令や例外のログを出力するかどうか
 
     pub(crate) riscv_tests_finished: bool, // riscv-testsが終了したかどうかを表すフラグ
-    pub(crate) riscv_tests_exit_memory_address: usize, // riscv-testsが終了するメモリアドレス
+    pub(crate) riscv_tests_exit_memory_address: Option<usize>, // riscv-testsが終了するメモリアドレス
+
+    pub(crate) tohost_address: Option<usize>, // ELFのtohostシンボルのアドレス
+    pub(crate) fromhost_address: Option<usize>, // ELFのfromhostシンボルのアドレス
+    pub(crate) exit_code: Option<u64>,        // HTIFかシステムコントローラで通知された終了コード
+
+    pub(crate) builtin_sbi: bool, // Sモードからのecallを組み込みのSBIで処理するかどうか
 }
 
 impl Emulator {
     // プログラムをロードする関数
-    // 将来的にはロードする位置を指定できるようにしたい。
+    // ELFファイルの場合は各セグメントを物理アドレスに配置し、pcをエントリポイントに設定する。
+    // それ以外の場合はフラットなバイナリとしてRAMの先頭(DRAM_BASE)からロードする。
+    // ハートが複数ある場合はすべてのハートが同じアドレスからMモードで実行を始める。
+    // ELFファイルの場合はELFのクラス(ELF32かELF64)に合わせてXLENを切り替える。
     // 遅延ロードとかもやってみたい。
     pub fn load<P: AsRef<Path>>(
         &mut self,
         filename: P,
     ) -> core::result::Result<(), Box<dyn Error>> {
-        self.initialize_regs();
-        self.initialize_csr();
+        let bytes = fs::read(&filename)?;
+
+        if elf::is_elf(&bytes) {
+            self.xlen = Elf::parse(&bytes)?.xlen();
+        }
+
+        self.reset_harts();
 
         self.riscv_tests_finished = false;
+        self.exit_code = None;
+        self.tohost_address = None;
+        self.fromhost_address = None;
+        self.bus.syscon = Syscon::default();
+        self.builtin_sbi = false;
+
+        if elf::is_elf(&bytes) {
+            self.load_elf(&bytes)?;
+        } else {
+            self.bus.dram.load(filename)?;
+            self.pc = DRAM_BASE;
+        }
+
+        let entry = self.pc;
 
-        self.memory.load(filename)?;
+        for hart in 1..self.harts() {
+            self.switch_hart(hart);
+            self.pc = entry;
+        }
+
+        self.switch_hart(0);
 
         Ok(())
     }
 
-    fn initialize_regs(&mut self) {
+    // ELFファイルをロードする関数
+    // PT_LOADのセグメントのみをメモリに配置する。
+    // tohost/fromhostシンボルがある場合はHTIFで終了を検出するので終了アドレスの指定は不要になる。
+    pub(crate) fn load_elf(&mut self, bytes: &[u8]) -> core::result::Result<(), ElfError> {
+        let elf = Elf::parse(bytes)?;
+
+        self.bus.dram.initialize();
+
+        for segment in elf.segments()? {
+            if !self
+                .bus
+                .load_segment(segment.paddr, segment.data, segment.mem_size as usize)
+            {
+                return Err(ElfError::InvalidSegment(segment.paddr));
+            }
+        }
+
+        self.pc = elf.entry();
+
+        self.tohost_address = elf.symbol("tohost").map(|address| address as usize);
+        self.fromhost_address = elf.symbol("fromhost").map(|address| address as usize);
+
+        if self.tohost_address.is_some() {
+            self.riscv_tests_exit_memory_address = None;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn initialize_regs(&mut self) {
         self.regs = [0; 31];
+        self.fregs = [0; 32];
+        self.vregs.fill(0);
         self.pc = 0;
     }
 
+    // 仮想アドレスの範囲を物理アドレスに変換する関数
+    // アクセスがページをまたぐ場合は二つ目のページの物理アドレスも返す。
+    // どちらかのページでページフォルトが起こる場合はメモリにアクセスする前にErrを返す。
+    fn translate_range(
+        &mut self,
+        address: usize,
+        size: usize,
+        access: AccessType,
+    ) -> Result<(usize, Option<usize>)> {
+        let paddr = self.translate(address as u64, access)? as usize;
+        let first = PAGE_SIZE as usize - (address % PAGE_SIZE as usize);
+
+        if size <= first {
+            self.check_pmp(address as u64, paddr as u64, size as u64, access)?;
+
+            return Ok((paddr, None));
+        }
+
+        let second_address = address.wrapping_add(first);
+        let second = self.translate(second_address as u64, access)? as usize;
+
+        self.check_pmp(address as u64, paddr as u64, first as u64, access)?;
+        self.check_pmp(
+            second_address as u64,
+            second as u64,
+            (size - first) as u64,
+            access,
+        )?;
+
+        Ok((paddr, Some(second)))
+    }
+
     // メモリを読み込むときに使用する関数
-    fn read_memory<const SIZE: usize>(&self, address: usize) -> Result<[u8; SIZE]> {
-        Ok(self.memory.read::<SIZE>(address))
+    pub(crate) fn read_memory<const SIZE: usize>(&mut self, address: usize) -> Result<[u8; SIZE]> {
+        let mut bytes = [0; SIZE];
+
+        match self.translate_range(address, SIZE, AccessType::Load)? {
+            (paddr, None) => self.read_physical_memory(address, paddr, &mut bytes)?,
+            (paddr, Some(second)) => {
+                let first = PAGE_SIZE as usize - (address % PAGE_SIZE as usize);
+
+                self.read_physical_memory(address, paddr, &mut bytes[..first])?;
+                self.read_physical_memory(
+                    address.wrapping_add(first),
+                    second,
+                    &mut bytes[first..],
+                )?;
+            }
+        }
+
+        Ok(bytes)
     }
 
     // メモリを書き込むときに使用する関数
-    fn write_memory(&mut self, address: usize, values: &[u8]) -> Result<()> {
-        if address == self.riscv_tests_exit_memory_address {
+    pub(crate) fn write_memory(&mut self, address: usize, values: &[u8]) -> Result<()> {
+        match self.translate_range(address, values.len(), AccessType::Store)? {
+            (paddr, None) => self.write_physical_memory(address, paddr, values)?,
+            (paddr, Some(second)) => {
+                let first = PAGE_SIZE as usize - (address % PAGE_SIZE as usize);
+
+                self.write_physical_memory(address, paddr, &values[..first])?;
+                self.write_physical_memory(address.wrapping_add(first), second, &values[first..])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // 物理アドレスでメモリを読み込む関数
+    // バスに割り当てられていないアドレスの場合はvaddrをxtvalとするアクセスフォルトを起こす。
+    fn read_physical_memory(
+        &mut self,
+        vaddr: usize,
+        address: usize,
+        bytes: &mut [u8],
+    ) -> Result<()> {
+        if self.bus.read(address as u64, bytes) {
+            Ok(())
+        } else {
+            Err(LoadAccessFault(vaddr as u64))
+        }
+    }
+
+    // 物理アドレスでメモリを書き込む関数
+    fn write_physical_memory(&mut self, vaddr: usize, address: usize, values: &[u8]) -> Result<()> {
+        if Some(address) == self.riscv_tests_exit_memory_address {
             self.riscv_tests_finished = true;
         }
 
-        self.memory.write(address, values);
+        if !self.bus.write(address as u64, values) {
+            return Err(StoreAccessFault(vaddr as u64));
+        }
+
+        if let Some(tohost) = self.tohost_address {
+            if address < tohost + 8 && address + values.len() > tohost {
+                self.handle_htif(tohost);
+            }
+        }
+
+        // システムコントローラで電源オフが要求された場合は終了する。
+        if let Some(code) = self.bus.syscon.exit_code() {
+            self.exit_code = Some(code);
+            self.riscv_tests_finished = true;
+        }
 
         Ok(())
     }
 
     // レジスタを読み込むときに使用する関数
-    fn read_reg(&self, reg: Register) -> u64 {
+    // XLENが32の場合は整数レジスタの上位32bitを無視し、下位32bitを符号拡張した値を返す。
+    pub(crate) fn read_reg(&self, reg: Register) -> u64 {
         use crate::register::Register::*;
 
         match reg {
@@ -214,7 +391,17 @@ impl Emulator {
                 if i > 31 {
                     panic!("Error: Unknown register x{}.", i);
                 } else {
-                    self.regs[i as usize - 1]
+                    match self.current_xlen() {
+                        Xlen::X32 => sign_extend(31, self.regs[i as usize - 1] & 0xffffffff),
+                        Xlen::X64 => self.regs[i as usize - 1],
+                    }
+                }
+            }
+            F(i) => {
+                if i > 31 {
+                    panic!("Error: Unknown register f{}.", i);
+                } else {
+                    self.fregs[i as usize]
                 }
             }
             Pc => self.pc,
@@ -222,7 +409,9 @@ impl Emulator {
     }
 
     // レジスタを書き込むときに使用する関数
-    fn write_reg(&mut self, reg: Register, value: u64) {
+    // 浮動小数点レジスタに書き込んだ場合はmstatus.FSをDirtyにする。
+    // XLENが32の場合は整数レジスタとpcに下位32bitを符号拡張した値を書き込む。
+    pub(crate) fn write_reg(&mut self, reg: Register, value: u64) {
         use crate::register::Register::*;
 
         match reg {
@@ -231,18 +420,39 @@ impl Emulator {
                 if i > 31 {
                     panic!("Error: Unknown register x{}.", i);
                 } else {
-                    self.regs[i as usize - 1] = value;
+                    self.regs[i as usize - 1] = match self.current_xlen() {
+                        Xlen::X32 => sign_extend(31, value & 0xffffffff),
+                        Xlen::X64 => value,
+                    };
+                }
+            }
+            F(i) => {
+                if i > 31 {
+                    panic!("Error: Unknown register f{}.", i);
+                } else {
+                    self.fregs[i as usize] = value;
+                    self.csr.set_fp_dirty();
+                }
+            }
+            Pc => {
+                self.pc = match self.current_xlen() {
+                    Xlen::X32 => sign_extend(31, value & 0xffffffff),
+                    Xlen::X64 => value,
                 }
             }
-            Pc => self.pc = value,
         }
     }
 
+    // シフト量として使うrs2やimmの下位のビットのマスク
+    fn shamt_mask(&self) -> u64 {
+        self.current_xlen().bits() as u64 - 1
+    }
+
     pub(crate) fn check_misaligned_nbyte_misaligned(&self, address: u64, n: u64) -> Result<()> {
         if address % n == 0 {
             Ok(())
         } else {
-            Err(InstructionAddressMissaligned)
+            Err(InstructionAddressMissaligned(address))
         }
     }
 
@@ -256,27 +466,33 @@ impl Emulator {
         }
     }
 
-    // 予約されたメモリ領域を追加する関数
-    // LR.D/Wで使用
-    // range: (begin, end)
-    // 同じ範囲が与えられたらそれを削除してpushする。
-    // 一部が被る場合は前に保存していた領域を削除する。
-    fn push_reserved_memory_range(&mut self, range: (usize, usize)) {
-        self.reserved_memory_ranges
-            .retain(|r| range.1 < r.0 || range.0 > r.1);
-        self.reserved_memory_ranges.push(range);
-    }
+    // 命令を取り出す関数
+    // run以外から呼んではいけない。
+    // 16bitずつ取り出すことでC拡張の命令がページの終端にある場合に次のページにアクセスしないようにする。
+    fn fetch(&mut self) -> Result<u32> {
+        let paddr = self.translate(self.pc, AccessType::Instruction)?;
+        self.check_pmp(self.pc, paddr, 2, AccessType::Instruction)?;
+        let low = self.fetch_half(self.pc, paddr)? as u32;
+
+        if low & 0x3 != 0x3 {
+            return Ok(low);
+        }
 
-    // 予約されたメモリ領域を一つ取り出す関数
-    // SC.D/Wで使用
-    fn pop_reserved_memory_range(&mut self) -> Option<(usize, usize)> {
-        self.reserved_memory_ranges.pop()
+        let paddr = self.translate(self.pc.wrapping_add(2), AccessType::Instruction)?;
+        self.check_pmp(self.pc.wrapping_add(2), paddr, 2, AccessType::Instruction)?;
+        let high = self.fetch_half(self.pc.wrapping_add(2), paddr)? as u32;
+
+        Ok((high << 16) | low)
     }
 
-    // 命令を取り出す関数
-    // run以外から呼んではいけない。
-    fn fetch(&mut self) -> u32 {
-        u32::from_le_bytes(self.memory.read::<4>(self.pc as usize))
+    fn fetch_half(&mut self, vaddr: u64, paddr: u64) -> Result<u16> {
+        let mut bytes = [0; 2];
+
+        if self.bus.read(paddr, &mut bytes) {
+            Ok(u16::from_le_bytes(bytes))
+        } else {
+            Err(InstructionAccessFault(vaddr))
+        }
     }
 
     fn can_exec(&self) -> bool {
@@ -294,6 +510,24 @@ impl Emulator {
             return Err(IllegralInstruction);
         }
 
+        // F拡張とD拡張の命令はfloat.rsで実行する。
+        if matches!(self.inst.isa(), InstIsa::F | InstIsa::D) {
+            return self.exec_float();
+        }
+
+        // ビット操作の拡張の命令はbitmanip.rsで実行する。
+        if matches!(
+            self.inst.isa(),
+            InstIsa::Zba | InstIsa::Zbb | InstIsa::Zbc | InstIsa::Zbs
+        ) {
+            return self.exec_bitmanip();
+        }
+
+        // V拡張の命令はvector.rsで実行する。
+        if *self.inst.isa() == InstIsa::V {
+            return self.exec_vector();
+        }
+
         use crate::cpu::InstFormat::*;
 
         let name = self.inst.name();
@@ -444,7 +678,8 @@ impl Emulator {
                     ),
                     "srli" => self.write_reg(
                         Register::X(rd),
-                        self.read_reg(Register::X(rs1)) >> (imm & 0x3f),
+                        (self.read_reg(Register::X(rs1)) & self.current_xlen().mask())
+                            >> (imm & 0x3f),
                     ),
                     "srai" => self.write_reg(
                         Register::X(rd),
@@ -599,7 +834,8 @@ impl Emulator {
                     ),
                     "sll" => self.write_reg(
                         Register::X(rd),
-                        self.read_reg(Register::X(rs1)) << (self.read_reg(Register::X(rs2)) & 0x3f),
+                        self.read_reg(Register::X(rs1))
+                            << (self.read_reg(Register::X(rs2)) & self.shamt_mask()),
                     ),
                     "mulh" => {
                         let rs1 = sign_extend_128bit(63, self.read_reg(Register::X(rs1)) as u128);
@@ -607,7 +843,7 @@ impl Emulator {
 
                         self.write_reg(
                             Register::X(rd),
-                            (((rs1 as i128) * (rs2 as i128)) >> 64) as u64,
+                            (((rs1 as i128) * (rs2 as i128)) >> self.current_xlen().bits()) as u64,
                         );
                     }
                     "slt" => self.write_reg(
@@ -622,9 +858,13 @@ impl Emulator {
                     ),
                     "mulhsu" => {
                         let rs1 = sign_extend_128bit(63, self.read_reg(Register::X(rs1)) as u128);
-                        let rs2 = self.read_reg(Register::X(rs2)) as u128;
+                        let rs2 =
+                            (self.read_reg(Register::X(rs2)) & self.current_xlen().mask()) as u128;
 
-                        self.write_reg(Register::X(rd), (rs1.wrapping_mul(rs2) >> 64) as u64);
+                        self.write_reg(
+                            Register::X(rd),
+                            (rs1.wrapping_mul(rs2) >> self.current_xlen().bits()) as u64,
+                        );
                     }
                     "sltu" => self.write_reg(
                         Register::X(rd),
@@ -635,10 +875,15 @@ impl Emulator {
                         },
                     ),
                     "mulhu" => {
-                        let rs1 = self.read_reg(Register::X(rs1)) as u128;
-                        let rs2 = self.read_reg(Register::X(rs2)) as u128;
+                        let rs1 =
+                            (self.read_reg(Register::X(rs1)) & self.current_xlen().mask()) as u128;
+                        let rs2 =
+                            (self.read_reg(Register::X(rs2)) & self.current_xlen().mask()) as u128;
 
-                        self.write_reg(Register::X(rd), (rs1.wrapping_mul(rs2) >> 64) as u64);
+                        self.write_reg(
+                            Register::X(rd),
+                            (rs1.wrapping_mul(rs2) >> self.current_xlen().bits()) as u64,
+                        );
                     }
                     "xor" => self.write_reg(
                         Register::X(rd),
@@ -666,7 +911,7 @@ impl Emulator {
                         );
                     }
                     "sra" => {
-                        let shift = self.read_reg(Register::X(rs2)) & 0x3f;
+                        let shift = self.read_reg(Register::X(rs2)) & self.shamt_mask();
 
                         self.write_reg(
                             Register::X(rd),
@@ -693,13 +938,16 @@ impl Emulator {
                         )
                     }
                     "srl" => {
-                        let shift = self.read_reg(Register::X(rs2)) & 0x3f;
+                        let shift = self.read_reg(Register::X(rs2)) & self.shamt_mask();
 
-                        self.write_reg(Register::X(rd), self.read_reg(Register::X(rs1)) >> shift);
+                        self.write_reg(
+                            Register::X(rd),
+                            (self.read_reg(Register::X(rs1)) & self.current_xlen().mask()) >> shift,
+                        );
                     }
                     "divu" => {
-                        let rs1 = self.read_reg(Register::X(rs1));
-                        let rs2 = self.read_reg(Register::X(rs2));
+                        let rs1 = self.read_reg(Register::X(rs1)) & self.current_xlen().mask();
+                        let rs2 = self.read_reg(Register::X(rs2)) & self.current_xlen().mask();
 
                         self.write_reg(
                             Register::X(rd),
@@ -711,11 +959,27 @@ impl Emulator {
                         self.read_reg(Register::X(rs1)) & self.read_reg(Register::X(rs2)),
                     ),
                     "remu" => {
-                        let rs1 = self.read_reg(Register::X(rs1));
-                        let rs2 = self.read_reg(Register::X(rs2));
+                        let rs1 = self.read_reg(Register::X(rs1)) & self.current_xlen().mask();
+                        let rs2 = self.read_reg(Register::X(rs2)) & self.current_xlen().mask();
 
                         self.write_reg(Register::X(rd), if rs2 == 0 { rs1 } else { rs1 % rs2 });
                     }
+                    "czero_eqz" => self.write_reg(
+                        Register::X(rd),
+                        if self.read_reg(Register::X(rs2)) == 0 {
+                            0
+                        } else {
+                            self.read_reg(Register::X(rs1))
+                        },
+                    ),
+                    "czero_nez" => self.write_reg(
+                        Register::X(rd),
+                        if self.read_reg(Register::X(rs2)) != 0 {
+                            0
+                        } else {
+                            self.read_reg(Register::X(rs1))
+                        },
+                    ),
                     "addw" => self.write_reg(
                         Register::X(rd),
                         sign_extend(
@@ -824,56 +1088,70 @@ impl Emulator {
                         );
                     }
                     "sfence_vma" => {
-                        if self.current_priv != Priv::S {
-                            panic!("Error: executing sfence.vma in S mode is only supported.");
-                        }
+                        let tvm = self.read_raw_csr(CSR_MSTATUS).unwrap() & CSR_MSTATUS_TVM_MASK;
 
-                        // よくわからないがriscv-testsでは
-                        // mstatus.TVM == 0 && current_priv == S_MODE
-                        // でも例外が発生するらしい。
-                        // ここはissueを投げるか検討
-
-                        return Err(IllegralInstruction);
+                        // Uモードの場合とSモードでmstatus.TVMが1の場合は不正命令
+                        if self.current_priv == Priv::U
+                            || (self.current_priv == Priv::S && tvm != 0)
+                        {
+                            return Err(IllegralInstruction);
+                        }
 
-                        //let tvm =
-                        //    self.read_raw_csr(CSR_MSTATUS).unwrap() & CSR_MSTATUS_TVM_MASK;
+                        // rs1がx0でない場合はそのアドレス、rs2がx0でない場合はそのASIDのみを無効化する。
+                        let vaddr = if rs1 != 0 {
+                            Some(self.read_reg(Register::X(rs1)) & self.current_xlen().mask())
+                        } else {
+                            None
+                        };
+                        let asid = if rs2 != 0 {
+                            Some(self.read_reg(Register::X(rs2)) as u16)
+                        } else {
+                            None
+                        };
 
-                        //if tvm != 0 {
-                        //    // tvmが設定されている場合はSモードで実行している場合は例外が発生する可能性があるらしい。
-                        //    panic!("Error: sfence.vma is not supported when mstatus.TVM equals 1.");
-                        //}
+                        self.tlb.flush(vaddr, asid);
                     }
                     name if *self.inst.isa() == InstIsa::A => {
                         let addr = self.read_reg(Register::X(rs1)) as usize;
 
+                        // AMOはミスアライメントの場合に例外を起こす。
+                        // LRはロード、AMOとSCはストアのミスアライメントとして扱う。
+                        let align = if name.ends_with("_w") { 4 } else { 8 };
+
+                        if !addr.is_multiple_of(align) {
+                            if name.starts_with("lr") {
+                                return Err(LoadAddressMisaligned(addr as u64));
+                            } else {
+                                return Err(StoreAddressMisaligned(addr as u64));
+                            }
+                        }
+
+                        // AMOとSCはストアとしてアドレス変換を行う。
+                        // 読み込みができないページの場合もストアのページフォルトを起こす。
+                        // LR/SCの予約は物理アドレスで管理する。
+                        let paddr = if name.starts_with("lr") {
+                            self.translate(addr as u64, AccessType::Load)?
+                        } else {
+                            self.translate(addr as u64, AccessType::Store)?
+                        };
+                        let hart = self.scheduler.current;
+
                         match name {
                             "amoswap_w" | "lr_w" | "sc_w" | "amoadd_w" | "amoand_w"
                             | "amoxor_w" | "amoor_w" | "amomin_w" | "amomax_w" | "amominu_w"
                             | "amomaxu_w" => {
-                                // 32bit版の場合は4バイトアライメント
-                                self.check_misaligned(addr as u64)?;
-
                                 if name == "sc_w" {
                                     // SC.W
+                                    // 予約が有効で予約の範囲内の場合のみ書き込みを行い、rdに0を書き込む。
+                                    // それ以外の場合はrdに1を書き込む。
+                                    if self.bus.reservations.take(hart, paddr, 4) {
+                                        self.write_memory(
+                                            addr,
+                                            &(self.read_reg(Register::X(rs2)) as u32).to_le_bytes(),
+                                        )?;
 
-                                    if let Some(range) = self.pop_reserved_memory_range() {
-                                        // 予約領域が存在している場合
-
-                                        if range.0 <= addr && range.1 >= addr + 4 {
-                                            // 予約領域内の場合はそのメモリ領域に書き込みを行い、rdに0を書き込む。
-                                            self.write_memory(
-                                                addr,
-                                                &(self.read_reg(Register::X(rs2)) as u32)
-                                                    .to_le_bytes(),
-                                            )?;
-
-                                            self.write_reg(Register::X(rd), 0);
-                                        } else {
-                                            // 上の条件に当てはまらない場合はrdに1を書き込むことにする。
-                                            self.write_reg(Register::X(rd), 1);
-                                        }
+                                        self.write_reg(Register::X(rd), 0);
                                     } else {
-                                        // ここで二回同じコードを書いているがif-let chainが使えるようになったら一つで済むようになる。
                                         self.write_reg(Register::X(rd), 1);
                                     }
                                 } else {
@@ -886,14 +1164,13 @@ impl Emulator {
                                                 &(self.read_reg(Register::X(rs2)) as u32)
                                                     .to_le_bytes(),
                                             )?;
-                                            self.write_reg(Register::X(rs2), v as u64);
                                         }
                                         "lr_w" => {
                                             self.write_reg(
                                                 Register::X(rd),
                                                 sign_extend(31, v as u64),
                                             );
-                                            self.push_reserved_memory_range((addr, addr + 4));
+                                            self.bus.reservations.reserve(hart, paddr, 4);
                                         }
                                         "amoadd_w" => self.write_memory(
                                             addr,
@@ -960,11 +1237,27 @@ impl Emulator {
                                     self.write_reg(Register::X(rd), sign_extend(31, v as u64));
                                 }
                             }
+                            "lr_d" => {
+                                let v = u64::from_le_bytes(self.read_memory::<8>(addr)?);
+
+                                self.write_reg(Register::X(rd), v);
+                                self.bus.reservations.reserve(hart, paddr, 8);
+                            }
+                            "sc_d" => {
+                                // 予約が有効で予約の範囲内の場合のみ書き込みを行い、rdに0を書き込む。
+                                if self.bus.reservations.take(hart, paddr, 8) {
+                                    self.write_memory(
+                                        addr,
+                                        &self.read_reg(Register::X(rs2)).to_le_bytes(),
+                                    )?;
+
+                                    self.write_reg(Register::X(rd), 0);
+                                } else {
+                                    self.write_reg(Register::X(rd), 1);
+                                }
+                            }
                             "amoswap_d" | "amoxor_d" | "amoadd_d" | "amoand_d" | "amoor_d"
                             | "amomin_d" | "amomax_d" | "amominu_d" | "amomaxu_d" => {
-                                // 64bit版の場合は8バイトアライメント
-                                self.check_misaligned_nbyte_misaligned(addr as u64, 8)?;
-
                                 let v = u64::from_le_bytes(self.read_memory::<8>(addr)?);
 
                                 match name {
@@ -973,7 +1266,6 @@ impl Emulator {
                                             addr,
                                             &self.read_reg(Register::X(rs2)).to_le_bytes(),
                                         )?;
-                                        self.write_reg(Register::X(rs2), v);
                                     }
                                     "amoxor_d" => self.write_memory(
                                         addr,
@@ -1148,7 +1440,11 @@ impl Emulator {
                 match name {
                     "c_srli" => {
                         if imm != 0 {
-                            self.write_reg(Register::X(rd), self.read_reg(Register::X(rd)) >> imm);
+                            self.write_reg(
+                                Register::X(rd),
+                                (self.read_reg(Register::X(rd)) & self.current_xlen().mask())
+                                    >> imm,
+                            );
                         } else {
                             // imm=0の場合はHINTsをエンコードするらしい。
                         }
@@ -1191,7 +1487,8 @@ impl Emulator {
                 }
             }
             Cj => match name {
-                "c_j" => {
+                // c.jalはRV32のみの命令で、c.addiwと同じエンコーディングになる。
+                "c_j" | "c_jal" => {
                     let imm = (self.inst.raw() >> 1) & 0xffe;
                     let offset = (imm & 0xb40)
                         | ((imm << 3) & 0x400)
@@ -1200,6 +1497,10 @@ impl Emulator {
                         | ((imm >> 6) & 0x10)
                         | ((imm >> 1) & 0xe);
 
+                    if name == "c_jal" {
+                        self.write_reg(Register::X(1), self.read_reg(Register::Pc).wrapping_add(2));
+                    }
+
                     self.write_reg(
                         Register::Pc,
                         self.read_reg(Register::Pc)
@@ -1304,13 +1605,38 @@ impl Emulator {
                             panic!("Error: Ths rd of {} is not zero.", name);
                         }
 
+                        let offset = calc_c_offset_5_3_8_6(imm);
+
+                        let bytes = self.read_memory::<8>(
+                            self.read_reg(Register::X(2)).wrapping_add(offset) as usize,
+                        )?;
+
+                        self.write_reg(Register::X(rd), u64::from_le_bytes(bytes));
+                    }
+                    "c_fldsp" => {
+                        self.check_fp_enabled()?;
+
+                        let offset = calc_c_offset_5_3_8_6(imm);
+
+                        let bytes = self.read_memory::<8>(
+                            self.read_reg(Register::X(2)).wrapping_add(offset) as usize,
+                        )?;
+
+                        self.write_reg(Register::F(rd), u64::from_le_bytes(bytes));
+                    }
+                    "c_flwsp" => {
+                        self.check_fp_enabled()?;
+
                         let offset = ((imm << 6) & 0xc0) | (imm & 0x3c);
 
+                        let bytes = self.read_memory::<4>(
+                            self.read_reg(Register::X(2)).wrapping_add(offset) as usize,
+                        )?;
+
+                        // 単精度の値はNaN-boxingして書き込む。
                         self.write_reg(
-                            Register::X(rd),
-                            u64::from_le_bytes(self.read_memory::<8>(
-                                self.read_reg(Register::X(2)).wrapping_add(offset) as usize,
-                            )?),
+                            Register::F(rd),
+                            u32::from_le_bytes(bytes) as u64 | 0xffff_ffff_0000_0000,
                         );
                     }
                     _ => unimplemented!(),
@@ -1404,6 +1730,30 @@ impl Emulator {
 
                         self.write_reg(Register::X(fr), u64::from_le_bytes(bytes));
                     }
+                    "c_fld" => {
+                        self.check_fp_enabled()?;
+
+                        let offset = calc_c_offset_5_3_7_6(imm);
+                        let bytes = self.read_memory::<8>(
+                            self.read_reg(Register::X(sr)).wrapping_add(offset) as usize,
+                        )?;
+
+                        self.write_reg(Register::F(fr), u64::from_le_bytes(bytes));
+                    }
+                    "c_flw" => {
+                        self.check_fp_enabled()?;
+
+                        let offset = calc_c_offset_5_3_2_6(imm);
+                        let bytes = self.read_memory::<4>(
+                            self.read_reg(Register::X(sr)).wrapping_add(offset) as usize,
+                        )?;
+
+                        // 単精度の値はNaN-boxingして書き込む。
+                        self.write_reg(
+                            Register::F(fr),
+                            u32::from_le_bytes(bytes) as u64 | 0xffff_ffff_0000_0000,
+                        );
+                    }
                     "c_sw" => {
                         let offset = calc_c_offset_5_3_2_6(imm);
                         let bytes = (self.read_reg(Register::X(fr)) as u32).to_le_bytes();
@@ -1422,6 +1772,28 @@ impl Emulator {
                             &bytes,
                         )?;
                     }
+                    "c_fsd" => {
+                        self.check_fp_enabled()?;
+
+                        let offset = calc_c_offset_5_3_7_6(imm);
+                        let bytes = self.read_reg(Register::F(fr)).to_le_bytes();
+
+                        self.write_memory(
+                            self.read_reg(Register::X(sr)).wrapping_add(offset) as usize,
+                            &bytes,
+                        )?;
+                    }
+                    "c_fsw" => {
+                        self.check_fp_enabled()?;
+
+                        let offset = calc_c_offset_5_3_2_6(imm);
+                        let bytes = (self.read_reg(Register::F(fr)) as u32).to_le_bytes();
+
+                        self.write_memory(
+                            self.read_reg(Register::X(sr)).wrapping_add(offset) as usize,
+                            &bytes,
+                        )?;
+                    }
                     _ => unimplemented!(),
                 }
             }
@@ -1440,27 +1812,49 @@ impl Emulator {
                         )?;
                     }
                     "c_sdsp" => {
-                        let offset = ((imm << 6) & 0x7) | (imm & 0x38);
+                        let offset = calc_c_offset_5_3_8_6(imm);
 
                         self.write_memory(
                             self.read_reg(Register::X(2)).wrapping_add(offset) as usize,
                             &self.read_reg(Register::X(rs2)).to_le_bytes(),
                         )?;
                     }
+                    "c_fsdsp" => {
+                        self.check_fp_enabled()?;
+
+                        let offset = calc_c_offset_5_3_8_6(imm);
+
+                        self.write_memory(
+                            self.read_reg(Register::X(2)).wrapping_add(offset) as usize,
+                            &self.read_reg(Register::F(rs2)).to_le_bytes(),
+                        )?;
+                    }
+                    "c_fswsp" => {
+                        self.check_fp_enabled()?;
+
+                        let offset = ((imm << 6) & 0xc0) | (imm & 0x3c);
+
+                        self.write_memory(
+                            self.read_reg(Register::X(2)).wrapping_add(offset) as usize,
+                            &(self.read_reg(Register::F(rs2)) as u32).to_le_bytes(),
+                        )?;
+                    }
                     _ => unimplemented!(),
                 }
             }
+            // R4形式の命令(積和演算)はF拡張とD拡張のみなので、exec_floatで実行している。
+            R4 => unreachable!(),
             Other => match name {
                 "fence" => {
                     // 並行処理系の工夫する構造はないので作るまでは実装しない。
-                    eprintln!("[warning]: fence may not work properly.");
+                    if self.trace {
+                        eprintln!("[warning]: fence may not work properly.");
+                    }
 
-                    match self.inst.raw() {
-                        0x8330000f | 0x0100000f => {
-                            // FENCE.TSO PAUSEは実装していない
-                            return Err(IllegralInstruction);
-                        } // FENCE.TSO PAUSE
-                        _ => {} //fence
+                    // FENCE.TSOは命令を順番に実行するので通常のfenceと同じでよい。
+                    if self.inst.raw() == 0x0100000f {
+                        // PAUSE(Zihintpause)はスピンループのヒントなので他のハートに実行を譲る。
+                        self.yield_hart();
                     }
                 }
                 "ecall" => match self.current_priv {
@@ -1482,20 +1876,29 @@ impl Emulator {
 
                             let spp = (mstatus & CSR_MSTATUS_SPP_MASK) >> 8;
                             let spie = (mstatus & CSR_MSTATUS_SPIE_MASK) >> 5;
-                            let new_sstaus = (mstatus
-                                & !CSR_SSTATUS_MASK
+                            // SIEにSPIEを戻し、SPIEを1、SPPをUにする。
+                            let new_mstatus = (mstatus
                                 & !CSR_MSTATUS_SPP_MASK
                                 & !CSR_MSTATUS_SPIE_MASK
                                 & !CSR_MSTATUS_SIE_MASK)
                                 | ((Priv::U as u64) << 8)
-                                | (1 << 8)
+                                | (1 << 5)
                                 | (spie << 1);
 
-                            self.write_csr(CSR_SSTATUS, new_sstaus).unwrap();
+                            self.write_raw_csr(CSR_MSTATUS, new_mstatus).unwrap();
                             self.write_reg(Register::Pc, self.read_csr(CSR_SEPC).unwrap());
                             self.current_priv = Priv::from(spp);
+                            self.bus.reservations.clear(self.scheduler.current);
+
+                            // Mモード以外に戻るのでmstatus.MPRVを0にする。
+                            let mstatus = self.read_raw_csr(CSR_MSTATUS).unwrap();
+                            self.write_raw_csr(CSR_MSTATUS, mstatus & !CSR_MSTATUS_MPRV_MASK)
+                                .unwrap();
+
+                            if self.trace {
+                                eprintln!("current_priv: {:?}", self.current_priv);
+                            }
 
-                            eprintln!("current_priv: {:?}", self.current_priv);
                             self.inst.set_class(InstClass::Jump(true));
                         }
                         _ => return Err(IllegralInstruction),
@@ -1508,22 +1911,32 @@ impl Emulator {
 
                             let mpp = (mstatus & CSR_MSTATUS_MPP_MASK) >> 11;
                             let mpie = (mstatus & CSR_MSTATUS_MPIE_MASK) >> 7;
-                            // Memory Privilege in mstatus Registerは後実装する。
-                            // let mprv = if mpp == Priv::M as u64 { 1 } else { 0 };
+                            // Mモード以外に戻る場合はmstatus.MPRVを0にする。
+                            let mprv = if mpp == Priv::M as u64 {
+                                mstatus & CSR_MSTATUS_MPRV_MASK
+                            } else {
+                                0
+                            };
 
                             let new_mstatus = (mstatus
                                 & !CSR_MSTATUS_MIE_MASK
                                 & !CSR_MSTATUS_MPP_MASK
+                                & !CSR_MSTATUS_MPRV_MASK
                                 & !(CSR_MSTATUS_MPIE_MASK))
                                 | (mpie << 3)
                                 | (1 << 7)
+                                | mprv
                                 | ((Priv::U as u64) << 11);
 
                             self.write_csr(CSR_MSTATUS, new_mstatus).unwrap();
                             self.write_reg(Register::Pc, self.read_csr(CSR_MEPC).unwrap());
                             self.current_priv = Priv::from(mpp);
+                            self.bus.reservations.clear(self.scheduler.current);
+
+                            if self.trace {
+                                eprintln!("current_priv: {:?}", self.current_priv);
+                            }
 
-                            eprintln!("current_priv: {:?}", self.current_priv);
                             self.inst.set_class(InstClass::Jump(true));
                         } // MRET
                         _ => {
@@ -1533,37 +1946,17 @@ impl Emulator {
                     }
                 }
                 "wfi" => {
-                    if self.current_priv != Priv::S {
-                        panic!("Error: wfi in only S MODE is supported.");
-                    }
-
+                    // 割り込みは命令の境界で確認するので、wfiは何もしない命令として扱う。
+                    // Uモードの場合とSモードでmstatus.TWが1の場合はIllegralInstructionを起こす。
                     let tw = self.read_raw_csr(CSR_MSTATUS).unwrap() & CSR_MSTATUS_TW_MASK;
 
-                    if tw == 0 {
-                        // timeoutがないとき
-                        eprintln!("[info]: Starting wfi loop...");
-                        loop {
-                            // mi{e,p}の値がそれぞれセットされている場合はxstatus.MIEにかかわらず終了する。
-                            let active = self.read_raw_csr(CSR_MIE).unwrap()
-                                & self.read_raw_csr(CSR_MIP).unwrap();
-
-                            if active != 0 {
-                                if active.count_ones() != 1 {
-                                    panic!("Error: Nested traps are not supported.");
-                                }
-
-                                match active {
-                                    2 => break,
-                                    _ => panic!("Error: The active interrupt is not suported."),
-                                }
-                            }
-                        }
-                        eprintln!("[info]: Ending wfi loop...");
-                    } else {
-                        // timeoutがあるとき
-                        panic!("Error: tw of wfi is not supported.");
+                    match self.current_priv {
+                        Priv::U => return Err(IllegralInstruction),
+                        Priv::S if tw != 0 => return Err(IllegralInstruction),
+                        _ => {}
                     }
                 }
+                "ebreak" | "c_ebreak" => return Err(Breakpoint(self.pc)),
                 _ => unimplemented!(),
             },
         };
@@ -1573,48 +1966,65 @@ impl Emulator {
 
     // 実行した命令に応じてPCを進める関数
     fn progress_pc(&mut self) {
+        // XLENが32の場合はpcが符号拡張されているので、オーバーフローしないようにする。
         if *self.inst.isa() == InstIsa::C {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         } else {
-            self.pc += 4;
+            self.pc = self.pc.wrapping_add(4);
         }
     }
 
+    // トラップを処理する関数
+    // medeleg/midelegで委譲されている場合はSモードで、それ以外はMモードで処理する。
+    // xtvalには例外が持つ値を設定するが、IllegralInstructionの場合は例外を起こした命令を設定する。
     fn handle_exception(&mut self, e: Exception) {
-        use crate::exception::Exception::*;
+        if self.trace {
+            eprintln!("EXCEPTION: {:?}", e);
+        }
 
-        eprintln!("EXCEPTION: {:?}", e);
+        // トラップに入るとLR/SCの予約は無効になる。
+        self.bus.reservations.clear(self.scheduler.current);
 
-        let is_interrupt = e as u64 >> 63 == 1;
-        let medeleg = self.read_raw_csr(CSR_MEDELEG).unwrap();
-        let mideleg = self.read_raw_csr(CSR_MIDELEG).unwrap();
-        let cause = if is_interrupt {
-            e as u64 & !(1 << 63)
+        // 組み込みのSBIを使う場合、Sモードからのecallはファームウェアの代わりにエミュレータが処理する。
+        if e == EnvironmentCallFromSMode && self.builtin_sbi {
+            self.pc = self.pc.wrapping_add(4);
+            self.handle_sbi_call();
+            return;
+        }
+
+        let is_interrupt = e.is_interrupt();
+        let cause = e.code() & !(1 << 63);
+        let deleg = if is_interrupt {
+            self.read_raw_csr(CSR_MIDELEG).unwrap()
         } else {
-            e as u64
+            self.read_raw_csr(CSR_MEDELEG).unwrap()
         };
 
-        if self.current_priv != Priv::M
-            && ((!is_interrupt && (medeleg >> cause) & 0x1 != 0)
-                || (is_interrupt && (mideleg >> cause) & 0x1 != 0))
-        {
+        let xtval = match e {
+            IllegralInstruction => self.inst.raw() as u64,
+            _ => e.trap_value(),
+        };
+
+        // 割り込みは命令の実行前に確認するので、epcには次に実行する命令のアドレスを設定する。
+        let epc = self.pc;
+
+        if self.current_priv != Priv::M && (deleg >> cause) & 0x1 != 0 {
             // 委譲
             let spp = self.current_priv as u64;
             self.current_priv = Priv::S;
 
-            let sstatus = self.read_raw_csr(CSR_SSTATUS).unwrap();
-            let spie = (sstatus & CSR_MSTATUS_SIE_MASK) >> 1;
-
-            let sepc = if is_interrupt { self.pc + 4 } else { self.pc };
-            self.write_raw_csr(CSR_SEPC, sepc).unwrap();
+            let mstatus = self.read_raw_csr(CSR_MSTATUS).unwrap();
+            let spie = (mstatus & CSR_MSTATUS_SIE_MASK) >> 1;
 
-            let next_sstatus =
-                sstatus & !CSR_MSTATUS_SPP_MASK & !CSR_MSTATUS_SPIE_MASK & !CSR_MSTATUS_SIE_MASK
+            let next_mstatus =
+                mstatus & !CSR_MSTATUS_SPP_MASK & !CSR_MSTATUS_SPIE_MASK & !CSR_MSTATUS_SIE_MASK
                     | (spp << 8)
                     | (spie << 5);
-            self.write_raw_csr(CSR_SSTATUS, next_sstatus).unwrap();
+            self.write_raw_csr(CSR_MSTATUS, next_mstatus).unwrap();
 
-            self.write_raw_csr(CSR_SCAUSE, e as u64).unwrap();
+            self.write_raw_csr(CSR_SEPC, epc).unwrap();
+            self.write_raw_csr(CSR_SCAUSE, e.code()).unwrap();
+            self.write_raw_csr(CSR_STVAL, xtval).unwrap();
         } else {
             let mpp = self.current_priv as u64;
             self.current_priv = Priv::M;
@@ -1622,22 +2032,15 @@ impl Emulator {
             let mstatus = self.read_raw_csr(CSR_MSTATUS).unwrap();
             let mpie = (mstatus & CSR_MSTATUS_MIE_MASK) >> 3;
 
-            if is_interrupt {
-                // 割り込み
-                // 次の命令のアドレスを保存する。wfiのところに記述がある。
-                self.write_raw_csr(CSR_MEPC, self.pc + 4).unwrap();
-            } else {
-                // 例外
-                self.write_raw_csr(CSR_MEPC, self.pc).unwrap();
-            }
-
             let next_mstatus =
                 (mstatus & !CSR_MSTATUS_MPP_MASK & !CSR_MSTATUS_MPIE_MASK & !CSR_MSTATUS_MIE_MASK)
                     | (mpp << 11)
                     | (mpie << 7);
             self.write_raw_csr(CSR_MSTATUS, next_mstatus).unwrap();
 
-            self.write_raw_csr(CSR_MCAUSE, e as u64).unwrap();
+            self.write_raw_csr(CSR_MEPC, epc).unwrap();
+            self.write_raw_csr(CSR_MCAUSE, e.code()).unwrap();
+            self.write_raw_csr(CSR_MTVAL, xtval).unwrap();
         }
 
         let xtvec = if self.current_priv == Priv::M {
@@ -1646,96 +2049,62 @@ impl Emulator {
             self.read_raw_csr(CSR_STVEC).unwrap()
         };
 
-        match e {
-            EnvironmentCallFromMMode
-            | EnvironmentCallFromUMode
-            | EnvironmentCallFromSMode
-            | InstructionAddressMissaligned => {
-                // 同期例外の場合はモードにかかわらずpcにBASEを設定する。
-                // 多分ハンドラがmcauseの値からどの処理を行うかを判定する感じかな。
-                self.exception_direct_jump(xtvec);
-            }
-            IllegralInstruction => {
-                let inst = self.inst.raw();
+        if is_interrupt && xtvec & 0x3 == 1 {
+            // Vectoredモードの割り込みの場合はBASE + 4 * causeにジャンプする。
+            self.interupt_vectored_jump(xtvec, e.code());
+        } else {
+            // 同期例外の場合はモードにかかわらずpcにBASEを設定する。
+            self.exception_direct_jump(xtvec);
+        }
+    }
 
-                // 命令が0、C拡張が有効でなく、C命令の場合はとりあえず不正命令の処理を行う
-                // C拡張が有効でなく、実行した命令がC拡張の命令の場合も不正命令の処理を行う。
-                // これは実装していない命令を見つけるための処置である。
-                if (*self.inst.isa() != InstIsa::C && self.inst.raw() == 0)
-                    || (!self.is_c_extension_enabled() && *self.inst.isa() == InstIsa::C)
-                {
-                    let xtval = inst;
+    // HTIFかシステムコントローラで終了が通知されるまで実行する関数
+    pub fn run(&mut self) {
+        while !self.riscv_tests_finished {
+            self.step();
+        }
+    }
 
-                    if self.current_priv == Priv::M {
-                        self.write_raw_csr(CSR_MTVAL, xtval as u64).unwrap();
-                    } else {
-                        self.write_raw_csr(CSR_STVAL, xtval as u64).unwrap();
-                    }
+    // 命令を1つ実行する関数
+    // 割り込みや命令フェッチの例外が起こった場合はトラップの処理のみを行う。
+    // 終了しないプログラム(xv6など)を出力を確認しながら実行する場合に使う。
+    // ハートが複数ある場合は、実行したあとに必要であれば次のハートに切り替える。
+    pub fn step(&mut self) {
+        self.step_hart();
+        self.schedule();
+    }
 
-                    self.exception_direct_jump(xtvec);
-                } else {
-                    let op = self.inst.op();
-                    let funct3 = (inst >> 12) & 0x7;
-                    let funct7 = inst >> 25;
-
-                    if op == 0b1110011
-                        && ((funct3 == 0b001 || funct3 == 0b010 || funct3 == 0b011)
-                            || (funct3 == 0 && funct7 == 0b1001)
-                            || inst == 0x10200073)
-                    {
-                        // CSRRW
-                        // CSRRS
-                        // SFENCE.VMA
-                        // SRET
-                        // この実装だと実装していないCSRを読み込むときはriscv-testsが失敗する想定
-                        // 正しく例外を起こしている（実装済みで正常な例外）場合はmtvalを設定し、同期例外の処理を行う。
-
-                        if self.current_priv == Priv::M {
-                            self.write_raw_csr(CSR_MTVAL, inst as u64).unwrap();
-                        } else {
-                            self.write_raw_csr(CSR_STVAL, inst as u64).unwrap();
-                        }
+    // 実行中のハートで命令を1つ実行する関数
+    fn step_hart(&mut self) {
+        // 割り込みは命令の境界で確認する。
+        self.update_interrupt_pending();
 
-                        self.exception_direct_jump(xtvec);
-                    } else {
-                        // 実装していない可能性がある命令はこっち
-                        panic!(
-                            "instruction: 0x{:08x} op: 0b{:07b} funct3: 0b{:03b}\nException: {:?}",
-                            inst, op, funct3, e
-                        );
-                    }
-                }
-            }
-            SuperSoftInt => {
-                self.interupt_vectored_jump(xtvec, e as u64);
-            }
+        if let Err(e) = self.check_interrupt_active() {
+            self.handle_exception(e);
+            return;
         }
-    }
-
-    pub fn run(&mut self) {
-        loop {
-            if self.riscv_tests_finished {
-                break;
-            }
 
+        if self.trace {
             eprintln!("PC: 0x{:016x}", self.pc,);
-            let raw_inst = self.fetch();
+        }
 
-            self.inst = self.decode(raw_inst);
+        let raw_inst = match self.fetch() {
+            Ok(raw_inst) => raw_inst,
+            Err(e) => {
+                self.handle_exception(e);
+                return;
+            }
+        };
 
-            match self.exec() {
-                Err(e) => self.handle_exception(e),
-                Ok(_) => {
-                    self.add_cycle();
+        self.inst = self.decode(raw_inst);
 
-                    if let Err(e) = self.check_interrupt_active() {
-                        self.handle_exception(e);
-                        continue;
-                    }
+        match self.exec() {
+            Err(e) => self.handle_exception(e),
+            Ok(_) => {
+                self.add_cycle();
 
-                    if InstClass::Jump(true) != *self.inst.class() {
-                        self.progress_pc();
-                    }
+                if InstClass::Jump(true) != *self.inst.class() {
+                    self.progress_pc();
                 }
             }
         }
@@ -1753,10 +2122,6 @@ impl Emulator {
     }
 
     fn interupt_vectored_jump(&mut self, xtvec: u64, xcause: u64) {
-        if self.current_priv != Priv::M {
-            panic!("Error: The vectored jump in only M mode is supported.");
-        }
-
         let base = xtvec & !0x3;
         let cause = xcause & !(1 << 63);
 
@@ -1774,14 +2139,107 @@ impl Emulator {
     }
 
     // riscv-testsが成功しているかどうかを確認する関数
+    // tohostがある場合はHTIFの終了コードが0かどうかで判定する。
     pub fn check_riscv_tests_result(&self) -> bool {
-        self.read_memory::<4>(self.riscv_tests_exit_memory_address)
-            .unwrap()
-            == [1, 0, 0, 0]
+        if self.tohost_address.is_some() {
+            return self.exit_code == Some(0);
+        }
+
+        match self.riscv_tests_exit_memory_address {
+            Some(address) => {
+                let mut bytes = [0; 4];
+
+                self.bus.read_dram(address as u64, &mut bytes) && bytes == [1, 0, 0, 0]
+            }
+            None => false,
+        }
     }
 
     // riscv-testsが終了するメモリアドレスを指定する関数
+    // tohostシンボルを持つELFをロードした場合は指定する必要はない。
     pub fn set_riscv_tests_exit_memory_address(&mut self, address: usize) {
-        self.riscv_tests_exit_memory_address = address;
+        self.riscv_tests_exit_memory_address = Some(address);
+    }
+
+    // XLEN(MXL)を設定する関数
+    // すべてのハートの状態は初期化される。ELFファイルをロードした場合はELFのクラスで上書きされる。
+    pub fn set_xlen(&mut self, xlen: Xlen) {
+        self.xlen = xlen;
+        self.reset_harts();
+    }
+
+    // XLENを返す関数
+    pub fn xlen(&self) -> Xlen {
+        self.xlen
+    }
+
+    // サポートするページングのモードを設定する関数
+    // ここに含まれないモードをsatpに書き込んだ場合は無視される。Bareは常にサポートする。
+    // Sv32はRV32、それ以外のモードはRV64でのみ使用できる。
+    pub fn set_supported_paging_modes(&mut self, modes: &[PagingMode]) {
+        self.paging_modes = PagingModes::new(modes);
+    }
+
+    // TLBの構成を設定する関数
+    // 設定するとTLBの内容とカウンタは初期化される。
+    pub fn set_tlb_config(&mut self, config: TlbConfig) {
+        self.tlb = Tlb::new(config);
+
+        for hart in &mut self.harts {
+            hart.tlb = Tlb::new(config);
+        }
+    }
+
+    // TLBのヒット数とミス数を返す関数
+    // ハートが複数ある場合はすべてのハートの合計を返す。
+    pub fn tlb_stats(&self) -> TlbStats {
+        self.tlbs().fold(TlbStats::default(), |total, tlb| {
+            let stats = tlb.stats();
+
+            TlbStats {
+                hits: total.hits + stats.hits,
+                misses: total.misses + stats.misses,
+            }
+        })
+    }
+
+    // RAMのサイズを設定する関数
+    // RAMの内容は初期化される。
+    pub fn set_memory_size(&mut self, size: usize) {
+        self.bus.dram = Memory::new(size);
+    }
+
+    // デバイスをbaseからsizeバイトの物理アドレスの範囲に接続する関数
+    pub fn attach_device(&mut self, base: u64, size: u64, device: Box<dyn Device>) {
+        self.bus.attach(base, size, None, device);
+    }
+
+    // 割り込みを起こすデバイスを接続する関数
+    // デバイスの割り込みはPLICのirq番の割り込み源につながる。
+    pub fn attach_device_with_irq(
+        &mut self,
+        base: u64,
+        size: u64,
+        irq: u32,
+        device: Box<dyn Device>,
+    ) {
+        self.bus.attach(base, size, Some(irq), device);
+    }
+
+    // 実行のログ(pc, CSRへのアクセス, 例外)を標準エラー出力に出力するかどうかを設定する関数
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    // CLINTのタイムベースの周波数を設定する関数
+    // mtimeとmtimecmpは初期化される。
+    pub fn set_timebase_frequency(&mut self, frequency: u64) {
+        self.bus.clint = Clint::new(self.harts(), frequency);
+    }
+
+    // HTIFかシステムコントローラで通知された終了コードを返す関数
+    // まだ終了していない場合はNoneを返す。
+    pub fn exit_code(&self) -> Option<u64> {
+        self.exit_code
     }
 }
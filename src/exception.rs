@@ -1,12 +1,103 @@
-#[derive(Debug, Clone, Copy)]
-#[repr(u64)]
+// 同期例外と割り込みを表す列挙体
+// 値を持つものはxtvalに設定する値を持つ。
+// IllegralInstructionのxtvalは例外を起こした命令なので、トラップを処理するときに設定する。
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Exception {
     // branchかjump命令を実行したときにターゲットアドレスが4byte(or2byte)のアライメントになっていなかったら起こる。
-    InstructionAddressMissaligned = 0,
-    IllegralInstruction = 2,
-    EnvironmentCallFromUMode = 8,
-    EnvironmentCallFromSMode = 9,
-    EnvironmentCallFromMMode = 11,
+    InstructionAddressMissaligned(u64),
+    InstructionAccessFault(u64),
+    IllegralInstruction,
+    Breakpoint(u64),
+    LoadAddressMisaligned(u64),
+    LoadAccessFault(u64),
+    StoreAddressMisaligned(u64),
+    StoreAccessFault(u64),
+    EnvironmentCallFromUMode,
+    EnvironmentCallFromSMode,
+    EnvironmentCallFromMMode,
+    // 仮想アドレスの変換に失敗したときに起こる。xtvalには仮想アドレスを設定する。
+    InstructionPageFault(u64),
+    LoadPageFault(u64),
+    StorePageFault(u64),
 
-    SuperSoftInt = 1 << 63 | 1,
+    SuperSoftInt,
+    MachineSoftInt,
+    SuperTimerInt,
+    MachineTimerInt,
+    SuperExternalInt,
+    MachineExternalInt,
+}
+
+const INTERRUPT_BIT: u64 = 1 << 63;
+
+impl Exception {
+    // xcauseに設定する値を返す関数
+    // 割り込みの場合は最上位ビットが1になる。
+    pub fn code(&self) -> u64 {
+        use Exception::*;
+
+        match self {
+            InstructionAddressMissaligned(_) => 0,
+            InstructionAccessFault(_) => 1,
+            IllegralInstruction => 2,
+            Breakpoint(_) => 3,
+            LoadAddressMisaligned(_) => 4,
+            LoadAccessFault(_) => 5,
+            StoreAddressMisaligned(_) => 6,
+            StoreAccessFault(_) => 7,
+            EnvironmentCallFromUMode => 8,
+            EnvironmentCallFromSMode => 9,
+            EnvironmentCallFromMMode => 11,
+            InstructionPageFault(_) => 12,
+            LoadPageFault(_) => 13,
+            StorePageFault(_) => 15,
+
+            SuperSoftInt => INTERRUPT_BIT | 1,
+            MachineSoftInt => INTERRUPT_BIT | 3,
+            SuperTimerInt => INTERRUPT_BIT | 5,
+            MachineTimerInt => INTERRUPT_BIT | 7,
+            SuperExternalInt => INTERRUPT_BIT | 9,
+            MachineExternalInt => INTERRUPT_BIT | 11,
+        }
+    }
+
+    pub fn is_interrupt(&self) -> bool {
+        self.code() & INTERRUPT_BIT != 0
+    }
+
+    // 割り込みのビット番号から割り込みを返す関数
+    // mip/mieのビットの位置とxcauseの値は一致する。
+    pub fn from_interrupt(bit: u64) -> Option<Self> {
+        use Exception::*;
+
+        match bit {
+            1 => Some(SuperSoftInt),
+            3 => Some(MachineSoftInt),
+            5 => Some(SuperTimerInt),
+            7 => Some(MachineTimerInt),
+            9 => Some(SuperExternalInt),
+            11 => Some(MachineExternalInt),
+            _ => None,
+        }
+    }
+
+    // xtvalに設定する値を返す関数
+    // IllegralInstructionの場合は命令がわからないので0を返す。
+    pub fn trap_value(&self) -> u64 {
+        use Exception::*;
+
+        match *self {
+            InstructionAddressMissaligned(v)
+            | InstructionAccessFault(v)
+            | Breakpoint(v)
+            | LoadAddressMisaligned(v)
+            | LoadAccessFault(v)
+            | StoreAddressMisaligned(v)
+            | StoreAccessFault(v)
+            | InstructionPageFault(v)
+            | LoadPageFault(v)
+            | StorePageFault(v) => v,
+            _ => 0,
+        }
+    }
 }
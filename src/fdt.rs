@@ -0,0 +1,159 @@
+// DTBのヘッダの値と構造ブロックのトークン(Devicetree Specification v0.4)
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_VERSION: u32 = 17;
+const FDT_LAST_COMP_VERSION: u32 = 16;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_END: u32 = 0x9;
+
+// ヘッダのサイズ(10個のu32)
+const FDT_HEADER_SIZE: usize = 40;
+
+// Flattened Device Tree(DTB)を生成する構造体
+// ノードとプロパティを順に追加し、finishでDTBのバイト列を作る。
+// 値はすべてビッグエンディアンで格納する。
+#[derive(Debug, Default)]
+pub struct Fdt {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+    depth: usize,
+}
+
+impl Fdt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push_u32(&mut self, value: u32) {
+        self.structure.extend_from_slice(&value.to_be_bytes());
+    }
+
+    // 構造ブロックを4バイト境界に揃える。
+    fn align(&mut self) {
+        while !self.structure.len().is_multiple_of(4) {
+            self.structure.push(0);
+        }
+    }
+
+    // プロパティ名を文字列ブロックに追加し、そのオフセットを返す関数
+    // 同じ名前が既にある場合はそれを使う。
+    fn string_offset(&mut self, name: &str) -> u32 {
+        let mut offset = 0;
+
+        for s in self.strings.split(|&b| b == 0) {
+            if s == name.as_bytes() && offset < self.strings.len() {
+                return offset as u32;
+            }
+
+            offset += s.len() + 1;
+        }
+
+        let offset = self.strings.len();
+
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+
+        offset as u32
+    }
+
+    pub fn begin_node(&mut self, name: &str) {
+        self.push_u32(FDT_BEGIN_NODE);
+        self.structure.extend_from_slice(name.as_bytes());
+        self.structure.push(0);
+        self.align();
+
+        self.depth += 1;
+    }
+
+    pub fn end_node(&mut self) {
+        self.push_u32(FDT_END_NODE);
+
+        self.depth -= 1;
+    }
+
+    pub fn property(&mut self, name: &str, value: &[u8]) {
+        let offset = self.string_offset(name);
+
+        self.push_u32(FDT_PROP);
+        self.push_u32(value.len() as u32);
+        self.push_u32(offset);
+        self.structure.extend_from_slice(value);
+        self.align();
+    }
+
+    // 値を持たないプロパティ(interrupt-controllerなど)
+    pub fn property_null(&mut self, name: &str) {
+        self.property(name, &[]);
+    }
+
+    pub fn property_u32(&mut self, name: &str, value: u32) {
+        self.property(name, &value.to_be_bytes());
+    }
+
+    pub fn property_u64(&mut self, name: &str, value: u64) {
+        self.property(name, &value.to_be_bytes());
+    }
+
+    pub fn property_cells(&mut self, name: &str, cells: &[u32]) {
+        let bytes: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+
+        self.property(name, &bytes);
+    }
+
+    pub fn property_string(&mut self, name: &str, value: &str) {
+        self.property_strings(name, &[value]);
+    }
+
+    // 文字列のリスト(compatibleなど)
+    pub fn property_strings(&mut self, name: &str, values: &[&str]) {
+        let mut bytes = Vec::new();
+
+        for value in values {
+            bytes.extend_from_slice(value.as_bytes());
+            bytes.push(0);
+        }
+
+        self.property(name, &bytes);
+    }
+
+    // DTBのバイト列を返す関数
+    // メモリ予約ブロックは空にする。
+    pub fn finish(mut self) -> Vec<u8> {
+        assert_eq!(self.depth, 0, "Error: The device tree has unclosed nodes.");
+
+        self.push_u32(FDT_END);
+
+        // メモリ予約ブロックは8バイト境界で、終端の16バイトのみ
+        let off_mem_rsvmap = FDT_HEADER_SIZE;
+        let off_dt_struct = off_mem_rsvmap + 16;
+        let off_dt_strings = off_dt_struct + self.structure.len();
+        let total_size = off_dt_strings + self.strings.len();
+
+        let header = [
+            FDT_MAGIC,
+            total_size as u32,
+            off_dt_struct as u32,
+            off_dt_strings as u32,
+            off_mem_rsvmap as u32,
+            FDT_VERSION,
+            FDT_LAST_COMP_VERSION,
+            0, // boot_cpuid_phys
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ];
+
+        let mut blob = Vec::with_capacity(total_size);
+
+        for value in header {
+            blob.extend_from_slice(&value.to_be_bytes());
+        }
+
+        blob.extend_from_slice(&[0; 16]);
+        blob.extend_from_slice(&self.structure);
+        blob.extend_from_slice(&self.strings);
+
+        blob
+    }
+}
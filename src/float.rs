@@ -0,0 +1,214 @@
+use crate::{
+    cpu::InstIsa,
+    emulator::{extract_i_type, extract_r_type, extract_s_type, sign_extend, Emulator},
+    exception::Exception::*,
+    register::Register,
+    softfloat::{classify, Format, RoundingMode, SoftFloat, F32, F64},
+    Result,
+};
+
+// 値を64bitの浮動小数点レジスタに格納する形にする関数
+// 単精度の値は上位32bitを1で埋める(NaN-boxing)。
+fn nan_box(format: Format, value: u64) -> u64 {
+    value | !format.mask()
+}
+
+impl Emulator {
+    // 浮動小数点の命令を実行できるかを確認する関数
+    // mstatus.FSが0(Off)の場合は不正な命令になる。
+    pub(crate) fn check_fp_enabled(&self) -> Result<()> {
+        if self.csr.is_fp_enabled() {
+            Ok(())
+        } else {
+            Err(IllegralInstruction)
+        }
+    }
+
+    // 浮動小数点レジスタから値を読み込む関数
+    // 単精度の場合、正しくNaN-boxingされていない値は正規化されたNaNとして扱う。
+    fn read_float(&self, format: Format, reg: u8) -> u64 {
+        let value = self.read_reg(Register::F(reg));
+
+        if value | format.mask() == u64::MAX {
+            value & format.mask()
+        } else {
+            format.canonical_nan()
+        }
+    }
+
+    // 命令の丸めモードを返す関数
+    // rmが7(動的)の場合はfrmを使う。予約されている値の場合は不正な命令になる。
+    fn rounding_mode(&self) -> Result<RoundingMode> {
+        let rm = match (self.inst.raw() as u64 >> 12) & 0x7 {
+            0b111 => self.csr.frm(),
+            rm => rm,
+        };
+
+        RoundingMode::from_bits(rm).ok_or(IllegralInstruction)
+    }
+
+    // F拡張とD拡張の命令を実行する関数
+    // 演算はsoftfloat.rsで行い、起こった例外はfflagsに累積する。
+    pub(crate) fn exec_float(&mut self) -> Result<()> {
+        self.check_fp_enabled()?;
+
+        let raw = self.inst.raw();
+        let (rd, rs1, rs2, _) = extract_r_type(raw);
+        let rs3 = (raw >> 27) as u8;
+
+        // 命令の形式(名前の末尾が_sか_d)
+        let format = if *self.inst.isa() == InstIsa::D {
+            F64
+        } else {
+            F32
+        };
+
+        match self.inst.name() {
+            "flw" | "fld" => {
+                let (rd, rs1, imm) = extract_i_type(raw);
+                let address = self
+                    .read_reg(Register::X(rs1))
+                    .wrapping_add(sign_extend(11, imm)) as usize;
+
+                let value = if format == F64 {
+                    u64::from_le_bytes(self.read_memory::<8>(address)?)
+                } else {
+                    u32::from_le_bytes(self.read_memory::<4>(address)?) as u64
+                };
+
+                self.write_reg(Register::F(rd), nan_box(format, value));
+            }
+            "fsw" | "fsd" => {
+                // NaN-boxingされているかどうかにかかわらず下位のビットをそのまま書き込む。
+                let (rs1, rs2, imm) = extract_s_type(raw);
+                let address = self
+                    .read_reg(Register::X(rs1))
+                    .wrapping_add(sign_extend(11, imm)) as usize;
+                let value = self.read_reg(Register::F(rs2));
+
+                if format == F64 {
+                    self.write_memory(address, &value.to_le_bytes())?;
+                } else {
+                    self.write_memory(address, &(value as u32).to_le_bytes())?;
+                }
+            }
+            name @ ("fsgnj_s" | "fsgnjn_s" | "fsgnjx_s" | "fsgnj_d" | "fsgnjn_d" | "fsgnjx_d") => {
+                let a = self.read_float(format, rs1);
+                let b = self.read_float(format, rs2);
+
+                let sign = match name {
+                    "fsgnj_s" | "fsgnj_d" => b,
+                    "fsgnjn_s" | "fsgnjn_d" => !b,
+                    _ => a ^ b,
+                } & format.sign_mask();
+
+                self.write_reg(
+                    Register::F(rd),
+                    nan_box(format, (a & !format.sign_mask()) | sign),
+                );
+            }
+            "fmv_x_w" => {
+                // NaN-boxingされているかどうかにかかわらず下位32bitをそのまま移す。
+                let value = self.read_reg(Register::F(rs1)) & F32.mask();
+
+                self.write_reg(Register::X(rd), sign_extend(31, value));
+            }
+            "fmv_x_d" => {
+                self.write_reg(Register::X(rd), self.read_reg(Register::F(rs1)));
+            }
+            "fmv_w_x" | "fmv_d_x" => {
+                let value = self.read_reg(Register::X(rs1)) & format.mask();
+
+                self.write_reg(Register::F(rd), nan_box(format, value));
+            }
+            "fclass_s" | "fclass_d" => {
+                self.write_reg(
+                    Register::X(rd),
+                    classify(format, self.read_float(format, rs1)),
+                );
+            }
+            name => {
+                // minとmax、比較は丸めないのでrmフィールドは使わない。
+                let rounding = match name {
+                    "fmin_s" | "fmax_s" | "feq_s" | "flt_s" | "fle_s" | "fmin_d" | "fmax_d"
+                    | "feq_d" | "flt_d" | "fle_d" => RoundingMode::NearestEven,
+                    _ => self.rounding_mode()?,
+                };
+
+                let mut fpu = SoftFloat::new(rounding);
+                let a = self.read_float(format, rs1);
+                let b = self.read_float(format, rs2);
+                let c = self.read_float(format, rs3);
+                let x = self.read_reg(Register::X(rs1));
+                let sign = format.sign_mask();
+
+                let (reg, value) = match name {
+                    "fadd_s" | "fadd_d" => (Register::F(rd), fpu.add(format, a, b)),
+                    "fsub_s" | "fsub_d" => (Register::F(rd), fpu.sub(format, a, b)),
+                    "fmul_s" | "fmul_d" => (Register::F(rd), fpu.mul(format, a, b)),
+                    "fdiv_s" | "fdiv_d" => (Register::F(rd), fpu.div(format, a, b)),
+                    "fsqrt_s" | "fsqrt_d" => (Register::F(rd), fpu.sqrt(format, a)),
+                    "fmin_s" | "fmin_d" => (Register::F(rd), fpu.min(format, a, b)),
+                    "fmax_s" | "fmax_d" => (Register::F(rd), fpu.max(format, a, b)),
+                    // 符号の反転はSpikeと同じようにオペランドの符号ビットを反転して行う。
+                    "fmadd_s" | "fmadd_d" => (Register::F(rd), fpu.mul_add(format, a, b, c)),
+                    "fmsub_s" | "fmsub_d" => (Register::F(rd), fpu.mul_add(format, a, b, c ^ sign)),
+                    "fnmsub_s" | "fnmsub_d" => {
+                        (Register::F(rd), fpu.mul_add(format, a ^ sign, b, c))
+                    }
+                    "fnmadd_s" | "fnmadd_d" => {
+                        (Register::F(rd), fpu.mul_add(format, a ^ sign, b, c ^ sign))
+                    }
+                    "fcvt_w_s" | "fcvt_w_d" => (
+                        Register::X(rd),
+                        sign_extend(31, fpu.round_to_int(format, a, true, 32)),
+                    ),
+                    "fcvt_wu_s" | "fcvt_wu_d" => (
+                        Register::X(rd),
+                        sign_extend(31, fpu.round_to_int(format, a, false, 32)),
+                    ),
+                    "fcvt_l_s" | "fcvt_l_d" => {
+                        (Register::X(rd), fpu.round_to_int(format, a, true, 64))
+                    }
+                    "fcvt_lu_s" | "fcvt_lu_d" => {
+                        (Register::X(rd), fpu.round_to_int(format, a, false, 64))
+                    }
+                    "fcvt_s_w" | "fcvt_d_w" => {
+                        (Register::F(rd), fpu.int_to_float(format, x, true, 32))
+                    }
+                    "fcvt_s_wu" | "fcvt_d_wu" => {
+                        (Register::F(rd), fpu.int_to_float(format, x, false, 32))
+                    }
+                    "fcvt_s_l" | "fcvt_d_l" => {
+                        (Register::F(rd), fpu.int_to_float(format, x, true, 64))
+                    }
+                    "fcvt_s_lu" | "fcvt_d_lu" => {
+                        (Register::F(rd), fpu.int_to_float(format, x, false, 64))
+                    }
+                    // 結果が単精度になるので、ここでNaN-boxingする。
+                    "fcvt_s_d" => (
+                        Register::F(rd),
+                        nan_box(F32, fpu.convert(F64, F32, self.read_float(F64, rs1))),
+                    ),
+                    "fcvt_d_s" => (
+                        Register::F(rd),
+                        fpu.convert(F32, F64, self.read_float(F32, rs1)),
+                    ),
+                    "feq_s" | "feq_d" => (Register::X(rd), fpu.eq(format, a, b) as u64),
+                    "flt_s" | "flt_d" => (Register::X(rd), fpu.lt(format, a, b) as u64),
+                    "fle_s" | "fle_d" => (Register::X(rd), fpu.le(format, a, b) as u64),
+                    _ => unimplemented!(),
+                };
+
+                self.csr.accrue_fflags(fpu.flags);
+
+                match reg {
+                    Register::F(rd) => self.write_reg(Register::F(rd), nan_box(format, value)),
+                    reg => self.write_reg(reg, value),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use std::mem;
+
+use crate::{
+    clint::Clint,
+    csr::Csr,
+    emulator::Emulator,
+    plic::Plic,
+    reservation::Reservations,
+    tlb::{Tlb, TlbConfig},
+    vector::VectorConfig,
+    Priv, Xlen,
+};
+
+// 1つのハートを続けて実行する命令数のデフォルト
+pub const DEFAULT_QUANTUM: usize = 1000;
+
+// LR/SCの予約を持っているハートの切り替えを遅らせる最大の命令数
+// 制約されたLR/SCのループは16命令以内なので、その間はほかのハートに予約を無効にされない。
+const LR_SC_MAX_STEPS: usize = 16;
+
+// ハートごとの状態
+// 実行中のハートの状態はEmulatorのフィールドにあり、それ以外のハートの状態はここに保存する。
+// stoppedはSBIのHSMで停止しているかどうかで、実行中のハートも含めて常にここで管理する。
+#[derive(Debug)]
+pub(crate) struct Hart {
+    regs: [u64; 31],
+    fregs: [u64; 32],
+    vregs: Vec<u8>,
+    pc: u64,
+    csr: Csr,
+    current_priv: Priv,
+    pub(crate) tlb: Tlb,
+    pub(crate) stopped: bool,
+}
+
+impl Hart {
+    fn new(hartid: usize, xlen: Xlen, tlb: TlbConfig, vector: VectorConfig) -> Self {
+        Self {
+            regs: [0; 31],
+            fregs: [0; 32],
+            vregs: vec![0; 32 * vector.vlenb()],
+            pc: 0,
+            csr: Csr::new(hartid, xlen),
+            current_priv: Priv::M,
+            tlb: Tlb::new(tlb),
+            stopped: false,
+        }
+    }
+}
+
+// ハートをラウンドロビンで切り替えるスケジューラ
+// 実行中のハートをquantum命令実行するか、実行中のハートが停止したら次のハートに切り替える。
+#[derive(Debug)]
+pub(crate) struct Scheduler {
+    pub(crate) current: usize,
+    quantum: usize,
+    steps: usize,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            current: 0,
+            quantum: DEFAULT_QUANTUM,
+            steps: 0,
+        }
+    }
+}
+
+impl Emulator {
+    // ハートの数を設定する関数
+    // CLINTとPLICはハートの数に合わせて作り直し、すべてのハートの状態は初期化される。
+    pub fn set_harts(&mut self, harts: usize) {
+        assert!(harts > 0, "Error: The number of harts must be at least 1.");
+
+        self.bus.clint = Clint::new(harts, self.bus.clint.frequency());
+        self.bus.plic = Plic::new(harts);
+
+        self.reset_harts();
+    }
+
+    // ハートの数を返す関数
+    pub fn harts(&self) -> usize {
+        self.bus.clint.harts()
+    }
+
+    // 1つのハートを続けて実行する命令数を設定する関数
+    pub fn set_quantum(&mut self, quantum: usize) {
+        assert!(quantum > 0, "Error: The quantum must be at least 1.");
+
+        self.scheduler.quantum = quantum;
+    }
+
+    // 実行中のハートのIDを返す関数
+    pub fn current_hart(&self) -> usize {
+        self.scheduler.current
+    }
+
+    // すべてのハートをリセット直後の状態(Mモード、pcは0)にし、ハート0を実行中にする関数
+    pub(crate) fn reset_harts(&mut self) {
+        let xlen = self.xlen;
+        let tlb = self.tlb.config();
+        let vector = self.vector;
+
+        self.harts = (0..self.harts())
+            .map(|id| Hart::new(id, xlen, tlb, vector))
+            .collect();
+        self.bus.reservations = Reservations::new(self.harts());
+        self.scheduler.current = 0;
+        self.scheduler.steps = 0;
+
+        self.swap_hart(0);
+    }
+
+    // 実行中のハートの状態と保存しているハートの状態を入れ替える関数
+    fn swap_hart(&mut self, hart: usize) {
+        let saved = &mut self.harts[hart];
+
+        mem::swap(&mut self.regs, &mut saved.regs);
+        mem::swap(&mut self.fregs, &mut saved.fregs);
+        mem::swap(&mut self.vregs, &mut saved.vregs);
+        mem::swap(&mut self.pc, &mut saved.pc);
+        mem::swap(&mut self.csr, &mut saved.csr);
+        mem::swap(&mut self.current_priv, &mut saved.current_priv);
+        mem::swap(&mut self.tlb, &mut saved.tlb);
+    }
+
+    // 実行するハートを切り替える関数
+    pub(crate) fn switch_hart(&mut self, hart: usize) {
+        if hart == self.scheduler.current {
+            return;
+        }
+
+        self.swap_hart(self.scheduler.current);
+        self.swap_hart(hart);
+
+        self.scheduler.current = hart;
+        self.scheduler.steps = 0;
+    }
+
+    // 命令を1つ実行したあとに呼び、必要であれば次のハートに切り替える関数
+    // LRとSCの間で切り替えると、ほかのハートの書き込みでSCが失敗し続けることがあるので、
+    // 予約を持っている場合は最大でLR_SC_MAX_STEPS命令だけ切り替えを遅らせる。
+    pub(crate) fn schedule(&mut self) {
+        if self.harts.len() <= 1 {
+            return;
+        }
+
+        self.scheduler.steps += 1;
+
+        let current = self.scheduler.current;
+
+        if !self.harts[current].stopped {
+            let quantum = if self.bus.reservations.is_reserved(current) {
+                self.scheduler.quantum + LR_SC_MAX_STEPS
+            } else {
+                self.scheduler.quantum
+            };
+
+            if self.scheduler.steps < quantum {
+                return;
+            }
+        }
+
+        let harts = self.harts.len();
+
+        if let Some(next) = (1..=harts)
+            .map(|i| (current + i) % harts)
+            .find(|&hart| !self.harts[hart].stopped)
+        {
+            self.switch_hart(next);
+        }
+
+        self.scheduler.steps = 0;
+    }
+
+    // 実行中のハートの残りのクォンタムを捨てて、次のscheduleで他のハートに切り替えさせる関数
+    pub(crate) fn yield_hart(&mut self) {
+        self.scheduler.steps = self.scheduler.quantum + LR_SC_MAX_STEPS;
+    }
+
+    // ハートのCSRを返す関数(実行中のハートの場合はEmulatorのもの)
+    pub(crate) fn hart_csr_mut(&mut self, hart: usize) -> &mut Csr {
+        if hart == self.scheduler.current {
+            &mut self.csr
+        } else {
+            &mut self.harts[hart].csr
+        }
+    }
+
+    // ハートのTLBを返す関数(実行中のハートの場合はEmulatorのもの)
+    pub(crate) fn hart_tlb_mut(&mut self, hart: usize) -> &mut Tlb {
+        if hart == self.scheduler.current {
+            &mut self.tlb
+        } else {
+            &mut self.harts[hart].tlb
+        }
+    }
+
+    // すべてのハートのTLBを返す関数
+    pub(crate) fn tlbs(&self) -> impl Iterator<Item = &Tlb> {
+        let current = self.scheduler.current;
+
+        self.harts
+            .iter()
+            .enumerate()
+            .filter(move |(id, _)| *id != current)
+            .map(|(_, hart)| &hart.tlb)
+            .chain([&self.tlb])
+    }
+}
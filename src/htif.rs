@@ -0,0 +1,129 @@
+use std::io::{self, Write};
+
+use crate::emulator::Emulator;
+
+// HTIF(Host Target Interface)のデバイスとコマンド
+// tohostの上位8bitがデバイス、次の8bitがコマンド、下位48bitがペイロードを表す。
+const HTIF_DEVICE_SYSCALL: u64 = 0;
+const HTIF_DEVICE_CONSOLE: u64 = 1;
+const HTIF_CONSOLE_PUTCHAR: u64 = 1;
+
+// プロキシするシステムコールの番号
+const SYS_WRITE: u64 = 64;
+const SYS_EXIT: u64 = 93;
+
+const ENOSYS: u64 = 38;
+const EFAULT: u64 = 14;
+
+impl Emulator {
+    // tohostへの書き込みを処理する関数
+    // ペイロードのbit0が1の場合は終了コード(payload >> 1)を記録して終了する。
+    // bit0が0の場合はペイロードをmagic_memのアドレスとしてシステムコールをプロキシする。
+    pub(crate) fn handle_htif(&mut self, tohost: usize) {
+        let value = self.read_htif(tohost);
+
+        if value == 0 {
+            return;
+        }
+
+        let device = value >> 56;
+        let command = (value >> 48) & 0xff;
+        let payload = value & 0xffff_ffff_ffff;
+
+        let response = match (device, command) {
+            (HTIF_DEVICE_SYSCALL, 0) => {
+                if payload & 0x1 == 1 {
+                    self.exit_code = Some(payload >> 1);
+                    self.riscv_tests_finished = true;
+                    None
+                } else {
+                    self.proxy_syscall(payload as usize);
+                    Some(1)
+                }
+            }
+            (HTIF_DEVICE_CONSOLE, HTIF_CONSOLE_PUTCHAR) => {
+                let mut stdout = io::stdout();
+                let _ = stdout.write_all(&[payload as u8]);
+                let _ = stdout.flush();
+
+                Some((HTIF_DEVICE_CONSOLE << 56) | (HTIF_CONSOLE_PUTCHAR << 48))
+            }
+            _ => {
+                eprintln!(
+                    "[warning]: The HTIF command(0x{:016x}) is not supported.",
+                    value
+                );
+                None
+            }
+        };
+
+        self.bus.write(tohost as u64, &0u64.to_le_bytes());
+
+        if let (Some(response), Some(fromhost)) = (response, self.fromhost_address) {
+            self.bus.write(fromhost as u64, &response.to_le_bytes());
+        }
+    }
+
+    // HTIFで使用する物理アドレスから8バイト読み込む関数
+    // 読み込めない場合は0を返す。
+    fn read_htif(&mut self, address: usize) -> u64 {
+        let mut bytes = [0; 8];
+
+        self.bus.read(address as u64, &mut bytes);
+
+        u64::from_le_bytes(bytes)
+    }
+
+    // magic_memに格納されたシステムコールを実行する関数
+    // magic_mem[0]がシステムコールの番号、magic_mem[1..]が引数で、戻り値はmagic_mem[0]に書き込む。
+    fn proxy_syscall(&mut self, magic_mem: usize) {
+        let arg = |emulator: &mut Self, i: usize| emulator.read_htif(magic_mem + i * 8);
+
+        let ret = match arg(self, 0) {
+            SYS_WRITE => {
+                let fd = arg(self, 1);
+                let buf = arg(self, 2);
+                let len = arg(self, 3);
+
+                self.sys_write(fd, buf, len)
+            }
+            SYS_EXIT => {
+                self.exit_code = Some(arg(self, 1));
+                self.riscv_tests_finished = true;
+                0
+            }
+            n => {
+                eprintln!("[warning]: The syscall({}) is not supported.", n);
+                -(ENOSYS as i64) as u64
+            }
+        };
+
+        self.bus.write(magic_mem as u64, &ret.to_le_bytes());
+    }
+
+    // SYS_WRITEを実行して戻り値を返す関数
+    // バッファがRAMに収まらない場合は、領域を確保せずに-EFAULTを返す。
+    fn sys_write(&self, fd: u64, buf: u64, len: u64) -> u64 {
+        if !self.bus.dram_contains(buf, len) {
+            return -(EFAULT as i64) as u64;
+        }
+
+        let mut bytes = vec![0; len as usize];
+
+        if !self.bus.read_dram(buf, &mut bytes) {
+            return -(EFAULT as i64) as u64;
+        }
+
+        let result = match fd {
+            1 => io::stdout().write_all(&bytes).and(io::stdout().flush()),
+            2 => io::stderr().write_all(&bytes),
+            _ => Err(io::Error::other("unsupported fd")),
+        };
+
+        if result.is_ok() {
+            len
+        } else {
+            -1i64 as u64
+        }
+    }
+}
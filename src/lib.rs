@@ -1,9 +1,30 @@
+pub mod bitmanip;
+pub mod boot;
+pub mod bus;
+pub mod clint;
 pub mod cpu;
 pub mod csr;
+pub mod device_tree;
+pub mod elf;
 pub mod emulator;
 pub mod exception;
+pub mod fdt;
+pub mod float;
+pub mod hart;
+pub mod htif;
 pub mod memory;
+pub mod mmu;
+pub mod plic;
+pub mod pmp;
 pub mod register;
+pub mod reservation;
+pub mod sbi;
+pub mod softfloat;
+pub mod syscon;
+pub mod tlb;
+pub mod uart;
+pub mod vector;
+pub mod virtio;
 
 pub type Result<T> = std::result::Result<T, crate::exception::Exception>;
 
@@ -31,3 +52,29 @@ impl Default for Priv {
         Self::M
     }
 }
+
+// XLEN(整数レジスタの幅)を示す列挙体
+// RV32ではレジスタの値を下位32bitから符号拡張した64bitの値として保持する。
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Xlen {
+    X32,
+    #[default]
+    X64,
+}
+
+impl Xlen {
+    pub fn bits(self) -> u32 {
+        match self {
+            Xlen::X32 => 32,
+            Xlen::X64 => 64,
+        }
+    }
+
+    // XLENビットを取り出すためのマスク
+    pub fn mask(self) -> u64 {
+        match self {
+            Xlen::X32 => 0xffff_ffff,
+            Xlen::X64 => u64::MAX,
+        }
+    }
+}
@@ -1,5 +1,15 @@
-use tiny_riscv_emulator::emulator::Emulator;
-const TEST_DIR: &str = "tests/isa/flats";
+use std::{env, path::PathBuf, process};
+
+use tiny_riscv_emulator::{
+    boot::BootConfig,
+    emulator::Emulator,
+    uart::{Uart, UART_BASE, UART_IRQ, UART_SIZE},
+    vector::VectorConfig,
+    virtio::{VirtioBlock, VIRTIO_BASE, VIRTIO_IRQ, VIRTIO_SIZE},
+    Xlen,
+};
+
+const TEST_DIR: &str = "tests/isa/elfs";
 
 fn display_start_test(name: &str) {
     eprintln!("[info]: start {}", name);
@@ -9,9 +19,8 @@ fn display_end_test(name: &str) {
     eprintln!("[info]: end {}", name);
 }
 
-fn run_test(emulator: &mut Emulator, test: &str, riscv_tests_exit_memory_address: usize) {
+fn run_test(emulator: &mut Emulator, test: &str) {
     emulator.load(format!("{}/{}", TEST_DIR, test)).unwrap();
-    emulator.set_riscv_tests_exit_memory_address(riscv_tests_exit_memory_address);
 
     emulator.run();
 
@@ -22,29 +31,214 @@ fn run_test(emulator: &mut Emulator, test: &str, riscv_tests_exit_memory_address
     }
 }
 
+fn usage() -> ! {
+    eprintln!(
+        "Usage: tiny-riscv-emulator [--memory <MiB>] [--harts <n>] [--quantum <n>] [--xlen <32|64>] [--vlen <bits>] [--elen <bits>] [--disk <image>] [--disk-ro <image>] <program>"
+    );
+    eprintln!(
+        "       tiny-riscv-emulator [options] [--bios <firmware>] --kernel <Image> [--initrd <file>] [--append <bootargs>]"
+    );
+    eprintln!("       Without --bios, the kernel starts in S-mode with the built-in SBI.");
+    eprintln!("       --harts runs <n> harts in turn, switching every --quantum instructions.");
+    eprintln!(
+        "       --xlen sets the XLEN of flat binaries. ELF files use the XLEN of their class."
+    );
+    eprintln!(
+        "       --vlen and --elen set the vector register and element widths (default 128 and 64)."
+    );
+    eprintln!("       --dump-dtb <file> writes the device tree passed to the guest and exits.");
+    process::exit(1);
+}
+
+fn next_arg<'a>(args: &mut impl Iterator<Item = &'a String>) -> &'a str {
+    match args.next() {
+        Some(arg) => arg,
+        None => usage(),
+    }
+}
+
+// プログラムを実行する関数
+// UARTを標準入出力に接続し、HTIFかシステムコントローラで終了コードが通知された場合はその値で終了する。
+// --diskと--disk-roで指定したディスクイメージはvirtioのブロックデバイスとして順に接続する。
+// --kernelを指定した場合はプログラムの代わりにファームウェア(--bios)からLinuxを起動する。
+// --biosを指定しない場合は組み込みのSBIを使い、SモードでLinuxを直接起動する。
+// --hartsを指定した場合はハートを複数にし、--quantumで指定した命令数ごとに切り替えて実行する。
+// --xlenを指定した場合はフラットなバイナリをそのXLENで実行する。ELFファイルはELFのクラスに合わせる。
+// --vlenと--elenを指定した場合はベクトルレジスタと要素の最大のビット数を変更する。
+// --dump-dtbを指定した場合はゲストに渡すデバイスツリーをファイルに書き出して、実行せずに終了する。
+fn run_program(args: &[String]) -> ! {
+    let mut emulator = Emulator::default();
+    let mut disks = Vec::new();
+    let mut memory = None;
+    let mut harts = None;
+    let mut quantum = None;
+    let mut xlen = None;
+    let mut vector = VectorConfig::default();
+    let mut firmware = None;
+    let mut kernel = None;
+    let mut initrd = None;
+    let mut bootargs = None;
+    let mut dump_dtb = None;
+    let mut path = None;
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--disk" => disks.push((next_arg(&mut args), false)),
+            "--disk-ro" => disks.push((next_arg(&mut args), true)),
+            "--memory" => match next_arg(&mut args).parse::<usize>() {
+                Ok(size) => memory = Some(size * 1024 * 1024),
+                Err(_) => usage(),
+            },
+            "--harts" => match next_arg(&mut args).parse::<usize>() {
+                Ok(n) if n > 0 => harts = Some(n),
+                _ => usage(),
+            },
+            "--quantum" => match next_arg(&mut args).parse::<usize>() {
+                Ok(n) if n > 0 => quantum = Some(n),
+                _ => usage(),
+            },
+            "--xlen" => match next_arg(&mut args) {
+                "32" => xlen = Some(Xlen::X32),
+                "64" => xlen = Some(Xlen::X64),
+                _ => usage(),
+            },
+            "--vlen" => match next_arg(&mut args).parse::<usize>() {
+                Ok(bits) => vector.vlen = bits,
+                Err(_) => usage(),
+            },
+            "--elen" => match next_arg(&mut args).parse::<usize>() {
+                Ok(bits) => vector.elen = bits,
+                Err(_) => usage(),
+            },
+            "--bios" => firmware = Some(next_arg(&mut args)),
+            "--kernel" => kernel = Some(next_arg(&mut args)),
+            "--initrd" => initrd = Some(next_arg(&mut args)),
+            "--append" => bootargs = Some(next_arg(&mut args)),
+            "--dump-dtb" => dump_dtb = Some(next_arg(&mut args)),
+            _ if arg.starts_with("--") || path.is_some() => usage(),
+            _ => path = Some(arg.as_str()),
+        }
+    }
+
+    if let Some(size) = memory {
+        emulator.set_memory_size(size);
+    }
+
+    if let Some(n) = harts {
+        emulator.set_harts(n);
+    }
+
+    if let Some(n) = quantum {
+        emulator.set_quantum(n);
+    }
+
+    if let Some(xlen) = xlen {
+        emulator.set_xlen(xlen);
+    }
+
+    if !vector.is_valid() {
+        eprintln!(
+            "[Error]: VLEN must be a power of two between ELEN and 65536, and ELEN must be 32 or 64."
+        );
+        process::exit(1);
+    }
+
+    emulator.set_vector_config(vector);
+
+    for (i, (image, read_only)) in disks.into_iter().enumerate() {
+        let disk = match VirtioBlock::open(image, read_only) {
+            Ok(disk) => disk,
+            Err(e) => {
+                eprintln!("[Error]: Failed to open {}: {}", image, e);
+                process::exit(1);
+            }
+        };
+
+        emulator.attach_device_with_irq(
+            VIRTIO_BASE + VIRTIO_SIZE * i as u64,
+            VIRTIO_SIZE,
+            VIRTIO_IRQ + i as u32,
+            Box::new(disk),
+        );
+    }
+
+    emulator.attach_device_with_irq(UART_BASE, UART_SIZE, UART_IRQ, Box::new(Uart::stdio()));
+    emulator.set_trace(env::var("TRACE").is_ok());
+
+    let result = match (kernel, path) {
+        (Some(kernel), None) => {
+            let mut config = match firmware {
+                Some(firmware) => BootConfig::new(firmware, kernel),
+                None => BootConfig::builtin_sbi(kernel),
+            };
+            config.initrd = initrd.map(PathBuf::from);
+            config.dump_fdt = dump_dtb.map(PathBuf::from);
+
+            if let Some(bootargs) = bootargs {
+                config.bootargs = bootargs.to_string();
+            }
+
+            emulator.boot(&config)
+        }
+        (None, Some(path)) => match dump_dtb {
+            Some(dtb) => emulator
+                .dump_device_tree(dtb, bootargs.unwrap_or(""), None)
+                .map_err(|e| e.into()),
+            None => emulator.load(path),
+        },
+        _ => usage(),
+    };
+
+    if let Err(e) = result {
+        eprintln!("[Error]: Failed to load: {}", e);
+        process::exit(1);
+    }
+
+    if dump_dtb.is_some() {
+        process::exit(0);
+    }
+
+    emulator.run();
+
+    let code = emulator.exit_code().unwrap_or(0);
+
+    // UARTの端末の設定を戻すためにprocess::exitの前にエミュレータを破棄する。
+    drop(emulator);
+    process::exit(code as i32);
+}
+
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if !args.is_empty() {
+        run_program(&args);
+    }
+
     let mut emulator = Emulator::default();
 
     let name = "si_tests";
 
     let si_tests = [
-        "rv64mi-p-csr.bin",
-        "rv64mi-p-illegal.bin",
-        "rv64mi-p-ld-misaligned.bin",
-        "rv64mi-p-lh-misaligned.bin",
-        "rv64mi-p-lw-misaligned.bin",
-        "rv64mi-p-ma_addr.bin",
-        "rv64mi-p-ma_fetch.bin",
-        "rv64mi-p-mcsr.bin",
-        "rv64mi-p-sd-misaligned.bin",
-        "rv64mi-p-sh-misaligned.bin",
-        "rv64mi-p-sw-misaligned.bin",
-        "rv64mi-p-scall.bin",
+        "rv64mi-p-csr",
+        "rv64mi-p-illegal",
+        "rv64mi-p-ld-misaligned",
+        "rv64mi-p-lh-misaligned",
+        "rv64mi-p-lw-misaligned",
+        "rv64mi-p-ma_addr",
+        "rv64mi-p-ma_fetch",
+        "rv64mi-p-mcsr",
+        "rv64mi-p-pmpaddr",
+        "rv64mi-p-sd-misaligned",
+        "rv64mi-p-sh-misaligned",
+        "rv64mi-p-sw-misaligned",
+        "rv64mi-p-scall",
     ];
 
     display_start_test(name);
     for test in si_tests {
-        run_test(&mut emulator, test, 0x1000);
+        run_test(&mut emulator, test);
     }
     display_end_test(name);
 }
@@ -5,14 +5,28 @@ use std::{
     path::Path,
 };
 
+// RAMを表す構造体
+// アドレスはRAMの先頭からのオフセットで、範囲の確認はバスで行う。
 #[derive(Debug, Default)]
-pub struct Memory<const MAX: usize> {
+pub struct Memory {
+    size: usize,
     array: Vec<u8>,
 }
 
-impl<const MAX: usize> Memory<MAX> {
+impl Memory {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            array: vec![0; size],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
     // プログラムをロードする関数
-    // 将来的にはロードする位置を指定できるようにしたい。
+    // 先頭からロードする。
     // 遅延ロードとかもやってみたい。割と遅延ロードにするといいかもしれない気がする。
     pub fn load<P: AsRef<Path>>(
         &mut self,
@@ -20,69 +34,53 @@ impl<const MAX: usize> Memory<MAX> {
     ) -> core::result::Result<(), Box<dyn Error>> {
         let file = File::open(filename)?;
         let mut reader = BufReader::new(file);
+        let mut bytes = Vec::new();
 
-        self.array = vec![0; MAX];
-
-        let n = reader.read(&mut self.array)?;
+        reader.read_to_end(&mut bytes)?;
 
-        if n > MAX {
-            panic!("Error: The file size is too big or MAX is too small.");
+        if bytes.len() > self.size {
+            return Err("The file size is bigger than the memory size.".into());
         }
 
+        self.initialize();
+        self.array[..bytes.len()].copy_from_slice(&bytes);
+
         Ok(())
     }
 
-    // メモリを読み出す関数
-    // SIZEでメモリのサイズを指定する。SIZEが最大メモリの大きさを超えた場合はパニックになる。
-    // addressで読み込みたいメモリの位置を指定する。addressの大きさが最大メモリの大きさを超えている場合は余りになる。
-    // address + SIZEが最大メモリの大きさを超えている場合はRISC-Vの仕様では0番地に戻る。
-    pub fn read<const SIZE: usize>(&self, address: usize) -> [u8; SIZE] {
-        if SIZE / MAX > 1 {
-            panic!("Error: SIZE is too big.");
-        }
+    // メモリを0で初期化する関数
+    pub fn initialize(&mut self) {
+        self.array = vec![0; self.size];
+    }
 
-        let address = address % MAX;
+    // セグメントをメモリに配置する関数
+    // dataを書き込んだあと、mem_sizeまでの残りの領域は0で埋める(.bss)。
+    // セグメントがメモリに収まらない場合はfalseを返す。
+    pub fn load_segment(&mut self, offset: usize, data: &[u8], mem_size: usize) -> bool {
+        if data.len() > mem_size || !self.contains(offset, mem_size) {
+            return false;
+        }
 
-        let mut array = [0; SIZE];
+        self.array[offset..offset + data.len()].copy_from_slice(data);
+        self.array[offset + data.len()..offset + mem_size].fill(0);
 
-        if address + SIZE > MAX {
-            let diff = MAX - address;
+        true
+    }
 
-            array.copy_from_slice(&self.array[address..]);
-            array.copy_from_slice(&self.array[..SIZE - diff]);
-        } else {
-            array.copy_from_slice(&self.array[address..address + SIZE]);
-        }
+    // offsetからsizeバイトの範囲がメモリに含まれるかを判定する関数
+    pub fn contains(&self, offset: usize, size: usize) -> bool {
+        offset.checked_add(size).is_some_and(|end| end <= self.size)
+    }
 
-        array
+    // メモリを読み出す関数
+    // offsetから読み込む。範囲外の場合はパニックになるので、呼び出し側でcontainsを確認する。
+    pub fn read(&self, offset: usize, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.array[offset..offset + bytes.len()]);
     }
 
     // メモリに書き出す関数
-    // addressで書き込みたいメモリの位置を指定する。addressの大きさが最大メモリの大きさを超えている場合は余りになる。
-    // valuesで書き込みたい配列を指定する。valuesのサイズが最大メモリの大きさを超えた場合はパニックになる。
-    // address + valuesのサイズが最大メモリの大きさを超えている場合はRISC-Vの仕様では0番地に戻る。
-    pub fn write(&mut self, address: usize, values: &[u8]) {
-        let size = values.len();
-
-        if size / MAX > 1 {
-            panic!("Error: The size of values is too big.");
-        }
-
-        let address = address % MAX;
-
-        if address + size > MAX {
-            let diff = MAX - address;
-
-            self.array[address..].copy_from_slice(&values[..diff]);
-            self.array[..diff].copy_from_slice(&values[diff..]);
-        } else {
-            println!(
-                "size: 0x{:016x} MAX: 0x{:016x} len: {}",
-                address,
-                MAX,
-                self.array.len()
-            );
-            self.array[address..address + size].copy_from_slice(values);
-        }
+    // offsetに書き込む。範囲外の場合はパニックになるので、呼び出し側でcontainsを確認する。
+    pub fn write(&mut self, offset: usize, values: &[u8]) {
+        self.array[offset..offset + values.len()].copy_from_slice(values);
     }
 }
@@ -0,0 +1,342 @@
+use crate::{
+    csr::{
+        CSR_MSTATUS, CSR_MSTATUS_MPP_MASK, CSR_MSTATUS_MPRV_MASK, CSR_MSTATUS_MXR_MASK,
+        CSR_MSTATUS_SUM_MASK, CSR_SATP,
+    },
+    emulator::Emulator,
+    exception::Exception::{self, *},
+    tlb::TlbEntry,
+    Priv, Result, Xlen,
+};
+
+pub(crate) const PAGE_SIZE: u64 = 4096;
+
+pub(crate) const SATP_PPN_MASK: u64 = (1 << 44) - 1;
+
+const PTE_V: u64 = 1 << 0;
+const PTE_R: u64 = 1 << 1;
+const PTE_W: u64 = 1 << 2;
+const PTE_X: u64 = 1 << 3;
+const PTE_U: u64 = 1 << 4;
+const PTE_G: u64 = 1 << 5;
+const PTE_A: u64 = 1 << 6;
+pub(crate) const PTE_D: u64 = 1 << 7;
+const PTE_PPN_MASK: u64 = (1 << 44) - 1;
+// N(Svnapot)とPBMT(Svpbmt)は実装していないので予約ビットとして扱う。
+const PTE_RESERVED_MASK: u64 = 0x3ff << 54;
+
+// satp.MODEで選択するページングのモード
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PagingMode {
+    Bare = 0,
+    Sv32 = 1,
+    Sv39 = 8,
+    Sv48 = 9,
+    Sv57 = 10,
+}
+
+impl PagingMode {
+    pub(crate) fn from_satp(satp: u64) -> Option<Self> {
+        match satp >> 60 {
+            0 => Some(PagingMode::Bare),
+            1 => Some(PagingMode::Sv32),
+            8 => Some(PagingMode::Sv39),
+            9 => Some(PagingMode::Sv48),
+            10 => Some(PagingMode::Sv57),
+            _ => None,
+        }
+    }
+
+    // ページテーブルの段数
+    fn levels(&self) -> u64 {
+        match self {
+            PagingMode::Bare => 0,
+            PagingMode::Sv32 => 2,
+            PagingMode::Sv39 => 3,
+            PagingMode::Sv48 => 4,
+            PagingMode::Sv57 => 5,
+        }
+    }
+
+    // 各レベルのVPNのビット数
+    fn vpn_bits(&self) -> u64 {
+        match self {
+            PagingMode::Sv32 => 10,
+            _ => 9,
+        }
+    }
+
+    // PTEのバイト数
+    fn pte_size(&self) -> u64 {
+        match self {
+            PagingMode::Sv32 => 4,
+            _ => 8,
+        }
+    }
+
+    // XLENごとにsatpに書き込めるモードかどうかを判定する関数
+    pub(crate) fn is_valid_for(&self, xlen: Xlen) -> bool {
+        match self {
+            PagingMode::Bare => true,
+            PagingMode::Sv32 => xlen == Xlen::X32,
+            _ => xlen == Xlen::X64,
+        }
+    }
+}
+
+// サポートするページングのモードの集合
+// Bareは常にサポートする。デフォルトではすべてのモードをサポートする。
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PagingModes(u16);
+
+impl Default for PagingModes {
+    fn default() -> Self {
+        Self::new(&[
+            PagingMode::Sv32,
+            PagingMode::Sv39,
+            PagingMode::Sv48,
+            PagingMode::Sv57,
+        ])
+    }
+}
+
+impl PagingModes {
+    pub(crate) fn new(modes: &[PagingMode]) -> Self {
+        let bits = modes
+            .iter()
+            .fold(1 << PagingMode::Bare as u16, |bits, &mode| {
+                bits | (1 << mode as u16)
+            });
+
+        Self(bits)
+    }
+
+    pub(crate) fn contains(&self, mode: PagingMode) -> bool {
+        self.0 & (1 << mode as u16) != 0
+    }
+}
+
+// メモリアクセスの種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AccessType {
+    Instruction,
+    Load,
+    Store,
+}
+
+impl AccessType {
+    pub(crate) fn page_fault(&self, vaddr: u64) -> Exception {
+        match self {
+            AccessType::Instruction => InstructionPageFault(vaddr),
+            AccessType::Load => LoadPageFault(vaddr),
+            AccessType::Store => StorePageFault(vaddr),
+        }
+    }
+
+    pub(crate) fn access_fault(&self, address: u64) -> Exception {
+        match self {
+            AccessType::Instruction => InstructionAccessFault(address),
+            AccessType::Load => LoadAccessFault(address),
+            AccessType::Store => StoreAccessFault(address),
+        }
+    }
+}
+
+impl Emulator {
+    // メモリアクセスに使用する権限を返す関数
+    // mstatus.MPRVが1のときはロードとストアはmstatus.MPPの権限で行う。
+    pub(crate) fn effective_priv(&self, access: AccessType) -> Priv {
+        let mstatus = self.read_raw_csr(CSR_MSTATUS).unwrap();
+
+        if access != AccessType::Instruction
+            && self.current_priv == Priv::M
+            && mstatus & CSR_MSTATUS_MPRV_MASK != 0
+        {
+            Priv::from((mstatus & CSR_MSTATUS_MPP_MASK) >> 11)
+        } else {
+            self.current_priv
+        }
+    }
+
+    // 仮想アドレスを物理アドレスに変換する関数
+    // Mモードの場合とsatpがBareの場合はそのまま返す。
+    // TLBにヒットした場合はページテーブルを辿らずに権限の確認のみを行う。
+    // XLENが32の場合はレジスタの値を符号拡張して保持しているので、下位32bitを仮想アドレスとする。
+    pub(crate) fn translate(&mut self, vaddr: u64, access: AccessType) -> Result<u64> {
+        let vaddr = vaddr & self.current_xlen().mask();
+        let effective_priv = self.effective_priv(access);
+        let satp = self.read_raw_csr(CSR_SATP).unwrap();
+
+        if effective_priv == Priv::M {
+            return Ok(vaddr);
+        }
+
+        let mode = match PagingMode::from_satp(satp) {
+            Some(PagingMode::Bare) => return Ok(vaddr),
+            Some(mode) => mode,
+            None => panic!("Error: The satp mode({}) is not supported.", satp >> 60),
+        };
+
+        let asid = ((satp >> 44) & 0xffff) as u16;
+
+        if let Some(entry) = self.tlb.lookup(vaddr, asid, access) {
+            if !self.is_leaf_permitted(entry.pte, access, effective_priv) {
+                return Err(access.page_fault(vaddr));
+            }
+
+            return Ok((entry.ppn << 12) | (vaddr & (PAGE_SIZE - 1)));
+        }
+
+        let (pte, level) = self.walk_page_table(vaddr, access, effective_priv, satp, mode)?;
+
+        let shift = mode.vpn_bits() * level;
+        let offset_mask = (1 << (12 + shift)) - 1;
+        let ppn = (pte >> 10) & PTE_PPN_MASK;
+        let paddr = ((ppn << 12) & !offset_mask) | (vaddr & offset_mask);
+
+        self.tlb.insert(
+            TlbEntry {
+                vpn: vaddr >> 12,
+                asid,
+                global: pte & PTE_G != 0,
+                shift,
+                ppn: paddr >> 12,
+                pte,
+            },
+            access,
+        );
+
+        Ok(paddr)
+    }
+
+    // リーフのPTEの権限でアクセスできるかを判定する関数
+    fn is_leaf_permitted(&self, pte: u64, access: AccessType, effective_priv: Priv) -> bool {
+        let mstatus = self.read_raw_csr(CSR_MSTATUS).unwrap();
+
+        let permitted = match access {
+            AccessType::Instruction => pte & PTE_X != 0,
+            AccessType::Load => {
+                pte & PTE_R != 0 || (mstatus & CSR_MSTATUS_MXR_MASK != 0 && pte & PTE_X != 0)
+            }
+            AccessType::Store => pte & PTE_W != 0,
+        };
+
+        let user_permitted = match effective_priv {
+            Priv::U => pte & PTE_U != 0,
+            Priv::S => {
+                pte & PTE_U == 0
+                    || (access != AccessType::Instruction && mstatus & CSR_MSTATUS_SUM_MASK != 0)
+            }
+            Priv::M => true,
+        };
+
+        permitted && user_permitted
+    }
+
+    // Sv32/Sv39/Sv48/Sv57のページテーブルを辿る関数
+    // Sv39/Sv48/Sv57の違いは段数のみで、Sv32はVPNが10bitでPTEが4byteになる。
+    // A/Dビットはハードウェアで更新する。
+    // 成功した場合は更新後のリーフのPTEとそのレベルを返す。
+    fn walk_page_table(
+        &mut self,
+        vaddr: u64,
+        access: AccessType,
+        effective_priv: Priv,
+        satp: u64,
+        mode: PagingMode,
+    ) -> Result<(u64, u64)> {
+        let levels = mode.levels();
+        let vpn_bits = mode.vpn_bits();
+        let pte_size = mode.pte_size();
+
+        // 上位のビットはva_bits - 1ビット目の符号拡張になっていなければならない。
+        // Sv32は仮想アドレスが32bitなので確認しない。
+        if mode != PagingMode::Sv32 {
+            let va_bits = 12 + vpn_bits * levels;
+            let upper = (vaddr as i64) >> (va_bits - 1);
+
+            if upper != 0 && upper != -1 {
+                return Err(access.page_fault(vaddr));
+            }
+        }
+
+        let mut table = (satp & SATP_PPN_MASK) * PAGE_SIZE;
+        let mut level = levels - 1;
+
+        loop {
+            let vpn = (vaddr >> (12 + vpn_bits * level)) & ((1 << vpn_bits) - 1);
+            let pte_address = (table + vpn * pte_size) as usize;
+
+            // ページテーブルへのアクセスもSモードの権限でPMPの確認を行う。
+            if !self
+                .csr
+                .pmp
+                .is_permitted(pte_address as u64, pte_size, AccessType::Load, Priv::S)
+            {
+                return Err(access.access_fault(vaddr));
+            }
+            let mut bytes = [0; 8];
+
+            if !self
+                .bus
+                .read(pte_address as u64, &mut bytes[..pte_size as usize])
+            {
+                return Err(access.access_fault(vaddr));
+            }
+
+            let pte = u64::from_le_bytes(bytes);
+
+            if pte & PTE_V == 0
+                || (pte & PTE_R == 0 && pte & PTE_W != 0)
+                || pte & PTE_RESERVED_MASK != 0
+            {
+                return Err(access.page_fault(vaddr));
+            }
+
+            let ppn = (pte >> 10) & PTE_PPN_MASK;
+
+            if pte & (PTE_R | PTE_X) == 0 {
+                // 次のレベルのページテーブルへのポインタ
+                if level == 0 {
+                    return Err(access.page_fault(vaddr));
+                }
+
+                level -= 1;
+                table = ppn * PAGE_SIZE;
+                continue;
+            }
+
+            // リーフのPTE
+            // スーパーページの場合は下位のPPNが0になっていなければならない。
+            let misaligned = ppn & ((1 << (vpn_bits * level)) - 1) != 0;
+
+            if !self.is_leaf_permitted(pte, access, effective_priv) || misaligned {
+                return Err(access.page_fault(vaddr));
+            }
+
+            let new_pte = pte
+                | PTE_A
+                | if access == AccessType::Store {
+                    PTE_D
+                } else {
+                    0
+                };
+
+            if new_pte != pte
+                && (!self.csr.pmp.is_permitted(
+                    pte_address as u64,
+                    pte_size,
+                    AccessType::Store,
+                    Priv::S,
+                ) || !self.bus.write(
+                    pte_address as u64,
+                    &new_pte.to_le_bytes()[..pte_size as usize],
+                ))
+            {
+                return Err(access.access_fault(vaddr));
+            }
+
+            return Ok((new_pte, level));
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use crate::bus::Device;
+
+// PLICを配置する物理アドレスとサイズ(QEMUのvirtと同じ)
+pub const PLIC_BASE: u64 = 0x0c00_0000;
+pub const PLIC_SIZE: u64 = 0x0400_0000;
+
+// 割り込み源の数(0番は割り込みなしを表すので1..PLIC_SOURCESが有効)
+pub const PLIC_SOURCES: usize = 64;
+
+// 各レジスタのオフセット(SiFiveのPLICと同じ配置)
+const PRIORITY_BASE: u64 = 0x0;
+const PENDING_BASE: u64 = 0x1000;
+const ENABLE_BASE: u64 = 0x2000;
+const ENABLE_STRIDE: u64 = 0x80;
+const CONTEXT_BASE: u64 = 0x20_0000;
+const CONTEXT_STRIDE: u64 = 0x1000;
+
+// 優先度は3bit(0-7)で、0の場合は割り込みが起こらない。
+const PRIORITY_MASK: u32 = 0x7;
+
+pub(crate) const MIP_SEIP: u64 = 1 << 9;
+pub(crate) const MIP_MEIP: u64 = 1 << 11;
+
+const WORDS: usize = PLIC_SOURCES.div_ceil(32);
+
+// PLIC(Platform-Level Interrupt Controller)
+// コンテキストはハートごとにMモード(2 * hart)とSモード(2 * hart + 1)の2つを持つ。
+// 割り込みはレベルトリガとして扱い、claimされてからcompleteされるまでは再びpendingにならない。
+#[derive(Debug)]
+pub struct Plic {
+    priority: [u32; PLIC_SOURCES],
+    pending: [u32; WORDS],
+    claimed: [u32; WORDS],
+    enable: Vec<[u32; WORDS]>,
+    threshold: Vec<u32>,
+}
+
+impl Default for Plic {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+fn bit(bits: &[u32; WORDS], source: usize) -> bool {
+    bits[source / 32] & (1 << (source % 32)) != 0
+}
+
+fn set_bit(bits: &mut [u32; WORDS], source: usize, value: bool) {
+    if value {
+        bits[source / 32] |= 1 << (source % 32);
+    } else {
+        bits[source / 32] &= !(1 << (source % 32));
+    }
+}
+
+impl Plic {
+    pub fn new(harts: usize) -> Self {
+        Self {
+            priority: [0; PLIC_SOURCES],
+            pending: [0; WORDS],
+            claimed: [0; WORDS],
+            enable: vec![[0; WORDS]; harts * 2],
+            threshold: vec![0; harts * 2],
+        }
+    }
+
+    // 割り込み源の信号を設定する関数
+    // claimされている間は信号が有効でもpendingにしない。
+    pub fn set_level(&mut self, source: u32, level: bool) {
+        let source = source as usize;
+
+        if source == 0 || source >= PLIC_SOURCES {
+            return;
+        }
+
+        if !bit(&self.claimed, source) {
+            set_bit(&mut self.pending, source, level);
+        }
+    }
+
+    // コンテキストに通知する割り込みのうち、最も優先度が高いものを返す関数
+    // 優先度が同じ場合は番号が小さいものを優先する。
+    // 命令ごとに呼ばれるので、pendingかつ有効になっている割り込み源のみを調べる。
+    fn best_source(&self, context: usize) -> Option<usize> {
+        let mut best: Option<usize> = None;
+
+        for word in 0..WORDS {
+            let mut candidates = self.pending[word] & self.enable[context][word];
+
+            while candidates != 0 {
+                let source = word * 32 + candidates.trailing_zeros() as usize;
+                let priority = self.priority[source];
+
+                candidates &= candidates - 1;
+
+                if priority <= self.threshold[context] {
+                    continue;
+                }
+
+                if best.is_none_or(|best| priority > self.priority[best]) {
+                    best = Some(source);
+                }
+            }
+        }
+
+        best
+    }
+
+    // ハートのmipに反映する割り込み(MEIP, SEIP)を返す関数
+    pub fn pending_interrupts(&self, hart: usize) -> u64 {
+        let meip = if self.best_source(hart * 2).is_some() {
+            MIP_MEIP
+        } else {
+            0
+        };
+
+        let seip = if self.best_source(hart * 2 + 1).is_some() {
+            MIP_SEIP
+        } else {
+            0
+        };
+
+        meip | seip
+    }
+
+    fn claim(&mut self, context: usize) -> u32 {
+        match self.best_source(context) {
+            Some(source) => {
+                set_bit(&mut self.pending, source, false);
+                set_bit(&mut self.claimed, source, true);
+
+                source as u32
+            }
+            None => 0,
+        }
+    }
+
+    fn complete(&mut self, source: u32) {
+        let source = source as usize;
+
+        if source != 0 && source < PLIC_SOURCES {
+            set_bit(&mut self.claimed, source, false);
+        }
+    }
+
+    fn contexts(&self) -> usize {
+        self.threshold.len()
+    }
+}
+
+impl Device for Plic {
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64> {
+        // すべてのレジスタは4バイト
+        if size != 4 || !offset.is_multiple_of(4) {
+            return None;
+        }
+
+        let value = match offset {
+            PRIORITY_BASE..PENDING_BASE => {
+                let source = ((offset - PRIORITY_BASE) / 4) as usize;
+
+                self.priority.get(source).copied().unwrap_or(0)
+            }
+            PENDING_BASE..ENABLE_BASE => {
+                let word = ((offset - PENDING_BASE) / 4) as usize;
+
+                self.pending.get(word).copied().unwrap_or(0)
+            }
+            ENABLE_BASE..CONTEXT_BASE => {
+                let context = ((offset - ENABLE_BASE) / ENABLE_STRIDE) as usize;
+                let word = ((offset - ENABLE_BASE) % ENABLE_STRIDE / 4) as usize;
+
+                self.enable
+                    .get(context)
+                    .and_then(|enable| enable.get(word).copied())
+                    .unwrap_or(0)
+            }
+            _ => {
+                let context = ((offset - CONTEXT_BASE) / CONTEXT_STRIDE) as usize;
+
+                if context >= self.contexts() {
+                    return Some(0);
+                }
+
+                match (offset - CONTEXT_BASE) % CONTEXT_STRIDE {
+                    0 => self.threshold[context],
+                    4 => self.claim(context),
+                    _ => 0,
+                }
+            }
+        };
+
+        Some(value as u64)
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> bool {
+        if size != 4 || !offset.is_multiple_of(4) {
+            return false;
+        }
+
+        let value = value as u32;
+
+        match offset {
+            PRIORITY_BASE..PENDING_BASE => {
+                let source = ((offset - PRIORITY_BASE) / 4) as usize;
+
+                // 0番の割り込み源は存在しないので優先度は0に固定する。
+                if source != 0 && source < PLIC_SOURCES {
+                    self.priority[source] = value & PRIORITY_MASK;
+                }
+            }
+            // pendingは読み込みのみ
+            PENDING_BASE..ENABLE_BASE => {}
+            ENABLE_BASE..CONTEXT_BASE => {
+                let context = ((offset - ENABLE_BASE) / ENABLE_STRIDE) as usize;
+                let word = ((offset - ENABLE_BASE) % ENABLE_STRIDE / 4) as usize;
+
+                if context < self.contexts() && word < WORDS {
+                    // 0番の割り込み源は有効にできない。
+                    let mask = if word == 0 { !0x1 } else { u32::MAX };
+
+                    self.enable[context][word] = value & mask;
+                }
+            }
+            _ => {
+                let context = ((offset - CONTEXT_BASE) / CONTEXT_STRIDE) as usize;
+
+                if context < self.contexts() {
+                    match (offset - CONTEXT_BASE) % CONTEXT_STRIDE {
+                        0 => self.threshold[context] = value & PRIORITY_MASK,
+                        4 => self.complete(value),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{emulator::Emulator, mmu::AccessType, Priv, Result};
+
+// 実装するPMPのエントリ数
+// pmpcfg0/pmpcfg2とpmpaddr0..15が有効で、それ以降のエントリは0に固定する。
+pub(crate) const PMP_ENTRIES: usize = 16;
+
+pub(crate) const CSR_PMPCFG0: u64 = 0x3a0;
+pub(crate) const CSR_PMPCFG15: u64 = 0x3af;
+pub(crate) const CSR_PMPADDR0: u64 = 0x3b0;
+pub(crate) const CSR_PMPADDR63: u64 = 0x3ef;
+
+const PMP_R: u8 = 1 << 0;
+const PMP_W: u8 = 1 << 1;
+const PMP_X: u8 = 1 << 2;
+const PMP_A_MASK: u8 = 0x3 << 3;
+const PMP_L: u8 = 1 << 7;
+// 5,6bit目は予約されているので0に固定する。
+const PMP_CFG_MASK: u8 = PMP_L | PMP_A_MASK | PMP_X | PMP_W | PMP_R;
+
+const PMP_A_TOR: u8 = 1 << 3;
+const PMP_A_NA4: u8 = 2 << 3;
+const PMP_A_NAPOT: u8 = 3 << 3;
+
+// pmpaddrは物理アドレスの55:2bitを保持する。
+const PMP_ADDR_MASK: u64 = (1 << 54) - 1;
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Pmp {
+    cfg: [u8; PMP_ENTRIES],
+    addr: [u64; PMP_ENTRIES],
+}
+
+impl Pmp {
+    // pmpcfgを読み込む関数
+    // RV64では偶数番号のpmpcfgのみ存在し、1つのCSRに8エントリ分の設定を持つ。
+    pub(crate) fn read_cfg(&self, csr: u64) -> Option<u64> {
+        if csr & 0x1 != 0 {
+            return None;
+        }
+
+        let base = ((csr - CSR_PMPCFG0) * 4) as usize;
+
+        let value = (0..8)
+            .filter(|i| base + i < PMP_ENTRIES)
+            .fold(0, |value, i| {
+                value | ((self.cfg[base + i] as u64) << (i * 8))
+            });
+
+        Some(value)
+    }
+
+    pub(crate) fn write_cfg(&mut self, csr: u64, value: u64) {
+        let base = ((csr - CSR_PMPCFG0) * 4) as usize;
+
+        for i in (0..8).filter(|i| base + i < PMP_ENTRIES) {
+            if self.cfg[base + i] & PMP_L != 0 {
+                // ロックされているエントリは変更しない。
+                continue;
+            }
+
+            let mut cfg = (value >> (i * 8)) as u8 & PMP_CFG_MASK;
+
+            // R=0, W=1の組み合わせは予約されているのでWを0にする。
+            if cfg & PMP_R == 0 {
+                cfg &= !PMP_W;
+            }
+
+            self.cfg[base + i] = cfg;
+        }
+    }
+
+    pub(crate) fn read_addr(&self, csr: u64) -> u64 {
+        let index = (csr - CSR_PMPADDR0) as usize;
+
+        if index < PMP_ENTRIES {
+            self.addr[index]
+        } else {
+            0
+        }
+    }
+
+    pub(crate) fn write_addr(&mut self, csr: u64, value: u64) {
+        let index = (csr - CSR_PMPADDR0) as usize;
+
+        if index < PMP_ENTRIES && !self.is_addr_locked(index) {
+            self.addr[index] = value & PMP_ADDR_MASK;
+        }
+    }
+
+    // pmpaddrが書き込み禁止かどうかを判定する関数
+    // 自身がロックされている場合と、次のエントリがロックされたTORの場合は書き込めない。
+    fn is_addr_locked(&self, index: usize) -> bool {
+        let next_locked_tor = self
+            .cfg
+            .get(index + 1)
+            .is_some_and(|&cfg| cfg & PMP_L != 0 && cfg & PMP_A_MASK == PMP_A_TOR);
+
+        self.cfg[index] & PMP_L != 0 || next_locked_tor
+    }
+
+    // エントリが対象とする物理アドレスの範囲[start, end)を返す関数
+    // OFFの場合はNoneを返す。
+    fn range(&self, index: usize) -> Option<(u64, u64)> {
+        let addr = self.addr[index];
+
+        match self.cfg[index] & PMP_A_MASK {
+            PMP_A_TOR => {
+                let start = if index == 0 {
+                    0
+                } else {
+                    self.addr[index - 1] << 2
+                };
+
+                Some((start, addr << 2))
+            }
+            PMP_A_NA4 => Some((addr << 2, (addr << 2) + 4)),
+            PMP_A_NAPOT => {
+                // 下位に連続する1の数でサイズが決まる。
+                let ones = addr.trailing_ones();
+                let start = (addr & !((1 << ones) - 1)) << 2;
+
+                Some((start, start + (1 << (ones + 3))))
+            }
+            _ => None,
+        }
+    }
+
+    // 物理アドレスaddressからsizeバイトのアクセスが許可されているかを判定する関数
+    // 番号が小さいエントリが優先され、アクセスの一部のみが一致する場合は失敗する。
+    // Mモードはロックされたエントリのみ権限を確認し、一致するエントリがない場合は成功する。
+    pub(crate) fn is_permitted(
+        &self,
+        address: u64,
+        size: u64,
+        access: AccessType,
+        current_priv: Priv,
+    ) -> bool {
+        let end = address.saturating_add(size);
+
+        for index in 0..PMP_ENTRIES {
+            let (start, limit) = match self.range(index) {
+                Some(range) => range,
+                None => continue,
+            };
+
+            if address >= limit || end <= start {
+                continue;
+            }
+
+            if address < start || end > limit {
+                return false;
+            }
+
+            let cfg = self.cfg[index];
+
+            if current_priv == Priv::M && cfg & PMP_L == 0 {
+                return true;
+            }
+
+            return match access {
+                AccessType::Instruction => cfg & PMP_X != 0,
+                AccessType::Load => cfg & PMP_R != 0,
+                AccessType::Store => cfg & PMP_W != 0,
+            };
+        }
+
+        current_priv == Priv::M
+    }
+}
+
+impl Emulator {
+    // PMPで物理アドレスへのアクセスを確認する関数
+    // 失敗した場合は仮想アドレスをxtvalに設定するアクセスフォルトを返す。
+    pub(crate) fn check_pmp(
+        &self,
+        vaddr: u64,
+        paddr: u64,
+        size: u64,
+        access: AccessType,
+    ) -> Result<()> {
+        let effective_priv = self.effective_priv(access);
+
+        if self
+            .csr
+            .pmp
+            .is_permitted(paddr, size, access, effective_priv)
+        {
+            Ok(())
+        } else {
+            Err(access.access_fault(vaddr))
+        }
+    }
+}
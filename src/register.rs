@@ -1,7 +1,8 @@
 // レジスターを表す列挙体
-// Xの値は0~31以外はパニックになる。
+// XとFの値は0~31以外はパニックになる。
 #[derive(Debug)]
 pub enum Register {
     X(u8),
+    F(u8),
     Pc,
 }
@@ -0,0 +1,58 @@
+// LR/SCの予約セット
+// ハートごとに最後のLRで読み込んだ物理アドレスの範囲(begin, end)を保持する。
+// どのハートやデバイス(DMA)からでも、範囲が重なる書き込みがあれば予約は無効になる。
+#[derive(Debug)]
+pub(crate) struct Reservations {
+    sets: Vec<Option<(u64, u64)>>,
+}
+
+impl Default for Reservations {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Reservations {
+    pub(crate) fn new(harts: usize) -> Self {
+        Self {
+            sets: vec![None; harts],
+        }
+    }
+
+    // LRでaddressからsizeバイトを予約する関数
+    // 以前の予約は破棄する。
+    pub(crate) fn reserve(&mut self, hart: usize, address: u64, size: usize) {
+        self.sets[hart] = Some((address, address + size as u64));
+    }
+
+    // SCで予約を取り出す関数
+    // SCは成否にかかわらず予約を破棄する。範囲が予約に含まれている場合のみtrueを返す。
+    pub(crate) fn take(&mut self, hart: usize, address: u64, size: usize) -> bool {
+        match self.sets[hart].take() {
+            Some((begin, end)) => begin <= address && address + size as u64 <= end,
+            None => false,
+        }
+    }
+
+    // ハートが予約を持っているかどうかを返す関数
+    pub(crate) fn is_reserved(&self, hart: usize) -> bool {
+        self.sets[hart].is_some()
+    }
+
+    // ハートの予約を破棄する関数
+    // トラップに入るときとxRETで使う。
+    pub(crate) fn clear(&mut self, hart: usize) {
+        self.sets[hart] = None;
+    }
+
+    // addressからsizeバイトへの書き込みと範囲が重なる予約をすべて破棄する関数
+    pub(crate) fn invalidate(&mut self, address: u64, size: usize) {
+        let end = address + size as u64;
+
+        for set in &mut self.sets {
+            if matches!(set, Some((begin, set_end)) if address < *set_end && *begin < end) {
+                *set = None;
+            }
+        }
+    }
+}
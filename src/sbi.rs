@@ -0,0 +1,400 @@
+use crate::{
+    csr::{
+        CSR_MCOUNTEREN, CSR_MEDELEG, CSR_MENVCFG, CSR_MENVCFG_STCE_MASK, CSR_MIDELEG, CSR_STIMECMP,
+    },
+    emulator::Emulator,
+    mmu::AccessType,
+    pmp::{CSR_PMPADDR0, CSR_PMPCFG0},
+    register::Register,
+    uart::{LSR, LSR_DR, RBR_THR_DLL},
+    Priv, Xlen,
+};
+
+// 拡張ID(EID)
+const SBI_EXT_LEGACY_CONSOLE_PUTCHAR: u64 = 0x01;
+const SBI_EXT_LEGACY_CONSOLE_GETCHAR: u64 = 0x02;
+const SBI_EXT_BASE: u64 = 0x10;
+const SBI_EXT_TIME: u64 = 0x5449_4d45; // "TIME"
+const SBI_EXT_IPI: u64 = 0x0073_5049; // "sPI"
+const SBI_EXT_RFENCE: u64 = 0x5246_4e43; // "RFNC"
+const SBI_EXT_HSM: u64 = 0x0048_534d; // "HSM"
+const SBI_EXT_SRST: u64 = 0x5352_5354; // "SRST"
+const SBI_EXT_DBCN: u64 = 0x4442_434e; // "DBCN"
+
+const SBI_EXTENSIONS: [u64; 9] = [
+    SBI_EXT_LEGACY_CONSOLE_PUTCHAR,
+    SBI_EXT_LEGACY_CONSOLE_GETCHAR,
+    SBI_EXT_BASE,
+    SBI_EXT_TIME,
+    SBI_EXT_IPI,
+    SBI_EXT_RFENCE,
+    SBI_EXT_HSM,
+    SBI_EXT_SRST,
+    SBI_EXT_DBCN,
+];
+
+// エラーコード
+const SBI_ERR_FAILED: i64 = -1;
+const SBI_ERR_NOT_SUPPORTED: i64 = -2;
+const SBI_ERR_INVALID_PARAM: i64 = -3;
+const SBI_ERR_ALREADY_AVAILABLE: i64 = -6;
+
+// SBIのバージョン(v2.0)と実装のIDとバージョン
+// 実装IDは登録されているもの(OpenSBIは1)と被らない値にする。
+const SBI_SPEC_VERSION: u64 = 2 << 24;
+const SBI_IMPL_ID: u64 = 0x7472_6576; // "trev"
+const SBI_IMPL_VERSION: u64 = 1;
+
+// HSMのハートの状態と中断の種類
+const SBI_HSM_STATE_STARTED: u64 = 0;
+const SBI_HSM_STATE_STOPPED: u64 = 1;
+const SBI_HSM_SUSPEND_RETENTIVE: u64 = 0;
+
+// SRSTのリセットの種類と理由
+const SBI_SRST_TYPE_SHUTDOWN: u64 = 0;
+const SBI_SRST_TYPE_WARM_REBOOT: u64 = 2;
+const SBI_SRST_REASON_SYSTEM_FAILURE: u64 = 1;
+
+// ファームウェアの代わりにSモードで直接カーネルを起動するときの設定
+// Sモードからのecall(9)とMモードからのecall(11)以外の例外と、Sモードの割り込みをすべて委譲する。
+const SBI_MEDELEG: u64 = 0xb1ff;
+const SBI_MIDELEG: u64 = 0x222;
+// cycle, time, instretをSモードから読めるようにする。
+const SBI_MCOUNTEREN: u64 = 0x7;
+// PMPはTORで物理アドレス全体をRWXにする。
+const SBI_PMPADDR: u64 = 0x003f_ffff_ffff_ffff;
+const SBI_PMPCFG: u64 = 0xf;
+
+// DBCNのconsole_writeでRAMからUARTに一度にコピーするバイト数
+const SBI_DBCN_CHUNK: usize = 256;
+
+// 関数の戻り値(成功した場合は値、失敗した場合はエラーコード)
+type SbiResult = core::result::Result<u64, i64>;
+
+impl Emulator {
+    // 組み込みのSBIを使ってSモードでカーネルを実行できるようにする関数
+    // OpenSBIがSモードに移る前に行う設定と同じ設定をする。
+    pub(crate) fn enter_supervisor_with_sbi(&mut self) {
+        self.write_raw_csr(CSR_MEDELEG, SBI_MEDELEG).unwrap();
+        self.write_raw_csr(CSR_MIDELEG, SBI_MIDELEG).unwrap();
+        self.write_raw_csr(CSR_MCOUNTEREN, SBI_MCOUNTEREN).unwrap();
+        // タイマはSstcのstimecmpで実装する。
+        self.write_raw_csr(CSR_MENVCFG, CSR_MENVCFG_STCE_MASK)
+            .unwrap();
+        self.write_raw_csr(CSR_PMPADDR0, SBI_PMPADDR).unwrap();
+        self.write_raw_csr(CSR_PMPCFG0, SBI_PMPCFG).unwrap();
+
+        self.builtin_sbi = true;
+        self.current_priv = Priv::S;
+    }
+
+    // Sモードからのecallを処理する関数
+    // a7に拡張ID、a6に関数ID、a0-a5に引数を設定して呼び出される。
+    // a0にエラーコード、a1に値を返す。レガシーな拡張の場合はa0に値のみを返す。
+    // XLENが32の場合、引数は下位32bitを使い、戻り値は符号拡張してレジスタに書き込む。
+    pub(crate) fn handle_sbi_call(&mut self) {
+        // a0-a7(x10-x17)
+        let mask = self.current_xlen().mask();
+        let args: [u64; 6] =
+            core::array::from_fn(|i| self.read_reg(Register::X(10 + i as u8)) & mask);
+        let fid = self.read_reg(Register::X(16)) & mask;
+        let eid = self.read_reg(Register::X(17)) & mask;
+
+        if self.trace {
+            eprintln!("[info]: SBI call eid: 0x{:x} fid: 0x{:x}", eid, fid);
+        }
+
+        let result = match eid {
+            SBI_EXT_LEGACY_CONSOLE_PUTCHAR => {
+                self.sbi_console_putchar(args[0] as u8);
+                self.write_reg(Register::X(10), 0);
+                return;
+            }
+            SBI_EXT_LEGACY_CONSOLE_GETCHAR => {
+                let c = match self.sbi_console_getchar() {
+                    Some(c) => c as u64,
+                    None => u64::MAX,
+                };
+
+                self.write_reg(Register::X(10), c);
+                return;
+            }
+            SBI_EXT_BASE => self.sbi_base(fid, args),
+            SBI_EXT_TIME => self.sbi_time(fid, args),
+            SBI_EXT_IPI => self.sbi_ipi(fid, args),
+            SBI_EXT_RFENCE => self.sbi_rfence(fid, args),
+            SBI_EXT_HSM => self.sbi_hsm(fid, args),
+            SBI_EXT_SRST => self.sbi_srst(fid, args),
+            SBI_EXT_DBCN => self.sbi_dbcn(fid, args),
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        };
+
+        let (error, value) = match result {
+            Ok(value) => (0, value),
+            Err(error) => (error as u64, 0),
+        };
+
+        self.write_reg(Register::X(10), error);
+        self.write_reg(Register::X(11), value);
+    }
+
+    fn sbi_base(&mut self, fid: u64, args: [u64; 6]) -> SbiResult {
+        match fid {
+            0 => Ok(SBI_SPEC_VERSION),
+            1 => Ok(SBI_IMPL_ID),
+            2 => Ok(SBI_IMPL_VERSION),
+            3 => Ok(SBI_EXTENSIONS.contains(&args[0]) as u64),
+            // mvendorid, marchid, mimpid
+            4..=6 => Ok(self.read_raw_csr(0xf11 + fid - 4).unwrap()),
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    // タイマはstimecmpに書き込むことで設定する。
+    // XLENが32の場合、64bitの時刻は下位をa0、上位をa1で渡される。
+    fn sbi_time(&mut self, fid: u64, args: [u64; 6]) -> SbiResult {
+        let stime_value = match self.current_xlen() {
+            Xlen::X32 => args[0] | (args[1] << 32),
+            Xlen::X64 => args[0],
+        };
+
+        match fid {
+            0 => {
+                self.write_raw_csr(CSR_STIMECMP, stime_value).unwrap();
+                Ok(0)
+            }
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    fn sbi_ipi(&mut self, fid: u64, args: [u64; 6]) -> SbiResult {
+        match fid {
+            0 => {
+                for hart in self.sbi_harts(args[0], args[1])? {
+                    // SSIP
+                    self.hart_csr_mut(hart as usize).raise_interrupt(0x2);
+                }
+
+                Ok(0)
+            }
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    // 命令キャッシュは持たないので、fence.iは何もしない。
+    // sfence.vmaはアドレスとASIDにかかわらずTLBをすべて破棄する。
+    fn sbi_rfence(&mut self, fid: u64, args: [u64; 6]) -> SbiResult {
+        match fid {
+            0..=2 => {
+                for hart in self.sbi_harts(args[0], args[1])? {
+                    if fid != 0 {
+                        self.hart_tlb_mut(hart as usize).flush_all();
+                    }
+                }
+
+                Ok(0)
+            }
+            // ハイパーバイザ拡張のhfenceはサポートしない。
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    // 停止しているハートはスケジューラが実行しない。
+    // 起動するハートはリセット直後の状態から、このSBIでSモードに移った状態にする。
+    fn sbi_hsm(&mut self, fid: u64, args: [u64; 6]) -> SbiResult {
+        let hart = args[0] as usize;
+        let hart_exists = hart < self.harts();
+
+        match fid {
+            // hart_start(a0にhartid、a1にopaqueを設定してstart_addrから実行する)
+            0 if !hart_exists => Err(SBI_ERR_INVALID_PARAM),
+            0 if !self.harts[hart].stopped => Err(SBI_ERR_ALREADY_AVAILABLE),
+            0 => {
+                let current = self.current_hart();
+
+                self.switch_hart(hart);
+                self.initialize_regs();
+                self.initialize_csr();
+                self.enter_supervisor_with_sbi();
+                self.bus.reservations.clear(hart);
+                self.write_reg(Register::X(10), args[0]);
+                self.write_reg(Register::X(11), args[2]);
+                self.write_reg(Register::Pc, args[1]);
+                self.switch_hart(current);
+
+                self.harts[hart].stopped = false;
+
+                Ok(0)
+            }
+            // hart_stop(最後のハートは止められない)
+            1 if self.harts.iter().filter(|hart| !hart.stopped).count() <= 1 => Err(SBI_ERR_FAILED),
+            1 => {
+                let current = self.current_hart();
+                self.harts[current].stopped = true;
+
+                Ok(0)
+            }
+            // hart_get_status
+            2 if !hart_exists => Err(SBI_ERR_INVALID_PARAM),
+            2 if self.harts[hart].stopped => Ok(SBI_HSM_STATE_STOPPED),
+            2 => Ok(SBI_HSM_STATE_STARTED),
+            // hart_suspend(保持する中断はwfiと同じなのですぐに戻る。保持しない中断はサポートしない。)
+            3 if args[0] as u32 as u64 == SBI_HSM_SUSPEND_RETENTIVE => Ok(0),
+            3 => Err(SBI_ERR_NOT_SUPPORTED),
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    // 再起動はできないので電源オフと同じように終了する。
+    // 理由がシステムの障害の場合は終了コードを1にする。
+    fn sbi_srst(&mut self, fid: u64, args: [u64; 6]) -> SbiResult {
+        let reset_type = args[0] as u32 as u64;
+        let reason = args[1] as u32 as u64;
+
+        match fid {
+            0 if reset_type <= SBI_SRST_TYPE_WARM_REBOOT
+                && reason <= SBI_SRST_REASON_SYSTEM_FAILURE =>
+            {
+                let code = (reset_type == SBI_SRST_TYPE_SHUTDOWN
+                    && reason == SBI_SRST_REASON_SYSTEM_FAILURE) as u64;
+
+                self.exit_code = Some(code);
+                self.riscv_tests_finished = true;
+
+                Ok(0)
+            }
+            0 => Err(SBI_ERR_INVALID_PARAM),
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    // アドレスは物理アドレスで、RV64では上位(base_addr_hi)は0でなければならない。
+    // XLENが32の場合はbase_addr_loとbase_addr_hiを合わせて64bitのアドレスにする。
+    fn sbi_dbcn(&mut self, fid: u64, args: [u64; 6]) -> SbiResult {
+        if self.sbi_console_base().is_none() {
+            return Err(SBI_ERR_FAILED);
+        }
+
+        let xlen = self.current_xlen();
+        let len = args[0];
+        let address = match xlen {
+            Xlen::X32 => args[1] | (args[2] << 32),
+            Xlen::X64 => args[1],
+        };
+
+        match fid {
+            0 | 1 if xlen == Xlen::X64 && args[2] != 0 => Err(SBI_ERR_INVALID_PARAM),
+            // console_write
+            // lenはゲストが指定するので、範囲を確認してから決まった大きさずつコピーする。
+            0 => {
+                if !self.sbi_memory_permitted(address, len, AccessType::Load) {
+                    return Err(SBI_ERR_INVALID_PARAM);
+                }
+
+                let mut chunk = [0; SBI_DBCN_CHUNK];
+                let mut offset = 0;
+
+                while offset < len {
+                    let size = (len - offset).min(SBI_DBCN_CHUNK as u64) as usize;
+
+                    self.bus.read(address + offset, &mut chunk[..size]);
+
+                    for &byte in &chunk[..size] {
+                        self.sbi_console_putchar(byte);
+                    }
+
+                    offset += size as u64;
+                }
+
+                Ok(len)
+            }
+            // console_read(読める分だけ読む)
+            1 => {
+                if !self.sbi_memory_permitted(address, len, AccessType::Store) {
+                    return Err(SBI_ERR_INVALID_PARAM);
+                }
+
+                let mut bytes = Vec::new();
+
+                while (bytes.len() as u64) < len {
+                    match self.sbi_console_getchar() {
+                        Some(c) => bytes.push(c),
+                        None => break,
+                    }
+                }
+
+                if !self.bus.write(address, &bytes) {
+                    return Err(SBI_ERR_INVALID_PARAM);
+                }
+
+                Ok(bytes.len() as u64)
+            }
+            // console_write_byte
+            2 => {
+                self.sbi_console_putchar(args[0] as u8);
+                Ok(0)
+            }
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    // hart_maskとhart_mask_baseで指定されたハートを返す関数
+    // hart_mask_baseが-1の場合はすべてのハートを表す。存在しないハートが含まれる場合はエラーにする。
+    fn sbi_harts(
+        &self,
+        hart_mask: u64,
+        hart_mask_base: u64,
+    ) -> core::result::Result<Vec<u64>, i64> {
+        let harts = self.harts() as u64;
+
+        if hart_mask_base == self.current_xlen().mask() {
+            return Ok((0..harts).collect());
+        }
+
+        let selected: Vec<u64> = (0..64)
+            .filter(|i| hart_mask & (1 << i) != 0)
+            .map(|i| hart_mask_base.wrapping_add(i))
+            .collect();
+
+        if selected.iter().any(|&hart| hart >= harts) {
+            return Err(SBI_ERR_INVALID_PARAM);
+        }
+
+        Ok(selected)
+    }
+
+    // RAMの範囲内で、Sモードからアクセスできる物理アドレスかをPMPで確認する関数
+    fn sbi_memory_permitted(&self, address: u64, len: u64, access: AccessType) -> bool {
+        address.checked_add(len).is_some()
+            && self.bus.dram_contains(address, len)
+            && self.csr.pmp.is_permitted(address, len, access, Priv::S)
+    }
+
+    // コンソールはデバイスツリーのstdout-pathと同じ最初に接続したUART
+    fn sbi_console_base(&self) -> Option<u64> {
+        self.bus
+            .device_tree_nodes()
+            .find(|(_, _, _, node)| node.name == "serial")
+            .map(|(base, _, _, _)| base)
+    }
+
+    fn sbi_console_putchar(&mut self, c: u8) {
+        if let Some(base) = self.sbi_console_base() {
+            self.bus.write(base + RBR_THR_DLL, &[c]);
+        }
+    }
+
+    fn sbi_console_getchar(&mut self) -> Option<u8> {
+        let base = self.sbi_console_base()?;
+        let mut lsr = [0];
+
+        if !self.bus.read(base + LSR, &mut lsr) || lsr[0] & LSR_DR == 0 {
+            return None;
+        }
+
+        let mut c = [0];
+        self.bus.read(base + RBR_THR_DLL, &mut c);
+
+        Some(c[0])
+    }
+}
@@ -0,0 +1,708 @@
+// IEEE 754の2進浮動小数点数の演算をソフトウェアで行うモジュール
+// ホストのFPUに依存せず、RISC-Vの丸めモードと例外フラグをSpike(Berkeley SoftFloat)と同じように扱う。
+// 値はビット列(u64)で扱い、形式(単精度、倍精度)はFormatで指定する。
+// NaNを返す場合はペイロードを伝播させず、常に正規化されたNaN(canonical NaN)を返す。
+// アンダーフローは丸めたあとで判定する(tininess after rounding)。
+
+// fflagsのビット
+pub(crate) const FLAG_NV: u64 = 0x10; // 無効な演算
+pub(crate) const FLAG_DZ: u64 = 0x08; // ゼロ除算
+pub(crate) const FLAG_OF: u64 = 0x04; // オーバーフロー
+pub(crate) const FLAG_UF: u64 = 0x02; // アンダーフロー
+pub(crate) const FLAG_NX: u64 = 0x01; // 不正確
+
+// 浮動小数点数の形式(指数部と仮数部のビット数)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Format {
+    exponent: u32,
+    fraction: u32,
+}
+
+pub(crate) const F32: Format = Format {
+    exponent: 8,
+    fraction: 23,
+};
+
+pub(crate) const F64: Format = Format {
+    exponent: 11,
+    fraction: 52,
+};
+
+impl Format {
+    fn bias(self) -> i32 {
+        (1 << (self.exponent - 1)) - 1
+    }
+
+    // 正規化数の最小の指数
+    fn min_exponent(self) -> i32 {
+        1 - self.bias()
+    }
+
+    // 無限大とNaNの(バイアスされた)指数
+    fn max_biased_exponent(self) -> u64 {
+        (1 << self.exponent) - 1
+    }
+
+    fn precision(self) -> i32 {
+        self.fraction as i32 + 1
+    }
+
+    fn fraction_mask(self) -> u64 {
+        (1 << self.fraction) - 1
+    }
+
+    fn quiet_mask(self) -> u64 {
+        1 << (self.fraction - 1)
+    }
+
+    pub(crate) fn sign_mask(self) -> u64 {
+        1 << (self.exponent + self.fraction)
+    }
+
+    // 形式のビット幅のマスク
+    pub(crate) fn mask(self) -> u64 {
+        (self.sign_mask() << 1).wrapping_sub(1)
+    }
+
+    fn pack(self, sign: bool, exponent: u64, fraction: u64) -> u64 {
+        if sign {
+            self.sign_mask() | exponent << self.fraction | fraction
+        } else {
+            exponent << self.fraction | fraction
+        }
+    }
+
+    fn zero(self, sign: bool) -> u64 {
+        self.pack(sign, 0, 0)
+    }
+
+    fn infinity(self, sign: bool) -> u64 {
+        self.pack(sign, self.max_biased_exponent(), 0)
+    }
+
+    fn max_finite(self, sign: bool) -> u64 {
+        self.pack(sign, self.max_biased_exponent() - 1, self.fraction_mask())
+    }
+
+    pub(crate) fn canonical_nan(self) -> u64 {
+        self.pack(false, self.max_biased_exponent(), self.quiet_mask())
+    }
+
+    pub(crate) fn is_nan(self, bits: u64) -> bool {
+        matches!(self.unpack(bits).1, Value::Nan(_))
+    }
+
+    fn is_signaling_nan(self, bits: u64) -> bool {
+        matches!(self.unpack(bits).1, Value::Nan(true))
+    }
+
+    fn unpack(self, bits: u64) -> (bool, Value) {
+        let sign = bits & self.sign_mask() != 0;
+        let exponent = (bits >> self.fraction) & self.max_biased_exponent();
+        let fraction = bits & self.fraction_mask();
+
+        let value = if exponent == self.max_biased_exponent() {
+            if fraction == 0 {
+                Value::Infinity
+            } else {
+                Value::Nan(fraction & self.quiet_mask() == 0)
+            }
+        } else if exponent == 0 {
+            if fraction == 0 {
+                Value::Zero
+            } else {
+                Value::Finite(fraction as u128, self.min_exponent() - self.fraction as i32)
+            }
+        } else {
+            Value::Finite(
+                (fraction | 1 << self.fraction) as u128,
+                exponent as i32 - self.bias() - self.fraction as i32,
+            )
+        };
+
+        (sign, value)
+    }
+}
+
+// 符号を除いた値
+// 有限の値は仮数 * 2^指数で表す。NaNはシグナリングかどうかを持つ。
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Zero,
+    Finite(u128, i32),
+    Infinity,
+    Nan(bool),
+}
+
+// 丸めモード(frmと命令のrmフィールドの値)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RoundingMode {
+    NearestEven,
+    TowardZero,
+    Down,
+    Up,
+    NearestMaxMagnitude,
+}
+
+impl RoundingMode {
+    // 予約されている値(5, 6)と動的丸め(7)の場合はNoneを返す。
+    pub(crate) fn from_bits(bits: u64) -> Option<Self> {
+        match bits {
+            0 => Some(Self::NearestEven),
+            1 => Some(Self::TowardZero),
+            2 => Some(Self::Down),
+            3 => Some(Self::Up),
+            4 => Some(Self::NearestMaxMagnitude),
+            _ => None,
+        }
+    }
+}
+
+// 浮動小数点数の演算器
+// 演算で起こった例外はflagsに累積する。
+#[derive(Debug)]
+pub(crate) struct SoftFloat {
+    rounding: RoundingMode,
+    pub(crate) flags: u64,
+}
+
+impl SoftFloat {
+    pub(crate) fn new(rounding: RoundingMode) -> Self {
+        Self { rounding, flags: 0 }
+    }
+
+    // NaNが含まれる演算の結果を返す関数
+    // シグナリングNaNが含まれる場合は無効な演算になる。
+    fn propagate_nan(&mut self, format: Format, operands: &[u64]) -> u64 {
+        if operands.iter().any(|&bits| format.is_signaling_nan(bits)) {
+            self.flags |= FLAG_NV;
+        }
+
+        format.canonical_nan()
+    }
+
+    fn invalid(&mut self, format: Format) -> u64 {
+        self.flags |= FLAG_NV;
+        format.canonical_nan()
+    }
+
+    // 厳密な結果が0になった場合の符号(切り下げの場合のみ負)
+    fn exact_zero_sign(&self) -> bool {
+        self.rounding == RoundingMode::Down
+    }
+
+    // sigをshiftビット右にシフトして整数に丸める関数
+    // 丸めた値と、不正確(捨てたビットが0でない)かどうかを返す。
+    fn round_shift(&self, sign: bool, sig: u128, shift: i32) -> (u128, bool) {
+        if shift <= 0 {
+            return (sig << -shift, false);
+        }
+
+        let shift = shift as u32;
+        let truncated = sig.checked_shr(shift).unwrap_or(0);
+        let round = sig.checked_shr(shift - 1).unwrap_or(0) & 1 != 0;
+        let sticky = sig
+            & 1u128
+                .checked_shl(shift - 1)
+                .map_or(u128::MAX, |bit| bit - 1)
+            != 0;
+        let inexact = round || sticky;
+
+        let increment = match self.rounding {
+            RoundingMode::NearestEven => round && (sticky || truncated & 1 != 0),
+            RoundingMode::TowardZero => false,
+            RoundingMode::Down => sign && inexact,
+            RoundingMode::Up => !sign && inexact,
+            RoundingMode::NearestMaxMagnitude => round,
+        };
+
+        (truncated + increment as u128, inexact)
+    }
+
+    // sig * 2^exponentを丸めて形式に合わせた値にする関数
+    // sigの最下位ビットは、それより下に0でないビットがあることを表すスティッキービットでもよい。
+    // 呼び出す側は丸める位置より2ビット以上下までsigに含める必要がある。
+    fn round_pack(&mut self, format: Format, sign: bool, sig: u128, exponent: i32) -> u64 {
+        if sig == 0 {
+            return format.zero(sign);
+        }
+
+        let precision = format.precision();
+        let min_exponent = format.min_exponent();
+
+        // 値は[2^top, 2^(top + 1))の範囲にある。
+        let top = exponent + 127 - sig.leading_zeros() as i32;
+
+        // 丸めたあとの最下位ビットの指数(非正規化数の場合は固定になる)
+        let unbounded_lsb = top - (precision - 1);
+        let mut lsb = unbounded_lsb.max(min_exponent - (precision - 1));
+
+        let (mut rounded, inexact) = self.round_shift(sign, sig, lsb - exponent);
+
+        // 繰り上がって桁が増えた場合
+        if rounded >> precision != 0 {
+            rounded >>= 1;
+            lsb += 1;
+        }
+
+        if inexact {
+            self.flags |= FLAG_NX;
+
+            // 指数の範囲に制限がないとして丸めた結果が正規化数の最小値より小さい場合にアンダーフローになる。
+            if top < min_exponent {
+                let (unbounded, _) = self.round_shift(sign, sig, unbounded_lsb - exponent);
+
+                if !(unbounded >> precision != 0 && top + 1 == min_exponent) {
+                    self.flags |= FLAG_UF;
+                }
+            }
+        }
+
+        // 非正規化数(または0)
+        if rounded >> (precision - 1) == 0 {
+            return format.pack(sign, 0, rounded as u64);
+        }
+
+        let biased = lsb + (precision - 1) + format.bias();
+
+        if biased >= format.max_biased_exponent() as i32 {
+            self.flags |= FLAG_OF | FLAG_NX;
+
+            let infinity = match self.rounding {
+                RoundingMode::NearestEven | RoundingMode::NearestMaxMagnitude => true,
+                RoundingMode::TowardZero => false,
+                RoundingMode::Down => sign,
+                RoundingMode::Up => !sign,
+            };
+
+            return if infinity {
+                format.infinity(sign)
+            } else {
+                format.max_finite(sign)
+            };
+        }
+
+        format.pack(sign, biased as u64, rounded as u64 & format.fraction_mask())
+    }
+
+    // 0でない有限の値どうしを加算する関数
+    // 仮数の最上位ビットを125ビット目に揃えてから、小さい方を右にシフトして足す。
+    #[allow(clippy::too_many_arguments)]
+    fn add_finite(
+        &mut self,
+        format: Format,
+        sign_a: bool,
+        sig_a: u128,
+        exp_a: i32,
+        sign_b: bool,
+        sig_b: u128,
+        exp_b: i32,
+    ) -> u64 {
+        let (sig_a, exp_a) = normalize(sig_a, exp_a);
+        let (sig_b, exp_b) = normalize(sig_b, exp_b);
+
+        let ((sign_x, sig_x, exp_x), (sign_y, sig_y, exp_y)) = if (exp_a, sig_a) >= (exp_b, sig_b) {
+            ((sign_a, sig_a, exp_a), (sign_b, sig_b, exp_b))
+        } else {
+            ((sign_b, sig_b, exp_b), (sign_a, sig_a, exp_a))
+        };
+
+        let sig_y = shift_right_jam(sig_y, (exp_x - exp_y) as u32);
+
+        let sig = if sign_x == sign_y {
+            sig_x + sig_y
+        } else {
+            sig_x - sig_y
+        };
+
+        if sig == 0 {
+            return format.zero(self.exact_zero_sign());
+        }
+
+        self.round_pack(format, sign_x, sig, exp_x)
+    }
+
+    pub(crate) fn add(&mut self, format: Format, a: u64, b: u64) -> u64 {
+        let (sign_a, value_a) = format.unpack(a);
+        let (sign_b, value_b) = format.unpack(b);
+
+        match (value_a, value_b) {
+            (Value::Nan(_), _) | (_, Value::Nan(_)) => self.propagate_nan(format, &[a, b]),
+            (Value::Infinity, Value::Infinity) if sign_a != sign_b => self.invalid(format),
+            (Value::Infinity, _) => format.infinity(sign_a),
+            (_, Value::Infinity) => format.infinity(sign_b),
+            (Value::Zero, Value::Zero) if sign_a == sign_b => format.zero(sign_a),
+            (Value::Zero, Value::Zero) => format.zero(self.exact_zero_sign()),
+            (Value::Zero, _) => b,
+            (_, Value::Zero) => a,
+            (Value::Finite(sig_a, exp_a), Value::Finite(sig_b, exp_b)) => {
+                self.add_finite(format, sign_a, sig_a, exp_a, sign_b, sig_b, exp_b)
+            }
+        }
+    }
+
+    pub(crate) fn sub(&mut self, format: Format, a: u64, b: u64) -> u64 {
+        // NaNの符号は結果に影響しないので、符号を反転して加算する。
+        self.add(format, a, b ^ format.sign_mask())
+    }
+
+    pub(crate) fn mul(&mut self, format: Format, a: u64, b: u64) -> u64 {
+        let (sign_a, value_a) = format.unpack(a);
+        let (sign_b, value_b) = format.unpack(b);
+        let sign = sign_a != sign_b;
+
+        match (value_a, value_b) {
+            (Value::Nan(_), _) | (_, Value::Nan(_)) => self.propagate_nan(format, &[a, b]),
+            (Value::Infinity, Value::Zero) | (Value::Zero, Value::Infinity) => self.invalid(format),
+            (Value::Infinity, _) | (_, Value::Infinity) => format.infinity(sign),
+            (Value::Zero, _) | (_, Value::Zero) => format.zero(sign),
+            (Value::Finite(sig_a, exp_a), Value::Finite(sig_b, exp_b)) => {
+                self.round_pack(format, sign, sig_a * sig_b, exp_a + exp_b)
+            }
+        }
+    }
+
+    pub(crate) fn div(&mut self, format: Format, a: u64, b: u64) -> u64 {
+        let (sign_a, value_a) = format.unpack(a);
+        let (sign_b, value_b) = format.unpack(b);
+        let sign = sign_a != sign_b;
+
+        match (value_a, value_b) {
+            (Value::Nan(_), _) | (_, Value::Nan(_)) => self.propagate_nan(format, &[a, b]),
+            (Value::Infinity, Value::Infinity) | (Value::Zero, Value::Zero) => self.invalid(format),
+            (Value::Infinity, _) => format.infinity(sign),
+            (_, Value::Infinity) => format.zero(sign),
+            (Value::Zero, _) => format.zero(sign),
+            (_, Value::Zero) => {
+                self.flags |= FLAG_DZ;
+                format.infinity(sign)
+            }
+            (Value::Finite(sig_a, exp_a), Value::Finite(sig_b, exp_b)) => {
+                // 商が丸めに必要な精度を持つように、被除数を左にシフトしてから割る。
+                let (sig_a, exp_a) = normalize(sig_a, exp_a);
+                let quotient = sig_a / sig_b;
+                let sticky = (sig_a % sig_b != 0) as u128;
+
+                self.round_pack(format, sign, quotient | sticky, exp_a - exp_b)
+            }
+        }
+    }
+
+    pub(crate) fn sqrt(&mut self, format: Format, a: u64) -> u64 {
+        let (sign, value) = format.unpack(a);
+
+        match value {
+            Value::Nan(_) => self.propagate_nan(format, &[a]),
+            Value::Zero => a,
+            _ if sign => self.invalid(format),
+            Value::Infinity => a,
+            Value::Finite(sig, exp) => {
+                // 指数が偶数になるように、仮数を偶数ビットだけ左にシフトする。
+                let (sig, exp) = if exp & 1 != 0 {
+                    (sig << 1, exp - 1)
+                } else {
+                    (sig, exp)
+                };
+                let shift = (sig.leading_zeros() as i32 - 2) & !1;
+                let (root, remainder) = isqrt(sig << shift);
+
+                self.round_pack(
+                    format,
+                    false,
+                    root | (remainder != 0) as u128,
+                    (exp - shift) / 2,
+                )
+            }
+        }
+    }
+
+    // a * b + cを一度だけ丸めて計算する関数
+    pub(crate) fn mul_add(&mut self, format: Format, a: u64, b: u64, c: u64) -> u64 {
+        let (sign_a, value_a) = format.unpack(a);
+        let (sign_b, value_b) = format.unpack(b);
+        let (sign_c, value_c) = format.unpack(c);
+        let sign_product = sign_a != sign_b;
+
+        match (value_a, value_b, value_c) {
+            (Value::Nan(_), _, _) | (_, Value::Nan(_), _) => self.propagate_nan(format, &[a, b, c]),
+            // 0 * ∞はcがNaNでも無効な演算になる。
+            (Value::Infinity, Value::Zero, _) | (Value::Zero, Value::Infinity, _) => {
+                self.invalid(format)
+            }
+            (_, _, Value::Nan(_)) => self.propagate_nan(format, &[c]),
+            (Value::Infinity, _, Value::Infinity) | (_, Value::Infinity, Value::Infinity)
+                if sign_product != sign_c =>
+            {
+                self.invalid(format)
+            }
+            (Value::Infinity, _, _) | (_, Value::Infinity, _) => format.infinity(sign_product),
+            (_, _, Value::Infinity) => format.infinity(sign_c),
+            (Value::Zero, _, Value::Zero) | (_, Value::Zero, Value::Zero) => {
+                if sign_product == sign_c {
+                    format.zero(sign_c)
+                } else {
+                    format.zero(self.exact_zero_sign())
+                }
+            }
+            (Value::Zero, _, _) | (_, Value::Zero, _) => c,
+            (Value::Finite(sig_a, exp_a), Value::Finite(sig_b, exp_b), Value::Zero) => {
+                self.round_pack(format, sign_product, sig_a * sig_b, exp_a + exp_b)
+            }
+            (
+                Value::Finite(sig_a, exp_a),
+                Value::Finite(sig_b, exp_b),
+                Value::Finite(sig_c, exp_c),
+            ) => self.add_finite(
+                format,
+                sign_product,
+                sig_a * sig_b,
+                exp_a + exp_b,
+                sign_c,
+                sig_c,
+                exp_c,
+            ),
+        }
+    }
+
+    // 浮動小数点数を整数に変換する関数
+    // widthビットの符号付き(signed)または符号なし整数に丸め、範囲外の場合は最大値か最小値にする。
+    // 結果はwidthビットの2の補数で返す。
+    pub(crate) fn round_to_int(&mut self, format: Format, a: u64, signed: bool, width: u32) -> u64 {
+        let mask = u64::MAX >> (64 - width);
+        let (max, min) = if signed {
+            (mask >> 1, (mask >> 1) + 1)
+        } else {
+            (mask, 0)
+        };
+
+        let (sign, value) = format.unpack(a);
+
+        let (magnitude, inexact) = match value {
+            Value::Nan(_) => {
+                self.flags |= FLAG_NV;
+                return max;
+            }
+            Value::Infinity => (u128::MAX, false),
+            Value::Zero => (0, false),
+            // 仮数は64ビット未満なので、指数が64より大きい場合は必ず範囲外になる。
+            Value::Finite(_, exp) if exp > 64 => (u128::MAX, false),
+            Value::Finite(sig, exp) => self.round_shift(sign, sig, -exp),
+        };
+
+        // 負の最小値の絶対値は最大値より1大きい。符号なしの場合は0のみ表せる。
+        let limit = if sign {
+            if signed {
+                max as u128 + 1
+            } else {
+                0
+            }
+        } else {
+            max as u128
+        };
+
+        if magnitude > limit {
+            self.flags |= FLAG_NV;
+            return if sign { min } else { max };
+        }
+
+        if inexact {
+            self.flags |= FLAG_NX;
+        }
+
+        if sign {
+            (magnitude as u64).wrapping_neg() & mask
+        } else {
+            magnitude as u64
+        }
+    }
+
+    // widthビットの符号付き(signed)または符号なし整数を浮動小数点数に変換する関数
+    pub(crate) fn int_to_float(
+        &mut self,
+        format: Format,
+        value: u64,
+        signed: bool,
+        width: u32,
+    ) -> u64 {
+        let value = value & (u64::MAX >> (64 - width));
+        let negative = signed && (value >> (width - 1)) & 1 != 0;
+
+        let magnitude = if negative {
+            value.wrapping_neg() & (u64::MAX >> (64 - width))
+        } else {
+            value
+        };
+
+        self.round_pack(format, negative, magnitude as u128, 0)
+    }
+
+    // 形式を変換する関数(単精度と倍精度の間の変換)
+    pub(crate) fn convert(&mut self, from: Format, to: Format, a: u64) -> u64 {
+        let (sign, value) = from.unpack(a);
+
+        match value {
+            Value::Nan(_) => {
+                if from.is_signaling_nan(a) {
+                    self.flags |= FLAG_NV;
+                }
+
+                to.canonical_nan()
+            }
+            Value::Infinity => to.infinity(sign),
+            Value::Zero => to.zero(sign),
+            Value::Finite(sig, exp) => self.round_pack(to, sign, sig, exp),
+        }
+    }
+
+    // a == bを返す関数
+    // 比較はquietなので、シグナリングNaNの場合のみ無効な演算になる。
+    pub(crate) fn eq(&mut self, format: Format, a: u64, b: u64) -> bool {
+        if format.is_nan(a) || format.is_nan(b) {
+            self.propagate_nan(format, &[a, b]);
+            return false;
+        }
+
+        a == b || is_zero(format, a) && is_zero(format, b)
+    }
+
+    // a < bを返す関数
+    // 比較はsignalingなので、NaNが含まれる場合は無効な演算になる。
+    pub(crate) fn lt(&mut self, format: Format, a: u64, b: u64) -> bool {
+        if format.is_nan(a) || format.is_nan(b) {
+            self.flags |= FLAG_NV;
+            return false;
+        }
+
+        less(format, a, b)
+    }
+
+    // a <= bを返す関数
+    pub(crate) fn le(&mut self, format: Format, a: u64, b: u64) -> bool {
+        if format.is_nan(a) || format.is_nan(b) {
+            self.flags |= FLAG_NV;
+            return false;
+        }
+
+        !less(format, b, a)
+    }
+
+    // IEEE 754-2019のminimumNumberとmaximumNumber
+    // 片方のみがNaNの場合はもう片方を返し、-0は+0より小さいとして扱う。
+    pub(crate) fn min(&mut self, format: Format, a: u64, b: u64) -> u64 {
+        self.min_max(format, a, b, true)
+    }
+
+    pub(crate) fn max(&mut self, format: Format, a: u64, b: u64) -> u64 {
+        self.min_max(format, a, b, false)
+    }
+
+    fn min_max(&mut self, format: Format, a: u64, b: u64, min: bool) -> u64 {
+        match (format.is_nan(a), format.is_nan(b)) {
+            (true, true) => self.propagate_nan(format, &[a, b]),
+            (true, false) => {
+                self.propagate_nan(format, &[a]);
+                b
+            }
+            (false, true) => {
+                self.propagate_nan(format, &[b]);
+                a
+            }
+            (false, false) => {
+                // 符号付きゼロも正しく順序付けるため、ゼロどうしは符号ビットで比べる。
+                let a_less = if is_zero(format, a) && is_zero(format, b) {
+                    a & format.sign_mask() > b & format.sign_mask()
+                } else {
+                    less(format, a, b)
+                };
+
+                if a_less == min {
+                    a
+                } else {
+                    b
+                }
+            }
+        }
+    }
+}
+
+// fclassの結果(ビットの位置が分類を表す)
+pub(crate) fn classify(format: Format, a: u64) -> u64 {
+    let (sign, value) = format.unpack(a);
+
+    let bit = match value {
+        Value::Infinity => 0,
+        Value::Finite(sig, _) if sig >> format.fraction != 0 => 1,
+        Value::Finite(..) => 2,
+        Value::Zero => 3,
+        Value::Nan(true) => return 1 << 8,
+        Value::Nan(false) => return 1 << 9,
+    };
+
+    if sign {
+        1 << bit
+    } else {
+        1 << (7 - bit)
+    }
+}
+
+fn is_zero(format: Format, a: u64) -> bool {
+    a & !format.sign_mask() == 0
+}
+
+// NaNでない値どうしでa < bを返す関数
+fn less(format: Format, a: u64, b: u64) -> bool {
+    let sign_a = a & format.sign_mask() != 0;
+    let sign_b = b & format.sign_mask() != 0;
+
+    if is_zero(format, a) && is_zero(format, b) {
+        false
+    } else if sign_a != sign_b {
+        sign_a
+    } else if sign_a {
+        a > b
+    } else {
+        a < b
+    }
+}
+
+// 仮数の最上位ビットが125ビット目になるように左にシフトする関数
+fn normalize(sig: u128, exp: i32) -> (u128, i32) {
+    let shift = sig.leading_zeros() as i32 - 2;
+
+    (sig << shift, exp - shift)
+}
+
+// 右にシフトし、捨てたビットが0でない場合は最下位ビットを1にする関数
+fn shift_right_jam(sig: u128, shift: u32) -> u128 {
+    if shift == 0 {
+        sig
+    } else if shift >= 128 {
+        (sig != 0) as u128
+    } else {
+        (sig >> shift) | (sig & ((1 << shift) - 1) != 0) as u128
+    }
+}
+
+// 整数の平方根(切り捨て)と余りを返す関数
+fn isqrt(n: u128) -> (u128, u128) {
+    let mut remainder = n;
+    let mut root = 0;
+    let mut bit = 1 << 126;
+
+    while bit > n {
+        bit >>= 2;
+    }
+
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+
+        bit >>= 2;
+    }
+
+    (root, remainder)
+}
@@ -0,0 +1,48 @@
+use crate::bus::Device;
+
+// システムコントローラを配置する物理アドレスとサイズ(QEMUのvirtと同じ)
+pub const SYSCON_BASE: u64 = 0x0010_0000;
+pub const SYSCON_SIZE: u64 = 0x1000;
+
+// 下位16bitに書き込む値
+// FAILの場合は上位16bitが終了コードになる。
+const SYSCON_FAIL: u32 = 0x3333;
+pub(crate) const SYSCON_PASS: u32 = 0x5555;
+pub(crate) const SYSCON_RESET: u32 = 0x7777;
+
+// SiFiveのtest finisher互換のシステムコントローラ
+// ゲストの電源オフやリセットの要求を受け取り、エミュレータを終了させる。
+// リセットは再起動せずに終了コード0の終了として扱う。
+#[derive(Debug, Default)]
+pub struct Syscon {
+    exit_code: Option<u64>,
+}
+
+impl Syscon {
+    // 電源オフかリセットが要求された場合は終了コードを返す関数
+    pub(crate) fn exit_code(&self) -> Option<u64> {
+        self.exit_code
+    }
+}
+
+impl Device for Syscon {
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64> {
+        (offset == 0 && size == 4).then_some(0)
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> bool {
+        if offset != 0 || size != 4 {
+            return false;
+        }
+
+        let value = value as u32;
+
+        match value & 0xffff {
+            SYSCON_FAIL => self.exit_code = Some((value >> 16) as u64),
+            SYSCON_PASS | SYSCON_RESET => self.exit_code = Some(0),
+            _ => {}
+        }
+
+        true
+    }
+}
@@ -0,0 +1,167 @@
+use crate::mmu::{AccessType, PTE_D};
+
+// TLBの設定
+// splitがtrueの場合は命令用とデータ用のTLBを分けて、それぞれentriesエントリ持つ。
+// entriesは2のべき乗に切り上げる。0の場合はTLBを使用しない。
+#[derive(Debug, Clone, Copy)]
+pub struct TlbConfig {
+    pub split: bool,
+    pub entries: usize,
+}
+
+impl Default for TlbConfig {
+    fn default() -> Self {
+        Self {
+            split: false,
+            entries: 256,
+        }
+    }
+}
+
+// TLBのヒット数とミス数
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TlbStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// 4KiBのページごとの変換結果
+// スーパーページの場合もアクセスした4KiBのページごとにエントリを作る。
+// shiftはスーパーページが含むVPNの下位のビット数で、sfence.vmaでスーパーページ全体を無効化するために保持する。
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TlbEntry {
+    pub(crate) vpn: u64,
+    pub(crate) asid: u16,
+    pub(crate) global: bool,
+    pub(crate) shift: u64,
+    pub(crate) ppn: u64,
+    pub(crate) pte: u64,
+}
+
+impl TlbEntry {
+    // vaddrがこのエントリのページ(スーパーページの場合はその全体)に含まれるかを判定する関数
+    fn contains(&self, vaddr: u64) -> bool {
+        (self.vpn >> self.shift) == ((vaddr >> 12) >> self.shift)
+    }
+
+    fn matches_asid(&self, asid: u16) -> bool {
+        self.global || self.asid == asid
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Tlb {
+    split: bool,
+    data: Vec<Option<TlbEntry>>,
+    instruction: Vec<Option<TlbEntry>>,
+    stats: TlbStats,
+}
+
+impl Default for Tlb {
+    fn default() -> Self {
+        Self::new(TlbConfig::default())
+    }
+}
+
+impl Tlb {
+    pub(crate) fn new(config: TlbConfig) -> Self {
+        let entries = if config.entries == 0 {
+            0
+        } else {
+            config.entries.next_power_of_two()
+        };
+
+        Self {
+            split: config.split,
+            data: vec![None; entries],
+            instruction: if config.split {
+                vec![None; entries]
+            } else {
+                Vec::new()
+            },
+            stats: TlbStats::default(),
+        }
+    }
+
+    // 同じ構成のTLBを作るための設定を返す関数
+    pub(crate) fn config(&self) -> TlbConfig {
+        TlbConfig {
+            split: self.split,
+            entries: self.data.len(),
+        }
+    }
+
+    // ダイレクトマップのインデックス
+    // 上位のビットも混ぜてコードとデータのページが衝突しにくくする。
+    fn index(vpn: u64, len: usize) -> usize {
+        let bits = len.trailing_zeros();
+
+        (vpn ^ (vpn >> bits)) as usize & (len - 1)
+    }
+
+    fn table(&mut self, access: AccessType) -> &mut Vec<Option<TlbEntry>> {
+        if self.split && access == AccessType::Instruction {
+            &mut self.instruction
+        } else {
+            &mut self.data
+        }
+    }
+
+    // 変換結果を探す関数
+    // ストアでDビットが立っていないエントリはページテーブルを更新する必要があるのでミスとして扱う。
+    pub(crate) fn lookup(&mut self, vaddr: u64, asid: u16, access: AccessType) -> Option<TlbEntry> {
+        let vpn = vaddr >> 12;
+        let table = self.table(access);
+
+        let entry = if table.is_empty() {
+            None
+        } else {
+            table[Self::index(vpn, table.len())].filter(|entry| {
+                entry.vpn == vpn
+                    && entry.matches_asid(asid)
+                    && (access != AccessType::Store || entry.pte & PTE_D != 0)
+            })
+        };
+
+        if entry.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+
+        entry
+    }
+
+    pub(crate) fn insert(&mut self, entry: TlbEntry, access: AccessType) {
+        let table = self.table(access);
+
+        if !table.is_empty() {
+            let index = Self::index(entry.vpn, table.len());
+            table[index] = Some(entry);
+        }
+    }
+
+    // sfence.vmaに対応する無効化を行う関数
+    // vaddrがNoneの場合はすべてのアドレス、asidがNoneの場合はすべてのアドレス空間が対象になる。
+    // asidを指定した場合はグローバルなエントリは無効化しない。
+    pub(crate) fn flush(&mut self, vaddr: Option<u64>, asid: Option<u16>) {
+        for entry in self.data.iter_mut().chain(self.instruction.iter_mut()) {
+            let matched = entry.is_some_and(|e| {
+                vaddr.is_none_or(|vaddr| e.contains(vaddr))
+                    && asid.is_none_or(|asid| !e.global && e.asid == asid)
+            });
+
+            if matched {
+                *entry = None;
+            }
+        }
+    }
+
+    pub(crate) fn flush_all(&mut self) {
+        self.flush(None, None);
+    }
+
+    pub(crate) fn stats(&self) -> TlbStats {
+        self.stats
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::VecDeque,
+    io::{self, IsTerminal, Read, Write},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::{bus::Device, device_tree::DeviceTreeNode};
+
+// UARTを配置する物理アドレスとサイズ、PLICの割り込み番号(QEMUのvirtと同じ)
+pub const UART_BASE: u64 = 0x1000_0000;
+pub const UART_SIZE: u64 = 0x100;
+pub const UART_IRQ: u32 = 10;
+
+// デバイスツリーに書く入力クロックの周波数(QEMUのvirtと同じ)
+// 送受信は即座に行うのでボーレートには影響しない。
+const UART_CLOCK_FREQUENCY: u32 = 3_686_400;
+
+// レジスタのオフセット
+// LCRのDLABが1の場合、0と1は分周器のラッチ(DLL, DLM)になる。
+pub(crate) const RBR_THR_DLL: u64 = 0;
+const IER_DLM: u64 = 1;
+const IIR_FCR: u64 = 2;
+const LCR: u64 = 3;
+const MCR: u64 = 4;
+pub(crate) const LSR: u64 = 5;
+const MSR: u64 = 6;
+const SCR: u64 = 7;
+
+const IER_RDI: u8 = 1 << 0; // 受信データの割り込み
+const IER_THRI: u8 = 1 << 1; // 送信バッファが空になったときの割り込み
+
+const IIR_NO_INT: u8 = 0x01;
+const IIR_THRI: u8 = 0x02;
+const IIR_RDI: u8 = 0x04;
+const IIR_FIFO_ENABLED: u8 = 0xc0;
+
+const FCR_FIFO_ENABLE: u8 = 1 << 0;
+
+const LCR_DLAB: u8 = 1 << 7;
+
+pub(crate) const LSR_DR: u8 = 1 << 0;
+const LSR_THRE: u8 = 1 << 5;
+const LSR_TEMT: u8 = 1 << 6;
+
+// CTS, DSR, DCDが有効になっている状態
+const MSR_DEFAULT: u8 = 0xb0;
+
+// ホストの端末をrawモードにし、終了時に元の設定に戻す構造体
+// 外部クレートを使わないようにsttyで設定する。
+struct RawTerminal {
+    saved: String,
+}
+
+impl RawTerminal {
+    fn enable() -> Option<Self> {
+        if !io::stdin().is_terminal() {
+            return None;
+        }
+
+        let output = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()
+            .ok()?;
+
+        let saved = String::from_utf8(output.stdout).ok()?.trim().to_string();
+
+        // Ctrl-Cでエミュレータを終了できるようにISIGは残す。
+        Command::new("stty")
+            .args(["-icanon", "-echo", "-icrnl", "min", "1"])
+            .stdin(Stdio::inherit())
+            .status()
+            .ok()?;
+
+        Some(Self { saved })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = Command::new("stty")
+            .arg(&self.saved)
+            .stdin(Stdio::inherit())
+            .status();
+    }
+}
+
+// NS16550A互換のUART
+// 受信したデータはinputのキューから取り出し、送信したデータはすぐにoutputに書き込む。
+// 送信は常にすぐ完了するので、THRは常に空になっている。
+pub struct Uart {
+    input: Arc<Mutex<VecDeque<u8>>>,
+    output: Box<dyn Write>,
+    ier: u8,
+    fcr: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    dll: u8,
+    dlm: u8,
+    // THRが空になったことによる割り込みが保留されているかどうか
+    thr_pending: bool,
+    _terminal: Option<RawTerminal>,
+}
+
+impl Uart {
+    // 入力のキューと出力先を指定してUARTを作る関数
+    pub fn new(input: Arc<Mutex<VecDeque<u8>>>, output: Box<dyn Write>) -> Self {
+        Self {
+            input,
+            output,
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            dll: 0,
+            dlm: 0,
+            thr_pending: false,
+            _terminal: None,
+        }
+    }
+
+    // ホストの標準入出力に接続したUARTを作る関数
+    // 端末の場合はrawモードにし、標準入力はスレッドで読み込んでキューに入れるのでブロックしない。
+    pub fn stdio() -> Self {
+        let input = Arc::new(Mutex::new(VecDeque::new()));
+        let queue = Arc::clone(&input);
+
+        let terminal = RawTerminal::enable();
+
+        thread::spawn(move || {
+            let mut buf = [0; 64];
+            let mut stdin = io::stdin();
+
+            while let Ok(n) = stdin.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+
+                queue.lock().unwrap().extend(&buf[..n]);
+            }
+        });
+
+        let mut uart = Self::new(input, Box::new(io::stdout()));
+        uart._terminal = terminal;
+
+        uart
+    }
+
+    fn has_input(&self) -> bool {
+        !self.input.lock().unwrap().is_empty()
+    }
+
+    // 現在の割り込みの要因を返す関数
+    // 受信データの割り込みが送信の割り込みより優先される。
+    fn interrupt_id(&self) -> u8 {
+        if self.ier & IER_RDI != 0 && self.has_input() {
+            IIR_RDI
+        } else if self.ier & IER_THRI != 0 && self.thr_pending {
+            IIR_THRI
+        } else {
+            IIR_NO_INT
+        }
+    }
+}
+
+impl Device for Uart {
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64> {
+        if size != 1 {
+            return None;
+        }
+
+        let dlab = self.lcr & LCR_DLAB != 0;
+
+        let value = match offset {
+            RBR_THR_DLL if dlab => self.dll,
+            RBR_THR_DLL => self.input.lock().unwrap().pop_front().unwrap_or(0),
+            IER_DLM if dlab => self.dlm,
+            IER_DLM => self.ier,
+            IIR_FCR => {
+                let id = self.interrupt_id();
+
+                // THRの割り込みはIIRを読み込んだときに解除される。
+                if id == IIR_THRI {
+                    self.thr_pending = false;
+                }
+
+                if self.fcr & FCR_FIFO_ENABLE != 0 {
+                    id | IIR_FIFO_ENABLED
+                } else {
+                    id
+                }
+            }
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => {
+                let dr = if self.has_input() { LSR_DR } else { 0 };
+
+                LSR_THRE | LSR_TEMT | dr
+            }
+            MSR => MSR_DEFAULT,
+            SCR => self.scr,
+            _ => return None,
+        };
+
+        Some(value as u64)
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> bool {
+        if size != 1 {
+            return false;
+        }
+
+        let value = value as u8;
+        let dlab = self.lcr & LCR_DLAB != 0;
+
+        match offset {
+            RBR_THR_DLL if dlab => self.dll = value,
+            RBR_THR_DLL => {
+                let _ = self.output.write_all(&[value]);
+                let _ = self.output.flush();
+
+                self.thr_pending = true;
+            }
+            IER_DLM if dlab => self.dlm = value,
+            IER_DLM => {
+                // 送信の割り込みを有効にした時点でTHRは空なので割り込みを起こす。
+                if self.ier & IER_THRI == 0 && value & IER_THRI != 0 {
+                    self.thr_pending = true;
+                }
+
+                self.ier = value & 0x0f;
+            }
+            IIR_FCR => self.fcr = value,
+            LCR => self.lcr = value,
+            MCR => self.mcr = value,
+            LSR | MSR => {}
+            SCR => self.scr = value,
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn irq(&self) -> bool {
+        self.interrupt_id() != IIR_NO_INT
+    }
+
+    fn device_tree_node(&self) -> Option<DeviceTreeNode> {
+        Some(DeviceTreeNode {
+            name: "serial",
+            compatible: &["ns16550a"],
+            properties: vec![("clock-frequency", UART_CLOCK_FREQUENCY)],
+        })
+    }
+}
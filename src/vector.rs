@@ -0,0 +1,1415 @@
+use crate::{
+    emulator::{extract_r_type, sign_extend, Emulator},
+    exception::Exception::*,
+    register::Register,
+    Result,
+};
+
+// vtypeのvill(設定が不正なことを表すビット)
+pub(crate) const VTYPE_VILL: u64 = 1 << 63;
+
+// 算術命令のオペランドの種類(funct3)
+const OPIVV: u32 = 0b000;
+const OPIVI: u32 = 0b011;
+const OPMVV: u32 = 0b010;
+
+// 固定小数点の丸めモード(vxrm)
+const VXRM_RNU: u64 = 0;
+const VXRM_RNE: u64 = 1;
+const VXRM_RDN: u64 = 2;
+
+// ベクトル拡張の構成
+// vlenはベクトルレジスタのビット数、elenは要素の最大のビット数(32か64)
+// 浮動小数点の命令はサポートしないので、Zve32xかZve64x相当になる。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VectorConfig {
+    pub vlen: usize,
+    pub elen: usize,
+}
+
+impl Default for VectorConfig {
+    fn default() -> Self {
+        Self {
+            vlen: 128,
+            elen: 64,
+        }
+    }
+}
+
+impl VectorConfig {
+    // VLENは2のべき乗でELEN以上65536以下、ELENは32か64である必要がある。
+    pub fn is_valid(&self) -> bool {
+        matches!(self.elen, 32 | 64)
+            && self.vlen.is_power_of_two()
+            && self.vlen >= self.elen
+            && self.vlen <= 65536
+    }
+
+    // ベクトルレジスタのバイト数(vlenb)
+    pub(crate) fn vlenb(&self) -> usize {
+        self.vlen / 8
+    }
+}
+
+// vtypeを解釈した値
+// lmulはLMULの2を底とする対数(-3から3)
+#[derive(Debug, Clone, Copy)]
+struct Vtype {
+    sew: usize,
+    lmul: i32,
+}
+
+impl Vtype {
+    // vtypeの値を解釈する関数
+    // 予約されている値やサポートしない組み合わせの場合はNoneを返す。(vtype.villが1になる)
+    fn new(value: u64, elen: usize) -> Option<Self> {
+        if value >> 8 != 0 {
+            return None;
+        }
+
+        let lmul = match value & 0x7 {
+            0b100 => return None,
+            vlmul if vlmul >= 0b101 => vlmul as i32 - 8,
+            vlmul => vlmul as i32,
+        };
+        let sew = match (value >> 3) & 0x7 {
+            vsew @ 0..=3 => 8 << vsew,
+            _ => return None,
+        };
+
+        // LMULが1より小さい場合はSEW <= LMUL * ELENでなければならない。
+        if sew > elen || (lmul < 0 && sew > elen >> -lmul) {
+            return None;
+        }
+
+        Some(Self { sew, lmul })
+    }
+
+    // VLMAX(LMUL * VLEN / SEW)
+    fn vlmax(&self, vlen: usize) -> usize {
+        emul_scale(vlen, self.lmul) / self.sew
+    }
+
+    // レジスタグループのレジスタの数
+    fn regs(&self) -> usize {
+        group_regs(self.lmul)
+    }
+
+    // 要素のビット数がeewのときのEMULの対数
+    // サポートしない範囲(1/8未満か8より大きい)の場合は不正な命令になる。
+    fn emul(&self, eew: usize) -> Result<i32> {
+        let emul = self.lmul + eew.trailing_zeros() as i32 - self.sew.trailing_zeros() as i32;
+
+        if (-3..=3).contains(&emul) {
+            Ok(emul)
+        } else {
+            Err(IllegralInstruction)
+        }
+    }
+}
+
+// LMULの対数がlmulのときのvalue * LMUL
+fn emul_scale(value: usize, lmul: i32) -> usize {
+    if lmul >= 0 {
+        value << lmul
+    } else {
+        value >> -lmul
+    }
+}
+
+// LMULの対数がlmulのときのレジスタグループのレジスタの数(LMULが1より小さい場合は1)
+fn group_regs(lmul: i32) -> usize {
+    1 << lmul.max(0)
+}
+
+// レジスタグループが重なっているかを確認する関数
+fn overlaps(a: u8, a_regs: usize, b: u8, b_regs: usize) -> bool {
+    (a as usize) < b as usize + b_regs && (b as usize) < a as usize + a_regs
+}
+
+// 要素の幅が異なるデスティネーションとソースのレジスタグループの重なりを確認する関数
+// デスティネーションの方が広い場合は、ソースのEMULが1以上でデスティネーションの最上位の部分で重なる場合のみ許される。
+// デスティネーションの方が狭い場合は、ソースの最下位の部分で重なる場合のみ許される。
+fn check_overlap(
+    dst: u8,
+    dst_regs: usize,
+    src: u8,
+    src_regs: usize,
+    src_emul: i32,
+    widening: bool,
+) -> Result<()> {
+    if !overlaps(dst, dst_regs, src, src_regs) {
+        return Ok(());
+    }
+
+    let allowed = if widening {
+        src_emul >= 0 && src as usize + src_regs == dst as usize + dst_regs
+    } else {
+        dst == src
+    };
+
+    if allowed {
+        Ok(())
+    } else {
+        Err(IllegralInstruction)
+    }
+}
+
+// SEWビットのマスク
+fn mask(sew: usize) -> u64 {
+    u64::MAX >> (64 - sew)
+}
+
+// SEWビットの値を符号付きの値にする関数
+fn signed(value: u64, sew: usize) -> i64 {
+    ((value << (64 - sew)) as i64) >> (64 - sew)
+}
+
+// 固定小数点の演算でshiftビット右シフトするときに丸めで加える値を返す関数
+fn round_increment(value: u128, shift: u32, vxrm: u64) -> u128 {
+    if shift == 0 {
+        return 0;
+    }
+
+    let bit = |i: u32| (value >> i) & 1;
+    let lower = |bits: u32| value & ((1 << bits) - 1) != 0;
+
+    match vxrm {
+        VXRM_RNU => bit(shift - 1),
+        VXRM_RNE => bit(shift - 1) & (lower(shift - 1) as u128 | bit(shift)),
+        VXRM_RDN => 0,
+        _ => (bit(shift) == 0 && lower(shift)) as u128,
+    }
+}
+
+fn roundoff_unsigned(value: u128, shift: u32, vxrm: u64) -> u128 {
+    (value >> shift) + round_increment(value, shift, vxrm)
+}
+
+fn roundoff_signed(value: i128, shift: u32, vxrm: u64) -> i128 {
+    (value >> shift) + round_increment(value as u128, shift, vxrm) as i128
+}
+
+// 符号付きの値をSEWビットに飽和させる関数
+// 飽和した場合はsaturatedを1にする。(vxsat)
+fn saturate_signed(value: i128, sew: usize, saturated: &mut bool) -> u64 {
+    let max = (1i128 << (sew - 1)) - 1;
+    let min = -(1i128 << (sew - 1));
+
+    let value = if value > max {
+        *saturated = true;
+        max
+    } else if value < min {
+        *saturated = true;
+        min
+    } else {
+        value
+    };
+
+    value as u64 & mask(sew)
+}
+
+// 要素の幅が変わらない算術命令の1要素を計算する関数
+// aはvs2、bはvs1かスカラーか即値、dはvdの要素で、carryはv0のマスクのビット
+#[allow(clippy::too_many_arguments)]
+fn single_width(
+    name: &str,
+    a: u64,
+    b: u64,
+    d: u64,
+    carry: bool,
+    sew: usize,
+    vxrm: u64,
+    saturated: &mut bool,
+) -> u64 {
+    let (sa, sb) = (signed(a, sew), signed(b, sew));
+    let shift = (b & (sew as u64 - 1)) as u32;
+
+    let value = match name {
+        "vadd" => a.wrapping_add(b),
+        "vsub" => a.wrapping_sub(b),
+        "vrsub" => b.wrapping_sub(a),
+        "vminu" => a.min(b),
+        "vmin" => sa.min(sb) as u64,
+        "vmaxu" => a.max(b),
+        "vmax" => sa.max(sb) as u64,
+        "vand" => a & b,
+        "vor" => a | b,
+        "vxor" => a ^ b,
+        "vsll" => a << shift,
+        "vsrl" => a >> shift,
+        "vsra" => (sa >> shift) as u64,
+        "vmul" => a.wrapping_mul(b),
+        "vmulh" => ((sa as i128 * sb as i128) >> sew) as u64,
+        "vmulhu" => ((a as u128 * b as u128) >> sew) as u64,
+        "vmulhsu" => ((sa as i128 * b as i128) >> sew) as u64,
+        "vdivu" => a.checked_div(b).unwrap_or(u64::MAX),
+        "vdiv" => match sb {
+            0 => u64::MAX,
+            _ => sa.wrapping_div(sb) as u64,
+        },
+        "vremu" => a.checked_rem(b).unwrap_or(a),
+        "vrem" => match sb {
+            0 => a,
+            _ => sa.wrapping_rem(sb) as u64,
+        },
+        "vmacc" => b.wrapping_mul(a).wrapping_add(d),
+        "vnmsac" => d.wrapping_sub(b.wrapping_mul(a)),
+        "vmadd" => b.wrapping_mul(d).wrapping_add(a),
+        "vnmsub" => a.wrapping_sub(b.wrapping_mul(d)),
+        "vsaddu" => match a as u128 + b as u128 > mask(sew) as u128 {
+            true => {
+                *saturated = true;
+                mask(sew)
+            }
+            false => a + b,
+        },
+        "vsadd" => saturate_signed(sa as i128 + sb as i128, sew, saturated),
+        "vssubu" => match a.checked_sub(b) {
+            Some(value) => value,
+            None => {
+                *saturated = true;
+                0
+            }
+        },
+        "vssub" => saturate_signed(sa as i128 - sb as i128, sew, saturated),
+        "vaaddu" => roundoff_unsigned(a as u128 + b as u128, 1, vxrm) as u64,
+        "vaadd" => roundoff_signed(sa as i128 + sb as i128, 1, vxrm) as u64,
+        "vasubu" => roundoff_signed(a as i128 - b as i128, 1, vxrm) as u64,
+        "vasub" => roundoff_signed(sa as i128 - sb as i128, 1, vxrm) as u64,
+        "vsmul" => saturate_signed(
+            roundoff_signed(sa as i128 * sb as i128, sew as u32 - 1, vxrm),
+            sew,
+            saturated,
+        ),
+        "vssrl" => roundoff_unsigned(a as u128, shift, vxrm) as u64,
+        "vssra" => roundoff_signed(sa as i128, shift, vxrm) as u64,
+        "vmerge" => {
+            if carry {
+                b
+            } else {
+                a
+            }
+        }
+        "vmv_v" => b,
+        "vadc" => a.wrapping_add(b).wrapping_add(carry as u64),
+        "vsbc" => a.wrapping_sub(b).wrapping_sub(carry as u64),
+        _ => unimplemented!(),
+    };
+
+    value & mask(sew)
+}
+
+// 2*SEWビットの結果を返す拡幅命令の1要素を計算する関数
+// _wの命令はaが2*SEWビットになる。
+fn widening(name: &str, a: u64, b: u64, d: u64, sew: usize) -> u64 {
+    let wide = sew * 2;
+    let sa = if name.ends_with("_w") {
+        signed(a, wide)
+    } else {
+        signed(a, sew)
+    };
+    let sb = signed(b, sew);
+
+    let value = match name {
+        "vwaddu" | "vwaddu_w" => a.wrapping_add(b),
+        "vwadd" | "vwadd_w" => sa.wrapping_add(sb) as u64,
+        "vwsubu" | "vwsubu_w" => a.wrapping_sub(b),
+        "vwsub" | "vwsub_w" => sa.wrapping_sub(sb) as u64,
+        "vwmulu" => a.wrapping_mul(b),
+        "vwmulsu" => sa.wrapping_mul(b as i64) as u64,
+        "vwmul" => sa.wrapping_mul(sb) as u64,
+        "vwmaccu" => d.wrapping_add(a.wrapping_mul(b)),
+        "vwmacc" => d.wrapping_add(sa.wrapping_mul(sb) as u64),
+        "vwmaccsu" => d.wrapping_add(sb.wrapping_mul(a as i64) as u64),
+        "vwmaccus" => d.wrapping_add(sa.wrapping_mul(b as i64) as u64),
+        _ => unimplemented!(),
+    };
+
+    value & mask(wide)
+}
+
+// 2*SEWビットのvs2からSEWビットの結果を返す縮小命令の1要素を計算する関数
+fn narrowing(name: &str, a: u64, b: u64, sew: usize, vxrm: u64, saturated: &mut bool) -> u64 {
+    let wide = sew * 2;
+    let shift = (b & (wide as u64 - 1)) as u32;
+
+    let value = match name {
+        "vnsrl" => a >> shift,
+        "vnsra" => (signed(a, wide) >> shift) as u64,
+        "vnclipu" => {
+            let value = roundoff_unsigned(a as u128, shift, vxrm);
+
+            if value > mask(sew) as u128 {
+                *saturated = true;
+                mask(sew)
+            } else {
+                value as u64
+            }
+        }
+        "vnclip" => saturate_signed(
+            roundoff_signed(signed(a, wide) as i128, shift, vxrm),
+            sew,
+            saturated,
+        ),
+        _ => unimplemented!(),
+    };
+
+    value & mask(sew)
+}
+
+// 比較命令とキャリー/ボローを求める命令の1要素を計算する関数
+fn compare(name: &str, a: u64, b: u64, carry: bool, sew: usize) -> bool {
+    let (sa, sb) = (signed(a, sew), signed(b, sew));
+
+    match name {
+        "vmseq" => a == b,
+        "vmsne" => a != b,
+        "vmsltu" => a < b,
+        "vmslt" => sa < sb,
+        "vmsleu" => a <= b,
+        "vmsle" => sa <= sb,
+        "vmsgtu" => a > b,
+        "vmsgt" => sa > sb,
+        "vmadc" => a as u128 + b as u128 + carry as u128 > mask(sew) as u128,
+        "vmsbc" => (a as u128) < b as u128 + carry as u128,
+        _ => unimplemented!(),
+    }
+}
+
+// リダクション命令で累積する値を計算する関数
+// 拡幅するリダクションの場合はaccが2*SEWビットになる。
+fn reduce(name: &str, acc: u64, value: u64, sew: usize) -> u64 {
+    match name {
+        "vredsum" => acc.wrapping_add(value) & mask(sew),
+        "vredand" => acc & value,
+        "vredor" => acc | value,
+        "vredxor" => acc ^ value,
+        "vredminu" => acc.min(value),
+        "vredmin" => signed(acc, sew).min(signed(value, sew)) as u64 & mask(sew),
+        "vredmaxu" => acc.max(value),
+        "vredmax" => signed(acc, sew).max(signed(value, sew)) as u64 & mask(sew),
+        "vwredsumu" => acc.wrapping_add(value) & mask(sew * 2),
+        "vwredsum" => acc.wrapping_add(signed(value, sew) as u64) & mask(sew * 2),
+        _ => unimplemented!(),
+    }
+}
+
+// マスクの論理演算の1ビットを計算する関数(aはvs2、bはvs1のビット)
+fn mask_logical(name: &str, a: bool, b: bool) -> bool {
+    match name {
+        "vmandn" => a & !b,
+        "vmand" => a & b,
+        "vmor" => a | b,
+        "vmxor" => a ^ b,
+        "vmorn" => a | !b,
+        "vmnand" => !(a & b),
+        "vmnor" => !(a | b),
+        "vmxnor" => !(a ^ b),
+        _ => unimplemented!(),
+    }
+}
+
+// 算術命令の2つ目のオペランド(vs1かx[rs1]か即値)
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Vector(u8),
+    Scalar(u64),
+}
+
+impl Emulator {
+    // ベクトル拡張の構成を設定する関数
+    // すべてのハートの状態は初期化される。
+    pub fn set_vector_config(&mut self, config: VectorConfig) {
+        assert!(
+            config.is_valid(),
+            "Error: The vector configuration (VLEN={}, ELEN={}) is not supported.",
+            config.vlen,
+            config.elen
+        );
+
+        self.vector = config;
+        self.reset_harts();
+    }
+
+    // ベクトル拡張の構成を返す関数
+    pub fn vector_config(&self) -> VectorConfig {
+        self.vector
+    }
+
+    // レジスタグループvregのindex番目の要素(eewビット)を読み込む関数
+    fn read_velem(&self, vreg: u8, eew: usize, index: usize) -> u64 {
+        let bytes = eew / 8;
+        let offset = vreg as usize * self.vector.vlenb() + index * bytes;
+        let mut value = [0; 8];
+
+        value[..bytes].copy_from_slice(&self.vregs[offset..offset + bytes]);
+
+        u64::from_le_bytes(value)
+    }
+
+    // レジスタグループvregのindex番目の要素(eewビット)を書き込む関数
+    fn write_velem(&mut self, vreg: u8, eew: usize, index: usize, value: u64) {
+        let bytes = eew / 8;
+        let offset = vreg as usize * self.vector.vlenb() + index * bytes;
+
+        self.vregs[offset..offset + bytes].copy_from_slice(&value.to_le_bytes()[..bytes]);
+        self.csr.set_vector_dirty();
+    }
+
+    // マスクレジスタvregのindex番目のビットを読み込む関数
+    fn read_mask(&self, vreg: u8, index: usize) -> bool {
+        let offset = vreg as usize * self.vector.vlenb() + index / 8;
+
+        (self.vregs[offset] >> (index % 8)) & 1 == 1
+    }
+
+    // マスクレジスタvregのindex番目のビットを書き込む関数
+    fn write_mask(&mut self, vreg: u8, index: usize, bit: bool) {
+        let offset = vreg as usize * self.vector.vlenb() + index / 8;
+
+        self.vregs[offset] =
+            (self.vregs[offset] & !(1 << (index % 8))) | ((bit as u8) << (index % 8));
+        self.csr.set_vector_dirty();
+    }
+
+    // index番目の要素が有効か(vmが0の場合はv0のマスクで決まる)を返す関数
+    fn is_active(&self, vm: bool, index: usize) -> bool {
+        vm || self.read_mask(0, index)
+    }
+
+    // オペランドのindex番目の要素をsewビットで返す関数
+    fn read_operand(&self, operand: Operand, sew: usize, index: usize) -> u64 {
+        match operand {
+            Operand::Vector(vs1) => self.read_velem(vs1, sew, index),
+            Operand::Scalar(value) => value & mask(sew),
+        }
+    }
+
+    // 現在のvtypeを返す関数
+    // vtype.villが1の場合は不正な命令になる。
+    fn vtype(&self) -> Result<Vtype> {
+        Vtype::new(self.csr.vtype, self.vector.elen).ok_or(IllegralInstruction)
+    }
+
+    // レジスタグループの先頭のレジスタがレジスタの数にそろっているかを確認する関数
+    fn check_vreg(&self, vreg: u8, regs: usize) -> Result<()> {
+        if (vreg as usize).is_multiple_of(regs) && vreg as usize + regs <= 32 {
+            Ok(())
+        } else {
+            Err(IllegralInstruction)
+        }
+    }
+
+    // マスクされる命令のデスティネーションがv0と重なっていないかを確認する関数
+    fn check_mask_overlap(&self, vm: bool, vd: u8) -> Result<()> {
+        if !vm && vd == 0 {
+            Err(IllegralInstruction)
+        } else {
+            Ok(())
+        }
+    }
+
+    // V拡張の命令を実行する関数
+    // 正常に終了した場合はvstartを0にする。
+    // ロードとストアで例外が起こった場合は、vstartに例外を起こした要素のインデックスが入る。
+    pub(crate) fn exec_vector(&mut self) -> Result<()> {
+        if !self.csr.is_vector_enabled() {
+            return Err(IllegralInstruction);
+        }
+
+        match self.inst.name() {
+            "vsetvli" | "vsetivli" | "vsetvl" => self.exec_vset()?,
+            "vle" | "vlse" | "vluxei" | "vloxei" | "vleff" | "vse" | "vsse" | "vsuxei"
+            | "vsoxei" => self.exec_vector_memory()?,
+            "vlr" | "vsr" => self.exec_vector_whole_register()?,
+            "vlm" | "vsm" => self.exec_vector_mask_memory()?,
+            "vmvnr" => self.exec_vmvnr()?,
+            _ => self.exec_vector_arith()?,
+        }
+
+        if self.csr.vstart != 0 {
+            self.csr.vstart = 0;
+            self.csr.set_vector_dirty();
+        }
+
+        Ok(())
+    }
+
+    // vsetvli、vsetivli、vsetvlを実行する関数
+    // vlはAVLとVLMAXの小さい方にする。
+    fn exec_vset(&mut self) -> Result<()> {
+        let raw = self.inst.raw();
+        let (rd, rs1, rs2, _) = extract_r_type(raw);
+
+        let (vtype, avl) = match self.inst.name() {
+            "vsetivli" => ((raw as u64 >> 20) & 0x3ff, Some(rs1 as u64)),
+            name => {
+                let vtype = if name == "vsetvli" {
+                    (raw as u64 >> 20) & 0x7ff
+                } else {
+                    self.read_reg(Register::X(rs2))
+                };
+
+                // rs1とrdがどちらもx0の場合はvlを変更しない。rs1だけがx0の場合はVLMAXにする。
+                let avl = match (rs1, rd) {
+                    (0, 0) => None,
+                    (0, _) => Some(u64::MAX),
+                    _ => Some(self.read_reg(Register::X(rs1))),
+                };
+
+                (vtype, avl)
+            }
+        };
+
+        match Vtype::new(vtype, self.vector.elen) {
+            Some(parsed) => {
+                let vlmax = parsed.vlmax(self.vector.vlen) as u64;
+
+                self.csr.vtype = vtype;
+                self.csr.vl = avl.unwrap_or(self.csr.vl).min(vlmax);
+            }
+            None => {
+                self.csr.vtype = VTYPE_VILL;
+                self.csr.vl = 0;
+            }
+        }
+
+        self.csr.vstart = 0;
+        self.csr.set_vector_dirty();
+        self.write_reg(Register::X(rd), self.csr.vl);
+
+        Ok(())
+    }
+
+    // 要素(eewビット)をメモリから読み込む関数
+    fn load_velem(&mut self, address: u64, eew: usize) -> Result<u64> {
+        let address = address as usize;
+
+        Ok(match eew {
+            8 => u8::from_le_bytes(self.read_memory::<1>(address)?) as u64,
+            16 => u16::from_le_bytes(self.read_memory::<2>(address)?) as u64,
+            32 => u32::from_le_bytes(self.read_memory::<4>(address)?) as u64,
+            _ => u64::from_le_bytes(self.read_memory::<8>(address)?),
+        })
+    }
+
+    // 要素(eewビット)をメモリに書き込む関数
+    fn store_velem(&mut self, address: u64, eew: usize, value: u64) -> Result<()> {
+        self.write_memory(address as usize, &value.to_le_bytes()[..eew / 8])
+    }
+
+    // ロードとストアの命令のwidthフィールドから要素のビット数を返す関数
+    fn memory_eew(&self) -> Result<usize> {
+        let eew = match (self.inst.raw() >> 12) & 0x7 {
+            0b000 => 8,
+            0b101 => 16,
+            0b110 => 32,
+            _ => 64,
+        };
+
+        if eew > self.vector.elen {
+            return Err(IllegralInstruction);
+        }
+
+        Ok(eew)
+    }
+
+    // ユニットストライド、ストライド、インデックスのロードとストアを実行する関数
+    // nfが1より大きい場合はセグメントのロードとストアになり、フィールドごとに別のレジスタグループを使う。
+    // fault-only-firstのロードは先頭以外の要素で例外が起こった場合にトラップせず、vlをその要素のインデックスにする。
+    fn exec_vector_memory(&mut self) -> Result<()> {
+        let vtype = self.vtype()?;
+        let name = self.inst.name();
+        let raw = self.inst.raw();
+        let (vd, rs1, rs2, _) = extract_r_type(raw);
+        let vm = (raw >> 25) & 0x1 == 1;
+        let nf = (raw >> 29) as usize + 1;
+        let is_load = name.starts_with("vl");
+        let indexed = matches!(name, "vluxei" | "vloxei" | "vsuxei" | "vsoxei");
+
+        // インデックスの場合はwidthがインデックスの幅で、データの幅はSEWになる。
+        let width = self.memory_eew()?;
+        let (eew, emul) = if indexed {
+            (vtype.sew, vtype.lmul)
+        } else {
+            (width, vtype.emul(width)?)
+        };
+        let regs = group_regs(emul);
+
+        if nf * regs > 8 {
+            return Err(IllegralInstruction);
+        }
+
+        self.check_vreg(vd, regs)?;
+        self.check_vreg(vd + ((nf - 1) * regs) as u8, regs)?;
+
+        if is_load {
+            self.check_mask_overlap(vm, vd)?;
+        }
+
+        let index_emul = if indexed { vtype.emul(width)? } else { 0 };
+
+        if indexed {
+            let index_regs = group_regs(index_emul);
+
+            self.check_vreg(rs2, index_regs)?;
+
+            // セグメントのロードではデスティネーションとインデックスが重なってはいけない。
+            if is_load {
+                if nf > 1 && overlaps(vd, nf * regs, rs2, index_regs) {
+                    return Err(IllegralInstruction);
+                }
+
+                if width != eew {
+                    check_overlap(vd, regs, rs2, index_regs, index_emul, eew > width)?;
+                }
+            }
+        }
+
+        let base = self.read_reg(Register::X(rs1));
+        let stride = match name {
+            "vlse" | "vsse" => self.read_reg(Register::X(rs2)),
+            _ => (nf * eew / 8) as u64,
+        };
+        let vl = self.csr.vl as usize;
+
+        for i in self.csr.vstart as usize..vl {
+            if !self.is_active(vm, i) {
+                continue;
+            }
+
+            let address = if indexed {
+                base.wrapping_add(self.read_velem(rs2, width, i))
+            } else {
+                base.wrapping_add(stride.wrapping_mul(i as u64))
+            };
+
+            let result = if is_load {
+                // 例外が起こった要素のフィールドは書き換えないように、すべて読み込んでから書き込む。
+                let mut fields = [0; 8];
+
+                (0..nf)
+                    .try_for_each(|f| {
+                        fields[f] =
+                            self.load_velem(address.wrapping_add((f * eew / 8) as u64), eew)?;
+
+                        Ok(())
+                    })
+                    .map(|_| {
+                        for (f, value) in fields.into_iter().enumerate().take(nf) {
+                            self.write_velem(vd + (f * regs) as u8, eew, i, value);
+                        }
+                    })
+            } else {
+                (0..nf).try_for_each(|f| {
+                    let value = self.read_velem(vd + (f * regs) as u8, eew, i);
+
+                    self.store_velem(address.wrapping_add((f * eew / 8) as u64), eew, value)
+                })
+            };
+
+            if let Err(e) = result {
+                if name == "vleff" && i > 0 {
+                    self.csr.vl = i as u64;
+                    self.csr.set_vector_dirty();
+                    break;
+                }
+
+                self.csr.vstart = i as u64;
+                self.csr.set_vector_dirty();
+
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // レジスタ全体のロードとストア(vl<nf>re<eew>.v、vs<nf>r.v)を実行する関数
+    // vtypeとvlに関係なく、nf個のレジスタ全体を読み書きする。
+    fn exec_vector_whole_register(&mut self) -> Result<()> {
+        let raw = self.inst.raw();
+        let (vd, rs1, _, _) = extract_r_type(raw);
+        let nf = (raw >> 29) as usize + 1;
+        let eew = self.memory_eew()?;
+        let is_load = self.inst.name() == "vlr";
+
+        // vmは1、nfは1、2、4、8のみで、ストアの要素の幅は8bitのみ
+        if (raw >> 25) & 0x1 == 0 || !nf.is_power_of_two() || (!is_load && eew != 8) {
+            return Err(IllegralInstruction);
+        }
+
+        self.check_vreg(vd, nf)?;
+
+        let base = self.read_reg(Register::X(rs1));
+        let evl = nf * self.vector.vlen / eew;
+
+        for i in self.csr.vstart as usize..evl {
+            let address = base.wrapping_add((i * eew / 8) as u64);
+
+            let result = if is_load {
+                self.load_velem(address, eew)
+                    .map(|value| self.write_velem(vd, eew, i, value))
+            } else {
+                let value = self.read_velem(vd, eew, i);
+
+                self.store_velem(address, eew, value)
+            };
+
+            if let Err(e) = result {
+                self.csr.vstart = i as u64;
+                self.csr.set_vector_dirty();
+
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // マスクのロードとストア(vlm.v、vsm.v)を実行する関数
+    // 要素の幅は8bitで、ceil(vl/8)バイトを読み書きする。
+    fn exec_vector_mask_memory(&mut self) -> Result<()> {
+        self.vtype()?;
+
+        let raw = self.inst.raw();
+        let (vd, rs1, _, _) = extract_r_type(raw);
+
+        if (raw >> 25) & 0x1 == 0 || raw >> 29 != 0 || self.memory_eew()? != 8 {
+            return Err(IllegralInstruction);
+        }
+
+        let base = self.read_reg(Register::X(rs1));
+        let evl = (self.csr.vl as usize).div_ceil(8);
+
+        for i in self.csr.vstart as usize..evl {
+            let address = base.wrapping_add(i as u64);
+
+            let result = if self.inst.name() == "vlm" {
+                self.load_velem(address, 8)
+                    .map(|value| self.write_velem(vd, 8, i, value))
+            } else {
+                let value = self.read_velem(vd, 8, i);
+
+                self.store_velem(address, 8, value)
+            };
+
+            if let Err(e) = result {
+                self.csr.vstart = i as u64;
+                self.csr.set_vector_dirty();
+
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // レジスタ全体のコピー(vmv<nr>r.v)を実行する関数
+    // vtypeが不正な場合でも実行でき、その場合は要素の幅を8bitとしてvstartを扱う。
+    fn exec_vmvnr(&mut self) -> Result<()> {
+        let (vd, rs1, vs2, _) = extract_r_type(self.inst.raw());
+        let nr = rs1 as usize + 1;
+
+        if !nr.is_power_of_two() || nr > 8 {
+            return Err(IllegralInstruction);
+        }
+
+        self.check_vreg(vd, nr)?;
+        self.check_vreg(vs2, nr)?;
+
+        let eew = self.vtype().map(|vtype| vtype.sew).unwrap_or(8);
+
+        for i in self.csr.vstart as usize..nr * self.vector.vlen / eew {
+            let value = self.read_velem(vs2, eew, i);
+
+            self.write_velem(vd, eew, i, value);
+        }
+
+        Ok(())
+    }
+
+    // 算術命令、マスク命令、置換命令、リダクション命令を実行する関数
+    fn exec_vector_arith(&mut self) -> Result<()> {
+        let vtype = self.vtype()?;
+        let name = self.inst.name();
+        let raw = self.inst.raw();
+        let (vd, rs1, vs2, _) = extract_r_type(raw);
+        let vm = (raw >> 25) & 0x1 == 1;
+        let funct3 = (raw >> 12) & 0x7;
+
+        // シフト、スライド、vrgatherの即値は符号拡張しない。
+        let operand = match funct3 {
+            OPIVV | OPMVV => Operand::Vector(rs1),
+            OPIVI => match name {
+                "vsll" | "vsrl" | "vsra" | "vssrl" | "vssra" | "vnsrl" | "vnsra" | "vnclipu"
+                | "vnclip" | "vslideup" | "vslidedown" | "vrgather" => Operand::Scalar(rs1 as u64),
+                _ => Operand::Scalar(sign_extend(4, rs1 as u64)),
+            },
+            _ => Operand::Scalar(self.read_reg(Register::X(rs1))),
+        };
+
+        match name {
+            "vwaddu" | "vwadd" | "vwsubu" | "vwsub" | "vwaddu_w" | "vwadd_w" | "vwsubu_w"
+            | "vwsub_w" | "vwmulu" | "vwmulsu" | "vwmul" | "vwmaccu" | "vwmacc" | "vwmaccsu"
+            | "vwmaccus" => self.exec_vector_widening(vtype, vd, vs2, operand, vm),
+            "vnsrl" | "vnsra" | "vnclipu" | "vnclip" => {
+                self.exec_vector_narrowing(vtype, vd, vs2, operand, vm)
+            }
+            "vmseq" | "vmsne" | "vmsltu" | "vmslt" | "vmsleu" | "vmsle" | "vmsgtu" | "vmsgt"
+            | "vmadc" | "vmsbc" => self.exec_vector_compare(vtype, vd, vs2, operand, vm),
+            "vredsum" | "vredand" | "vredor" | "vredxor" | "vredminu" | "vredmin" | "vredmaxu"
+            | "vredmax" | "vwredsumu" | "vwredsum" => {
+                self.exec_vector_reduction(vtype, vd, vs2, rs1, vm)
+            }
+            "vmandn" | "vmand" | "vmor" | "vmxor" | "vmorn" | "vmnand" | "vmnor" | "vmxnor" => {
+                for i in self.csr.vstart as usize..self.csr.vl as usize {
+                    let bit = mask_logical(name, self.read_mask(vs2, i), self.read_mask(rs1, i));
+
+                    self.write_mask(vd, i, bit);
+                }
+
+                Ok(())
+            }
+            "vcpop_m" | "vfirst_m" | "vmsbf_m" | "vmsif_m" | "vmsof_m" | "viota_m" | "vid_v" => {
+                self.exec_vector_mask(vtype, vd, vs2, vm)
+            }
+            "vzext_vf2" | "vsext_vf2" | "vzext_vf4" | "vsext_vf4" | "vzext_vf8" | "vsext_vf8" => {
+                self.exec_vector_extension(vtype, vd, vs2, vm)
+            }
+            "vmv_x_s" | "vmv_s_x" | "vslideup" | "vslidedown" | "vslide1up" | "vslide1down"
+            | "vrgather" | "vrgatherei16" | "vcompress" => {
+                self.exec_vector_permutation(vtype, vd, vs2, operand, vm)
+            }
+            _ => self.exec_vector_single_width(vtype, vd, vs2, operand, vm),
+        }
+    }
+
+    // 要素の幅が変わらない算術命令を実行する関数
+    // vmerge、vadc、vsbcはv0のマスクを値として使い、すべての要素を計算する。
+    fn exec_vector_single_width(
+        &mut self,
+        vtype: Vtype,
+        vd: u8,
+        vs2: u8,
+        operand: Operand,
+        vm: bool,
+    ) -> Result<()> {
+        let name = self.inst.name();
+        let sew = vtype.sew;
+        let regs = vtype.regs();
+        let uses_carry = matches!(name, "vmerge" | "vadc" | "vsbc");
+
+        if uses_carry && vm {
+            return Err(IllegralInstruction);
+        }
+
+        self.check_vreg(vd, regs)?;
+        self.check_vreg(vs2, regs)?;
+        self.check_mask_overlap(vm, vd)?;
+
+        if let Operand::Vector(vs1) = operand {
+            self.check_vreg(vs1, regs)?;
+        }
+
+        let vxrm = self.csr.vxrm;
+        let mut saturated = false;
+
+        for i in self.csr.vstart as usize..self.csr.vl as usize {
+            let carry = self.read_mask(0, i);
+
+            if !vm && !carry && !uses_carry {
+                continue;
+            }
+
+            let a = self.read_velem(vs2, sew, i);
+            let b = self.read_operand(operand, sew, i);
+            let d = self.read_velem(vd, sew, i);
+
+            let value = single_width(name, a, b, d, carry, sew, vxrm, &mut saturated);
+
+            self.write_velem(vd, sew, i, value);
+        }
+
+        if saturated {
+            self.csr.vxsat = 1;
+        }
+
+        Ok(())
+    }
+
+    // SEWビットの要素から2*SEWビットの要素を求める拡幅命令を実行する関数
+    fn exec_vector_widening(
+        &mut self,
+        vtype: Vtype,
+        vd: u8,
+        vs2: u8,
+        operand: Operand,
+        vm: bool,
+    ) -> Result<()> {
+        let name = self.inst.name();
+        let sew = vtype.sew;
+
+        if sew * 2 > self.vector.elen || vtype.lmul >= 3 {
+            return Err(IllegralInstruction);
+        }
+
+        let regs = vtype.regs();
+        let wide_regs = group_regs(vtype.lmul + 1);
+        let wide_vs2 = name.ends_with("_w");
+
+        self.check_vreg(vd, wide_regs)?;
+        self.check_mask_overlap(vm, vd)?;
+
+        if wide_vs2 {
+            self.check_vreg(vs2, wide_regs)?;
+        } else {
+            self.check_vreg(vs2, regs)?;
+            check_overlap(vd, wide_regs, vs2, regs, vtype.lmul, true)?;
+        }
+
+        if let Operand::Vector(vs1) = operand {
+            self.check_vreg(vs1, regs)?;
+            check_overlap(vd, wide_regs, vs1, regs, vtype.lmul, true)?;
+        }
+
+        for i in self.csr.vstart as usize..self.csr.vl as usize {
+            if !self.is_active(vm, i) {
+                continue;
+            }
+
+            let a = self.read_velem(vs2, if wide_vs2 { sew * 2 } else { sew }, i);
+            let b = self.read_operand(operand, sew, i);
+            let d = self.read_velem(vd, sew * 2, i);
+
+            self.write_velem(vd, sew * 2, i, widening(name, a, b, d, sew));
+        }
+
+        Ok(())
+    }
+
+    // 2*SEWビットの要素からSEWビットの要素を求める縮小命令を実行する関数
+    fn exec_vector_narrowing(
+        &mut self,
+        vtype: Vtype,
+        vd: u8,
+        vs2: u8,
+        operand: Operand,
+        vm: bool,
+    ) -> Result<()> {
+        let name = self.inst.name();
+        let sew = vtype.sew;
+
+        if sew * 2 > self.vector.elen || vtype.lmul >= 3 {
+            return Err(IllegralInstruction);
+        }
+
+        let regs = vtype.regs();
+        let wide_regs = group_regs(vtype.lmul + 1);
+
+        self.check_vreg(vd, regs)?;
+        self.check_vreg(vs2, wide_regs)?;
+        self.check_mask_overlap(vm, vd)?;
+        check_overlap(vd, regs, vs2, wide_regs, vtype.lmul + 1, false)?;
+
+        if let Operand::Vector(vs1) = operand {
+            self.check_vreg(vs1, regs)?;
+        }
+
+        let vxrm = self.csr.vxrm;
+        let mut saturated = false;
+
+        for i in self.csr.vstart as usize..self.csr.vl as usize {
+            if !self.is_active(vm, i) {
+                continue;
+            }
+
+            let a = self.read_velem(vs2, sew * 2, i);
+            let b = self.read_operand(operand, sew, i);
+
+            let value = narrowing(name, a, b, sew, vxrm, &mut saturated);
+
+            self.write_velem(vd, sew, i, value);
+        }
+
+        if saturated {
+            self.csr.vxsat = 1;
+        }
+
+        Ok(())
+    }
+
+    // 結果をマスクレジスタに書き込む比較命令とvmadc、vmsbcを実行する関数
+    // vmadcとvmsbcはvmが0の場合にv0をキャリー/ボローとして使い、すべての要素を計算する。
+    fn exec_vector_compare(
+        &mut self,
+        vtype: Vtype,
+        vd: u8,
+        vs2: u8,
+        operand: Operand,
+        vm: bool,
+    ) -> Result<()> {
+        let name = self.inst.name();
+        let sew = vtype.sew;
+        let regs = vtype.regs();
+        let uses_carry = matches!(name, "vmadc" | "vmsbc");
+
+        self.check_vreg(vs2, regs)?;
+        check_overlap(vd, 1, vs2, regs, vtype.lmul, false)?;
+
+        if let Operand::Vector(vs1) = operand {
+            self.check_vreg(vs1, regs)?;
+            check_overlap(vd, 1, vs1, regs, vtype.lmul, false)?;
+        }
+
+        for i in self.csr.vstart as usize..self.csr.vl as usize {
+            let mask = self.read_mask(0, i);
+
+            if !vm && !mask && !uses_carry {
+                continue;
+            }
+
+            let a = self.read_velem(vs2, sew, i);
+            let b = self.read_operand(operand, sew, i);
+            let carry = uses_carry && !vm && mask;
+
+            self.write_mask(vd, i, compare(name, a, b, carry, sew));
+        }
+
+        Ok(())
+    }
+
+    // リダクション命令を実行する関数
+    // vs1の先頭の要素を初期値として有効な要素を累積し、vdの先頭の要素に書き込む。
+    fn exec_vector_reduction(
+        &mut self,
+        vtype: Vtype,
+        vd: u8,
+        vs2: u8,
+        vs1: u8,
+        vm: bool,
+    ) -> Result<()> {
+        let name = self.inst.name();
+        let sew = vtype.sew;
+        let acc_eew = if name.starts_with("vw") { sew * 2 } else { sew };
+
+        if acc_eew > self.vector.elen || self.csr.vstart != 0 {
+            return Err(IllegralInstruction);
+        }
+
+        self.check_vreg(vs2, vtype.regs())?;
+
+        let vl = self.csr.vl as usize;
+
+        if vl == 0 {
+            return Ok(());
+        }
+
+        let mut acc = self.read_velem(vs1, acc_eew, 0);
+
+        for i in 0..vl {
+            if self.is_active(vm, i) {
+                acc = reduce(name, acc, self.read_velem(vs2, sew, i), sew);
+            }
+        }
+
+        self.write_velem(vd, acc_eew, 0, acc);
+
+        Ok(())
+    }
+
+    // マスクを扱う命令(vcpop、vfirst、vmsbf、vmsif、vmsof、viota、vid)を実行する関数
+    fn exec_vector_mask(&mut self, vtype: Vtype, vd: u8, vs2: u8, vm: bool) -> Result<()> {
+        let name = self.inst.name();
+        let vl = self.csr.vl as usize;
+
+        // vid以外はvstartが0でなければならない。
+        if name != "vid_v" && self.csr.vstart != 0 {
+            return Err(IllegralInstruction);
+        }
+
+        match name {
+            "vcpop_m" => {
+                let count = (0..vl)
+                    .filter(|&i| self.is_active(vm, i) && self.read_mask(vs2, i))
+                    .count();
+
+                self.write_reg(Register::X(vd), count as u64);
+            }
+            "vfirst_m" => {
+                let first = (0..vl).find(|&i| self.is_active(vm, i) && self.read_mask(vs2, i));
+
+                self.write_reg(Register::X(vd), first.map_or(u64::MAX, |i| i as u64));
+            }
+            "vmsbf_m" | "vmsif_m" | "vmsof_m" => {
+                if vd == vs2 {
+                    return Err(IllegralInstruction);
+                }
+
+                self.check_mask_overlap(vm, vd)?;
+
+                let mut found = false;
+
+                for i in 0..vl {
+                    if !self.is_active(vm, i) {
+                        continue;
+                    }
+
+                    let bit = self.read_mask(vs2, i);
+                    let value = match name {
+                        "vmsbf_m" => !found && !bit,
+                        "vmsif_m" => !found,
+                        _ => !found && bit,
+                    };
+
+                    found |= bit;
+                    self.write_mask(vd, i, value);
+                }
+            }
+            "viota_m" => {
+                self.check_vreg(vd, vtype.regs())?;
+                self.check_mask_overlap(vm, vd)?;
+
+                if overlaps(vd, vtype.regs(), vs2, 1) {
+                    return Err(IllegralInstruction);
+                }
+
+                let mut count = 0;
+
+                for i in 0..vl {
+                    if !self.is_active(vm, i) {
+                        continue;
+                    }
+
+                    self.write_velem(vd, vtype.sew, i, count);
+
+                    if self.read_mask(vs2, i) {
+                        count += 1;
+                    }
+                }
+            }
+            _ => {
+                self.check_vreg(vd, vtype.regs())?;
+                self.check_mask_overlap(vm, vd)?;
+
+                for i in self.csr.vstart as usize..vl {
+                    if self.is_active(vm, i) {
+                        self.write_velem(vd, vtype.sew, i, i as u64);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // 整数の拡張命令(vzext.vf2/4/8、vsext.vf2/4/8)を実行する関数
+    // vs2のSEW/fビットの要素をSEWビットに拡張する。
+    fn exec_vector_extension(&mut self, vtype: Vtype, vd: u8, vs2: u8, vm: bool) -> Result<()> {
+        let name = self.inst.name();
+        let factor: usize = match &name[name.len() - 1..] {
+            "2" => 2,
+            "4" => 4,
+            _ => 8,
+        };
+        let sew = vtype.sew;
+        let eew = sew / factor;
+
+        if eew < 8 {
+            return Err(IllegralInstruction);
+        }
+
+        let emul = vtype.emul(eew)?;
+        let src_regs = group_regs(emul);
+
+        self.check_vreg(vd, vtype.regs())?;
+        self.check_vreg(vs2, src_regs)?;
+        self.check_mask_overlap(vm, vd)?;
+        check_overlap(vd, vtype.regs(), vs2, src_regs, emul, true)?;
+
+        for i in self.csr.vstart as usize..self.csr.vl as usize {
+            if !self.is_active(vm, i) {
+                continue;
+            }
+
+            let value = self.read_velem(vs2, eew, i);
+            let value = if name.starts_with("vsext") {
+                signed(value, eew) as u64 & mask(sew)
+            } else {
+                value
+            };
+
+            self.write_velem(vd, sew, i, value);
+        }
+
+        Ok(())
+    }
+
+    // 置換命令(vmv.x.s、vmv.s.x、スライド、vrgather、vcompress)を実行する関数
+    fn exec_vector_permutation(
+        &mut self,
+        vtype: Vtype,
+        vd: u8,
+        vs2: u8,
+        operand: Operand,
+        vm: bool,
+    ) -> Result<()> {
+        let name = self.inst.name();
+        let sew = vtype.sew;
+        let regs = vtype.regs();
+        let vl = self.csr.vl as usize;
+        let vstart = self.csr.vstart as usize;
+        let vlmax = vtype.vlmax(self.vector.vlen);
+
+        // スカラーのオペランドはスライドの量やインデックスとしてXLENビットのまま使う。
+        let scalar = match operand {
+            Operand::Scalar(value) => value,
+            Operand::Vector(_) => 0,
+        };
+
+        match name {
+            "vmv_x_s" => {
+                let value = sign_extend(sew as u8 - 1, self.read_velem(vs2, sew, 0));
+
+                self.write_reg(Register::X(vd), value);
+
+                return Ok(());
+            }
+            "vmv_s_x" => {
+                if vstart < vl {
+                    self.write_velem(vd, sew, 0, scalar & mask(sew));
+                }
+
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        self.check_vreg(vd, regs)?;
+        self.check_vreg(vs2, regs)?;
+        self.check_mask_overlap(vm, vd)?;
+
+        // スライドアップ、vrgather、vcompressはデスティネーションとソースが重なってはいけない。
+        if matches!(
+            name,
+            "vslideup" | "vslide1up" | "vrgather" | "vrgatherei16" | "vcompress"
+        ) && overlaps(vd, regs, vs2, regs)
+        {
+            return Err(IllegralInstruction);
+        }
+
+        match name {
+            "vslideup" => {
+                let offset = scalar.min(vl as u64) as usize;
+
+                for i in vstart.max(offset)..vl {
+                    if self.is_active(vm, i) {
+                        let value = self.read_velem(vs2, sew, i - offset);
+
+                        self.write_velem(vd, sew, i, value);
+                    }
+                }
+            }
+            "vslidedown" => {
+                for i in vstart..vl {
+                    if !self.is_active(vm, i) {
+                        continue;
+                    }
+
+                    let value = match scalar.checked_add(i as u64) {
+                        Some(src) if src < vlmax as u64 => self.read_velem(vs2, sew, src as usize),
+                        _ => 0,
+                    };
+
+                    self.write_velem(vd, sew, i, value);
+                }
+            }
+            "vslide1up" => {
+                for i in vstart..vl {
+                    if !self.is_active(vm, i) {
+                        continue;
+                    }
+
+                    let value = match i {
+                        0 => scalar & mask(sew),
+                        _ => self.read_velem(vs2, sew, i - 1),
+                    };
+
+                    self.write_velem(vd, sew, i, value);
+                }
+            }
+            "vslide1down" => {
+                for i in vstart..vl {
+                    if !self.is_active(vm, i) {
+                        continue;
+                    }
+
+                    let value = if i + 1 < vl {
+                        self.read_velem(vs2, sew, i + 1)
+                    } else {
+                        scalar & mask(sew)
+                    };
+
+                    self.write_velem(vd, sew, i, value);
+                }
+            }
+            "vrgather" | "vrgatherei16" => {
+                // vrgatherei16はvs1のインデックスが16bitになる。
+                let (index_eew, index_regs) = match (name, operand) {
+                    ("vrgatherei16", _) => (16, group_regs(vtype.emul(16)?)),
+                    _ => (sew, regs),
+                };
+
+                if let Operand::Vector(vs1) = operand {
+                    self.check_vreg(vs1, index_regs)?;
+
+                    if overlaps(vd, regs, vs1, index_regs) {
+                        return Err(IllegralInstruction);
+                    }
+                }
+
+                for i in vstart..vl {
+                    if !self.is_active(vm, i) {
+                        continue;
+                    }
+
+                    let index = match operand {
+                        Operand::Vector(vs1) => self.read_velem(vs1, index_eew, i),
+                        Operand::Scalar(value) => value,
+                    };
+                    let value = if index < vlmax as u64 {
+                        self.read_velem(vs2, sew, index as usize)
+                    } else {
+                        0
+                    };
+
+                    self.write_velem(vd, sew, i, value);
+                }
+            }
+            _ => {
+                // vcompressはvs1のマスクが1の要素をvdの先頭から詰める。
+                let Operand::Vector(vs1) = operand else {
+                    unreachable!();
+                };
+
+                if vstart != 0 || overlaps(vd, regs, vs1, 1) {
+                    return Err(IllegralInstruction);
+                }
+
+                let mut count = 0;
+
+                for i in 0..vl {
+                    if self.read_mask(vs1, i) {
+                        let value = self.read_velem(vs2, sew, i);
+
+                        self.write_velem(vd, sew, count, value);
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
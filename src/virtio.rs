@@ -0,0 +1,514 @@
+use std::{
+    error::Error,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    bus::{Device, Dma},
+    device_tree::DeviceTreeNode,
+};
+
+// virtio-mmioのデバイスを配置する物理アドレスとサイズ、PLICの割り込み番号(QEMUのvirtと同じ)
+// 複数のデバイスを接続する場合はVIRTIO_SIZEずつずらして、割り込み番号も1ずつ増やす。
+pub const VIRTIO_BASE: u64 = 0x1000_1000;
+pub const VIRTIO_SIZE: u64 = 0x1000;
+pub const VIRTIO_IRQ: u32 = 1;
+
+// virtio-mmioのレジスタのオフセット(version 2)
+const MAGIC_VALUE: u64 = 0x000;
+const VERSION: u64 = 0x004;
+const DEVICE_ID: u64 = 0x008;
+const VENDOR_ID: u64 = 0x00c;
+const DEVICE_FEATURES: u64 = 0x010;
+const DEVICE_FEATURES_SEL: u64 = 0x014;
+const DRIVER_FEATURES: u64 = 0x020;
+const DRIVER_FEATURES_SEL: u64 = 0x024;
+const QUEUE_SEL: u64 = 0x030;
+const QUEUE_NUM_MAX: u64 = 0x034;
+const QUEUE_NUM: u64 = 0x038;
+const QUEUE_READY: u64 = 0x044;
+const QUEUE_NOTIFY: u64 = 0x050;
+const INTERRUPT_STATUS: u64 = 0x060;
+const INTERRUPT_ACK: u64 = 0x064;
+const STATUS: u64 = 0x070;
+const QUEUE_DESC_LOW: u64 = 0x080;
+const QUEUE_DESC_HIGH: u64 = 0x084;
+const QUEUE_DRIVER_LOW: u64 = 0x090;
+const QUEUE_DRIVER_HIGH: u64 = 0x094;
+const QUEUE_DEVICE_LOW: u64 = 0x0a0;
+const QUEUE_DEVICE_HIGH: u64 = 0x0a4;
+const CONFIG_GENERATION: u64 = 0x0fc;
+const CONFIG: u64 = 0x100;
+
+const MAGIC: u32 = 0x7472_6976; // "virt"
+const VENDOR: u32 = 0x554d_4551; // "QEMU"
+const DEVICE_ID_BLOCK: u32 = 2;
+
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+const VIRTIO_BLK_F_BLK_SIZE: u64 = 1 << 6;
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+
+const QUEUE_SIZE_MAX: u32 = 256;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+const VIRTIO_BLK_T_GET_ID: u32 = 8;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_IOERR: u8 = 1;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+const INTERRUPT_USED_BUFFER: u32 = 1;
+
+const SECTOR_SIZE: u64 = 512;
+
+// split virtqueueのディスクリプタ
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    address: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+// split virtqueue
+// desc, driver(available ring), device(used ring)はゲストの物理アドレスである。
+#[derive(Debug, Default, Clone, Copy)]
+struct Queue {
+    num: u32,
+    ready: bool,
+    desc: u64,
+    driver: u64,
+    device: u64,
+    // 次に処理するavailable ringのインデックス
+    last_avail: u16,
+}
+
+impl Queue {
+    fn descriptor(&self, memory: &Dma, index: u16) -> Option<Descriptor> {
+        let mut bytes = [0; 16];
+
+        if index as u32 >= self.num || !memory.read(self.desc + index as u64 * 16, &mut bytes) {
+            return None;
+        }
+
+        Some(Descriptor {
+            address: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            len: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            flags: u16::from_le_bytes(bytes[12..14].try_into().unwrap()),
+            next: u16::from_le_bytes(bytes[14..16].try_into().unwrap()),
+        })
+    }
+
+    // ディスクリプタのチェーンを返す関数
+    // ループしている場合に備えてキューの大きさより長いチェーンは不正とする。
+    fn chain(&self, memory: &Dma, head: u16) -> Option<Vec<Descriptor>> {
+        let mut chain = Vec::new();
+        let mut index = head;
+
+        loop {
+            let descriptor = self.descriptor(memory, index)?;
+            chain.push(descriptor);
+
+            if descriptor.flags & VIRTQ_DESC_F_NEXT == 0 {
+                return Some(chain);
+            }
+
+            if chain.len() > self.num as usize {
+                return None;
+            }
+
+            index = descriptor.next;
+        }
+    }
+}
+
+fn read_u16(memory: &Dma, address: u64) -> Option<u16> {
+    let mut bytes = [0; 2];
+
+    memory
+        .read(address, &mut bytes)
+        .then(|| u16::from_le_bytes(bytes))
+}
+
+// ホストのディスクイメージファイルを使うvirtio-mmio(version 2)のブロックデバイス
+// キューは1つで、読み込み、書き込み、フラッシュの要求に対応する。
+pub struct VirtioBlock {
+    file: File,
+    capacity: u64,
+    read_only: bool,
+    device_features_sel: u32,
+    driver_features: u64,
+    driver_features_sel: u32,
+    queue_sel: u32,
+    queue: Queue,
+    notified: bool,
+    interrupt_status: u32,
+    status: u32,
+}
+
+impl VirtioBlock {
+    // ディスクイメージを開いてブロックデバイスを作る関数
+    // read_onlyの場合はファイルを読み込み専用で開き、書き込みの要求はエラーになる。
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        read_only: bool,
+    ) -> core::result::Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new().read(true).write(!read_only).open(path)?;
+
+        let capacity = file.metadata()?.len() / SECTOR_SIZE;
+
+        Ok(Self {
+            file,
+            capacity,
+            read_only,
+            device_features_sel: 0,
+            driver_features: 0,
+            driver_features_sel: 0,
+            queue_sel: 0,
+            queue: Queue::default(),
+            notified: false,
+            interrupt_status: 0,
+            status: 0,
+        })
+    }
+
+    fn device_features(&self) -> u64 {
+        let ro = if self.read_only { VIRTIO_BLK_F_RO } else { 0 };
+
+        VIRTIO_F_VERSION_1 | VIRTIO_BLK_F_BLK_SIZE | VIRTIO_BLK_F_FLUSH | ro
+    }
+
+    fn reset(&mut self) {
+        self.device_features_sel = 0;
+        self.driver_features = 0;
+        self.driver_features_sel = 0;
+        self.queue_sel = 0;
+        self.queue = Queue::default();
+        self.notified = false;
+        self.interrupt_status = 0;
+        self.status = 0;
+    }
+
+    // 設定空間(struct virtio_blk_config)を読み込む関数
+    // capacity(0x00), size_max(0x08), seg_max(0x0c), geometry(0x10), blk_size(0x14)
+    fn read_config(&self, offset: u64, size: usize) -> u64 {
+        let mut config = [0; 0x18];
+
+        config[0x00..0x08].copy_from_slice(&self.capacity.to_le_bytes());
+        config[0x14..0x18].copy_from_slice(&(SECTOR_SIZE as u32).to_le_bytes());
+
+        let offset = offset as usize;
+        let mut bytes = [0; 8];
+
+        if offset + size <= config.len() {
+            bytes[..size].copy_from_slice(&config[offset..offset + size]);
+        }
+
+        u64::from_le_bytes(bytes)
+    }
+
+    // avail ringにある要求をすべて処理する関数
+    fn process_queue(&mut self, memory: &mut Dma) {
+        let queue = self.queue;
+
+        if !queue.ready || queue.num == 0 {
+            return;
+        }
+
+        // available ring: flags(2), idx(2), ring[num](2)
+        let Some(avail_idx) = read_u16(memory, queue.driver + 2) else {
+            return;
+        };
+
+        while self.queue.last_avail != avail_idx {
+            let slot = (self.queue.last_avail as u32 % queue.num) as u64;
+
+            let Some(head) = read_u16(memory, queue.driver + 4 + slot * 2) else {
+                return;
+            };
+
+            let len = match queue.chain(memory, head) {
+                Some(chain) => self.process_request(memory, &chain),
+                None => 0,
+            };
+
+            // used ring: flags(2), idx(2), ring[num](id(4), len(4))
+            let Some(used_idx) = read_u16(memory, queue.device + 2) else {
+                return;
+            };
+
+            let element = queue.device + 4 + (used_idx as u32 % queue.num) as u64 * 8;
+
+            memory.write(element, &(head as u32).to_le_bytes());
+            memory.write(element + 4, &len.to_le_bytes());
+            memory.write(queue.device + 2, &used_idx.wrapping_add(1).to_le_bytes());
+
+            self.queue.last_avail = self.queue.last_avail.wrapping_add(1);
+            self.interrupt_status |= INTERRUPT_USED_BUFFER;
+        }
+    }
+
+    // 1つの要求を処理して、ゲストに書き込んだバイト数を返す関数
+    // チェーンの最初がヘッダ(type, reserved, sector)、最後がステータスの1バイト、その間がデータである。
+    fn process_request(&mut self, memory: &mut Dma, chain: &[Descriptor]) -> u32 {
+        let (Some(header), Some(status)) = (chain.first(), chain.last()) else {
+            return 0;
+        };
+
+        let mut bytes = [0; 16];
+
+        if chain.len() < 2 || header.len < 16 || !memory.read(header.address, &mut bytes) {
+            return 0;
+        }
+
+        let request_type = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let sector = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let data = &chain[1..chain.len() - 1];
+
+        let mut written = 0;
+
+        let result = match request_type {
+            VIRTIO_BLK_T_IN => self.read_sectors(memory, sector, data, &mut written),
+            VIRTIO_BLK_T_OUT if self.read_only => Err(VIRTIO_BLK_S_IOERR),
+            VIRTIO_BLK_T_OUT => self.write_sectors(memory, sector, data),
+            VIRTIO_BLK_T_FLUSH => self.file.sync_data().map_err(|_| VIRTIO_BLK_S_IOERR),
+            VIRTIO_BLK_T_GET_ID => {
+                // 20バイトのID文字列
+                let id = b"tiny-riscv-emulator\0";
+
+                match data.first() {
+                    Some(descriptor) => {
+                        let len = id.len().min(descriptor.len as usize);
+
+                        memory.write(descriptor.address, &id[..len]);
+                        written += len as u32;
+
+                        Ok(())
+                    }
+                    None => Err(VIRTIO_BLK_S_IOERR),
+                }
+            }
+            _ => Err(VIRTIO_BLK_S_UNSUPP),
+        };
+
+        let status_byte = match result {
+            Ok(()) => VIRTIO_BLK_S_OK,
+            Err(status) => status,
+        };
+
+        if status.flags & VIRTQ_DESC_F_WRITE != 0 && status.len >= 1 {
+            memory.write(status.address + status.len as u64 - 1, &[status_byte]);
+            written += 1;
+        }
+
+        written
+    }
+
+    // リクエストのセクタ番号からディスク上のバイト位置を返す関数
+    // セクタ番号はゲストが指定するので、容量を超える場合やオーバーフローする場合はエラーにする。
+    fn sector_position(&self, sector: u64) -> core::result::Result<u64, u8> {
+        if sector >= self.capacity {
+            return Err(VIRTIO_BLK_S_IOERR);
+        }
+
+        sector.checked_mul(SECTOR_SIZE).ok_or(VIRTIO_BLK_S_IOERR)
+    }
+
+    // positionからlenバイトがディスクに収まっているかを確認する関数
+    fn check_range(&self, position: u64, len: u32) -> core::result::Result<(), u8> {
+        match position.checked_add(len as u64) {
+            Some(end) if end <= self.capacity * SECTOR_SIZE => Ok(()),
+            _ => Err(VIRTIO_BLK_S_IOERR),
+        }
+    }
+
+    fn read_sectors(
+        &mut self,
+        memory: &mut Dma,
+        sector: u64,
+        data: &[Descriptor],
+        written: &mut u32,
+    ) -> core::result::Result<(), u8> {
+        let mut position = self.sector_position(sector)?;
+
+        for descriptor in data {
+            if descriptor.flags & VIRTQ_DESC_F_WRITE == 0 {
+                return Err(VIRTIO_BLK_S_IOERR);
+            }
+
+            self.check_range(position, descriptor.len)?;
+
+            let mut buffer = vec![0; descriptor.len as usize];
+
+            self.file
+                .seek(SeekFrom::Start(position))
+                .and_then(|_| self.file.read_exact(&mut buffer))
+                .map_err(|_| VIRTIO_BLK_S_IOERR)?;
+
+            if !memory.write(descriptor.address, &buffer) {
+                return Err(VIRTIO_BLK_S_IOERR);
+            }
+
+            position += descriptor.len as u64;
+            *written += descriptor.len;
+        }
+
+        Ok(())
+    }
+
+    fn write_sectors(
+        &mut self,
+        memory: &Dma,
+        sector: u64,
+        data: &[Descriptor],
+    ) -> core::result::Result<(), u8> {
+        let mut position = self.sector_position(sector)?;
+
+        for descriptor in data {
+            if descriptor.flags & VIRTQ_DESC_F_WRITE != 0 {
+                return Err(VIRTIO_BLK_S_IOERR);
+            }
+
+            self.check_range(position, descriptor.len)?;
+
+            let mut buffer = vec![0; descriptor.len as usize];
+
+            if !memory.read(descriptor.address, &mut buffer) {
+                return Err(VIRTIO_BLK_S_IOERR);
+            }
+
+            self.file
+                .seek(SeekFrom::Start(position))
+                .and_then(|_| self.file.write_all(&buffer))
+                .map_err(|_| VIRTIO_BLK_S_IOERR)?;
+
+            position += descriptor.len as u64;
+        }
+
+        Ok(())
+    }
+}
+
+impl Device for VirtioBlock {
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64> {
+        if offset >= CONFIG {
+            return Some(self.read_config(offset - CONFIG, size));
+        }
+
+        // 設定空間以外のレジスタは4バイト
+        if size != 4 {
+            return None;
+        }
+
+        let value = match offset {
+            MAGIC_VALUE => MAGIC,
+            VERSION => 2,
+            DEVICE_ID => DEVICE_ID_BLOCK,
+            VENDOR_ID => VENDOR,
+            DEVICE_FEATURES => match self.device_features_sel {
+                0 => self.device_features() as u32,
+                1 => (self.device_features() >> 32) as u32,
+                _ => 0,
+            },
+            QUEUE_NUM_MAX if self.queue_sel == 0 => QUEUE_SIZE_MAX,
+            QUEUE_READY if self.queue_sel == 0 => self.queue.ready as u32,
+            QUEUE_NUM_MAX | QUEUE_READY => 0,
+            INTERRUPT_STATUS => self.interrupt_status,
+            STATUS => self.status,
+            CONFIG_GENERATION => 0,
+            _ => 0,
+        };
+
+        Some(value as u64)
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> bool {
+        if offset >= CONFIG {
+            // 設定空間は書き込めない。
+            return true;
+        }
+
+        if size != 4 {
+            return false;
+        }
+
+        let value = value as u32;
+
+        // キューは0番のみ存在する。
+        let queue = (self.queue_sel == 0).then_some(&mut self.queue);
+
+        match (offset, queue) {
+            (DEVICE_FEATURES_SEL, _) => self.device_features_sel = value,
+            (DRIVER_FEATURES, _) => {
+                let shift = self.driver_features_sel * 32;
+
+                if shift < 64 {
+                    self.driver_features = (self.driver_features & !(0xffff_ffff << shift))
+                        | ((value as u64) << shift);
+                }
+            }
+            (DRIVER_FEATURES_SEL, _) => self.driver_features_sel = value,
+            (QUEUE_SEL, _) => self.queue_sel = value,
+            (QUEUE_NUM, Some(queue)) => queue.num = value.min(QUEUE_SIZE_MAX),
+            (QUEUE_READY, Some(queue)) => queue.ready = value & 0x1 != 0,
+            (QUEUE_NOTIFY, _) => self.notified |= value == 0,
+            (INTERRUPT_ACK, _) => self.interrupt_status &= !value,
+            (STATUS, _) => {
+                if value == 0 {
+                    self.reset();
+                } else {
+                    self.status = value;
+                }
+            }
+            (QUEUE_DESC_LOW, Some(queue)) => {
+                queue.desc = (queue.desc & !0xffff_ffff) | value as u64;
+            }
+            (QUEUE_DESC_HIGH, Some(queue)) => {
+                queue.desc = (queue.desc & 0xffff_ffff) | ((value as u64) << 32);
+            }
+            (QUEUE_DRIVER_LOW, Some(queue)) => {
+                queue.driver = (queue.driver & !0xffff_ffff) | value as u64;
+            }
+            (QUEUE_DRIVER_HIGH, Some(queue)) => {
+                queue.driver = (queue.driver & 0xffff_ffff) | ((value as u64) << 32);
+            }
+            (QUEUE_DEVICE_LOW, Some(queue)) => {
+                queue.device = (queue.device & !0xffff_ffff) | value as u64;
+            }
+            (QUEUE_DEVICE_HIGH, Some(queue)) => {
+                queue.device = (queue.device & 0xffff_ffff) | ((value as u64) << 32);
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn irq(&self) -> bool {
+        self.interrupt_status != 0
+    }
+
+    fn device_tree_node(&self) -> Option<DeviceTreeNode> {
+        Some(DeviceTreeNode {
+            name: "virtio_mmio",
+            compatible: &["virtio,mmio"],
+            properties: Vec::new(),
+        })
+    }
+
+    // QueueNotifyに書き込まれた場合に要求を処理する。
+    fn dma(&mut self, memory: &mut Dma) {
+        if self.notified {
+            self.notified = false;
+            self.process_queue(memory);
+        }
+    }
+}
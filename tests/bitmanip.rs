@@ -0,0 +1,470 @@
+mod common;
+
+use common::{program_bytes, run_program, EPILOGUE};
+
+// ビット操作の拡張(Zba, Zbb, Zbc, Zbs)の命令を1つずつ確かめるテスト
+// 命令ごとに(rs1, rs2, rdの期待値)のテーブルを作り、テーブルの行ごとに命令を実行するプログラムをRAMの先頭にロードする。
+// すべての行で一致した場合はシステムコントローラに0x5555を、一致しなかった場合は(行の番号 << 16) | 0x3333を書き込む。
+// 行の番号は1から数えるので、終了コードで一致しなかった行がわかる。
+
+// テストする命令はrdにa2(x12)、rs1にa0(x10)、rs2にa1(x11)を使う。
+const RD: u32 = 12;
+const RS1: u32 = 10;
+const RS2: u32 = 11;
+
+const OP_IMM: u32 = 0b0010011;
+const OP_IMM_32: u32 = 0b0011011;
+const OP: u32 = 0b0110011;
+const OP_32: u32 = 0b0111011;
+
+// テストする命令を置く位置
+const INST_INDEX: usize = 8;
+
+// テーブルの行ごとに命令を実行する部分(このあとにEPILOGUEとテーブルを置く)
+// プログラムの後ろ(table)には行の数と、(rs1, rs2, rdの期待値)の行を続けて置く。
+const LOOP: [u32; 13] = [
+    // _start:
+    0x00000417, // auipc s0, 0
+    0x06040413, // addi s0, s0, 96 (table)
+    0x00043483, // ld s1, 0(s0)
+    0x00840413, // addi s0, s0, 8
+    0x00000913, // li s2, 0
+    // loop:
+    0x00043503, // ld a0, 0(s0)
+    0x00843583, // ld a1, 8(s0)
+    0x01043683, // ld a3, 16(s0)
+    0x00000013, // テストする命令
+    0x00190913, // addi s2, s2, 1
+    0x00d61c63, // bne a2, a3, fail
+    0x01840413, // addi s0, s0, 24
+    0xfe9912e3, // bne s2, s1, loop
+];
+
+// テーブルを8バイト境界に揃えるためのnop
+const PADDING: [u32; 1] = [0x00000013];
+
+fn r_type(funct7: u32, funct3: u32, opcode: u32) -> u32 {
+    (funct7 << 25) | (RS2 << 20) | (RS1 << 15) | (funct3 << 12) | (RD << 7) | opcode
+}
+
+// 即値(imm[11:0])にはfunct6やfunct7とシフト量、ビットの位置を含める。
+fn i_type(imm: u32, funct3: u32, opcode: u32) -> u32 {
+    (imm << 20) | (RS1 << 15) | (funct3 << 12) | (RD << 7) | opcode
+}
+
+fn run(name: &str, inst: u32, cases: &[(u64, u64, u64)]) -> Option<u64> {
+    let mut program = [&LOOP[..], &EPILOGUE, &PADDING].concat();
+    program[INST_INDEX] = inst;
+
+    let mut bytes = program_bytes(&program);
+    bytes.extend((cases.len() as u64).to_le_bytes());
+
+    for (rs1, rs2, rd) in cases {
+        bytes.extend(rs1.to_le_bytes());
+        bytes.extend(rs2.to_le_bytes());
+        bytes.extend(rd.to_le_bytes());
+    }
+
+    run_program(
+        &format!("bitmanip-{}", name.replace([' ', '.'], "-")),
+        &bytes,
+    )
+}
+
+fn check(name: &str, inst: u32, cases: &[(u64, u64, u64)]) {
+    assert_eq!(run(name, inst, cases), Some(0), "{}", name);
+}
+
+#[test]
+fn test_zba() {
+    check(
+        "sh1add",
+        r_type(0b0010000, 0b010, OP),
+        &[
+            (5, 100, 110),
+            (0x8000_0000_0000_0001, 0, 2),
+            (0xffff_ffff_ffff_ffff, 1, 0xffff_ffff_ffff_ffff),
+        ],
+    );
+    check(
+        "sh2add",
+        r_type(0b0010000, 0b100, OP),
+        &[
+            (5, 100, 120),
+            (0x8000_0000_0000_0001, 0, 4),
+            (0xffff_ffff_ffff_ffff, 1, 0xffff_ffff_ffff_fffd),
+        ],
+    );
+    check(
+        "sh3add",
+        r_type(0b0010000, 0b110, OP),
+        &[
+            (5, 100, 140),
+            (0x8000_0000_0000_0001, 0, 8),
+            (0xffff_ffff_ffff_ffff, 1, 0xffff_ffff_ffff_fff9),
+        ],
+    );
+    check(
+        "add.uw",
+        r_type(0b0000100, 0b000, OP_32),
+        &[
+            (0xffff_ffff_8000_0000, 1, 0x8000_0001),
+            (0x1234_5678, 0x1_0000_0000, 0x1_1234_5678),
+            (0xffff_ffff_ffff_ffff, 0xffff_ffff_ffff_ffff, 0xffff_fffe),
+        ],
+    );
+    check(
+        "sh1add.uw",
+        r_type(0b0010000, 0b010, OP_32),
+        &[
+            (0xdead_beef_ffff_ffff, 0, 0x1_ffff_fffe),
+            (0x8000_0000, 1, 0x1_0000_0001),
+        ],
+    );
+    check(
+        "sh2add.uw",
+        r_type(0b0010000, 0b100, OP_32),
+        &[
+            (0xdead_beef_ffff_ffff, 0, 0x3_ffff_fffc),
+            (0x8000_0000, 1, 0x2_0000_0001),
+        ],
+    );
+    check(
+        "sh3add.uw",
+        r_type(0b0010000, 0b110, OP_32),
+        &[
+            (0xdead_beef_ffff_ffff, 0, 0x7_ffff_fff8),
+            (0x8000_0000, 1, 0x4_0000_0001),
+        ],
+    );
+    check(
+        "slli.uw 4",
+        i_type(0x080 | 4, 0b001, OP_IMM_32),
+        &[(0xffff_ffff_8000_0001, 0, 0x8_0000_0010)],
+    );
+    check(
+        "slli.uw 32",
+        i_type(0x080 | 32, 0b001, OP_IMM_32),
+        &[(0xffff_ffff_8000_0001, 0, 0x8000_0001_0000_0000)],
+    );
+    check(
+        "slli.uw 0",
+        i_type(0x080, 0b001, OP_IMM_32),
+        &[(0xffff_ffff_8000_0001, 0, 0x8000_0001)],
+    );
+}
+
+#[test]
+fn test_zbb() {
+    check(
+        "andn",
+        r_type(0b0100000, 0b111, OP),
+        &[(
+            0xff00_ff00_ff00_ff00,
+            0xff0_0ff0_0ff0_0ff0,
+            0xf000_f000_f000_f000,
+        )],
+    );
+    check(
+        "orn",
+        r_type(0b0100000, 0b110, OP),
+        &[(
+            0xff00_ff00_ff00_ff00,
+            0xff0_0ff0_0ff0_0ff0,
+            0xff0f_ff0f_ff0f_ff0f,
+        )],
+    );
+    check(
+        "xnor",
+        r_type(0b0100000, 0b100, OP),
+        &[(
+            0xff00_ff00_ff00_ff00,
+            0xff0_0ff0_0ff0_0ff0,
+            0xf0f_0f0f_0f0f_0f0f,
+        )],
+    );
+    check(
+        "clz",
+        i_type(0x600, 0b001, OP_IMM),
+        &[
+            (0, 0, 64),
+            (1, 0, 63),
+            (0x8000_0000_0000_0000, 0, 0),
+            (0x1000_0000_0000, 0, 19),
+        ],
+    );
+    check(
+        "ctz",
+        i_type(0x601, 0b001, OP_IMM),
+        &[
+            (0, 0, 64),
+            (1, 0, 0),
+            (0x8000_0000_0000_0000, 0, 63),
+            (0x1000_0000_0000, 0, 44),
+        ],
+    );
+    check(
+        "cpop",
+        i_type(0x602, 0b001, OP_IMM),
+        &[
+            (0, 0, 0),
+            (0xffff_ffff_ffff_ffff, 0, 64),
+            (0x8000_0001_0000_0003, 0, 4),
+        ],
+    );
+    check(
+        "clzw",
+        i_type(0x600, 0b001, OP_IMM_32),
+        &[
+            (0xffff_ffff_0000_0000, 0, 32),
+            (0x1_0000, 0, 15),
+            (0x8000_0000, 0, 0),
+        ],
+    );
+    check(
+        "ctzw",
+        i_type(0x601, 0b001, OP_IMM_32),
+        &[
+            (0xffff_ffff_0000_0000, 0, 32),
+            (0x1_0000, 0, 16),
+            (0x8000_0000, 0, 31),
+        ],
+    );
+    check(
+        "cpopw",
+        i_type(0x602, 0b001, OP_IMM_32),
+        &[(0xffff_ffff_0000_0000, 0, 0), (0x1_ffff_ffff, 0, 32)],
+    );
+    check(
+        "max",
+        r_type(0b0000101, 0b110, OP),
+        &[
+            (0xffff_ffff_ffff_ffff, 1, 1),
+            (
+                0x8000_0000_0000_0000,
+                0x7fff_ffff_ffff_ffff,
+                0x7fff_ffff_ffff_ffff,
+            ),
+        ],
+    );
+    check(
+        "min",
+        r_type(0b0000101, 0b100, OP),
+        &[
+            (0xffff_ffff_ffff_ffff, 1, 0xffff_ffff_ffff_ffff),
+            (
+                0x8000_0000_0000_0000,
+                0x7fff_ffff_ffff_ffff,
+                0x8000_0000_0000_0000,
+            ),
+        ],
+    );
+    check(
+        "maxu",
+        r_type(0b0000101, 0b111, OP),
+        &[
+            (0xffff_ffff_ffff_ffff, 1, 0xffff_ffff_ffff_ffff),
+            (
+                0x8000_0000_0000_0000,
+                0x7fff_ffff_ffff_ffff,
+                0x8000_0000_0000_0000,
+            ),
+        ],
+    );
+    check(
+        "minu",
+        r_type(0b0000101, 0b101, OP),
+        &[
+            (0xffff_ffff_ffff_ffff, 1, 1),
+            (
+                0x8000_0000_0000_0000,
+                0x7fff_ffff_ffff_ffff,
+                0x7fff_ffff_ffff_ffff,
+            ),
+        ],
+    );
+    check(
+        "sext.b",
+        i_type(0x604, 0b001, OP_IMM),
+        &[(128, 0, 0xffff_ffff_ffff_ff80), (0x1234_567f, 0, 127)],
+    );
+    check(
+        "sext.h",
+        i_type(0x605, 0b001, OP_IMM),
+        &[
+            (0x1234_8000, 0, 0xffff_ffff_ffff_8000),
+            (0xffff_7fff, 0, 0x7fff),
+        ],
+    );
+    check(
+        "zext.h",
+        i_type(0x080, 0b100, OP_32),
+        &[(0xffff_ffff_ffff_8000, 0, 0x8000), (0x1234_5678, 0, 0x5678)],
+    );
+    check(
+        "rol",
+        r_type(0b0110000, 0b001, OP),
+        &[
+            (0x8000_0000_0000_0001, 1, 3),
+            (0x8000_0000_0000_0001, 65, 3),
+            (0x123_4567_89ab_cdef, 0, 0x123_4567_89ab_cdef),
+        ],
+    );
+    check(
+        "ror",
+        r_type(0b0110000, 0b101, OP),
+        &[
+            (0x8000_0000_0000_0001, 1, 0xc000_0000_0000_0000),
+            (0x8000_0000_0000_0001, 65, 0xc000_0000_0000_0000),
+            (0x123_4567_89ab_cdef, 0, 0x123_4567_89ab_cdef),
+        ],
+    );
+    check(
+        "rolw",
+        r_type(0b0110000, 0b001, OP_32),
+        &[
+            (0x4000_0000, 1, 0xffff_ffff_8000_0000),
+            (0xffff_ffff_0000_0001, 36, 16),
+            (0x8000_0000, 1, 1),
+            (0x1234_5678, 0, 0x1234_5678),
+        ],
+    );
+    check(
+        "rorw",
+        r_type(0b0110000, 0b101, OP_32),
+        &[
+            (1, 1, 0xffff_ffff_8000_0000),
+            (0xffff_ffff_0000_0010, 36, 1),
+            (0x8000_0000, 0, 0xffff_ffff_8000_0000),
+            (0x1234_5678, 0, 0x1234_5678),
+        ],
+    );
+    check(
+        "rori 4",
+        i_type(0x600 | 4, 0b101, OP_IMM),
+        &[(1, 0, 0x1000_0000_0000_0000)],
+    );
+    check("rori 63", i_type(0x600 | 63, 0b101, OP_IMM), &[(1, 0, 2)]);
+    check(
+        "roriw 1",
+        i_type(0x600 | 1, 0b101, OP_IMM_32),
+        &[(1, 0, 0xffff_ffff_8000_0000)],
+    );
+    check(
+        "roriw 1",
+        i_type(0x600 | 1, 0b101, OP_IMM_32),
+        &[(0xffff_ffff_0000_0002, 0, 1)],
+    );
+    check(
+        "roriw 31",
+        i_type(0x600 | 31, 0b101, OP_IMM_32),
+        &[(0x4000_0000, 0, 0xffff_ffff_8000_0000)],
+    );
+    check(
+        "orc.b",
+        i_type(0x287, 0b101, OP_IMM),
+        &[(0x100_0000_0010_8000, 0, 0xff00_0000_00ff_ff00), (0, 0, 0)],
+    );
+    check(
+        "rev8",
+        i_type(0x6b8, 0b101, OP_IMM),
+        &[
+            (0x102_0304_0506_0708, 0, 0x807_0605_0403_0201),
+            (255, 0, 0xff00_0000_0000_0000),
+        ],
+    );
+}
+
+#[test]
+fn test_zbc() {
+    check(
+        "clmul",
+        r_type(0b0000101, 0b001, OP),
+        &[
+            (3, 3, 5),
+            (
+                0xffff_ffff_ffff_ffff,
+                0xffff_ffff_ffff_ffff,
+                0x5555_5555_5555_5555,
+            ),
+            (0x8000_0000_0000_0001, 3, 0x8000_0000_0000_0003),
+        ],
+    );
+    check(
+        "clmulh",
+        r_type(0b0000101, 0b011, OP),
+        &[
+            (0x8000_0000_0000_0000, 2, 1),
+            (
+                0xffff_ffff_ffff_ffff,
+                0xffff_ffff_ffff_ffff,
+                0x5555_5555_5555_5555,
+            ),
+            (0x8000_0000_0000_0001, 3, 1),
+        ],
+    );
+    check(
+        "clmulr",
+        r_type(0b0000101, 0b010, OP),
+        &[
+            (0x8000_0000_0000_0000, 1, 1),
+            (
+                0xffff_ffff_ffff_ffff,
+                0xffff_ffff_ffff_ffff,
+                0xaaaa_aaaa_aaaa_aaaa,
+            ),
+            (0x8000_0000_0000_0001, 3, 3),
+        ],
+    );
+}
+
+#[test]
+fn test_zbs() {
+    check(
+        "bset",
+        r_type(0b0010100, 0b001, OP),
+        &[(0, 63, 0x8000_0000_0000_0000), (0, 64, 1), (1, 0, 1)],
+    );
+    check(
+        "bclr",
+        r_type(0b0100100, 0b001, OP),
+        &[
+            (0xffff_ffff_ffff_ffff, 0, 0xffff_ffff_ffff_fffe),
+            (0xffff_ffff_ffff_ffff, 127, 0x7fff_ffff_ffff_ffff),
+            (0, 5, 0),
+        ],
+    );
+    check(
+        "binv",
+        r_type(0b0110100, 0b001, OP),
+        &[(16, 4, 0), (0, 63, 0x8000_0000_0000_0000)],
+    );
+    check(
+        "bext",
+        r_type(0b0100100, 0b101, OP),
+        &[
+            (16, 4, 1),
+            (16, 68, 1),
+            (0x8000_0000_0000_0000, 63, 1),
+            (16, 5, 0),
+        ],
+    );
+    check(
+        "bseti 63",
+        i_type(0x280 | 63, 0b001, OP_IMM),
+        &[(0, 0, 0x8000_0000_0000_0000)],
+    );
+    check(
+        "bclri 63",
+        i_type(0x480 | 63, 0b001, OP_IMM),
+        &[(0xffff_ffff_ffff_ffff, 0, 0x7fff_ffff_ffff_ffff)],
+    );
+    check("binvi 0", i_type(0x680, 0b001, OP_IMM), &[(1, 0, 0)]);
+    check(
+        "bexti 63",
+        i_type(0x480 | 63, 0b101, OP_IMM),
+        &[(0x8000_0000_0000_0000, 0, 1)],
+    );
+    check("bexti 4", i_type(0x480 | 4, 0b101, OP_IMM), &[(16, 0, 1)]);
+    check("bexti 5", i_type(0x480 | 5, 0b101, OP_IMM), &[(16, 0, 0)]);
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use common::{program_bytes, run_program, EPILOGUE};
+use tiny_riscv_emulator::{bus::Device, clint::Clint};
+
+// 各レジスタのアドレス(CLINTの先頭からのオフセット)
+const MSIP: u64 = 0x0;
+const MTIMECMP: u64 = 0x4000;
+const MTIME: u64 = 0xbff8;
+
+const MIP_MSIP: u64 = 1 << 3;
+const MIP_MTIP: u64 = 1 << 7;
+
+fn msip(hart: u64) -> u64 {
+    MSIP + hart * 4
+}
+
+fn mtimecmp(hart: u64) -> u64 {
+    MTIMECMP + hart * 8
+}
+
+// 周波数を0にするとmtimeは書き込んだ値から進まない。
+fn clint(harts: usize) -> Clint {
+    Clint::new(harts, 0)
+}
+
+#[test]
+fn test_clint_msip() {
+    let mut clint = clint(2);
+
+    assert_eq!(clint.read(msip(1), 4), Some(0));
+    assert_eq!(clint.pending_interrupts(1, 0), 0);
+
+    // msipは最下位ビットのみ有効で、書き込んだハートだけにMSIPが立つ。
+    assert!(clint.write(msip(1), 4, 0xffff_ffff));
+    assert_eq!(clint.read(msip(1), 4), Some(1));
+    assert_eq!(clint.pending_interrupts(1, 0), MIP_MSIP);
+    assert_eq!(clint.pending_interrupts(0, 0), 0);
+
+    assert!(clint.write(msip(1), 4, 0));
+    assert_eq!(clint.pending_interrupts(1, 0), 0);
+
+    // 存在しないハートのmsipにはアクセスできない。
+    assert_eq!(clint.read(msip(2), 4), None);
+    assert!(!clint.write(msip(2), 4, 1));
+}
+
+#[test]
+fn test_clint_mtimecmp() {
+    let mut clint = clint(2);
+
+    // mtimecmpの初期値は最大値なので、MTIPは立たない。
+    assert_eq!(clint.read(mtimecmp(0), 8), Some(u64::MAX));
+    assert_eq!(clint.pending_interrupts(0, u64::MAX - 1), 0);
+
+    // mtimeがmtimecmp以上になるとMTIPが立つ。
+    assert!(clint.write(mtimecmp(1), 8, 100));
+    assert_eq!(clint.pending_interrupts(1, 99), 0);
+    assert_eq!(clint.pending_interrupts(1, 100), MIP_MTIP);
+    assert_eq!(clint.pending_interrupts(0, 100), 0);
+
+    assert!(clint.write(msip(1), 4, 1));
+    assert_eq!(clint.pending_interrupts(1, 100), MIP_MSIP | MIP_MTIP);
+
+    // 存在しないハートのmtimecmpにはアクセスできない。
+    assert_eq!(clint.read(mtimecmp(2), 8), None);
+}
+
+#[test]
+fn test_clint_partial_mtimecmp_write() {
+    let mut clint = clint(1);
+
+    // RV32では上位と下位の4バイトずつ書き込む。書き込まない側の4バイトは保持される。
+    assert!(clint.write(mtimecmp(0), 4, 0x5555_5555));
+    assert_eq!(clint.read(mtimecmp(0), 8), Some(0xffff_ffff_5555_5555));
+
+    assert!(clint.write(mtimecmp(0) + 4, 4, 0x1));
+    assert_eq!(clint.read(mtimecmp(0), 8), Some(0x1_5555_5555));
+    assert_eq!(clint.read(mtimecmp(0), 4), Some(0x5555_5555));
+    assert_eq!(clint.read(mtimecmp(0) + 4, 4), Some(0x1));
+
+    assert_eq!(clint.pending_interrupts(0, 0x1_5555_5554), 0);
+    assert_eq!(clint.pending_interrupts(0, 0x1_5555_5555), MIP_MTIP);
+
+    // レジスタの境界をまたぐアクセスはできない。
+    assert_eq!(clint.read(mtimecmp(0) + 4, 8), None);
+    assert!(!clint.write(mtimecmp(0) + 4, 8, 0));
+}
+
+#[test]
+fn test_clint_mtime() {
+    let mut clint = clint(1);
+
+    assert!(clint.write(MTIME, 8, 0x1234_5678_9abc_def0));
+    assert_eq!(clint.read(MTIME, 8), Some(0x1234_5678_9abc_def0));
+    assert_eq!(clint.mtime(), 0x1234_5678_9abc_def0);
+
+    assert!(clint.write(MTIME + 4, 4, 0x1));
+    assert_eq!(clint.read(MTIME, 8), Some(0x1_9abc_def0));
+    assert_eq!(clint.read(MTIME + 4, 4), Some(0x1));
+
+    // 周波数が0でなければmtimeは書き込んだ値から進む。
+    let mut clint = Clint::new(1, 1_000_000_000);
+
+    assert!(clint.write(MTIME, 8, 1 << 32));
+    assert!(clint.mtime() >= 1 << 32);
+}
+
+// ハート0でtime CSRとMSIP、MTIPの割り込みを確かめるプログラム
+// 割り込みのハンドラはmcauseをs3に入れて、msipを0、mtimecmpを最大値に戻す。
+// 番号は失敗した確認の番号(s2)で、このあとにEPILOGUEを置く。
+const INTERRUPTS: &[u32] = &[
+    // _start:
+    0x00000297, // auipc t0, 0
+    0x08828293, // addi t0, t0, 136 (trap)
+    0x30529073, // csrw mtvec, t0
+    0x00000993, // li s3, 0
+    // timeはmtimeに書き込んだ値から進む。
+    0x00100913, // li s2, 1
+    0x0200c2b7, // lui t0, 0x200c
+    0xff82829b, // addiw t0, t0, -8 (mtime)
+    0x00100313, // li t1, 1
+    0x02031313, // slli t1, t1, 32
+    0x0062b023, // sd t1, 0(t0)
+    0xc01023f3, // rdtime t2
+    0x0863e263, // bltu t2, t1, fail
+    // msipに書き込むとMSIPの割り込みが起こる。
+    0x00200913, // li s2, 2
+    0x08800293, // li t0, 0x88 (MTIE | MSIE)
+    0x3042a073, // csrs mie, t0
+    0x30046073, // csrsi mstatus, 8 (MIE)
+    0x020002b7, // lui t0, 0x2000 (msip)
+    0x00100313, // li t1, 1
+    0x0062a023, // sw t1, 0(t0)
+    0x00000013, // nop
+    0xfff00313, // li t1, -1
+    0x00135313, // srli t1, t1, 1
+    0x00430313, // addi t1, t1, 4
+    0x04699a63, // bne s3, t1, fail
+    // mtimecmpをmtime以下にするとMTIPの割り込みが起こる。
+    0x00300913, // li s2, 3
+    0x00000993, // li s3, 0
+    0x020042b7, // lui t0, 0x2004 (mtimecmp)
+    0x0002b023, // sd zero, 0(t0)
+    0x00000013, // nop
+    0xfff00313, // li t1, -1
+    0x00135313, // srli t1, t1, 1
+    0x00830313, // addi t1, t1, 8
+    0x02699863, // bne s3, t1, fail
+    0x0200006f, // j pass
+    // trap:
+    0x342029f3, // csrr s3, mcause
+    0x020002b7, // lui t0, 0x2000
+    0x0002a023, // sw zero, 0(t0)
+    0x020042b7, // lui t0, 0x2004
+    0xfff00313, // li t1, -1
+    0x0062b023, // sd t1, 0(t0)
+    0x30200073, // mret
+];
+
+#[test]
+fn test_clint_interrupts_and_time_csr() {
+    let program = [INTERRUPTS, &EPILOGUE].concat();
+
+    assert_eq!(run_program("clint", &program_bytes(&program)), Some(0));
+}
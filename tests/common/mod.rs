@@ -0,0 +1,119 @@
+// 結合テストで共有する関数と定数
+// テストごとに使うものが違うので、使わないものがあっても警告にしない。
+#![allow(dead_code)]
+
+use std::{
+    collections::VecDeque,
+    env, fs,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use tiny_riscv_emulator::{emulator::Emulator, uart::Uart};
+
+// 終了しない場合(ライブロックや不正命令のトラップのループなど)にテストを打ち切るまでの命令数
+pub const MAX_STEPS: usize = 10_000_000;
+
+// プログラムの最後に置いて、結果をシステムコントローラに書き込んで終了する命令列
+// passに飛ぶ(またはそのまま実行する)と0x5555を、failに飛ぶと(s2 << 16) | 0x3333を書き込む。
+pub const EPILOGUE: [u32; 10] = [
+    // pass:
+    0x00005337, // lui t1, 5
+    0x55530313, // addi t1, t1, 0x555
+    0x0140006f, // j finish
+    // fail:
+    0x01091313, // slli t1, s2, 16
+    0x000033b7, // lui t2, 3
+    0x33338393, // addi t2, t2, 0x333
+    0x00736333, // or t1, t1, t2
+    // finish:
+    0x001002b7, // lui t0, 0x100
+    0x0062a023, // sw t1, 0(t0)
+    0x0000006f, // 1: j 1b
+];
+
+// 命令列をリトルエンディアンのバイト列にする関数
+pub fn program_bytes(program: &[u32]) -> Vec<u8> {
+    program.iter().flat_map(|inst| inst.to_le_bytes()).collect()
+}
+
+// フラットなバイナリのプログラムをRAMの先頭にロードする関数
+// すべてのハートが先頭から実行する。ハートの数などはロードする前に設定しておく。
+pub fn load_program(emulator: &mut Emulator, name: &str, bytes: &[u8]) {
+    let path = env::temp_dir().join(format!("tiny-riscv-emulator-{}.bin", name));
+    fs::write(&path, bytes).unwrap();
+
+    emulator.load(&path).unwrap();
+
+    let _ = fs::remove_file(&path);
+}
+
+// 終了するまで(最大でMAX_STEPS命令)実行して終了コードを返す関数
+pub fn run_to_exit(emulator: &mut Emulator) -> Option<u64> {
+    for _ in 0..MAX_STEPS {
+        if emulator.exit_code().is_some() {
+            break;
+        }
+
+        emulator.step();
+    }
+
+    emulator.exit_code()
+}
+
+// 1つのハートでプログラムを実行して終了コードを返す関数
+pub fn run_program(name: &str, bytes: &[u8]) -> Option<u64> {
+    let mut emulator = Emulator::default();
+
+    load_program(&mut emulator, name, bytes);
+    run_to_exit(&mut emulator)
+}
+
+// UARTの出力を保存し、シェルのプロンプトが表示されたらコマンドを入力する。
+struct Console {
+    output: Arc<Mutex<Vec<u8>>>,
+    input: Arc<Mutex<VecDeque<u8>>>,
+    prompt: &'static [u8],
+    command: &'static [u8],
+    sent: bool,
+}
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut output = self.output.lock().unwrap();
+        output.extend_from_slice(buf);
+
+        if !self.sent && output.ends_with(self.prompt) {
+            self.input.lock().unwrap().extend(self.command);
+            self.sent = true;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// 最初にpromptが出力されたときにcommandを入力するUARTと、UARTの出力を返す関数
+pub fn console_uart(prompt: &'static [u8], command: &'static [u8]) -> (Uart, Arc<Mutex<Vec<u8>>>) {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let input = Arc::new(Mutex::new(VecDeque::new()));
+
+    let console = Console {
+        output: Arc::clone(&output),
+        input: Arc::clone(&input),
+        prompt,
+        command,
+        sent: false,
+    };
+
+    (Uart::new(input, Box::new(console)), output)
+}
+
+pub fn contains(output: &[u8], pattern: &[u8]) -> bool {
+    output
+        .windows(pattern.len())
+        .any(|window| window == pattern)
+}
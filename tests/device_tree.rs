@@ -0,0 +1,297 @@
+use std::{
+    collections::VecDeque,
+    env, fs, io,
+    sync::{Arc, Mutex},
+};
+
+use tiny_riscv_emulator::{
+    emulator::Emulator,
+    uart::{Uart, UART_BASE, UART_IRQ, UART_SIZE},
+    vector::VectorConfig,
+    Xlen,
+};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+// ヘッダのサイズ(10個のu32)
+const FDT_HEADER_SIZE: usize = 40;
+
+const EXTENSIONS: &str = "zicond_zicsr_zifencei_zihintntl_zihintpause_zba_zbb_zbc_zbs";
+
+// DTBのノード(ルートからのパス)とそのプロパティ
+struct Node {
+    path: String,
+    properties: Vec<(String, Vec<u8>)>,
+}
+
+impl Node {
+    fn property(&self, name: &str) -> Option<&[u8]> {
+        self.properties
+            .iter()
+            .find(|(property, _)| property == name)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        let value = self.property(name)?;
+
+        std::str::from_utf8(value.strip_suffix(&[0])?).ok()
+    }
+
+    fn cells(&self, name: &str) -> Option<Vec<u32>> {
+        let value = self.property(name)?;
+
+        Some(value.chunks(4).map(|cell| be_u32(cell, 0)).collect())
+    }
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+// NULで終わる文字列を読む関数
+fn c_str(bytes: &[u8], offset: usize) -> &str {
+    let len = bytes[offset..].iter().position(|&b| b == 0).unwrap();
+
+    std::str::from_utf8(&bytes[offset..offset + len]).unwrap()
+}
+
+// ヘッダを確かめてから構造ブロックをたどり、すべてのノードを出てくる順に返す関数
+fn parse(dtb: &[u8]) -> Vec<Node> {
+    assert!(dtb.len() >= FDT_HEADER_SIZE);
+
+    let header = |index: usize| be_u32(dtb, index * 4) as usize;
+
+    let total_size = header(1);
+    let off_dt_struct = header(2);
+    let off_dt_strings = header(3);
+    let off_mem_rsvmap = header(4);
+    let size_dt_strings = header(8);
+    let size_dt_struct = header(9);
+
+    assert_eq!(header(0) as u32, FDT_MAGIC);
+    assert_eq!(total_size, dtb.len());
+    assert_eq!(header(5), 17);
+    assert_eq!(header(6), 16);
+
+    // メモリ予約ブロックは8バイト境界で、終端(16バイトの0)で終わる。
+    assert_eq!(off_mem_rsvmap % 8, 0);
+    assert!(off_mem_rsvmap >= FDT_HEADER_SIZE);
+    assert_eq!(dtb[off_mem_rsvmap..off_mem_rsvmap + 16], [0; 16]);
+
+    // 構造ブロックは4バイト境界で、各ブロックはDTBの中に収まる。
+    assert_eq!(off_dt_struct % 4, 0);
+    assert!(off_dt_struct >= off_mem_rsvmap + 16);
+    assert!(off_dt_struct + size_dt_struct <= off_dt_strings);
+    assert_eq!(off_dt_strings + size_dt_strings, total_size);
+
+    let structure = &dtb[off_dt_struct..off_dt_struct + size_dt_struct];
+    let strings = &dtb[off_dt_strings..];
+
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut stack: Vec<usize> = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let token = be_u32(structure, offset);
+        offset += 4;
+
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = c_str(structure, offset);
+                offset = (offset + name.len() + 1).next_multiple_of(4);
+
+                let path = match stack.last() {
+                    Some(&parent) if nodes[parent].path == "/" => format!("/{}", name),
+                    Some(&parent) => format!("{}/{}", nodes[parent].path, name),
+                    None => "/".to_string(),
+                };
+
+                stack.push(nodes.len());
+                nodes.push(Node {
+                    path,
+                    properties: Vec::new(),
+                });
+            }
+            FDT_END_NODE => {
+                stack.pop().expect("FDT_END_NODE without FDT_BEGIN_NODE");
+            }
+            FDT_PROP => {
+                let len = be_u32(structure, offset) as usize;
+                let name_offset = be_u32(structure, offset + 4) as usize;
+                let value = structure[offset + 8..offset + 8 + len].to_vec();
+                offset = (offset + 8 + len).next_multiple_of(4);
+
+                assert!(name_offset < size_dt_strings);
+
+                let node = *stack.last().expect("FDT_PROP outside of a node");
+                nodes[node]
+                    .properties
+                    .push((c_str(strings, name_offset).to_string(), value));
+            }
+            FDT_NOP => {}
+            FDT_END => break,
+            _ => panic!("unknown token 0x{:x} at 0x{:x}", token, offset - 4),
+        }
+    }
+
+    // すべてのノードが閉じていて、FDT_ENDが構造ブロックの最後にある。
+    assert!(stack.is_empty());
+    assert_eq!(offset, size_dt_struct);
+
+    nodes
+}
+
+fn node<'a>(nodes: &'a [Node], path: &str) -> &'a Node {
+    nodes
+        .iter()
+        .find(|node| node.path == path)
+        .unwrap_or_else(|| panic!("{} is missing", path))
+}
+
+#[test]
+fn test_isa_string() {
+    let emulator = Emulator::default();
+
+    assert_eq!(
+        emulator.isa_string(),
+        format!("rv64imafdc_{}_zve64x_zvl128b_sstc", EXTENSIONS)
+    );
+
+    // ベクトル拡張の構成はZve*xとZvl*bに反映される。
+    let mut emulator = Emulator::default();
+    emulator.set_vector_config(VectorConfig {
+        vlen: 256,
+        elen: 32,
+    });
+
+    assert_eq!(
+        emulator.isa_string(),
+        format!("rv64imafdc_{}_zve32x_zvl256b_sstc", EXTENSIONS)
+    );
+
+    // RV32ではFとD、ベクトル拡張をサポートしない。
+    let mut emulator = Emulator::default();
+    emulator.set_xlen(Xlen::X32);
+
+    assert_eq!(
+        emulator.isa_string(),
+        format!("rv32imac_{}_sstc", EXTENSIONS)
+    );
+}
+
+#[test]
+fn test_device_tree() {
+    let mut emulator = Emulator::default();
+
+    emulator.set_harts(2);
+    emulator.set_timebase_frequency(1_000_000);
+    emulator.attach_device_with_irq(
+        UART_BASE,
+        UART_SIZE,
+        UART_IRQ,
+        Box::new(Uart::new(
+            Arc::new(Mutex::new(VecDeque::new())),
+            Box::new(io::sink()),
+        )),
+    );
+
+    let dtb = emulator.device_tree("console=ttyS0", Some((0x8400_0000, 0x8410_0000)));
+    let nodes = parse(&dtb);
+
+    assert_eq!(
+        node(&nodes, "/").string("model"),
+        Some("tiny-riscv-emulator")
+    );
+
+    let chosen = node(&nodes, "/chosen");
+    assert_eq!(chosen.string("bootargs"), Some("console=ttyS0"));
+    assert_eq!(chosen.string("stdout-path"), Some("/soc/serial@10000000"));
+    assert_eq!(
+        chosen.cells("linux,initrd-start"),
+        Some(vec![0, 0x8400_0000])
+    );
+    assert_eq!(chosen.cells("linux,initrd-end"), Some(vec![0, 0x8410_0000]));
+
+    let cpus = node(&nodes, "/cpus");
+    assert_eq!(cpus.cells("timebase-frequency"), Some(vec![1_000_000]));
+
+    // ハートごとにcpuノードと割り込みコントローラがある。
+    for hart in 0..2 {
+        let cpu = node(&nodes, &format!("/cpus/cpu@{}", hart));
+
+        assert_eq!(cpu.string("device_type"), Some("cpu"));
+        assert_eq!(cpu.cells("reg"), Some(vec![hart]));
+        assert_eq!(
+            cpu.string("riscv,isa"),
+            Some(emulator.isa_string().as_str())
+        );
+        assert_eq!(cpu.string("riscv,isa-base"), Some("rv64i"));
+        assert_eq!(cpu.string("mmu-type"), Some("riscv,sv57"));
+
+        let intc = node(&nodes, &format!("/cpus/cpu@{}/interrupt-controller", hart));
+        assert_eq!(intc.string("compatible"), Some("riscv,cpu-intc"));
+    }
+
+    assert!(nodes.iter().all(|node| node.path != "/cpus/cpu@2"));
+
+    // CLINTは各ハートのMSIPとMTIP、PLICは各ハートのMEIPとSEIPにつながる。
+    let intc = |hart: u32| {
+        let cpu = node(&nodes, &format!("/cpus/cpu@{}/interrupt-controller", hart));
+        cpu.cells("phandle").unwrap()[0]
+    };
+
+    let clint = node(&nodes, "/soc/clint@2000000");
+    assert_eq!(clint.cells("reg"), Some(vec![0, 0x200_0000, 0, 0x1_0000]));
+    assert_eq!(
+        clint.cells("interrupts-extended"),
+        Some(vec![intc(0), 3, intc(0), 7, intc(1), 3, intc(1), 7])
+    );
+
+    let plic = node(&nodes, "/soc/plic@c000000");
+    assert!(plic.property("interrupt-controller").is_some());
+    assert_eq!(
+        plic.cells("interrupts-extended"),
+        Some(vec![intc(0), 11, intc(0), 9, intc(1), 11, intc(1), 9])
+    );
+
+    // 接続したデバイスはPLICの割り込み源につながる。
+    let serial = node(&nodes, "/soc/serial@10000000");
+    assert_eq!(serial.cells("reg"), Some(vec![0, 0x1000_0000, 0, 0x100]));
+    assert_eq!(serial.cells("interrupts"), Some(vec![UART_IRQ]));
+    assert_eq!(serial.cells("interrupt-parent"), plic.cells("phandle"));
+
+    // dump_device_treeは同じDTBを書き出す。
+    let path = env::temp_dir().join("tiny-riscv-emulator-device-tree.dtb");
+    emulator
+        .dump_device_tree(&path, "console=ttyS0", Some((0x8400_0000, 0x8410_0000)))
+        .unwrap();
+
+    assert_eq!(fs::read(&path).unwrap(), dtb);
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn test_device_tree_rv32() {
+    let mut emulator = Emulator::default();
+
+    emulator.set_xlen(Xlen::X32);
+
+    let nodes = parse(&emulator.device_tree("", None));
+
+    // initrdを指定しない場合はinitrdのプロパティを含めない。
+    let chosen = node(&nodes, "/chosen");
+    assert_eq!(chosen.string("bootargs"), Some(""));
+    assert!(chosen.property("linux,initrd-start").is_none());
+
+    let cpu = node(&nodes, "/cpus/cpu@0");
+    assert_eq!(cpu.string("riscv,isa-base"), Some("rv32i"));
+    assert_eq!(cpu.string("mmu-type"), Some("riscv,sv32"));
+}
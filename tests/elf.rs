@@ -0,0 +1,240 @@
+mod common;
+
+use std::{env, fs};
+
+use common::{load_program, program_bytes, run_to_exit, EPILOGUE};
+use tiny_riscv_emulator::{
+    elf::{Elf, ElfError},
+    emulator::Emulator,
+    Xlen,
+};
+
+const EM_RISCV: u16 = 243;
+const EM_X86_64: u16 = 62;
+
+const ELFDATA2MSB: u8 = 2;
+
+const DRAM_BASE: u64 = 0x8000_0000;
+
+// 1つのPT_LOADのセグメントを持つELFを作る関数
+// セグメントはdataをpaddrに配置し、mem_sizeまでの残りは.bssとして0で埋める。
+fn elf(xlen: Xlen, entry: u64, data: &[u8], mem_size: u64) -> Vec<u8> {
+    // ELFヘッダとプログラムヘッダの大きさ
+    let (class, ehdr_size, phdr_size) = match xlen {
+        Xlen::X32 => (1, 52, 32),
+        Xlen::X64 => (2, 64, 56),
+    };
+
+    let word = |value: u64| match xlen {
+        Xlen::X32 => (value as u32).to_le_bytes().to_vec(),
+        Xlen::X64 => value.to_le_bytes().to_vec(),
+    };
+
+    let mut bytes = vec![0x7f, b'E', b'L', b'F', class, 1, 1, 0];
+    bytes.resize(16, 0);
+
+    bytes.extend(2u16.to_le_bytes()); // e_type(ET_EXEC)
+    bytes.extend(EM_RISCV.to_le_bytes());
+    bytes.extend(1u32.to_le_bytes()); // e_version
+    bytes.extend(word(entry));
+    bytes.extend(word(ehdr_size)); // e_phoff
+    bytes.extend(word(0)); // e_shoff
+    bytes.extend(0u32.to_le_bytes()); // e_flags
+    bytes.extend((ehdr_size as u16).to_le_bytes());
+    bytes.extend((phdr_size as u16).to_le_bytes());
+    bytes.extend(1u16.to_le_bytes()); // e_phnum
+    bytes.extend(0u16.to_le_bytes()); // e_shentsize
+    bytes.extend(0u16.to_le_bytes()); // e_shnum
+    bytes.extend(0u16.to_le_bytes()); // e_shstrndx
+    assert_eq!(bytes.len() as u64, ehdr_size);
+
+    let offset = ehdr_size + phdr_size;
+    let file_size = data.len() as u64;
+
+    // ELF32とELF64ではp_flagsの位置が異なる。
+    bytes.extend(1u32.to_le_bytes()); // p_type(PT_LOAD)
+
+    if xlen == Xlen::X64 {
+        bytes.extend(7u32.to_le_bytes()); // p_flags(RWX)
+    }
+
+    for value in [offset, entry, entry, file_size, mem_size] {
+        bytes.extend(word(value));
+    }
+
+    if xlen == Xlen::X32 {
+        bytes.extend(7u32.to_le_bytes());
+    }
+
+    bytes.extend(word(0x1000)); // p_align
+    assert_eq!(bytes.len() as u64, offset);
+
+    bytes.extend_from_slice(data);
+
+    bytes
+}
+
+// ELFファイルとして書き出してロードする関数
+// 失敗した場合はエラーのメッセージを返す。
+fn load(emulator: &mut Emulator, name: &str, bytes: &[u8]) -> Result<(), String> {
+    let path = env::temp_dir().join(format!("tiny-riscv-emulator-{}.elf", name));
+    fs::write(&path, bytes).unwrap();
+
+    let result = emulator.load(&path).map_err(|e| e.to_string());
+
+    let _ = fs::remove_file(&path);
+
+    result
+}
+
+#[test]
+fn test_elf_parse() {
+    let data = [0x13, 0, 0, 0];
+
+    let elf64 = elf(Xlen::X64, DRAM_BASE, &data, 0x10);
+    let parsed = Elf::parse(&elf64).unwrap();
+
+    assert_eq!(parsed.xlen(), Xlen::X64);
+    assert_eq!(parsed.entry(), DRAM_BASE);
+
+    let segments = parsed.segments().unwrap();
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0].paddr, DRAM_BASE);
+    assert_eq!(segments[0].data, data);
+    assert_eq!(segments[0].mem_size, 0x10);
+
+    let elf32 = elf(Xlen::X32, DRAM_BASE, &data, 0x10);
+    let parsed = Elf::parse(&elf32).unwrap();
+
+    assert_eq!(parsed.xlen(), Xlen::X32);
+    assert_eq!(parsed.entry(), DRAM_BASE);
+    assert_eq!(parsed.segments().unwrap()[0].data, data);
+}
+
+#[test]
+fn test_elf_unsupported() {
+    for xlen in [Xlen::X32, Xlen::X64] {
+        let valid = elf(xlen, DRAM_BASE, &[0x13, 0, 0, 0], 4);
+
+        // e_machine
+        let mut bytes = valid.clone();
+        bytes[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+
+        assert!(matches!(
+            Elf::parse(&bytes),
+            Err(ElfError::UnsupportedMachine(EM_X86_64))
+        ));
+
+        let error = load(&mut Emulator::default(), "x86-64", &bytes).unwrap_err();
+        assert!(error.contains("Only RISC-V is supported"), "{}", error);
+
+        // EI_DATA(ビッグエンディアン)
+        let mut bytes = valid.clone();
+        bytes[5] = ELFDATA2MSB;
+
+        assert!(matches!(
+            Elf::parse(&bytes),
+            Err(ElfError::UnsupportedEndian(ELFDATA2MSB))
+        ));
+
+        let error = load(&mut Emulator::default(), "big-endian", &bytes).unwrap_err();
+        assert!(
+            error.contains("Only little endian is supported"),
+            "{}",
+            error
+        );
+
+        // EI_CLASS
+        let mut bytes = valid;
+        bytes[4] = 3;
+
+        assert!(matches!(
+            Elf::parse(&bytes),
+            Err(ElfError::UnsupportedClass(3))
+        ));
+    }
+}
+
+#[test]
+fn test_elf_truncated() {
+    for xlen in [Xlen::X32, Xlen::X64] {
+        let valid = elf(xlen, DRAM_BASE, &[0x13, 0, 0, 0], 4);
+        let ehdr_size = if xlen == Xlen::X64 { 64 } else { 52 };
+
+        // ELFヘッダのどこで切れていてもパニックにならずにエラーになる。
+        for len in 4..ehdr_size {
+            assert!(
+                matches!(Elf::parse(&valid[..len]), Err(ElfError::Truncated)),
+                "{:?} {}",
+                xlen,
+                len
+            );
+        }
+
+        // プログラムヘッダが切れている場合
+        let bytes = &valid[..ehdr_size + 8];
+        assert!(matches!(
+            Elf::parse(bytes).unwrap().segments(),
+            Err(ElfError::Truncated)
+        ));
+
+        // セグメントのデータがファイルに収まっていない場合
+        let bytes = &valid[..valid.len() - 1];
+        assert!(matches!(
+            Elf::parse(bytes).unwrap().segments(),
+            Err(ElfError::InvalidSegment(DRAM_BASE))
+        ));
+
+        let error = load(
+            &mut Emulator::default(),
+            "truncated",
+            &valid[..ehdr_size - 1],
+        )
+        .unwrap_err();
+        assert!(error.contains("truncated"), "{}", error);
+    }
+
+    // マジックナンバーが一致しないファイルはELFとして扱わない。
+    assert!(matches!(Elf::parse(b"\x7fEL"), Err(ElfError::InvalidMagic)));
+}
+
+#[test]
+fn test_elf_segment_out_of_memory() {
+    let bytes = elf(Xlen::X64, 0x1000, &[0x13, 0, 0, 0], 4);
+
+    let error = load(&mut Emulator::default(), "out-of-memory", &bytes).unwrap_err();
+    assert!(error.contains("out of the file or the memory"), "{}", error);
+}
+
+// セグメントのファイル上のデータの直後(bss)から0x80002000までが0であることを確かめるプログラム
+// 番号は失敗した確認の番号(s2)で、このあとにEPILOGUEを置き、その直後からがbssになる。
+const BSS: &[u32] = &[
+    // _start:
+    0x00100913, // li s2, 1
+    0x00000013, // nop (bssを8バイト境界に揃える)
+    // la t0, bss
+    0x00000297, // auipc t0, 0
+    0x04828293, // addi t0, t0, 72
+    // li t1, 0x80002000
+    0x40001337, // lui t1, 0x40001
+    0x00131313, // slli t1, t1, 1
+    // 1:
+    0x0002b383, // ld t2, 0(t0)
+    0x00039c63, // bnez t2, fail
+    0x00828293, // addi t0, t0, 8
+    0xfe62eae3, // bltu t0, t1, 1b
+];
+
+#[test]
+fn test_elf_bss_zero_filled() {
+    let data = program_bytes(&[BSS, &EPILOGUE].concat());
+    let bytes = elf(Xlen::X64, DRAM_BASE, &data, 0x2000);
+
+    // 先にRAMを0以外の値で埋めておいても、bssは0で埋められる。
+    let mut emulator = Emulator::default();
+
+    load_program(&mut emulator, "bss-fill", &[0xff; 0x3000]);
+    load(&mut emulator, "bss", &bytes).unwrap();
+
+    assert_eq!(run_to_exit(&mut emulator), Some(0));
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::path::Path;
+
+use common::{console_uart, contains};
+use tiny_riscv_emulator::{
+    boot::BootConfig,
+    emulator::Emulator,
+    uart::{UART_BASE, UART_IRQ, UART_SIZE},
+};
+
+// OpenSBI(fw_jump.binかfw_dynamic.bin)、LinuxのImage、initramfs(rootfs.cpio)を置くディレクトリ
+// initramfsにはシェルとpoweroffコマンドが必要になる(busyboxなど)。
+const LINUX_DIR: &str = "tests/linux";
+
+const PROMPT: &[u8] = b"# ";
+const COMMAND: &[u8] = b"echo tiny-riscv-$((40 + 2))\npoweroff -f\n";
+const EXPECTED: &[u8] = b"tiny-riscv-42";
+
+#[test]
+#[ignore = "requires OpenSBI, a kernel Image and an initramfs in tests/linux"]
+fn test_boot_linux() {
+    let dir = Path::new(LINUX_DIR);
+
+    let firmware = ["fw_jump.bin", "fw_dynamic.bin"]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+        .expect("OpenSBI firmware is not found.");
+
+    let mut config = BootConfig::new(firmware, dir.join("Image"));
+    config.initrd = Some(dir.join("rootfs.cpio"));
+    config.bootargs = "console=ttyS0 rdinit=/bin/sh".to_string();
+
+    let (uart, output) = console_uart(PROMPT, COMMAND);
+
+    let mut emulator = Emulator::default();
+
+    emulator.set_memory_size(256 * 1024 * 1024);
+    emulator.attach_device_with_irq(UART_BASE, UART_SIZE, UART_IRQ, Box::new(uart));
+
+    emulator.boot(&config).unwrap();
+    emulator.run();
+
+    let output = output.lock().unwrap();
+
+    assert_eq!(emulator.exit_code(), Some(0));
+    assert!(
+        contains(&output, EXPECTED),
+        "{}",
+        String::from_utf8_lossy(&output)
+    );
+}
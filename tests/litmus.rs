@@ -0,0 +1,197 @@
+mod common;
+
+use common::{load_program, program_bytes, run_to_exit};
+use tiny_riscv_emulator::emulator::Emulator;
+
+// 2つのハートでLR/SCの動作を確かめるリトマステスト
+// プログラムはRAMの先頭にロードするフラットなバイナリで、両方のハートが先頭から実行する。
+// 共有する変数は0x8000_1000から64バイトずつ離して置く。
+// 成功した場合はシステムコントローラに0x5555を、失敗した場合は(番号 << 16) | 0x3333を書き込む。
+
+// 切り替える間隔が短いほどLRとSCの間や、クリティカルセクションの途中で切り替わりやすくなる。
+const QUANTUMS: [usize; 4] = [1, 2, 3, 1000];
+
+// 各ハートがLR/SCで実装したスピンロックを取って、共有するカウンタをロックなしの命令で500回ずつ増やす。
+// 排他制御ができていればカウンタは1000になる。
+const SPINLOCK: &[u32] = &[
+    // _start:
+    0xf1402573, // csrr a0, mhartid
+    0x00080437, // lui s0, 128
+    0x0014041b, // addiw s0, s0, 1
+    0x00c41413, // slli s0, s0, 12
+    0x000804b7, // lui s1, 128
+    0x0014849b, // addiw s1, s1, 1
+    0x00c49493, // slli s1, s1, 12
+    0x04048493, // addi s1, s1, 64
+    0x00080937, // lui s2, 128
+    0x0019091b, // addiw s2, s2, 1
+    0x00c91913, // slli s2, s2, 12
+    0x08090913, // addi s2, s2, 128
+    0x1f400993, // li s3, 500
+    // loop:
+    0x100422af, // lr.w t0, (s0)
+    0xfe029ee3, // bnez t0, loop
+    0x00100313, // li t1, 1
+    0x186422af, // sc.w t0, t1, (s0)
+    0xfe0298e3, // bnez t0, loop
+    0x0004b383, // ld t2, 0(s1)
+    0x00138393, // addi t2, t2, 1
+    0x0074b023, // sd t2, 0(s1)
+    0x00042023, // sw zero, 0(s0)
+    0xfff98993, // addi s3, s3, -1
+    0xfc099ce3, // bnez s3, loop
+    0x00100313, // li t1, 1
+    0x0069202f, // amoadd.w zero, t1, (s2)
+    0x02051663, // bnez a0, park
+    // wait:
+    0x00092283, // lw t0, 0(s2)
+    0x00200313, // li t1, 2
+    0xfe629ce3, // bne t0, t1, wait
+    0x0004b383, // ld t2, 0(s1)
+    0x3e800313, // li t1, 1000
+    0x001002b7, // lui t0, 256
+    0x00639a63, // bne t2, t1, fail
+    0x00005337, // lui t1, 5
+    0x5553031b, // addiw t1, t1, 1365
+    0x0062a023, // sw t1, 0(t0)
+    // park:
+    0x0000006f, // j park
+    // fail:
+    0x00013337, // lui t1, 19
+    0x3333031b, // addiw t1, t1, 819
+    0x0062a023, // sw t1, 0(t0)
+    0xff1ff06f, // j park
+];
+
+// ハート0がLR/SCを実行し、ハート1はフラグで指示されたアドレスに書き込む。
+const RESERVATION: &[u32] = &[
+    // _start:
+    0xf1402573, // csrr a0, mhartid
+    0x00080437, // lui s0, 128
+    0x0014041b, // addiw s0, s0, 1
+    0x00c41413, // slli s0, s0, 12
+    0x000804b7, // lui s1, 128
+    0x0014849b, // addiw s1, s1, 1
+    0x00c49493, // slli s1, s1, 12
+    0x04048493, // addi s1, s1, 64
+    0x00080937, // lui s2, 128
+    0x0019091b, // addiw s2, s2, 1
+    0x00c91913, // slli s2, s2, 12
+    0x08090913, // addi s2, s2, 128
+    0x00100d37, // lui s10, 256
+    0x0c051863, // bnez a0, helper
+    0x00700313, // li t1, 7
+    // 1: LRのあとにSCすると成功する。
+    0x00100d93, // li s11, 1
+    0x100422af, // lr.w t0, (s0)
+    0x186423af, // sc.w t2, t1, (s0)
+    0x08039a63, // bnez t2, fail
+    // 2: SCは予約を破棄するので、もう一度SCすると失敗する。
+    0x00200d93, // li s11, 2
+    0x186423af, // sc.w t2, t1, (s0)
+    0x08038463, // beqz t2, fail
+    // 3: 予約していないアドレスへのSCは失敗する。
+    0x00300d93, // li s11, 3
+    0x100422af, // lr.w t0, (s0)
+    0x1864a3af, // sc.w t2, t1, (s1)
+    0x06038c63, // beqz t2, fail
+    // 4: LRとSCの間にトラップが起こるとSCは失敗する。
+    0x00400d93, // li s11, 4
+    0x00000297, // auipc t0, 0
+    0x08828293, // addi t0, t0, 136
+    0x30529073, // csrw mtvec, t0
+    0x100422af, // lr.w t0, (s0)
+    0x00000073, // ecall
+    0x186423af, // sc.w t2, t1, (s0)
+    0x04038c63, // beqz t2, fail
+    // 5: LRとSCの間にほかのハートが同じアドレスに書き込むとSCは失敗する。
+    0x00500d93, // li s11, 5
+    0x100422af, // lr.w t0, (s0)
+    0x00100e13, // li t3, 1
+    0x01c4a023, // sw t3, 0(s1)
+    // wait_store:
+    0x00092e03, // lw t3, 0(s2)
+    0xfe0e0ee3, // beqz t3, wait_store
+    0x186423af, // sc.w t2, t1, (s0)
+    0x02038c63, // beqz t2, fail
+    // 6: ほかのハートが別のアドレスに書き込んでもSCは成功する。
+    0x00600d93, // li s11, 6
+    0x100422af, // lr.w t0, (s0)
+    0x00200e13, // li t3, 2
+    0x01c4a023, // sw t3, 0(s1)
+    // wait_other:
+    0x00092e03, // lw t3, 0(s2)
+    0x00200e93, // li t4, 2
+    0xffde1ce3, // bne t3, t4, wait_other
+    0x186423af, // sc.w t2, t1, (s0)
+    0x00039a63, // bnez t2, fail
+    0x000052b7, // lui t0, 5
+    0x5552829b, // addiw t0, t0, 1365
+    0x005d2023, // sw t0, 0(s10)
+    // park:
+    0x0000006f, // j park
+    // fail:
+    0x010d9d93, // slli s11, s11, 16
+    0x000032b7, // lui t0, 3
+    0x3332829b, // addiw t0, t0, 819
+    0x005dedb3, // or s11, s11, t0
+    0x01bd2023, // sw s11, 0(s10)
+    0xfe9ff06f, // j park
+    // trap:
+    0x34102e73, // csrr t3, mepc
+    0x004e0e13, // addi t3, t3, 4
+    0x341e1073, // csrw mepc, t3
+    0x30200073, // mret
+    // helper:
+    0x00100e93, // li t4, 1
+    // helper_store:
+    0x0004ae03, // lw t3, 0(s1)
+    0xffde1ee3, // bne t3, t4, helper_store
+    0x00900f13, // li t5, 9
+    0x01e42023, // sw t5, 0(s0)
+    0x01d92023, // sw t4, 0(s2)
+    0x00200e93, // li t4, 2
+    // helper_other:
+    0x0004ae03, // lw t3, 0(s1)
+    0xffde1ee3, // bne t3, t4, helper_other
+    0x05e92023, // sw t5, 64(s2)
+    0x01d92023, // sw t4, 0(s2)
+    0xfa9ff06f, // j park
+];
+
+fn run(name: &str, program: &[u32], quantum: usize) -> Option<u64> {
+    let mut emulator = Emulator::default();
+    emulator.set_harts(2);
+    emulator.set_quantum(quantum);
+
+    load_program(
+        &mut emulator,
+        &format!("{}-{}", name, quantum),
+        &program_bytes(program),
+    );
+    run_to_exit(&mut emulator)
+}
+
+#[test]
+fn test_lr_sc_spinlock() {
+    for quantum in QUANTUMS {
+        assert_eq!(
+            run("spinlock", SPINLOCK, quantum),
+            Some(0),
+            "quantum: {}",
+            quantum
+        );
+    }
+}
+
+#[test]
+fn test_lr_sc_reservation() {
+    for quantum in QUANTUMS {
+        assert_eq!(
+            run("reservation", RESERVATION, quantum),
+            Some(0),
+            "quantum: {}",
+            quantum
+        );
+    }
+}
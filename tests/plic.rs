@@ -0,0 +1,112 @@
+use tiny_riscv_emulator::{bus::Device, plic::Plic};
+
+// 各レジスタのアドレス(PLICの先頭からのオフセット)
+const PRIORITY: u64 = 0x0;
+const PENDING: u64 = 0x1000;
+const ENABLE: u64 = 0x2000;
+const THRESHOLD: u64 = 0x20_0000;
+const CLAIM: u64 = 0x20_0004;
+
+const MIP_SEIP: u64 = 1 << 9;
+const MIP_MEIP: u64 = 1 << 11;
+
+fn enable(context: u64) -> u64 {
+    ENABLE + context * 0x80
+}
+
+fn threshold(context: u64) -> u64 {
+    THRESHOLD + context * 0x1000
+}
+
+fn claim(context: u64) -> u64 {
+    CLAIM + context * 0x1000
+}
+
+// ハート0のMモード(コンテキスト0)で割り込み源1と2を有効にしたPLIC
+fn plic() -> Plic {
+    let mut plic = Plic::new(1);
+
+    assert!(plic.write(PRIORITY + 4, 4, 1));
+    assert!(plic.write(PRIORITY + 8, 4, 2));
+    assert!(plic.write(enable(0), 4, 0b110));
+
+    plic
+}
+
+#[test]
+fn test_plic_claim_complete() {
+    let mut plic = plic();
+
+    plic.set_level(1, true);
+    plic.set_level(2, true);
+
+    assert_eq!(plic.read(PENDING, 4), Some(0b110));
+    assert_eq!(plic.pending_interrupts(0), MIP_MEIP);
+
+    // 優先度が高い割り込み源から順にclaimされる。
+    assert_eq!(plic.read(claim(0), 4), Some(2));
+    assert_eq!(plic.read(claim(0), 4), Some(1));
+    assert_eq!(plic.read(claim(0), 4), Some(0));
+    assert_eq!(plic.read(PENDING, 4), Some(0));
+    assert_eq!(plic.pending_interrupts(0), 0);
+
+    // completeされるまでは信号が有効でもpendingにならない。
+    plic.set_level(2, true);
+    assert_eq!(plic.read(claim(0), 4), Some(0));
+
+    assert!(plic.write(claim(0), 4, 2));
+    plic.set_level(2, true);
+    assert_eq!(plic.read(claim(0), 4), Some(2));
+}
+
+#[test]
+fn test_plic_threshold() {
+    let mut plic = plic();
+
+    plic.set_level(1, true);
+    plic.set_level(2, true);
+
+    // 優先度がthresholdより大きい割り込みのみ通知する。
+    assert!(plic.write(threshold(0), 4, 1));
+    assert_eq!(plic.read(threshold(0), 4), Some(1));
+    assert_eq!(plic.read(claim(0), 4), Some(2));
+    assert_eq!(plic.read(claim(0), 4), Some(0));
+    assert_eq!(plic.pending_interrupts(0), 0);
+
+    assert!(plic.write(threshold(0), 4, 0));
+    assert_eq!(plic.pending_interrupts(0), MIP_MEIP);
+    assert_eq!(plic.read(claim(0), 4), Some(1));
+
+    // 優先度が0の割り込み源は通知しない。
+    assert!(plic.write(PRIORITY + 4, 4, 0));
+    assert!(plic.write(claim(0), 4, 1));
+    plic.set_level(1, true);
+    assert_eq!(plic.pending_interrupts(0), 0);
+    assert_eq!(plic.read(claim(0), 4), Some(0));
+}
+
+#[test]
+fn test_plic_context_enable() {
+    let mut plic = plic();
+
+    plic.set_level(1, true);
+
+    // Sモード(コンテキスト1)では有効にしていないので通知しない。
+    assert_eq!(plic.pending_interrupts(0), MIP_MEIP);
+    assert_eq!(plic.read(claim(1), 4), Some(0));
+
+    assert!(plic.write(enable(0), 4, 0));
+    assert!(plic.write(enable(1), 4, 0b10));
+    assert_eq!(plic.read(enable(1), 4), Some(0b10));
+    assert_eq!(plic.pending_interrupts(0), MIP_SEIP);
+    assert_eq!(plic.read(claim(0), 4), Some(0));
+    assert_eq!(plic.read(claim(1), 4), Some(1));
+
+    // 0番の割り込み源は有効にできない。
+    assert!(plic.write(enable(1), 4, 0b1));
+    assert_eq!(plic.read(enable(1), 4), Some(0));
+
+    // 4バイト以外のアクセスは失敗する。
+    assert_eq!(plic.read(claim(1), 8), None);
+    assert!(!plic.write(enable(1) + 2, 4, 0));
+}
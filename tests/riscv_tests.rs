@@ -1,10 +1,9 @@
 use tiny_riscv_emulator::emulator::Emulator;
 
-const TEST_DIR: &str = "tests/isa/flats";
+const TEST_DIR: &str = "tests/isa/elfs";
 
-fn run_test(emulator: &mut Emulator, test: &str, riscv_tests_exit_memory_address: usize) {
+fn run_test(emulator: &mut Emulator, test: &str) {
     emulator.load(format!("{}/{}", TEST_DIR, test)).unwrap();
-    emulator.set_riscv_tests_exit_memory_address(riscv_tests_exit_memory_address);
 
     emulator.run();
 
@@ -16,67 +15,64 @@ fn test_ui_p() {
     let mut emulator = Emulator::default();
 
     let ui_p_tests = [
-        "rv64ui-p-add.bin",
-        "rv64ui-p-addi.bin",
-        "rv64ui-p-addiw.bin",
-        "rv64ui-p-addw.bin",
-        "rv64ui-p-and.bin",
-        "rv64ui-p-andi.bin",
-        "rv64ui-p-auipc.bin",
-        "rv64ui-p-beq.bin",
-        "rv64ui-p-bge.bin",
-        "rv64ui-p-bgeu.bin",
-        "rv64ui-p-blt.bin",
-        "rv64ui-p-bltu.bin",
-        "rv64ui-p-bne.bin",
-        "rv64ui-p-fence_i.bin",
-        "rv64ui-p-jal.bin",
-        "rv64ui-p-jalr.bin",
-        "rv64ui-p-lb.bin",
-        "rv64ui-p-lbu.bin",
-        "rv64ui-p-ld.bin",
-        "rv64ui-p-ld_st.bin",
-        "rv64ui-p-lh.bin",
-        "rv64ui-p-lhu.bin",
-        "rv64ui-p-lui.bin",
-        "rv64ui-p-lw.bin",
-        "rv64ui-p-lwu.bin",
-        "rv64ui-p-ma_data.bin",
-        "rv64ui-p-or.bin",
-        "rv64ui-p-ori.bin",
-        "rv64ui-p-sb.bin",
-        "rv64ui-p-sd.bin",
-        "rv64ui-p-sh.bin",
-        "rv64ui-p-simple.bin",
-        "rv64ui-p-sll.bin",
-        "rv64ui-p-slli.bin",
-        "rv64ui-p-slliw.bin",
-        "rv64ui-p-sllw.bin",
-        "rv64ui-p-slt.bin",
-        "rv64ui-p-slti.bin",
-        "rv64ui-p-sltiu.bin",
-        "rv64ui-p-sltu.bin",
-        "rv64ui-p-sra.bin",
-        "rv64ui-p-srai.bin",
-        "rv64ui-p-sraiw.bin",
-        "rv64ui-p-sraw.bin",
-        "rv64ui-p-srl.bin",
-        "rv64ui-p-srli.bin",
-        "rv64ui-p-srliw.bin",
-        "rv64ui-p-srlw.bin",
-        "rv64ui-p-st_ld.bin",
-        "rv64ui-p-sub.bin",
-        "rv64ui-p-subw.bin",
-        "rv64ui-p-sw.bin",
-        "rv64ui-p-xor.bin",
-        "rv64ui-p-xori.bin",
+        "rv64ui-p-add",
+        "rv64ui-p-addi",
+        "rv64ui-p-addiw",
+        "rv64ui-p-addw",
+        "rv64ui-p-and",
+        "rv64ui-p-andi",
+        "rv64ui-p-auipc",
+        "rv64ui-p-beq",
+        "rv64ui-p-bge",
+        "rv64ui-p-bgeu",
+        "rv64ui-p-blt",
+        "rv64ui-p-bltu",
+        "rv64ui-p-bne",
+        "rv64ui-p-fence_i",
+        "rv64ui-p-jal",
+        "rv64ui-p-jalr",
+        "rv64ui-p-lb",
+        "rv64ui-p-lbu",
+        "rv64ui-p-ld",
+        "rv64ui-p-ld_st",
+        "rv64ui-p-lh",
+        "rv64ui-p-lhu",
+        "rv64ui-p-lui",
+        "rv64ui-p-lw",
+        "rv64ui-p-lwu",
+        "rv64ui-p-ma_data",
+        "rv64ui-p-or",
+        "rv64ui-p-ori",
+        "rv64ui-p-sb",
+        "rv64ui-p-sd",
+        "rv64ui-p-sh",
+        "rv64ui-p-simple",
+        "rv64ui-p-sll",
+        "rv64ui-p-slli",
+        "rv64ui-p-slliw",
+        "rv64ui-p-sllw",
+        "rv64ui-p-slt",
+        "rv64ui-p-slti",
+        "rv64ui-p-sltiu",
+        "rv64ui-p-sltu",
+        "rv64ui-p-sra",
+        "rv64ui-p-srai",
+        "rv64ui-p-sraiw",
+        "rv64ui-p-sraw",
+        "rv64ui-p-srl",
+        "rv64ui-p-srli",
+        "rv64ui-p-srliw",
+        "rv64ui-p-srlw",
+        "rv64ui-p-st_ld",
+        "rv64ui-p-sub",
+        "rv64ui-p-subw",
+        "rv64ui-p-sw",
+        "rv64ui-p-xor",
+        "rv64ui-p-xori",
     ];
 
     for test in ui_p_tests {
-        match test {
-            "rv64ui-p-ld_st.bin" | "rv64ui-p-ma_data.bin" => run_test(&mut emulator, test, 0x2000),
-            _ => run_test(&mut emulator, test, 0x1000),
-        }
+        run_test(&mut emulator, test);
     }
 }
 
@@ -85,23 +81,23 @@ fn test_um_p() {
     let mut emulator = Emulator::default();
 
     let um_p_tests = [
-        "rv64um-p-div.bin",
-        "rv64um-p-divu.bin",
-        "rv64um-p-divuw.bin",
-        "rv64um-p-divw.bin",
-        "rv64um-p-mul.bin",
-        "rv64um-p-mulh.bin",
-        "rv64um-p-mulhsu.bin",
-        "rv64um-p-mulhu.bin",
-        "rv64um-p-mulw.bin",
-        "rv64um-p-rem.bin",
-        "rv64um-p-remu.bin",
-        "rv64um-p-remuw.bin",
-        "rv64um-p-remw.bin",
+        "rv64um-p-div",
+        "rv64um-p-divu",
+        "rv64um-p-divuw",
+        "rv64um-p-divw",
+        "rv64um-p-mul",
+        "rv64um-p-mulh",
+        "rv64um-p-mulhsu",
+        "rv64um-p-mulhu",
+        "rv64um-p-mulw",
+        "rv64um-p-rem",
+        "rv64um-p-remu",
+        "rv64um-p-remuw",
+        "rv64um-p-remw",
     ];
 
     for test in um_p_tests {
-        run_test(&mut emulator, test, 0x1000);
+        run_test(&mut emulator, test);
     }
 }
 
@@ -110,29 +106,163 @@ fn test_ua_p() {
     let mut emulator = Emulator::default();
 
     let ua_p_tests = [
-        "rv64ua-p-amoadd_d.bin",
-        "rv64ua-p-amoadd_w.bin",
-        "rv64ua-p-amoand_d.bin",
-        "rv64ua-p-amoand_w.bin",
-        "rv64ua-p-amomax_d.bin",
-        "rv64ua-p-amomax_w.bin",
-        "rv64ua-p-amomaxu_d.bin",
-        "rv64ua-p-amomaxu_w.bin",
-        "rv64ua-p-amomin_d.bin",
-        "rv64ua-p-amomin_w.bin",
-        "rv64ua-p-amominu_d.bin",
-        "rv64ua-p-amominu_w.bin",
-        "rv64ua-p-amoor_d.bin",
-        "rv64ua-p-amoor_w.bin",
-        "rv64ua-p-amoswap_d.bin",
-        "rv64ua-p-amoswap_w.bin",
-        "rv64ua-p-amoxor_d.bin",
-        "rv64ua-p-amoxor_w.bin",
-        "rv64ua-p-lrsc.bin",
+        "rv64ua-p-amoadd_d",
+        "rv64ua-p-amoadd_w",
+        "rv64ua-p-amoand_d",
+        "rv64ua-p-amoand_w",
+        "rv64ua-p-amomax_d",
+        "rv64ua-p-amomax_w",
+        "rv64ua-p-amomaxu_d",
+        "rv64ua-p-amomaxu_w",
+        "rv64ua-p-amomin_d",
+        "rv64ua-p-amomin_w",
+        "rv64ua-p-amominu_d",
+        "rv64ua-p-amominu_w",
+        "rv64ua-p-amoor_d",
+        "rv64ua-p-amoor_w",
+        "rv64ua-p-amoswap_d",
+        "rv64ua-p-amoswap_w",
+        "rv64ua-p-amoxor_d",
+        "rv64ua-p-amoxor_w",
+        "rv64ua-p-lrsc",
     ];
 
     for test in ua_p_tests {
-        run_test(&mut emulator, test, 0x1000);
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_uf_p() {
+    let mut emulator = Emulator::default();
+
+    let uf_p_tests = [
+        "rv64uf-p-fadd",
+        "rv64uf-p-fclass",
+        "rv64uf-p-fcmp",
+        "rv64uf-p-fcvt",
+        "rv64uf-p-fcvt_w",
+        "rv64uf-p-fdiv",
+        "rv64uf-p-fmadd",
+        "rv64uf-p-fmin",
+        "rv64uf-p-ldst",
+        "rv64uf-p-move",
+        "rv64uf-p-recoding",
+    ];
+
+    for test in uf_p_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_ud_p() {
+    let mut emulator = Emulator::default();
+
+    let ud_p_tests = [
+        "rv64ud-p-fadd",
+        "rv64ud-p-fclass",
+        "rv64ud-p-fcmp",
+        "rv64ud-p-fcvt",
+        "rv64ud-p-fcvt_w",
+        "rv64ud-p-fdiv",
+        "rv64ud-p-fmadd",
+        "rv64ud-p-fmin",
+        "rv64ud-p-ldst",
+        "rv64ud-p-move",
+        "rv64ud-p-recoding",
+        "rv64ud-p-structural",
+    ];
+
+    for test in ud_p_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_uzba_p() {
+    let mut emulator = Emulator::default();
+
+    let uzba_p_tests = [
+        "rv64uzba-p-add_uw",
+        "rv64uzba-p-sh1add",
+        "rv64uzba-p-sh1add_uw",
+        "rv64uzba-p-sh2add",
+        "rv64uzba-p-sh2add_uw",
+        "rv64uzba-p-sh3add",
+        "rv64uzba-p-sh3add_uw",
+        "rv64uzba-p-slli_uw",
+    ];
+
+    for test in uzba_p_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_uzbb_p() {
+    let mut emulator = Emulator::default();
+
+    let uzbb_p_tests = [
+        "rv64uzbb-p-andn",
+        "rv64uzbb-p-clz",
+        "rv64uzbb-p-clzw",
+        "rv64uzbb-p-cpop",
+        "rv64uzbb-p-cpopw",
+        "rv64uzbb-p-ctz",
+        "rv64uzbb-p-ctzw",
+        "rv64uzbb-p-max",
+        "rv64uzbb-p-maxu",
+        "rv64uzbb-p-min",
+        "rv64uzbb-p-minu",
+        "rv64uzbb-p-orc_b",
+        "rv64uzbb-p-orn",
+        "rv64uzbb-p-rev8",
+        "rv64uzbb-p-rol",
+        "rv64uzbb-p-rolw",
+        "rv64uzbb-p-ror",
+        "rv64uzbb-p-rori",
+        "rv64uzbb-p-roriw",
+        "rv64uzbb-p-rorw",
+        "rv64uzbb-p-sext_b",
+        "rv64uzbb-p-sext_h",
+        "rv64uzbb-p-xnor",
+        "rv64uzbb-p-zext_h",
+    ];
+
+    for test in uzbb_p_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_uzbc_p() {
+    let mut emulator = Emulator::default();
+
+    let uzbc_p_tests = ["rv64uzbc-p-clmul", "rv64uzbc-p-clmulh", "rv64uzbc-p-clmulr"];
+
+    for test in uzbc_p_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_uzbs_p() {
+    let mut emulator = Emulator::default();
+
+    let uzbs_p_tests = [
+        "rv64uzbs-p-bclr",
+        "rv64uzbs-p-bclri",
+        "rv64uzbs-p-bext",
+        "rv64uzbs-p-bexti",
+        "rv64uzbs-p-binv",
+        "rv64uzbs-p-binvi",
+        "rv64uzbs-p-bset",
+        "rv64uzbs-p-bseti",
+    ];
+
+    for test in uzbs_p_tests {
+        run_test(&mut emulator, test);
     }
 }
 
@@ -140,10 +270,10 @@ fn test_ua_p() {
 fn test_uc_p_rvc() {
     let mut emulator = Emulator::default();
 
-    let uc_p_rvc_tests = ["rv64uc-p-rvc.bin"];
+    let uc_p_rvc_tests = ["rv64uc-p-rvc"];
 
     for test in uc_p_rvc_tests {
-        run_test(&mut emulator, test, 0x3000);
+        run_test(&mut emulator, test);
     }
 }
 
@@ -152,22 +282,23 @@ fn test_mi_p() {
     let mut emulator = Emulator::default();
 
     let mi_tests = [
-        "rv64mi-p-csr.bin",
-        "rv64mi-p-illegal.bin",
-        "rv64mi-p-ld-misaligned.bin",
-        "rv64mi-p-lh-misaligned.bin",
-        "rv64mi-p-lw-misaligned.bin",
-        "rv64mi-p-ma_addr.bin",
-        "rv64mi-p-ma_fetch.bin",
-        "rv64mi-p-mcsr.bin",
-        "rv64mi-p-sd-misaligned.bin",
-        "rv64mi-p-sh-misaligned.bin",
-        "rv64mi-p-sw-misaligned.bin",
-        "rv64mi-p-scall.bin",
+        "rv64mi-p-csr",
+        "rv64mi-p-illegal",
+        "rv64mi-p-ld-misaligned",
+        "rv64mi-p-lh-misaligned",
+        "rv64mi-p-lw-misaligned",
+        "rv64mi-p-ma_addr",
+        "rv64mi-p-ma_fetch",
+        "rv64mi-p-mcsr",
+        "rv64mi-p-pmpaddr",
+        "rv64mi-p-sd-misaligned",
+        "rv64mi-p-sh-misaligned",
+        "rv64mi-p-sw-misaligned",
+        "rv64mi-p-scall",
     ];
 
     for test in mi_tests {
-        run_test(&mut emulator, test, 0x1000);
+        run_test(&mut emulator, test);
     }
 }
 
@@ -176,13 +307,283 @@ fn test_si_p() {
     let mut emulator = Emulator::default();
 
     let si_tests = [
-        "rv64si-p-csr.bin",
-        "rv64si-p-ma_fetch.bin",
-        "rv64si-p-scall.bin",
-        "rv64si-p-wfi.bin",
+        "rv64si-p-csr",
+        "rv64si-p-dirty",
+        "rv64si-p-ma_fetch",
+        "rv64si-p-scall",
+        "rv64si-p-wfi",
+    ];
+
+    for test in si_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_ui_v() {
+    let mut emulator = Emulator::default();
+
+    let ui_v_tests = [
+        "rv64ui-v-add",
+        "rv64ui-v-addi",
+        "rv64ui-v-addiw",
+        "rv64ui-v-addw",
+        "rv64ui-v-and",
+        "rv64ui-v-andi",
+        "rv64ui-v-auipc",
+        "rv64ui-v-beq",
+        "rv64ui-v-bge",
+        "rv64ui-v-bgeu",
+        "rv64ui-v-blt",
+        "rv64ui-v-bltu",
+        "rv64ui-v-bne",
+        "rv64ui-v-fence_i",
+        "rv64ui-v-jal",
+        "rv64ui-v-jalr",
+        "rv64ui-v-lb",
+        "rv64ui-v-lbu",
+        "rv64ui-v-ld",
+        "rv64ui-v-ld_st",
+        "rv64ui-v-lh",
+        "rv64ui-v-lhu",
+        "rv64ui-v-lui",
+        "rv64ui-v-lw",
+        "rv64ui-v-lwu",
+        "rv64ui-v-ma_data",
+        "rv64ui-v-or",
+        "rv64ui-v-ori",
+        "rv64ui-v-sb",
+        "rv64ui-v-sd",
+        "rv64ui-v-sh",
+        "rv64ui-v-simple",
+        "rv64ui-v-sll",
+        "rv64ui-v-slli",
+        "rv64ui-v-slliw",
+        "rv64ui-v-sllw",
+        "rv64ui-v-slt",
+        "rv64ui-v-slti",
+        "rv64ui-v-sltiu",
+        "rv64ui-v-sltu",
+        "rv64ui-v-sra",
+        "rv64ui-v-srai",
+        "rv64ui-v-sraiw",
+        "rv64ui-v-sraw",
+        "rv64ui-v-srl",
+        "rv64ui-v-srli",
+        "rv64ui-v-srliw",
+        "rv64ui-v-srlw",
+        "rv64ui-v-st_ld",
+        "rv64ui-v-sub",
+        "rv64ui-v-subw",
+        "rv64ui-v-sw",
+        "rv64ui-v-xor",
+        "rv64ui-v-xori",
+    ];
+
+    for test in ui_v_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_um_v() {
+    let mut emulator = Emulator::default();
+
+    let um_v_tests = [
+        "rv64um-v-div",
+        "rv64um-v-divu",
+        "rv64um-v-divuw",
+        "rv64um-v-divw",
+        "rv64um-v-mul",
+        "rv64um-v-mulh",
+        "rv64um-v-mulhsu",
+        "rv64um-v-mulhu",
+        "rv64um-v-mulw",
+        "rv64um-v-rem",
+        "rv64um-v-remu",
+        "rv64um-v-remuw",
+        "rv64um-v-remw",
+    ];
+
+    for test in um_v_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_ua_v() {
+    let mut emulator = Emulator::default();
+
+    let ua_v_tests = [
+        "rv64ua-v-amoadd_d",
+        "rv64ua-v-amoadd_w",
+        "rv64ua-v-amoand_d",
+        "rv64ua-v-amoand_w",
+        "rv64ua-v-amomax_d",
+        "rv64ua-v-amomax_w",
+        "rv64ua-v-amomaxu_d",
+        "rv64ua-v-amomaxu_w",
+        "rv64ua-v-amomin_d",
+        "rv64ua-v-amomin_w",
+        "rv64ua-v-amominu_d",
+        "rv64ua-v-amominu_w",
+        "rv64ua-v-amoor_d",
+        "rv64ua-v-amoor_w",
+        "rv64ua-v-amoswap_d",
+        "rv64ua-v-amoswap_w",
+        "rv64ua-v-amoxor_d",
+        "rv64ua-v-amoxor_w",
+        "rv64ua-v-lrsc",
+    ];
+
+    for test in ua_v_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_rv32ui_p() {
+    let mut emulator = Emulator::default();
+
+    let ui_p_tests = [
+        "rv32ui-p-add",
+        "rv32ui-p-addi",
+        "rv32ui-p-and",
+        "rv32ui-p-andi",
+        "rv32ui-p-auipc",
+        "rv32ui-p-beq",
+        "rv32ui-p-bge",
+        "rv32ui-p-bgeu",
+        "rv32ui-p-blt",
+        "rv32ui-p-bltu",
+        "rv32ui-p-bne",
+        "rv32ui-p-fence_i",
+        "rv32ui-p-jal",
+        "rv32ui-p-jalr",
+        "rv32ui-p-lb",
+        "rv32ui-p-lbu",
+        "rv32ui-p-ld_st",
+        "rv32ui-p-lh",
+        "rv32ui-p-lhu",
+        "rv32ui-p-lui",
+        "rv32ui-p-lw",
+        "rv32ui-p-ma_data",
+        "rv32ui-p-or",
+        "rv32ui-p-ori",
+        "rv32ui-p-sb",
+        "rv32ui-p-sh",
+        "rv32ui-p-simple",
+        "rv32ui-p-sll",
+        "rv32ui-p-slli",
+        "rv32ui-p-slt",
+        "rv32ui-p-slti",
+        "rv32ui-p-sltiu",
+        "rv32ui-p-sltu",
+        "rv32ui-p-sra",
+        "rv32ui-p-srai",
+        "rv32ui-p-srl",
+        "rv32ui-p-srli",
+        "rv32ui-p-st_ld",
+        "rv32ui-p-sub",
+        "rv32ui-p-sw",
+        "rv32ui-p-xor",
+        "rv32ui-p-xori",
+    ];
+
+    for test in ui_p_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_rv32um_p() {
+    let mut emulator = Emulator::default();
+
+    let um_p_tests = [
+        "rv32um-p-div",
+        "rv32um-p-divu",
+        "rv32um-p-mul",
+        "rv32um-p-mulh",
+        "rv32um-p-mulhsu",
+        "rv32um-p-mulhu",
+        "rv32um-p-rem",
+        "rv32um-p-remu",
+    ];
+
+    for test in um_p_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_rv32ua_p() {
+    let mut emulator = Emulator::default();
+
+    let ua_p_tests = [
+        "rv32ua-p-amoadd_w",
+        "rv32ua-p-amoand_w",
+        "rv32ua-p-amomax_w",
+        "rv32ua-p-amomaxu_w",
+        "rv32ua-p-amomin_w",
+        "rv32ua-p-amominu_w",
+        "rv32ua-p-amoor_w",
+        "rv32ua-p-amoswap_w",
+        "rv32ua-p-amoxor_w",
+        "rv32ua-p-lrsc",
+    ];
+
+    for test in ua_p_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_rv32uc_p_rvc() {
+    let mut emulator = Emulator::default();
+
+    let uc_p_rvc_tests = ["rv32uc-p-rvc"];
+
+    for test in uc_p_rvc_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_rv32mi_p() {
+    let mut emulator = Emulator::default();
+
+    let mi_tests = [
+        "rv32mi-p-csr",
+        "rv32mi-p-illegal",
+        "rv32mi-p-lh-misaligned",
+        "rv32mi-p-lw-misaligned",
+        "rv32mi-p-ma_addr",
+        "rv32mi-p-ma_fetch",
+        "rv32mi-p-mcsr",
+        "rv32mi-p-pmpaddr",
+        "rv32mi-p-scall",
+        "rv32mi-p-sh-misaligned",
+        "rv32mi-p-shamt",
+        "rv32mi-p-sw-misaligned",
+    ];
+
+    for test in mi_tests {
+        run_test(&mut emulator, test);
+    }
+}
+
+#[test]
+fn test_rv32si_p() {
+    let mut emulator = Emulator::default();
+
+    let si_tests = [
+        "rv32si-p-csr",
+        "rv32si-p-ma_fetch",
+        "rv32si-p-scall",
+        "rv32si-p-wfi",
     ];
 
     for test in si_tests {
-        run_test(&mut emulator, test, 0x1000);
+        run_test(&mut emulator, test);
     }
 }
@@ -0,0 +1,204 @@
+mod common;
+
+use std::{
+    collections::VecDeque,
+    env, fs,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use common::{program_bytes, run_to_exit, EPILOGUE};
+use tiny_riscv_emulator::{
+    boot::{BootConfig, DEFAULT_KERNEL_ADDRESS},
+    emulator::Emulator,
+    uart::{Uart, UART_BASE, UART_IRQ, UART_SIZE},
+};
+
+// 組み込みのSBIで起動したSモードのプログラムからSBIを呼び出すテスト
+// プログラムはDEFAULT_KERNEL_ADDRESSにロードするフラットなバイナリで、ハート0のみが先頭から実行する。
+// 成功した場合はSRSTのシステムリセットで終了し、失敗した場合はシステムコントローラに(番号 << 16) | 0x3333を書き込む。
+
+// DBCNで書き込む文字列と、カーネルの先頭からのオフセット
+const MESSAGE: &[u8] = b"Hello from S-mode\n";
+const MESSAGE_OFFSET: usize = 0x200;
+
+// ハート1が起動したことを知らせるための領域(opaque, hartid)のオフセット
+const FLAG_OFFSET: usize = 0x300;
+
+// ハート0で実行する部分
+// 番号は失敗した確認の番号(s2)で、このあとにEPILOGUEとSECONDARYを置く。
+const SBI: &[u32] = &[
+    // _start:
+    0x00100913, // li s2, 1
+    0x14051063, // bnez a0, fail
+    // sbi_probe_extension(DBCN)は1を返す。
+    0x00200913, // li s2, 2
+    0x01000893, // li a7, 0x10 (BASE)
+    0x00300813, // li a6, 3
+    // li a0, 0x4442434e (DBCN)
+    0x44424537, // lui a0, 0x44424
+    0x34e5051b, // addiw a0, a0, 846
+    0x00000073, // ecall
+    0x12051263, // bnez a0, fail
+    0x00100293, // li t0, 1
+    0x10559e63, // bne a1, t0, fail
+    // 存在しない拡張には0を返す。
+    0x00300913, // li s2, 3
+    0x01000893, // li a7, 0x10 (BASE)
+    0x00300813, // li a6, 3
+    // li a0, 0x12345
+    0x00012537, // lui a0, 0x12
+    0x3455051b, // addiw a0, a0, 837
+    0x00000073, // ecall
+    0x10051063, // bnez a0, fail
+    0x0e059e63, // bnez a1, fail
+    // sbi_debug_console_write(18, message, 0)は書き込んだバイト数を返す。
+    0x00400913, // li s2, 4
+    // li a7, 0x4442434e (DBCN)
+    0x444248b7, // lui a7, 0x44424
+    0x34e8889b, // addiw a7, a7, 846
+    0x00000813, // li a6, 0
+    0x01200513, // li a0, 18
+    // la a1, message
+    0x00000597, // auipc a1, 0
+    0x1a058593, // addi a1, a1, 416
+    0x00000613, // li a2, 0
+    0x00000073, // ecall
+    0x0c051a63, // bnez a0, fail
+    0x01200293, // li t0, 18
+    0x0c559663, // bne a1, t0, fail
+    // sbi_hart_get_status(1)は停止(1)を返す。
+    0x00500913, // li s2, 5
+    // li a7, 0x48534d (HSM)
+    0x004858b7, // lui a7, 0x485
+    0x34d8889b, // addiw a7, a7, 845
+    0x00200813, // li a6, 2
+    0x00100513, // li a0, 1
+    0x00000073, // ecall
+    0x0a051863, // bnez a0, fail
+    0x00100293, // li t0, 1
+    0x0a559463, // bne a1, t0, fail
+    // sbi_hart_start(1, secondary, 0x1234)
+    0x00600913, // li s2, 6
+    // li a7, 0x48534d (HSM)
+    0x004858b7, // lui a7, 0x485
+    0x34d8889b, // addiw a7, a7, 845
+    0x00000813, // li a6, 0
+    0x00100513, // li a0, 1
+    // la a1, secondary
+    0x00000597, // auipc a1, 0
+    0x0ac58593, // addi a1, a1, 172
+    // li a2, 0x1234
+    0x00001637, // lui a2, 1
+    0x2346061b, // addiw a2, a2, 564
+    0x00000073, // ecall
+    0x06051e63, // bnez a0, fail
+    // ハート1がa1のopaqueとa0のhartidを書き込むまで待つ。
+    0x00700913, // li s2, 7
+    // la t2, flag
+    0x00000397, // auipc t2, 0
+    0x23038393, // addi t2, t2, 560
+    // 1:
+    0x0003b283, // ld t0, 0(t2)
+    0xfe028ee3, // beqz t0, 1b
+    // li t1, 0x1234
+    0x00001337, // lui t1, 1
+    0x2343031b, // addiw t1, t1, 564
+    0x04629e63, // bne t0, t1, fail
+    0x0083b283, // ld t0, 8(t2)
+    0x00100313, // li t1, 1
+    0x04629863, // bne t0, t1, fail
+    // ハート1がsbi_hart_stopで停止するまで待つ。
+    0x00800913, // li s2, 8
+    // 2:
+    // li a7, 0x48534d (HSM)
+    0x004858b7, // lui a7, 0x485
+    0x34d8889b, // addiw a7, a7, 845
+    0x00200813, // li a6, 2
+    0x00100513, // li a0, 1
+    0x00000073, // ecall
+    0x02051a63, // bnez a0, fail
+    0xfe0584e3, // beqz a1, 2b
+    // sbi_system_reset(SHUTDOWN, NO_REASON)は戻らない。
+    // li a7, 0x53525354 (SRST)
+    0x535258b7, // lui a7, 0x53525
+    0x3548889b, // addiw a7, a7, 852
+    0x00000813, // li a6, 0
+    0x00000513, // li a0, 0
+    0x00000593, // li a1, 0
+    0x00000073, // ecall
+    0x00900913, // li s2, 9
+    0x0100006f, // j fail
+];
+
+// sbi_hart_startで起動したハート1で実行する部分
+// Sモードで実行していることをsstatusを読んで確かめてから、hartidとopaqueを書き込んで停止する。
+const SECONDARY: &[u32] = &[
+    // secondary:
+    0x100022f3, // csrr t0, sstatus
+    // la t2, flag
+    0x00000397, // auipc t2, 0
+    0x19c38393, // addi t2, t2, 412
+    0x00a3b423, // sd a0, 8(t2)
+    0x00b3b023, // sd a1, 0(t2)
+    // li a7, 0x48534d (HSM)
+    0x004858b7, // lui a7, 0x485
+    0x34d8889b, // addiw a7, a7, 845
+    0x00100813, // li a6, 1
+    0x00000073, // ecall
+    0x00a00913, // li s2, 10
+    0xfbdff06f, // j fail
+];
+
+// UARTに書き込まれた文字を保存する。
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_builtin_sbi() {
+    let mut kernel = program_bytes(&[SBI, &EPILOGUE, SECONDARY].concat());
+    assert!(kernel.len() <= MESSAGE_OFFSET);
+
+    kernel.resize(MESSAGE_OFFSET, 0);
+    kernel.extend_from_slice(MESSAGE);
+    kernel.resize(FLAG_OFFSET + 16, 0);
+
+    let path = env::temp_dir().join("tiny-riscv-emulator-sbi.bin");
+    fs::write(&path, kernel).unwrap();
+
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let mut emulator = Emulator::default();
+
+    emulator.set_harts(2);
+    emulator.attach_device_with_irq(
+        UART_BASE,
+        UART_SIZE,
+        UART_IRQ,
+        Box::new(Uart::new(
+            Arc::new(Mutex::new(VecDeque::new())),
+            Box::new(Output(Arc::clone(&output))),
+        )),
+    );
+
+    let config = BootConfig::builtin_sbi(&path);
+    assert_eq!(config.kernel_address, DEFAULT_KERNEL_ADDRESS);
+
+    emulator.boot(&config).unwrap();
+
+    let exit_code = run_to_exit(&mut emulator);
+
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(exit_code, Some(0));
+    assert_eq!(output.lock().unwrap().as_slice(), MESSAGE);
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use common::{load_program, program_bytes, run_to_exit, EPILOGUE};
+use tiny_riscv_emulator::{
+    emulator::Emulator,
+    tlb::{TlbConfig, TlbStats},
+};
+
+// Sv39でのTLBのテスト
+// プログラムはRAMの先頭にロードするフラットなバイナリで、M-modeでページテーブルを作ってからS-modeで実行する。
+// S-modeでの確認がすべて終わるとecallでM-modeに戻り、システムコントローラに0x5555を書き込む。
+// 失敗した場合はS-modeからシステムコントローラに書き込めないので、例外でM-modeに戻ってから(番号 << 16) | 0x3333を書き込む。
+
+// 0x200000と0x201000のページをTLBに載せたあとにページテーブルを書き換え、
+// sfence.vmaで無効化したエントリだけが新しい変換になることを確かめる。
+// 0x200000のページはグローバルでなく、0x201000のページはグローバル(G)にする。
+// 番号は失敗した確認の番号(s2)で、このあとにEPILOGUEを置く。
+const SFENCE: &[u32] = &[
+    // _start:
+    0xfff00293, // li t0, -1
+    0x3b029073, // csrw pmpaddr0, t0
+    0x01f00293, // li t0, 0x1f
+    0x3a029073, // csrw pmpcfg0, t0
+    0x00000297, // auipc t0, 0
+    0x0e028293, // addi t0, t0, 224 (trap)
+    0x30529073, // csrw mtvec, t0
+    0x00100913, // li s2, 1 (準備中に例外が起きた場合の番号)
+    // ページテーブル(pt2: 0x80010000、pt1: 0x80011000、pt0: 0x80012000)を作る。
+    // li s3, 0x80010000 (pt2)
+    0x080019b7, // lui s3, 0x8001
+    0x00499993, // slli s3, s3, 4
+    // pt2[2]: 0x80000000からのギガページ(G、RWX、A、D)
+    // li t1, (0x80000000 >> 12) << 10 | 0xef
+    0x20000337, // lui t1, 0x20000
+    0x0ef3031b, // addiw t1, t1, 239
+    0x0069b823, // sd t1, 16(s3)
+    // pt2[0] -> pt1
+    // li t1, (0x80011000 >> 12) << 10 | 1
+    0x20004337, // lui t1, 0x20004
+    0x4013031b, // addiw t1, t1, 1025
+    0x0069b023, // sd t1, 0(s3)
+    // pt1[1] -> pt0 (0x200000からの2MiB)
+    // li t0, 0x80011000 (pt1)
+    0x000802b7, // lui t0, 0x80
+    0x0112829b, // addiw t0, t0, 17
+    0x00c29293, // slli t0, t0, 12
+    // li t1, (0x80012000 >> 12) << 10 | 1
+    0x20005337, // lui t1, 0x20005
+    0x8013031b, // addiw t1, t1, -2047
+    0x0062b423, // sd t1, 8(t0)
+    // pt0[0]: 0x200000 -> 0x80013000(RW、A、D)、pt0[1]: 0x201000 -> 0x80014000(G、RW、A、D)
+    // li s4, 0x80012000 (pt0)
+    0x40009a37, // lui s4, 0x40009
+    0x001a1a13, // slli s4, s4, 1
+    // li t1, (0x80013000 >> 12) << 10 | 0xc7
+    0x20005337, // lui t1, 0x20005
+    0xcc73031b, // addiw t1, t1, -825
+    0x006a3023, // sd t1, 0(s4)
+    // li t1, (0x80014000 >> 12) << 10 | 0xe7
+    0x20005337, // lui t1, 0x20005
+    0x0e73031b, // addiw t1, t1, 231
+    0x006a3423, // sd t1, 8(s4)
+    // 0x80013000、0x80014000、0x80015000のページに0x111、0x222、0x333を書いておく。
+    0x11100313, // li t1, 0x111
+    // li t0, 0x80013000
+    0x000802b7, // lui t0, 0x80
+    0x0132829b, // addiw t0, t0, 19
+    0x00c29293, // slli t0, t0, 12
+    0x0062b023, // sd t1, 0(t0)
+    0x22200313, // li t1, 0x222
+    // li t0, 0x80014000
+    0x200052b7, // lui t0, 0x20005
+    0x00229293, // slli t0, t0, 2
+    0x0062b023, // sd t1, 0(t0)
+    0x33300313, // li t1, 0x333
+    // li t0, 0x80015000
+    0x000802b7, // lui t0, 0x80
+    0x0152829b, // addiw t0, t0, 21
+    0x00c29293, // slli t0, t0, 12
+    0x0062b023, // sd t1, 0(t0)
+    // Sv39、ASIDは1
+    // li t0, (8 << 60) | (1 << 44) | (0x80010000 >> 12)
+    0x800012b7, // lui t0, 0x80001
+    0x00d29293, // slli t0, t0, 13
+    0x00128293, // addi t0, t0, 1
+    0x01329293, // slli t0, t0, 19
+    0x01028293, // addi t0, t0, 16
+    0x18029073, // csrw satp, t0
+    // S-modeに移る。
+    // li t0, 3 << 11
+    0x000022b7, // lui t0, 0x2
+    0x8002829b, // addiw t0, t0, -2048
+    0x3002b073, // csrc mstatus, t0
+    // li t0, 1 << 11
+    0x000012b7, // lui t0, 0x1
+    0x8002829b, // addiw t0, t0, -2048
+    0x3002a073, // csrs mstatus, t0
+    0x00000297, // auipc t0, 0
+    0x02028293, // addi t0, t0, 32 (smode)
+    0x34129073, // csrw mepc, t0
+    0x30200073, // mret
+    // trap:
+    0x342022f3, // csrr t0, mcause
+    0x00900313, // li t1, 9
+    0x0a628c63, // beq t0, t1, pass
+    0x0c00006f, // j fail
+    // smode:
+    0x00200913, // li s2, 2
+    0x00200ab7, // lui s5, 0x200
+    0x00201b37, // lui s6, 0x201
+    0x000ab283, // ld t0, 0(s5)
+    0x11100313, // li t1, 0x111
+    0x0a629463, // bne t0, t1, fail
+    0x00300913, // li s2, 3
+    0x000b3283, // ld t0, 0(s6)
+    0x22200313, // li t1, 0x222
+    0x08629c63, // bne t0, t1, fail
+    // 両方のページを0x80015000に付け替える。TLBには古い変換が残っている。
+    0x00400913, // li s2, 4
+    // li t1, (0x80015000 >> 12) << 10 | 0xc7
+    0x20005337, // lui t1, 0x20005
+    0x4c73031b, // addiw t1, t1, 1223
+    0x006a3023, // sd t1, 0(s4)
+    // li t1, (0x80015000 >> 12) << 10 | 0xe7
+    0x20005337, // lui t1, 0x20005
+    0x4e73031b, // addiw t1, t1, 1255
+    0x006a3423, // sd t1, 8(s4)
+    0x000ab283, // ld t0, 0(s5)
+    0x11100313, // li t1, 0x111
+    0x06629863, // bne t0, t1, fail
+    // 別のASIDや別のアドレスを指定したsfence.vmaでは無効化されない。
+    0x00500913, // li s2, 5
+    0x00200393, // li t2, 2
+    0x127a8073, // sfence.vma s5, t2
+    0x002023b7, // lui t2, 0x202
+    0x00100e13, // li t3, 1
+    0x13c38073, // sfence.vma t2, t3
+    0x000ab283, // ld t0, 0(s5)
+    0x04629863, // bne t0, t1, fail
+    // アドレスとASIDが一致するグローバルでないエントリは無効化される。
+    0x00600913, // li s2, 6
+    0x13ca8073, // sfence.vma s5, t3
+    0x000ab283, // ld t0, 0(s5)
+    0x33300313, // li t1, 0x333
+    0x02629e63, // bne t0, t1, fail
+    // ASIDを指定した場合はグローバルなエントリは無効化されない。
+    0x00700913, // li s2, 7
+    0x13cb0073, // sfence.vma s6, t3
+    0x000b3283, // ld t0, 0(s6)
+    0x22200313, // li t1, 0x222
+    0x02629463, // bne t0, t1, fail
+    // ASIDを指定しない場合はグローバルなエントリも無効化される。
+    0x00800913, // li s2, 8
+    0x120b0073, // sfence.vma s6
+    0x000b3283, // ld t0, 0(s6)
+    0x33300313, // li t1, 0x333
+    0x00629a63, // bne t0, t1, fail
+    0x00000073, // ecall
+];
+
+fn run(name: &str, config: TlbConfig) -> (Option<u64>, TlbStats) {
+    let mut emulator = Emulator::default();
+    emulator.set_tlb_config(config);
+
+    let program = [SFENCE, &EPILOGUE].concat();
+    load_program(&mut emulator, name, &program_bytes(&program));
+
+    assert_eq!(emulator.tlb_stats(), TlbStats::default());
+
+    let exit_code = run_to_exit(&mut emulator);
+
+    (exit_code, emulator.tlb_stats())
+}
+
+// ヒットとミスがどちらも数えられていて、ページテーブルを辿るよりTLBにヒットすることの方が多いことを確かめる。
+fn check_stats(stats: TlbStats) {
+    assert!(stats.misses > 0, "{:?}", stats);
+    assert!(stats.hits > stats.misses, "{:?}", stats);
+}
+
+#[test]
+fn test_sfence_vma_unified() {
+    let (exit_code, stats) = run(
+        "tlb-unified",
+        TlbConfig {
+            split: false,
+            entries: 64,
+        },
+    );
+
+    assert_eq!(exit_code, Some(0));
+    check_stats(stats);
+}
+
+#[test]
+fn test_sfence_vma_split() {
+    let (exit_code, stats) = run(
+        "tlb-split",
+        TlbConfig {
+            split: true,
+            entries: 64,
+        },
+    );
+
+    assert_eq!(exit_code, Some(0));
+    check_stats(stats);
+}
@@ -0,0 +1,488 @@
+mod common;
+
+use common::{program_bytes, run_program, EPILOGUE};
+
+// ベクトル拡張(V)の命令を確かめるテスト
+// 小さなプログラムをRAMの先頭にロードして、プログラムの中で結果を確かめる。
+// 成功した場合はシステムコントローラに0x5555を、失敗した場合は(番号 << 16) | 0x3333を書き込む。
+// 番号は失敗した確認の番号(s2)で、VLENは128、ELENは64(デフォルトの構成)とする。
+
+// プログラムが読むデータ(data)と結果を書き込む領域(dst)の、RAMの先頭からのオフセット
+const DATA_OFFSET: usize = 0x400;
+const DST_OFFSET: usize = 0x500;
+
+// vsetvli, vsetivli, vsetvlでvlとvtypeを設定するプログラム
+// トラップハンドラは不正命令の例外(mcause = 2)の数をs3に数えて次の命令に進む。
+const VSETVL: &[u32] = &[
+    // _start:
+    // la t0, trap
+    0x00000297, // auipc t0, 0
+    0x0f028293, // addi t0, t0, 240
+    0x30529073, // csrw mtvec, t0
+    0x00100913, // li s2, 1
+    0x00000993, // li s3, 0
+    // mstatus.VSを0以外にしてベクトル命令を使えるようにする。
+    0x20000293, // li t0, 0x200
+    0x3002a073, // csrs mstatus, t0
+    // AVLがVLMAXより大きい場合、vlはVLMAX(VLEN / SEW * LMUL)になる。
+    0x00a00513, // li a0, 10
+    0x050572d7, // vsetvli t0, a0, e32, m1, ta, mu
+    0x00400313, // li t1, 4
+    0x0e629a63, // bne t0, t1, fail
+    0xc20023f3, // csrr t2, vl
+    0x0e639663, // bne t2, t1, fail
+    // rs1がx0でrdがx0以外の場合、vlはVLMAXになる。
+    0x00200913, // li s2, 2
+    0x043072d7, // vsetvli t0, zero, e8, m8, ta, mu
+    0x08000313, // li t1, 128
+    0x0c629e63, // bne t0, t1, fail
+    0x00300913, // li s2, 3
+    0x04f072d7, // vsetvli t0, zero, e16, mf2, ta, mu
+    0x00400313, // li t1, 4
+    0x0c629663, // bne t0, t1, fail
+    // AVLがVLMAX以下の場合、vlはAVLになる。
+    0x00400913, // li s2, 4
+    0x00300513, // li a0, 3
+    0x059572d7, // vsetvli t0, a0, e64, m2, ta, mu
+    0x00300313, // li t1, 3
+    0x0a629c63, // bne t0, t1, fail
+    // SEWがELEN * LMULより大きいvtypeはvillを立て、vlを0にする。
+    0x00500913, // li s2, 5
+    0x05f072d7, // vsetvli t0, zero, e64, mf2, ta, mu
+    0x0a029663, // bnez t0, fail
+    0xc20022f3, // csrr t0, vl
+    0x0a029263, // bnez t0, fail
+    0xc21022f3, // csrr t0, vtype
+    0x00100313, // li t1, 1
+    0x03f31313, // slli t1, t1, 63
+    0x08629a63, // bne t0, t1, fail
+    // villが立っている間のベクトル命令は不正命令になる。
+    0x00600913, // li s2, 6
+    0x022180d7, // vadd.vv v1, v2, v3
+    0x00100313, // li t1, 1
+    0x08699263, // bne s3, t1, fail
+    // 予約されたビットが立っているvtypeもvillになる。
+    0x00700913, // li s2, 7
+    0x00400513, // li a0, 4
+    0x10000313, // li t1, 0x100
+    0x806572d7, // vsetvl t0, a0, t1
+    0x06029863, // bnez t0, fail
+    0xc21022f3, // csrr t0, vtype
+    0x0602d463, // bgez t0, fail
+    // rs1とrdがx0の場合、vlを変えずにvtypeだけを変える。
+    0x00800913, // li s2, 8
+    0xc481f2d7, // vsetivli t0, 3, e16, m1, ta, mu
+    0x00300313, // li t1, 3
+    0x04629c63, // bne t0, t1, fail
+    0x05007057, // vsetvli zero, zero, e32, m1, ta, mu
+    0xc20022f3, // csrr t0, vl
+    0x04629663, // bne t0, t1, fail
+    // vsetvliなどでベクトルの状態を書き換えるとmstatus.VSはDirtyになる。
+    0x00900913, // li s2, 9
+    0x300022f3, // csrr t0, mstatus
+    0x0092d293, // srli t0, t0, 9
+    0x0032f293, // andi t0, t0, 3
+    0x00300313, // li t1, 3
+    0x02629a63, // bne t0, t1, fail
+    0x0240006f, // j pass
+    // trap:
+    0x342022f3, // csrr t0, mcause
+    0x00200313, // li t1, 2
+    0x02629263, // bne t0, t1, fail
+    0x00198993, // addi s3, s3, 1
+    0x341022f3, // csrr t0, mepc
+    0x00428293, // addi t0, t0, 4
+    0x34129073, // csrw mepc, t0
+    0x30200073, // mret
+];
+
+// マスクされたvadd.viとセグメントロードのプログラム
+// dataには1から8までの32ビットの値を置く。トラップハンドラはVSETVLと同じ。
+const MASKED_SEGMENT: &[u32] = &[
+    // _start:
+    // la t0, trap
+    0x00000297, // auipc t0, 0
+    0x12828293, // addi t0, t0, 296
+    0x30529073, // csrw mtvec, t0
+    0x00100913, // li s2, 1
+    0x00000993, // li s3, 0
+    // mstatus.VSを0以外にしてベクトル命令を使えるようにする。
+    0x20000293, // li t0, 0x200
+    0x3002a073, // csrs mstatus, t0
+    // la a1, data
+    0x00000597, // auipc a1, 0
+    0x3e458593, // addi a1, a1, 996
+    // la a2, dst
+    0x00000617, // auipc a2, 0
+    0x4dc60613, // addi a2, a2, 1244
+    0xc5027057, // vsetivli zero, 4, e32, m1, ta, mu
+    0x0205e087, // vle32.v v1, (a1)
+    // v0 = 0b0101のマスクで、マスクされた要素(1, 3)はvdの値が残る。
+    0x00500293, // li t0, 5
+    0x4202e057, // vmv.s.x v0, t0
+    0x5e0fb157, // vmv.v.i v2, -1
+    0x00153157, // vadd.vi v2, v1, 10, v0.t
+    0x02066127, // vse32.v v2, (a2)
+    0x00062283, // lw t0, 0(a2)
+    0x00b00313, // li t1, 11
+    0x10629263, // bne t0, t1, fail
+    0x00462283, // lw t0, 4(a2)
+    0xfff00313, // li t1, -1
+    0x0e629c63, // bne t0, t1, fail
+    0x00862283, // lw t0, 8(a2)
+    0x00d00313, // li t1, 13
+    0x0e629663, // bne t0, t1, fail
+    0x00c62283, // lw t0, 12(a2)
+    0xfff00313, // li t1, -1
+    0x0e629063, // bne t0, t1, fail
+    // vlseg2e32は(1, 2), (3, 4), ...をv4とv5に分けてロードする。
+    0x00200913, // li s2, 2
+    0x2205e207, // vlseg2e32.v v4, (a1)
+    0x02066227, // vse32.v v4, (a2)
+    0x00462283, // lw t0, 4(a2)
+    0x00300313, // li t1, 3
+    0x0c629463, // bne t0, t1, fail
+    0x00c62283, // lw t0, 12(a2)
+    0x00700313, // li t1, 7
+    0x0a629e63, // bne t0, t1, fail
+    0x020662a7, // vse32.v v5, (a2)
+    0x00062283, // lw t0, 0(a2)
+    0x00200313, // li t1, 2
+    0x0a629663, // bne t0, t1, fail
+    0x00c62283, // lw t0, 12(a2)
+    0x00800313, // li t1, 8
+    0x0a629063, // bne t0, t1, fail
+    // マスクされたセグメントロードは、マスクされた要素のすべてのフィールドを書き換えない。
+    0x00300913, // li s2, 3
+    0xc5017057, // vsetivli zero, 2, e32, m1, ta, mu
+    0x5e0fb457, // vmv.v.i v8, -1
+    0x5e0fb4d7, // vmv.v.i v9, -1
+    0x5e0fb557, // vmv.v.i v10, -1
+    0x4005e407, // vlseg3e32.v v8, (a1), v0.t
+    0x02066527, // vse32.v v10, (a2)
+    0x00062283, // lw t0, 0(a2)
+    0x00300313, // li t1, 3
+    0x06629c63, // bne t0, t1, fail
+    0x00462283, // lw t0, 4(a2)
+    0xfff00313, // li t1, -1
+    0x06629663, // bne t0, t1, fail
+    0x020664a7, // vse32.v v9, (a2)
+    0x00062283, // lw t0, 0(a2)
+    0x00200313, // li t1, 2
+    0x04629e63, // bne t0, t1, fail
+    // NFIELDS * EMULが8を超えるセグメントロードは不正命令になる。
+    0x00400913, // li s2, 4
+    0xc5217057, // vsetivli zero, 2, e32, m4, ta, mu
+    0x4205e407, // vlseg3e32.v v8, (a1)
+    0x00100313, // li t1, 1
+    0x04699463, // bne s3, t1, fail
+    // マスクされたベクトル命令のvdがv0の場合は不正命令になる。
+    0x00500913, // li s2, 5
+    0xc5017057, // vsetivli zero, 2, e32, m1, ta, mu
+    0x0005e007, // vle32.v v0, (a1), v0.t
+    0x00200313, // li t1, 2
+    0x02699a63, // bne s3, t1, fail
+    0x0240006f, // j pass
+    // trap:
+    0x342022f3, // csrr t0, mcause
+    0x00200313, // li t1, 2
+    0x02629263, // bne t0, t1, fail
+    0x00198993, // addi s3, s3, 1
+    0x341022f3, // csrr t0, mepc
+    0x00428293, // addi t0, t0, 4
+    0x34129073, // csrw mepc, t0
+    0x30200073, // mret
+];
+
+// フォールトオンリーファーストのロード(vle32ff.v)のプログラム
+// トラップハンドラはmcauseをs3に、vstartをs4に保存し、vstartを0に戻して次の命令に進む。
+const FAULT_ONLY_FIRST: &[u32] = &[
+    // _start:
+    // la t0, trap
+    0x00000297, // auipc t0, 0
+    0x0e028293, // addi t0, t0, 224
+    0x30529073, // csrw mtvec, t0
+    0x00100913, // li s2, 1
+    0x00000993, // li s3, 0
+    // mstatus.VSを0以外にしてベクトル命令を使えるようにする。
+    0x20000293, // li t0, 0x200
+    0x3002a073, // csrs mstatus, t0
+    // RAMの終わりの8バイト前から4つの要素をロードすると、要素2で例外が起きる。
+    // li a5, 0x87fffff8
+    0x01100793, // li a5, 17
+    0x01b79793, // slli a5, a5, 27
+    0xff878793, // addi a5, a5, -8
+    // la a2, dst
+    0x00000617, // auipc a2, 0
+    0x4d860613, // addi a2, a2, 1240
+    0xc5027057, // vsetivli zero, 4, e32, m1, ta, mu
+    0x5e03b4d7, // vmv.v.i v9, 7
+    0x0307e487, // vle32ff.v v9, (a5)
+    0x0c099663, // bnez s3, fail
+    0xc20022f3, // csrr t0, vl
+    0x00200313, // li t1, 2
+    0x0c629063, // bne t0, t1, fail
+    0x008022f3, // csrr t0, vstart
+    0x0a029c63, // bnez t0, fail
+    0x020664a7, // vse32.v v9, (a2)
+    0x00062283, // lw t0, 0(a2)
+    0x0a029663, // bnez t0, fail
+    // フォールトオンリーファーストでないロードは、vstartを例外の起きた要素にしてトラップする。
+    0x00200913, // li s2, 2
+    0xc5027057, // vsetivli zero, 4, e32, m1, ta, mu
+    0x0207e487, // vle32.v v9, (a5)
+    0x00500313, // li t1, 5
+    0x08699c63, // bne s3, t1, fail
+    0x00200313, // li t1, 2
+    0x086a1863, // bne s4, t1, fail
+    0xc20022f3, // csrr t0, vl
+    0x00400313, // li t1, 4
+    0x08629263, // bne t0, t1, fail
+    // 要素0で例外が起きた場合は、フォールトオンリーファーストでもトラップする。
+    0x00300913, // li s2, 3
+    0x00000993, // li s3, 0
+    0x00000a13, // li s4, 0
+    // li a6, 0x88000000
+    0x01100813, // li a6, 17
+    0x01b81813, // slli a6, a6, 27
+    0x03086487, // vle32ff.v v9, (a6)
+    0x00500313, // li t1, 5
+    0x06699263, // bne s3, t1, fail
+    0x060a1063, // bnez s4, fail
+    0xc20022f3, // csrr t0, vl
+    0x00400313, // li t1, 4
+    0x04629a63, // bne t0, t1, fail
+    // マスクされた要素では例外が起きない。
+    0x00400913, // li s2, 4
+    0x00000993, // li s3, 0
+    0x00300293, // li t0, 3
+    0x4202e057, // vmv.s.x v0, t0
+    0x0107e487, // vle32ff.v v9, (a5), v0.t
+    0x02099e63, // bnez s3, fail
+    0xc20022f3, // csrr t0, vl
+    0x00400313, // li t1, 4
+    0x02629863, // bne t0, t1, fail
+    0x0200006f, // j pass
+    // trap:
+    0x342029f3, // csrr s3, mcause
+    0x00802a73, // csrr s4, vstart
+    0x00805073, // csrwi vstart, 0
+    0x341022f3, // csrr t0, mepc
+    0x00428293, // addi t0, t0, 4
+    0x34129073, // csrw mepc, t0
+    0x30200073, // mret
+];
+
+// vxrmの丸めモードごとにvssrl.viとvnclip.wiの結果を確かめるプログラム
+// 4つの要素の結果をdstに書き込み、まとめて32ビットの値として比べる。
+const FIXED_POINT: &[u32] = &[
+    // _start:
+    // mstatus.VSを0以外にしてベクトル命令を使えるようにする。
+    0x20000293, // li t0, 0x200
+    0x3002a073, // csrs mstatus, t0
+    // la a1, data
+    0x00000597, // auipc a1, 0
+    0x3f858593, // addi a1, a1, 1016
+    // la a2, dst
+    0x00000617, // auipc a2, 0
+    0x4f060613, // addi a2, a2, 1264
+    // v4にe16の(-10, 10, 0x7ff0, -0x8000)を、v1にe8の(6, 10, 0xff, 1)をロードする。
+    0xcc827057, // vsetivli zero, 4, e16, m1, ta, ma
+    0x0205d207, // vle16.v v4, (a1)
+    0x00858593, // addi a1, a1, 8
+    0xcc027057, // vsetivli zero, 4, e8, m1, ta, ma
+    0x02058087, // vle8.v v1, (a1)
+    0x00905073, // csrwi vxsat, 0
+    // vxrm = 0(RNU)
+    0x00100913, // li s2, 1
+    0x00a05073, // csrwi vxrm, 0
+    0xaa113157, // vssrl.vi v2, v1, 2
+    0x02060127, // vse8.v v2, (a2)
+    0x00066283, // lwu t0, 0(a2)
+    // li t1, 0x00400302
+    0x00400337, // lui t1, 0x400
+    0x3023031b, // addiw t1, t1, 770
+    0x0e629a63, // bne t0, t1, fail
+    0xbe4131d7, // vnclip.wi v3, v4, 2
+    0x020601a7, // vse8.v v3, (a2)
+    0x00066283, // lwu t0, 0(a2)
+    // li t1, 0x807f03fe
+    0x0807f337, // lui t1, 0x807f
+    0x00431313, // slli t1, t1, 4
+    0x3fe30313, // addi t1, t1, 1022
+    0x0c629c63, // bne t0, t1, fail
+    // vxrm = 1(RNE)
+    0x00200913, // li s2, 2
+    0x00a0d073, // csrwi vxrm, 1
+    0xaa113157, // vssrl.vi v2, v1, 2
+    0x02060127, // vse8.v v2, (a2)
+    0x00066283, // lwu t0, 0(a2)
+    // li t1, 0x00400202
+    0x00400337, // lui t1, 0x400
+    0x2023031b, // addiw t1, t1, 514
+    0x0a629c63, // bne t0, t1, fail
+    0xbe4131d7, // vnclip.wi v3, v4, 2
+    0x020601a7, // vse8.v v3, (a2)
+    0x00066283, // lwu t0, 0(a2)
+    // li t1, 0x807f02fe
+    0x0807f337, // lui t1, 0x807f
+    0x00431313, // slli t1, t1, 4
+    0x2fe30313, // addi t1, t1, 766
+    0x08629e63, // bne t0, t1, fail
+    // vxrm = 2(RDN)
+    0x00300913, // li s2, 3
+    0x00a15073, // csrwi vxrm, 2
+    0xaa113157, // vssrl.vi v2, v1, 2
+    0x02060127, // vse8.v v2, (a2)
+    0x00066283, // lwu t0, 0(a2)
+    // li t1, 0x003f0201
+    0x003f0337, // lui t1, 0x3f0
+    0x2013031b, // addiw t1, t1, 513
+    0x06629e63, // bne t0, t1, fail
+    0xbe4131d7, // vnclip.wi v3, v4, 2
+    0x020601a7, // vse8.v v3, (a2)
+    0x00066283, // lwu t0, 0(a2)
+    // li t1, 0x807f02fd
+    0x0807f337, // lui t1, 0x807f
+    0x00431313, // slli t1, t1, 4
+    0x2fd30313, // addi t1, t1, 765
+    0x06629063, // bne t0, t1, fail
+    // vxrm = 3(ROD)
+    0x00400913, // li s2, 4
+    0x00a1d073, // csrwi vxrm, 3
+    0xaa113157, // vssrl.vi v2, v1, 2
+    0x02060127, // vse8.v v2, (a2)
+    0x00066283, // lwu t0, 0(a2)
+    // li t1, 0x013f0301
+    0x013f0337, // lui t1, 0x13f0
+    0x3013031b, // addiw t1, t1, 769
+    0x04629063, // bne t0, t1, fail
+    0xbe4131d7, // vnclip.wi v3, v4, 2
+    0x020601a7, // vse8.v v3, (a2)
+    0x00066283, // lwu t0, 0(a2)
+    // li t1, 0x807f03fd
+    0x0807f337, // lui t1, 0x807f
+    0x00431313, // slli t1, t1, 4
+    0x3fd30313, // addi t1, t1, 1021
+    0x02629263, // bne t0, t1, fail
+    // vnclipで飽和した要素があるのでvxsatが立つ。
+    0x00500913, // li s2, 5
+    0x009022f3, // csrr t0, vxsat
+    0x00100313, // li t1, 1
+    0x00629a63, // bne t0, t1, fail
+    0x0040006f, // j pass
+];
+
+// ワイドニングとナローイングの命令でvdとソースのレジスタの重なりを確かめるプログラム
+// トラップハンドラはVSETVLと同じ。
+const REGISTER_OVERLAP: &[u32] = &[
+    // _start:
+    // la t0, trap
+    0x00000297, // auipc t0, 0
+    0x08828293, // addi t0, t0, 136
+    0x30529073, // csrw mtvec, t0
+    0x00100913, // li s2, 1
+    0x00000993, // li s3, 0
+    // mstatus.VSを0以外にしてベクトル命令を使えるようにする。
+    0x20000293, // li t0, 0x200
+    0x3002a073, // csrs mstatus, t0
+    0xcc027057, // vsetivli zero, 4, e8, m1, ta, ma
+    // ソースのEMULが1以上なら、vdのグループの上半分とは重なってもよい。
+    0xc2322157, // vwaddu.vv v2, v3, v4
+    0xc241a157, // vwaddu.vv v2, v4, v3
+    0x08099663, // bnez s3, fail
+    // vdのグループの下半分とソースが重なる場合は不正命令になる。
+    0x00200913, // li s2, 2
+    0xc2222157, // vwaddu.vv v2, v2, v4
+    0x00100313, // li t1, 1
+    0x06699e63, // bne s3, t1, fail
+    // vdのグループがEMUL(2)の倍数でない場合は不正命令になる。
+    0x00300913, // li s2, 3
+    0xc242a1d7, // vwaddu.vv v3, v4, v5
+    0x00200313, // li t1, 2
+    0x06699663, // bne s3, t1, fail
+    // ソースのEMULが1より小さい場合は、vdのグループと重なってはいけない。
+    0x00400913, // li s2, 4
+    0xcc727057, // vsetivli zero, 4, e8, mf2, ta, ma
+    0xc2322157, // vwaddu.vv v2, v3, v4
+    0xc2222157, // vwaddu.vv v2, v2, v4
+    0x00300313, // li t1, 3
+    0x04699a63, // bne s3, t1, fail
+    // ナローイングはソースの下半分とvdが同じレジスタであれば重なってもよい。
+    0x00500913, // li s2, 5
+    0xcc027057, // vsetivli zero, 4, e8, m1, ta, ma
+    0xb2403257, // vnsrl.wi v4, v4, 0
+    0x00300313, // li t1, 3
+    0x04699063, // bne s3, t1, fail
+    0xb24032d7, // vnsrl.wi v5, v4, 0
+    0x00400313, // li t1, 4
+    0x02699a63, // bne s3, t1, fail
+    0x0240006f, // j pass
+    // trap:
+    0x342022f3, // csrr t0, mcause
+    0x00200313, // li t1, 2
+    0x02629263, // bne t0, t1, fail
+    0x00198993, // addi s3, s3, 1
+    0x341022f3, // csrr t0, mepc
+    0x00428293, // addi t0, t0, 4
+    0x34129073, // csrw mepc, t0
+    0x30200073, // mret
+];
+
+// プログラムのあとにEPILOGUEを置き、DATA_OFFSETにdataを置いて実行する関数
+fn run_vector_program(name: &str, program: &[u32], data: &[u8]) -> Option<u64> {
+    let mut bytes = program_bytes(&[program, &EPILOGUE].concat());
+    assert!(bytes.len() <= DATA_OFFSET);
+
+    bytes.resize(DATA_OFFSET, 0);
+    bytes.extend_from_slice(data);
+    bytes.resize(DST_OFFSET + 16, 0);
+
+    run_program(name, &bytes)
+}
+
+#[test]
+fn test_vsetvl() {
+    assert_eq!(run_vector_program("vsetvl", VSETVL, &[]), Some(0));
+}
+
+#[test]
+fn test_vector_masked_segment() {
+    let data: Vec<u8> = (1..=8u32).flat_map(|value| value.to_le_bytes()).collect();
+
+    assert_eq!(
+        run_vector_program("vector-segment", MASKED_SEGMENT, &data),
+        Some(0)
+    );
+}
+
+#[test]
+fn test_vector_fault_only_first() {
+    assert_eq!(
+        run_vector_program("vector-fault-only-first", FAULT_ONLY_FIRST, &[]),
+        Some(0)
+    );
+}
+
+#[test]
+fn test_vector_fixed_point_rounding() {
+    // e16の(-10, 10, 0x7ff0, -0x8000)とe8の(6, 10, 0xff, 1)
+    let mut data: Vec<u8> = [0xfff6u16, 0x000a, 0x7ff0, 0x8000]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    data.extend_from_slice(&[6, 10, 0xff, 1]);
+
+    assert_eq!(
+        run_vector_program("vector-fixed-point", FIXED_POINT, &data),
+        Some(0)
+    );
+}
+
+#[test]
+fn test_vector_register_overlap() {
+    assert_eq!(
+        run_vector_program("vector-overlap", REGISTER_OVERLAP, &[]),
+        Some(0)
+    );
+}
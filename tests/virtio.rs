@@ -0,0 +1,353 @@
+mod common;
+
+use std::{env, fs, path::PathBuf};
+
+use common::{load_program, program_bytes, run_to_exit, EPILOGUE};
+use tiny_riscv_emulator::{
+    bus::Device,
+    emulator::Emulator,
+    virtio::{VirtioBlock, VIRTIO_BASE, VIRTIO_IRQ, VIRTIO_SIZE},
+};
+
+// 各レジスタのアドレス(デバイスの先頭からのオフセット)
+const MAGIC_VALUE: u64 = 0x000;
+const VERSION: u64 = 0x004;
+const DEVICE_ID: u64 = 0x008;
+const DEVICE_FEATURES: u64 = 0x010;
+const DEVICE_FEATURES_SEL: u64 = 0x014;
+const QUEUE_NUM_MAX: u64 = 0x034;
+const STATUS: u64 = 0x070;
+const CONFIG: u64 = 0x100;
+
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+
+const SECTOR_SIZE: usize = 512;
+
+// セクタ0から順に'A', 'B', 'C', 'D'で埋めた4セクタのディスクイメージを作る関数
+fn disk_image(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("tiny-riscv-emulator-virtio-{}.img", name));
+
+    let image: Vec<u8> = (b'A'..=b'D').flat_map(|byte| [byte; SECTOR_SIZE]).collect();
+
+    fs::write(&path, image).unwrap();
+
+    path
+}
+
+fn sector(image: &[u8], sector: usize) -> &[u8] {
+    &image[sector * SECTOR_SIZE..(sector + 1) * SECTOR_SIZE]
+}
+
+#[test]
+fn test_virtio_block_registers() {
+    for read_only in [false, true] {
+        let path = disk_image(&format!("registers-{}", read_only));
+        let mut disk = VirtioBlock::open(&path, read_only).unwrap();
+
+        assert_eq!(disk.read(MAGIC_VALUE, 4), Some(0x7472_6976));
+        assert_eq!(disk.read(VERSION, 4), Some(2));
+        assert_eq!(disk.read(DEVICE_ID, 4), Some(2));
+        assert_eq!(disk.read(QUEUE_NUM_MAX, 4), Some(256));
+
+        // 読み込み専用のイメージの場合のみVIRTIO_BLK_F_ROを通知する。
+        let ro = if read_only { VIRTIO_BLK_F_RO } else { 0 };
+        assert_eq!(
+            disk.read(DEVICE_FEATURES, 4).map(|f| f & VIRTIO_BLK_F_RO),
+            Some(ro)
+        );
+
+        // 上位の32ビットはVIRTIO_F_VERSION_1
+        assert!(disk.write(DEVICE_FEATURES_SEL, 4, 1));
+        assert_eq!(disk.read(DEVICE_FEATURES, 4), Some(1));
+
+        // 設定空間のcapacity(セクタ数)とblk_size
+        assert_eq!(disk.read(CONFIG, 8), Some(4));
+        assert_eq!(disk.read(CONFIG + 0x14, 4), Some(SECTOR_SIZE as u64));
+
+        // 設定空間以外のレジスタは4バイトでのみアクセスできる。
+        assert_eq!(disk.read(MAGIC_VALUE, 8), None);
+        assert!(!disk.write(STATUS, 2, 1));
+
+        // statusに0を書き込むとリセットされる。
+        assert!(disk.write(STATUS, 4, 0xf));
+        assert_eq!(disk.read(STATUS, 4), Some(0xf));
+        assert!(disk.write(STATUS, 4, 0));
+        assert_eq!(disk.read(STATUS, 4), Some(0));
+
+        assert!(!disk.irq());
+
+        let _ = fs::remove_file(&path);
+    }
+}
+
+// RAMの先頭から0x1000に置くvirtqueueとバッファの物理アドレス
+// descはディスクリプタテーブル、availはavailable ring、usedはused ringである。
+const DATA: u64 = 0x8000_1000;
+const HEADER: u64 = DATA + 0x300;
+const STATUS_BYTE: u64 = DATA + 0x310;
+const BUFFER: u64 = DATA + 0x400;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+// ヘッダ(0) -> データ(1) -> ステータス(2)のチェーンを作るディスクリプタテーブル
+// プログラムは要求ごとにデータのディスクリプタのflagsとlen、ヘッダのnextを書き換える。
+fn descriptors() -> Vec<u8> {
+    let descriptors: [(u64, u32, u16, u16); 3] = [
+        (HEADER, 16, VIRTQ_DESC_F_NEXT, 1),
+        (BUFFER, 512, VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE, 2),
+        (STATUS_BYTE, 1, VIRTQ_DESC_F_WRITE, 0),
+    ];
+
+    descriptors
+        .iter()
+        .flat_map(|&(address, len, flags, next)| {
+            let mut bytes = Vec::new();
+
+            bytes.extend(address.to_le_bytes());
+            bytes.extend(len.to_le_bytes());
+            bytes.extend(flags.to_le_bytes());
+            bytes.extend(next.to_le_bytes());
+
+            bytes
+        })
+        .collect()
+}
+
+// キュー0(大きさ8)を初期化して、ディスクリプタ0から始まる要求を順に出すプログラム
+// s0はvirtio、s1はPLIC、s3はDATAのアドレスで、s4にはVIRTIO_BLK_F_ROが立っているかを入れる。
+// 読み込み専用の場合は書き込みの要求がIOERR(1)になることを確かめる。
+// 番号は失敗した確認の番号(s2)で、このあとにEPILOGUEとSUBMITを置く。
+const VIRTQUEUE: &[u32] = &[
+    // _start:
+    0x00100913, // li s2, 1
+    0x10001437, // lui s0, 0x10001 (virtio)
+    0x0c0004b7, // lui s1, 0xc000 (PLIC)
+    // li s3, 0x80001000 (DATA)
+    0x000809b7, // lui s3, 0x80
+    0x0019899b, // addiw s3, s3, 1
+    0x00c99993, // slli s3, s3, 12
+    // マジックナンバーとデバイスID、capacityを確かめる。
+    0x00042283, // lw t0, 0(s0)
+    // li t1, 0x74726976
+    0x74727337, // lui t1, 0x74727
+    0x9763031b, // addiw t1, t1, -1674
+    0x20629e63, // bne t0, t1, fail
+    0x00842283, // lw t0, 8(s0)
+    0x00200313, // li t1, 2
+    0x20629863, // bne t0, t1, fail
+    0x00200913, // li s2, 2
+    0x10043283, // ld t0, 0x100(s0)
+    0x00400313, // li t1, 4
+    0x20629063, // bne t0, t1, fail
+    // 機能の上位32ビットはVIRTIO_F_VERSION_1のみ
+    0x00300913, // li s2, 3
+    0x00100293, // li t0, 1
+    0x00542a23, // sw t0, 0x14(s0)
+    0x01042283, // lw t0, 0x10(s0)
+    0x00100313, // li t1, 1
+    0x1e629463, // bne t0, t1, fail
+    0x00042a23, // sw zero, 0x14(s0)
+    0x01042283, // lw t0, 0x10(s0)
+    0x0052da13, // srli s4, t0, 5
+    0x001a7a13, // andi s4, s4, 1
+    // status: ACKNOWLEDGE | DRIVER | FEATURES_OK
+    0x00b00293, // li t0, 11
+    0x06542823, // sw t0, 0x70(s0)
+    0x02042823, // sw zero, 0x30(s0) (QueueSel)
+    0x00800293, // li t0, 8
+    0x02542c23, // sw t0, 0x38(s0) (QueueNum)
+    0x09342023, // sw s3, 0x80(s0) (QueueDescLow)
+    0x08042223, // sw zero, 0x84(s0)
+    0x10098293, // addi t0, s3, 0x100
+    0x08542823, // sw t0, 0x90(s0) (QueueDriverLow)
+    0x08042a23, // sw zero, 0x94(s0)
+    0x20098293, // addi t0, s3, 0x200
+    0x0a542023, // sw t0, 0xa0(s0) (QueueDeviceLow)
+    0x0a042223, // sw zero, 0xa4(s0)
+    0x00100293, // li t0, 1
+    0x04542223, // sw t0, 0x44(s0) (QueueReady)
+    // status: ACKNOWLEDGE | DRIVER | FEATURES_OK | DRIVER_OK
+    0x00f00293, // li t0, 15
+    0x06542823, // sw t0, 0x70(s0)
+    // PLICで割り込み源1の優先度を1にして、コンテキスト0で有効にする。
+    0x00100293, // li t0, 1
+    0x0054a223, // sw t0, 4(s1)
+    0x00002337, // lui t1, 2
+    0x00648333, // add t1, s1, t1
+    0x00200293, // li t0, 2
+    0x00532023, // sw t0, 0(t1)
+    // IN: セクタ1を読み込む。
+    0x00400913, // li s2, 4
+    0x3009a023, // sw zero, 0x300(s3) (type)
+    0x00100293, // li t0, 1
+    0x3059b423, // sd t0, 0x308(s3) (sector)
+    0x184000ef, // jal submit
+    0x16051263, // bnez a0, fail
+    0x4009c283, // lbu t0, 0x400(s3)
+    0x04200313, // li t1, 'B'
+    0x14629c63, // bne t0, t1, fail
+    0x5ff9c283, // lbu t0, 0x5ff(s3)
+    0x14629863, // bne t0, t1, fail
+    // used ring: idx = 1, ring[0] = (id 0, len 512 + 1)
+    0x00500913, // li s2, 5
+    0x2029d283, // lhu t0, 0x202(s3)
+    0x00100313, // li t1, 1
+    0x14629063, // bne t0, t1, fail
+    0x2049a283, // lw t0, 0x204(s3)
+    0x12029c63, // bnez t0, fail
+    0x2089a283, // lw t0, 0x208(s3)
+    0x20100313, // li t1, 513
+    0x12629663, // bne t0, t1, fail
+    // InterruptStatusとMEIPが立ち、PLICから割り込み源1をclaimできる。
+    // InterruptACKに書き込んでcompleteすると両方とも下がる。
+    0x00600913, // li s2, 6
+    0x06042283, // lw t0, 0x60(s0)
+    0x00100313, // li t1, 1
+    0x10629e63, // bne t0, t1, fail
+    0x344022f3, // csrr t0, mip
+    0x00b2d293, // srli t0, t0, 11
+    0x0012f293, // andi t0, t0, 1
+    0x10028663, // beqz t0, fail
+    0x002003b7, // lui t2, 0x200
+    0x007483b3, // add t2, s1, t2
+    0x0043a283, // lw t0, 4(t2) (claim)
+    0x0e629e63, // bne t0, t1, fail
+    0x06642223, // sw t1, 0x64(s0)
+    0x0053a223, // sw t0, 4(t2) (complete)
+    0x06042283, // lw t0, 0x60(s0)
+    0x0e029663, // bnez t0, fail
+    0x344022f3, // csrr t0, mip
+    0x00b2d293, // srli t0, t0, 11
+    0x0012f293, // andi t0, t0, 1
+    0x0c029e63, // bnez t0, fail
+    // OUT: 読み込んだセクタ1の内容をセクタ2に書き込む。
+    0x00700913, // li s2, 7
+    0x00100293, // li t0, 1
+    0x3059a023, // sw t0, 0x300(s3)
+    0x00200293, // li t0, 2
+    0x3059b423, // sd t0, 0x308(s3)
+    0x00100293, // li t0, 1
+    0x00599e23, // sh t0, 0x1c(s3) (flags = NEXT)
+    0x0d8000ef, // jal submit
+    0x0b451c63, // bne a0, s4, fail
+    // FLUSH: ヘッダとステータスのみのチェーン
+    0x00800913, // li s2, 8
+    0x00400293, // li t0, 4
+    0x3059a023, // sw t0, 0x300(s3)
+    0x00200293, // li t0, 2
+    0x00599723, // sh t0, 0xe(s3) (next = 2)
+    0x0bc000ef, // jal submit
+    0x08051e63, // bnez a0, fail
+    0x00100293, // li t0, 1
+    0x00599723, // sh t0, 0xe(s3)
+    // GET_ID: 20バイトのIDを書き込む。
+    0x00900913, // li s2, 9
+    0x00800293, // li t0, 8
+    0x3059a023, // sw t0, 0x300(s3)
+    0x00300293, // li t0, 3
+    0x00599e23, // sh t0, 0x1c(s3) (flags = NEXT | WRITE)
+    0x098000ef, // jal submit
+    0x06051c63, // bnez a0, fail
+    0x4009a283, // lw t0, 0x400(s3)
+    // li t1, 0x796e6974 ("tiny")
+    0x796e7337, // lui t1, 0x796e7
+    0x9743031b, // addiw t1, t1, -1676
+    0x06629463, // bne t0, t1, fail
+    0x2209a283, // lw t0, 0x220(s3) (ring[3].len)
+    0x01500313, // li t1, 21
+    0x04629e63, // bne t0, t1, fail
+    // IN: capacityと同じセクタ番号はIOERR
+    0x00a00913, // li s2, 10
+    0x3009a023, // sw zero, 0x300(s3)
+    0x00400293, // li t0, 4
+    0x3059b423, // sd t0, 0x308(s3)
+    0x064000ef, // jal submit
+    0x00100313, // li t1, 1
+    0x04651063, // bne a0, t1, fail
+    // IN: 最後のセクタから2セクタ分の読み込みはIOERR
+    0x00b00913, // li s2, 11
+    0x00300293, // li t0, 3
+    0x3059b423, // sd t0, 0x308(s3)
+    0x40000293, // li t0, 1024
+    0x0059ac23, // sw t0, 0x18(s3) (len)
+    0x044000ef, // jal submit
+    0x00100313, // li t1, 1
+    0x02651063, // bne a0, t1, fail
+    // すべての要求がused ringに返されている。
+    0x00c00913, // li s2, 12
+    0x2029d283, // lhu t0, 0x202(s3)
+    0x00600313, // li t1, 6
+    0x00629863, // bne t0, t1, fail
+];
+
+// ディスクリプタ0をavailable ringに追加してQueueNotifyに書き込み、ステータスをa0に返す関数
+// 処理されなかった場合にわかるように、ステータスは0xffにしておく。
+const SUBMIT: &[u32] = &[
+    // submit:
+    0x0ff00293, // li t0, 0xff
+    0x30598823, // sb t0, 0x310(s3)
+    0x1029d283, // lhu t0, 0x102(s3) (idx)
+    0x0072f313, // andi t1, t0, 7
+    0x00131313, // slli t1, t1, 1
+    0x01330333, // add t1, t1, s3
+    0x10031223, // sh zero, 0x104(t1) (ring[idx % 8])
+    0x00128293, // addi t0, t0, 1
+    0x10599123, // sh t0, 0x102(s3)
+    0x04042823, // sw zero, 0x50(s0) (QueueNotify)
+    0x3109c503, // lbu a0, 0x310(s3)
+    0x00008067, // ret
+];
+
+// ディスクイメージをvirtioのブロックデバイスとして接続してプログラムを実行する関数
+// 実行後のディスクイメージの内容と終了コードを返す。
+fn run_virtqueue(name: &str, read_only: bool) -> (Vec<u8>, Option<u64>) {
+    let path = disk_image(name);
+
+    let mut bytes = program_bytes(&[VIRTQUEUE, &EPILOGUE, SUBMIT].concat());
+    assert!(bytes.len() <= (DATA - 0x8000_0000) as usize);
+
+    bytes.resize((DATA - 0x8000_0000) as usize, 0);
+    bytes.extend(descriptors());
+
+    let mut emulator = Emulator::default();
+
+    emulator.attach_device_with_irq(
+        VIRTIO_BASE,
+        VIRTIO_SIZE,
+        VIRTIO_IRQ,
+        Box::new(VirtioBlock::open(&path, read_only).unwrap()),
+    );
+
+    load_program(&mut emulator, name, &bytes);
+
+    let exit_code = run_to_exit(&mut emulator);
+    let image = fs::read(&path).unwrap();
+
+    let _ = fs::remove_file(&path);
+
+    (image, exit_code)
+}
+
+#[test]
+fn test_virtio_block_virtqueue() {
+    let (image, exit_code) = run_virtqueue("virtqueue", false);
+
+    assert_eq!(exit_code, Some(0));
+
+    // セクタ2にはセクタ1の内容が書き込まれ、他のセクタは変わらない。
+    assert!(sector(&image, 0).iter().all(|&byte| byte == b'A'));
+    assert!(sector(&image, 1).iter().all(|&byte| byte == b'B'));
+    assert!(sector(&image, 2).iter().all(|&byte| byte == b'B'));
+    assert!(sector(&image, 3).iter().all(|&byte| byte == b'D'));
+}
+
+#[test]
+fn test_virtio_block_read_only() {
+    let (image, exit_code) = run_virtqueue("read-only", true);
+
+    assert_eq!(exit_code, Some(0));
+
+    // 書き込みの要求は拒否され、ディスクイメージは変わらない。
+    assert!(sector(&image, 2).iter().all(|&byte| byte == b'C'));
+}
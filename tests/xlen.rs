@@ -0,0 +1,141 @@
+mod common;
+
+use common::{program_bytes, run_program, EPILOGUE};
+
+// RV64のM-modeからmstatus.UXLを1(32bit)にしてU-modeで実行するプログラムのテスト
+// プログラムはRAMの先頭にロードするフラットなバイナリで、U-modeからもシステムコントローラに書き込めるようにPMPですべてのアドレスを許可する。
+// 成功した場合はシステムコントローラに0x5555を、失敗した場合は(確認の番号 << 16) | 0x3333を書き込む。
+
+// U-modeでF、D拡張とZba、Zbb、Zbs拡張の命令が32bitの幅で動くことと、
+// RV64のみのエンコーディング(fcvt.l.s、fmv.x.d、add.uw、clzw)が不正な命令になることを確かめる。
+// 不正な命令の例外はM-modeでs3に数えて次の命令に戻る。それ以外の例外は失敗にする。
+// 番号は失敗した確認の番号(s2)で、このあとにEPILOGUEを置く。
+const UXL32: &[u32] = &[
+    // _start:
+    0x00000297, // auipc t0, 0
+    0x07028293, // addi t0, t0, 112 (trap)
+    0x30529073, // csrw mtvec, t0
+    0xfff00293, // li t0, -1
+    0x3b029073, // csrw pmpaddr0, t0
+    0x01f00293, // li t0, 31
+    0x3a029073, // csrw pmpcfg0, t0
+    0x00f00913, // li s2, 15 (準備中に例外が起きた場合の番号)
+    0x00000993, // li s3, 0
+    // mstatusのUXLを1、MPPを0(U-mode)、FSを1(Initial)にする。
+    0x300022f3, // csrr t0, mstatus
+    0x00300313, // li t1, 3
+    0x02031313, // slli t1, t1, 32
+    0xfff34313, // not t1, t1
+    0x0062f2b3, // and t0, t0, t1
+    0x00002337, // lui t1, 2
+    0x8003031b, // addiw t1, t1, -2048
+    0xfff34313, // not t1, t1
+    0x0062f2b3, // and t0, t0, t1
+    0x00100313, // li t1, 1
+    0x02031313, // slli t1, t1, 32
+    0x0062e2b3, // or t0, t0, t1
+    0x00002337, // lui t1, 2
+    0x0062e2b3, // or t0, t0, t1
+    0x30029073, // csrw mstatus, t0
+    0x00000297, // auipc t0, 0
+    0x03028293, // addi t0, t0, 48 (user)
+    0x34129073, // csrw mepc, t0
+    0x30200073, // mret
+    // trap:
+    0x342022f3, // csrr t0, mcause
+    0x00200313, // li t1, 2
+    0x16629263, // bne t0, t1, fail
+    0x00198993, // addi s3, s3, 1
+    0x341022f3, // csrr t0, mepc
+    0x00428293, // addi t0, t0, 4
+    0x34129073, // csrw mepc, t0
+    0x30200073, // mret
+    // user:
+    0x00100913, // li s2, 1
+    0x3fc002b7, // lui t0, 0x3fc00 (1.5)
+    0xf0028053, // fmv.w.x ft0, t0
+    0x401002b7, // lui t0, 0x40100 (2.25)
+    0xf00280d3, // fmv.w.x ft1, t0
+    0x00107153, // fadd.s ft2, ft0, ft1
+    0xe0010353, // fmv.x.w t1, ft2
+    0x407003b7, // lui t2, 0x40700 (3.75)
+    0x12731663, // bne t1, t2, fail
+    0x00200913, // li s2, 2
+    0xc0011353, // fcvt.w.s t1, ft2, rtz
+    0x00300393, // li t2, 3
+    0x10731e63, // bne t1, t2, fail
+    0x00300913, // li s2, 3
+    0xff900293, // li t0, -7
+    0xd20281d3, // fcvt.d.w ft3, t0
+    0x0231f1d3, // fadd.d ft3, ft3, ft3
+    0xc201f353, // fcvt.w.d t1, ft3
+    0xff200393, // li t2, -14
+    0x10731063, // bne t1, t2, fail
+    0x00400913, // li s2, 4
+    0x80001437, // lui s0, 0x80001
+    0x00242027, // fsw ft2, 0(s0)
+    0x00042303, // lw t1, 0(s0)
+    0x407003b7, // lui t2, 0x40700
+    0x0e731463, // bne t1, t2, fail
+    0x00042207, // flw ft4, 0(s0)
+    0xa0222353, // feq.s t1, ft4, ft2
+    0x0c030e63, // beqz t1, fail
+    0x00500913, // li s2, 5
+    0x00500513, // li a0, 5
+    0x06400593, // li a1, 100
+    0x20b54333, // sh2add t1, a0, a1
+    0x07800393, // li t2, 120
+    0x0c731263, // bne t1, t2, fail
+    0x00600913, // li s2, 6
+    0x00100293, // li t0, 1
+    0x60029313, // clz t1, t0
+    0x01f00393, // li t2, 31
+    0x0a731863, // bne t1, t2, fail
+    0x00700913, // li s2, 7
+    0x60101313, // ctz t1, zero
+    0x02000393, // li t2, 32
+    0x0a731063, // bne t1, t2, fail
+    0x00800913, // li s2, 8
+    0xfff00293, // li t0, -1
+    0x60229313, // cpop t1, t0
+    0x08731863, // bne t1, t2, fail
+    0x00900913, // li s2, 9
+    0x123452b7, // lui t0, 0x12345
+    0x67828293, // addi t0, t0, 0x678
+    0x6982d313, // rev8 t1, t0 (RV32のエンコーディング)
+    0x785633b7, // lui t2, 0x78563
+    0x41238393, // addi t2, t2, 0x412
+    0x06731a63, // bne t1, t2, fail
+    0x00a00913, // li s2, 10
+    0x0802c333, // zext.h t1, t0 (RV32のエンコーディング)
+    0x000053b7, // lui t2, 5
+    0x67838393, // addi t2, t2, 0x678
+    0x06731063, // bne t1, t2, fail
+    0x00b00913, // li s2, 11
+    0x6042d313, // rori t1, t0, 4
+    0x812343b7, // lui t2, 0x81234
+    0x56738393, // addi t2, t2, 0x567
+    0x04731663, // bne t1, t2, fail
+    0x00c00913, // li s2, 12
+    0x29f01313, // bseti t1, zero, 31
+    0x800003b7, // lui t2, 0x80000
+    0x02731e63, // bne t1, t2, fail
+    0x00d00913, // li s2, 13
+    0xc0207353, // fcvt.l.s t1, ft0
+    0xe2018353, // fmv.x.d t1, ft3
+    0x0852833b, // add.uw t1, t0, t0
+    0x6002931b, // clzw t1, t0
+    0x00400393, // li t2, 4
+    0x02799063, // bne s3, t2, fail
+    0x00e00913, // li s2, 14
+    0xe2019353, // fclass.d t1, ft3
+    0x00200393, // li t2, 2
+    0x00731863, // bne t1, t2, fail
+];
+
+#[test]
+fn test_uxl32_fp_and_bitmanip() {
+    let program = [UXL32, &EPILOGUE].concat();
+
+    assert_eq!(run_program("uxl32", &program_bytes(&program)), Some(0));
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::{
+    env, fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use common::{console_uart, contains};
+use tiny_riscv_emulator::{
+    emulator::Emulator,
+    uart::{UART_BASE, UART_IRQ, UART_SIZE},
+    virtio::{VirtioBlock, VIRTIO_BASE, VIRTIO_IRQ, VIRTIO_SIZE},
+};
+
+// xv6-riscvをビルドしたkernel(kernel/kernel)とfs.imgを置くディレクトリ
+const XV6_DIR: &str = "tests/xv6";
+
+const PROMPT: &[u8] = b"$ ";
+const COMMAND: &[u8] = b"usertests -q\n";
+const PASSED: &[u8] = b"ALL TESTS PASSED";
+const FAILED: &[u8] = b"SOME TESTS FAILED";
+
+// 出力を確認する間隔(命令数)と、テストを打ち切るまでの時間
+const STEPS: usize = 1_000_000;
+const TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+#[test]
+#[ignore = "requires an xv6-riscv kernel and fs.img in tests/xv6"]
+fn test_xv6_usertests() {
+    let dir = Path::new(XV6_DIR);
+
+    // usertestsはファイルシステムに書き込むので、コピーしたイメージを使う。
+    let image = env::temp_dir().join("tiny-riscv-emulator-xv6-fs.img");
+    fs::copy(dir.join("fs.img"), &image).unwrap();
+
+    let (uart, output) = console_uart(PROMPT, COMMAND);
+
+    let mut emulator = Emulator::default();
+
+    emulator.attach_device_with_irq(UART_BASE, UART_SIZE, UART_IRQ, Box::new(uart));
+    emulator.attach_device_with_irq(
+        VIRTIO_BASE,
+        VIRTIO_SIZE,
+        VIRTIO_IRQ,
+        Box::new(VirtioBlock::open(&image, false).unwrap()),
+    );
+
+    emulator.load(dir.join("kernel")).unwrap();
+
+    // xv6は終了しないので、結果が出力されるまで実行する。
+    let start = Instant::now();
+
+    loop {
+        for _ in 0..STEPS {
+            emulator.step();
+        }
+
+        let output = output.lock().unwrap();
+
+        if contains(&output, PASSED) || contains(&output, FAILED) {
+            break;
+        }
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "{}",
+            String::from_utf8_lossy(&output)
+        );
+    }
+
+    let _ = fs::remove_file(&image);
+
+    let output = output.lock().unwrap();
+
+    assert!(
+        contains(&output, PASSED),
+        "{}",
+        String::from_utf8_lossy(&output)
+    );
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use common::{load_program, program_bytes, run_program, run_to_exit, EPILOGUE};
+use tiny_riscv_emulator::emulator::Emulator;
+
+// Zicond、Zihintpause、Zihintntlの命令とFENCE.TSOのテスト
+// プログラムはRAMの先頭にロードするフラットなバイナリで、すべてのハートが先頭から実行する。
+// 成功した場合はシステムコントローラに0x5555を、失敗した場合は(番号 << 16) | 0x3333を書き込む。
+// 不正命令になった場合はmtvec(0)に飛んで終了しないので、MAX_STEPSで打ち切って失敗にする。
+
+// PAUSEで他のハートに実行を譲ったかを確かめるときの切り替えの間隔
+const QUANTUM: usize = 1000;
+
+// czero.eqzとczero.nezをrs2が0の場合と0でない場合(上位のビットのみが1の場合を含む)で確かめる。
+// 番号は失敗した確認の番号(s2)で、このあとにEPILOGUEを置く。
+const CZERO: &[u32] = &[
+    // _start:
+    0x4d200513, // li a0, 1234
+    0x00000593, // li a1, 0
+    0x00700613, // li a2, 7
+    0x00100913, // li s2, 1
+    0x0eb556b3, // czero.eqz a3, a0, a1
+    0x06069063, // bnez a3, fail
+    0x00200913, // li s2, 2
+    0x0ec556b3, // czero.eqz a3, a0, a2
+    0x04a69a63, // bne a3, a0, fail
+    0x00300913, // li s2, 3
+    0x0eb576b3, // czero.nez a3, a0, a1
+    0x04a69463, // bne a3, a0, fail
+    0x00400913, // li s2, 4
+    0x0ec576b3, // czero.nez a3, a0, a2
+    0x02069e63, // bnez a3, fail
+    0x00500913, // li s2, 5
+    0x00100593, // li a1, 1
+    0x03f59593, // slli a1, a1, 63
+    0x0eb556b3, // czero.eqz a3, a0, a1
+    0x02a69463, // bne a3, a0, fail
+    0x00600913, // li s2, 6
+    0x0eb576b3, // czero.nez a3, a0, a1
+    0x00069e63, // bnez a3, fail
+    0x00700913, // li s2, 7
+    0x0ec55033, // czero.eqz zero, a0, a2
+    0x00001863, // bnez zero, fail
+];
+
+// FENCE.TSO、PAUSE、NTL.*とC.NTL.*が不正命令にならずに実行できることを確かめる。(このあとにEPILOGUEを置く)
+const FENCE_HINTS: &[u32] = &[
+    // _start:
+    0x8330000f, // fence.tso
+    0x0100000f, // pause
+    0x00200033, // ntl.p1
+    0x00300033, // ntl.pall
+    0x00400033, // ntl.s1
+    0x00500033, // ntl.all
+    0x9016900a, // c.ntl.p1; c.ntl.all
+];
+
+// ハート0はPAUSEを入れたスピンループでハート1がフラグを立てるのを待ち、ループした回数(s2)を数える。
+// PAUSEで実行を譲っていれば、ハート1が切り替えの間隔ごとに進むだけなので、ループは数回で終わる。
+// ループが16回以上になった場合は回数を番号にして失敗にする。
+// ハート0の部分のあとにEPILOGUEを、そのあとにハート1の部分を置く。
+const PAUSE_INDEX: usize = 6;
+
+const PAUSE_HART0: &[u32] = &[
+    // _start:
+    0xf1402573, // csrr a0, mhartid
+    0x80001437, // lui s0, 0x80001
+    0x02041413, // slli s0, s0, 32
+    0x02045413, // srli s0, s0, 32
+    0x04051463, // bnez a0, secondary
+    0x00000913, // li s2, 0
+    // loop:
+    0x0100000f, // pause
+    0x00190913, // addi s2, s2, 1
+    0x00043e03, // ld t3, 0(s0)
+    0xfe0e0ae3, // beqz t3, loop
+    0x01000e93, // li t4, 16
+    0x01d97863, // bgeu s2, t4, fail
+];
+
+const PAUSE_HART1: &[u32] = &[
+    // secondary:
+    0x000012b7, // lui t0, 1
+    0xbb82829b, // addiw t0, t0, -1096
+    // 2:
+    0xfff28293, // addi t0, t0, -1
+    0xfe029ee3, // bnez t0, 2b
+    0x00100313, // li t1, 1
+    0x00643023, // sd t1, 0(s0)
+    0x0000006f, // 3: j 3b
+];
+
+const NOP: u32 = 0x00000013;
+
+fn run_pause(name: &str, program: &[u32]) -> Option<u64> {
+    let mut emulator = Emulator::default();
+    emulator.set_harts(2);
+    emulator.set_quantum(QUANTUM);
+
+    load_program(&mut emulator, name, &program_bytes(program));
+    run_to_exit(&mut emulator)
+}
+
+#[test]
+fn test_czero() {
+    let program = [CZERO, &EPILOGUE].concat();
+
+    assert_eq!(run_program("czero", &program_bytes(&program)), Some(0));
+}
+
+#[test]
+fn test_fence_tso_and_hints() {
+    let program = [FENCE_HINTS, &EPILOGUE].concat();
+
+    assert_eq!(
+        run_program("fence-hints", &program_bytes(&program)),
+        Some(0)
+    );
+}
+
+#[test]
+fn test_pause_yields() {
+    let mut program = [PAUSE_HART0, &EPILOGUE, PAUSE_HART1].concat();
+
+    assert_eq!(run_pause("pause", &program), Some(0));
+
+    // PAUSEの代わりにnopを使うと、切り替えの間隔いっぱいまでスピンループを続ける。
+    program[PAUSE_INDEX] = NOP;
+
+    assert_ne!(run_pause("pause-nop", &program), Some(0));
+}